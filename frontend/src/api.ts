@@ -22,16 +22,23 @@ export const apiClient = {
     api.get('/health').then(res => res.data),
 
   // Sessions
-  createSession: (data: CreateSessionRequest): Promise<SessionResponse> =>
-    api.post('/sessions', data).then(res => res.data),
+  // A user ID that was used before needs its key
+  createSession: (data: CreateSessionRequest, userKey?: string | null): Promise<SessionResponse> =>
+    api.post('/sessions', data, {
+      headers: userKey ? { Authorization: `Bearer ${userKey}` } : undefined,
+    }).then(res => res.data),
 
-  listSessions: (userId: string): Promise<SessionSummary[]> =>
+  // Listing needs the user key, a session its own token
+  listSessions: (userId: string, userKey: string): Promise<SessionSummary[]> =>
     api.get('/sessions', {
-      params: { user_id: userId }
+      params: { user_id: userId },
+      headers: { Authorization: `Bearer ${userKey}` },
     }).then(res => res.data),
 
-  getSession: (sessionId: string): Promise<Session> =>
-    api.get(`/sessions/${sessionId}`).then(res => res.data),
+  getSession: (sessionId: string, sessionToken: string): Promise<Session> =>
+    api.get(`/sessions/${sessionId}`, {
+      headers: { Authorization: `Bearer ${sessionToken}` },
+    }).then(res => res.data),
 };
 
 export default apiClient;
\ No newline at end of file
@@ -1,4 +1,4 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
 import { NoxTerminal } from './NoxTerminal';
 import { PrivacyControls } from './PrivacyControls';
 import { anonymousApi } from '../services/anonymousApi';
@@ -19,6 +19,7 @@ export const NoxApp: React.FC = () => {
   const [selectedImage, setSelectedImage] = useState('ubuntu:22.04');
   const [isLoading, setIsLoading] = useState(false);
   const [privacyEnabled, setPrivacyEnabled] = useState(false);
+  const [userKey, setUserKey] = useState<string | null>(null);
 
   const containerImages = [
     { name: 'Ubuntu 22.04', value: 'ubuntu:22.04', description: 'Latest Ubuntu LTS with full package support' },
@@ -31,6 +32,15 @@ export const NoxApp: React.FC = () => {
     { name: 'Rust', value: 'rust:latest', description: 'Rust development environment' },
   ];
 
+  // A user ID's key is only handed out once, so it is kept in this browser
+  const userKeyStorage = (id: string) => `noxterm.user_key.${id}`;
+  // Likewise a session's token, which the terminal connection needs
+  const sessionTokenStorage = (id: string) => `noxterm.session_token.${id}`;
+
+  useEffect(() => {
+    setUserKey(userId.trim() ? localStorage.getItem(userKeyStorage(userId)) : null);
+  }, [userId]);
+
   const handlePrivacyChange = (enabled: boolean) => {
     setPrivacyEnabled(enabled);
   };
@@ -51,9 +61,15 @@ export const NoxApp: React.FC = () => {
       const sessionData = await anonymousApi.createSession({
         user_id: userId,
         container_image: selectedImage,
-      });
+        privacy: privacyEnabled,
+      }, userKey);
 
       const session = sessionData;
+      if (session.user_key) {
+        localStorage.setItem(userKeyStorage(userId), session.user_key);
+        setUserKey(session.user_key);
+      }
+      localStorage.setItem(sessionTokenStorage(session.session_id), session.session_token);
       
       // Add to sessions list  
       const newSession = {
@@ -82,10 +98,12 @@ export const NoxApp: React.FC = () => {
   };
 
   const loadSessions = async () => {
-    if (!userId.trim()) return;
+    if (!userId.trim() || !userKey) return;
     
     try {
-      const response = await fetch(`http://localhost:3001/api/sessions?user_id=${userId}`);
+      const response = await fetch(`http://localhost:3001/api/sessions?user_id=${encodeURIComponent(userId)}`, {
+        headers: { 'Authorization': `Bearer ${userKey}` },
+      });
       if (response.ok) {
         const data = await response.json();
         setSessions(data.sessions || []);
@@ -134,6 +152,7 @@ export const NoxApp: React.FC = () => {
         <div className="flex-1">
           <NoxTerminal
             sessionId={activeSessionId}
+            sessionToken={localStorage.getItem(sessionTokenStorage(activeSessionId)) ?? ''}
             userId={userId}
             containerImage={selectedImage}
           />
@@ -175,7 +194,7 @@ export const NoxApp: React.FC = () => {
 
           {/* Privacy Controls */}
           <div className="mb-8">
-            <PrivacyControls onPrivacyChange={handlePrivacyChange} />
+            <PrivacyControls userId={userId} userKey={userKey} onPrivacyChange={handlePrivacyChange} />
           </div>
 
           {/* Features */}
@@ -6,12 +6,14 @@ import 'xterm/css/xterm.css';
 
 interface NoxTerminalProps {
   sessionId: string;
+  sessionToken: string;
   userId: string;
   containerImage?: string;
 }
 
 export const NoxTerminal: React.FC<NoxTerminalProps> = ({ 
   sessionId, 
+  sessionToken,
   userId, 
   containerImage = 'ubuntu:22.04' 
 }) => {
@@ -119,9 +121,11 @@ export const NoxTerminal: React.FC<NoxTerminalProps> = ({
     const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
     const host = window.location.hostname;
     const port = window.location.port || (window.location.protocol === 'https:' ? '443' : '3001');
+    // WebSockets cannot carry headers, so the session token goes in the query
+    const token = `?token=${encodeURIComponent(sessionToken)}`;
     const wsUrl = usePtyMode ?
-      `${protocol}//${host}:${port}/pty/${sessionId}` :
-      `${protocol}//${host}:${port}/ws/${sessionId}`;
+      `${protocol}//${host}:${port}/pty/${sessionId}${token}` :
+      `${protocol}//${host}:${port}/ws/${sessionId}${token}`;
     
     socket.current = new WebSocket(wsUrl);
 
@@ -1,12 +1,15 @@
 import React, { useState, useEffect } from 'react';
 
 interface PrivacyControlsProps {
+  userId: string;
+  /** The user's key, handed out when the user ID was first used; the stored default needs it */
+  userKey: string | null;
   onPrivacyChange: (enabled: boolean) => void;
 }
 
 type CircuitStatus = 'disconnected' | 'connecting' | 'connected' | 'error';
 
-export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ onPrivacyChange }) => {
+export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ userId, userKey, onPrivacyChange }) => {
   const [privacyEnabled, setPrivacyEnabled] = useState(false);
   const [circuitStatus, setCircuitStatus] = useState<CircuitStatus>('disconnected');
   const [isLoading, setIsLoading] = useState(false);
@@ -20,15 +23,24 @@ export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ onPrivacyChang
   };
   
   const enableAnonymity = async () => {
+    if (!userId.trim() || !userKey) {
+      // Nothing to persist yet; the choice is sent with the next session
+      setPrivacyEnabled(true);
+      setCircuitStatus('connected');
+      onPrivacyChange(true);
+      return;
+    }
+
     try {
       setIsLoading(true);
       setCircuitStatus('connecting');
       
-      // Call backend to enable Anyone protocol
-      const response = await fetch('/api/privacy/enable', {
+      // Remember the choice for this user; new sessions are created with it
+      const response = await fetch(`/api/privacy/enable?user_id=${encodeURIComponent(userId)}`, {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
+          'Authorization': `Bearer ${userKey}`,
         },
       });
       
@@ -50,14 +62,22 @@ export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ onPrivacyChang
   };
   
   const disableAnonymity = async () => {
+    if (!userId.trim() || !userKey) {
+      setPrivacyEnabled(false);
+      setCircuitStatus('disconnected');
+      onPrivacyChange(false);
+      return;
+    }
+
     try {
       setIsLoading(true);
       
-      // Call backend to disable Anyone protocol
-      const response = await fetch('/api/privacy/disable', {
+      // Existing sessions keep their setting, only new ones are affected
+      const response = await fetch(`/api/privacy/disable?user_id=${encodeURIComponent(userId)}`, {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
+          'Authorization': `Bearer ${userKey}`,
         },
       });
       
@@ -74,15 +94,20 @@ export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ onPrivacyChang
     }
   };
 
-  // Check privacy status on component mount
+  // Load the user's privacy default whenever the user or their key changes
   useEffect(() => {
+    if (!userId.trim() || !userKey) return;
+
     const checkPrivacyStatus = async () => {
       try {
-        const response = await fetch('/api/privacy/status');
+        const response = await fetch(`/api/privacy/status?user_id=${encodeURIComponent(userId)}`, {
+          headers: { 'Authorization': `Bearer ${userKey}` },
+        });
         if (response.ok) {
           const data = await response.json();
           setPrivacyEnabled(data.enabled);
           setCircuitStatus(data.enabled ? 'connected' : 'disconnected');
+          onPrivacyChange(data.enabled);
         }
       } catch (error) {
         console.error('Failed to check privacy status:', error);
@@ -90,7 +115,7 @@ export const PrivacyControls: React.FC<PrivacyControlsProps> = ({ onPrivacyChang
     };
     
     checkPrivacyStatus();
-  }, []);
+  }, [userId, userKey]);
 
   const getStatusColor = () => {
     switch (circuitStatus) {
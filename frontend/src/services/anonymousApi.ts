@@ -47,40 +47,56 @@ class AnonymousApiClient {
     return response.data;
   }
 
-  // Privacy control endpoints
-  async enablePrivacyMode(): Promise<{ status: string; socks_port: number }> {
-    const response = await this.standardClient.post('/privacy/enable');
+  // Privacy control endpoints (per-user default for new sessions); each
+  // takes the user's key
+  async enablePrivacyMode(userId: string, userKey: string): Promise<{ status: string; socks_port: number }> {
+    const response = await this.standardClient.post('/privacy/enable', null, {
+      params: { user_id: userId },
+      headers: { Authorization: `Bearer ${userKey}` },
+    });
     this.enablePrivacy();
     return response.data;
   }
 
-  async disablePrivacyMode(): Promise<{ status: string }> {
-    const response = await this.standardClient.post('/privacy/disable');
+  async disablePrivacyMode(userId: string, userKey: string): Promise<{ status: string }> {
+    const response = await this.standardClient.post('/privacy/disable', null, {
+      params: { user_id: userId },
+      headers: { Authorization: `Bearer ${userKey}` },
+    });
     this.disablePrivacy();
     return response.data;
   }
 
-  async getPrivacyStatus(): Promise<{ enabled: boolean; socks_port?: number }> {
-    const response = await this.standardClient.get('/privacy/status');
+  async getPrivacyStatus(userId: string, userKey: string): Promise<{ enabled: boolean; socks_port?: number; active_sessions: number }> {
+    const response = await this.standardClient.get('/privacy/status', {
+      params: { user_id: userId },
+      headers: { Authorization: `Bearer ${userKey}` },
+    });
     this.privacyEnabled = response.data.enabled;
     return response.data;
   }
 
-  // Session management
-  async createSession(data: CreateSessionRequest): Promise<SessionResponse> {
-    const response = await this.getClient().post('/sessions', data);
+  // Session management; a user ID that was used before needs its key
+  async createSession(data: CreateSessionRequest, userKey?: string | null): Promise<SessionResponse> {
+    const response = await this.getClient().post('/sessions', data, {
+      headers: userKey ? { Authorization: `Bearer ${userKey}` } : undefined,
+    });
     return response.data;
   }
 
-  async listSessions(userId: string): Promise<SessionSummary[]> {
+  // Listing needs the user key, a session its own token
+  async listSessions(userId: string, userKey: string): Promise<SessionSummary[]> {
     const response = await this.getClient().get('/sessions', {
-      params: { user_id: userId }
+      params: { user_id: userId },
+      headers: { Authorization: `Bearer ${userKey}` },
     });
     return response.data;
   }
 
-  async getSession(sessionId: string): Promise<any> {
-    const response = await this.getClient().get(`/sessions/${sessionId}`);
+  async getSession(sessionId: string, sessionToken: string): Promise<any> {
+    const response = await this.getClient().get(`/sessions/${sessionId}`, {
+      headers: { Authorization: `Bearer ${sessionToken}` },
+    });
     return response.data;
   }
 
@@ -16,11 +16,16 @@ export interface SessionResponse {
   status: SessionStatus;
   container_id: string | null;
   created_at: string;
+  /** Proves ownership of the session */
+  session_token: string;
+  /** Proves the user ID from now on; only sent when this session claimed it */
+  user_key?: string;
 }
 
 export interface CreateSessionRequest {
   user_id: string;
   container_image?: string;
+  privacy?: boolean;
 }
 
 export interface SessionSummary {
@@ -13,7 +13,7 @@ CREATE TABLE IF NOT EXISTS sessions (
     last_activity TIMESTAMPTZ NOT NULL DEFAULT NOW(),
     disconnected_at TIMESTAMPTZ,
     expires_at TIMESTAMPTZ,
-    resource_limits JSONB DEFAULT '{"memory_mb": 1024, "cpu_percent": 100, "pids_limit": 200}',
+    resource_limits JSONB DEFAULT '{"memory_mb": 1024, "cpu_percent": 100, "pids_limit": 200, "disk_mb": 2048, "tmpfs_mb": 256}',
     metadata JSONB DEFAULT '{}'
 );
 
@@ -61,6 +61,12 @@ CREATE TABLE IF NOT EXISTS command_history (
     executed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
+-- Exec API results and terminal lines (exit_code is NULL for commands
+-- killed on timeout and for lines typed into the terminal)
+ALTER TABLE command_history ADD COLUMN IF NOT EXISTS source VARCHAR(20) NOT NULL DEFAULT 'exec';
+ALTER TABLE command_history ADD COLUMN IF NOT EXISTS duration_ms BIGINT;
+ALTER TABLE command_history ADD COLUMN IF NOT EXISTS timed_out BOOLEAN NOT NULL DEFAULT FALSE;
+
 -- Security events table (blocked commands, violations)
 CREATE TABLE IF NOT EXISTS security_events (
     id BIGSERIAL PRIMARY KEY,
@@ -74,6 +80,42 @@ CREATE TABLE IF NOT EXISTS security_events (
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
+-- Batch jobs (logs and artifacts are kept on disk under NOXTERM_JOB_ARTIFACT_DIR)
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY,
+    session_id UUID NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    image VARCHAR(255) NOT NULL,
+    tier VARCHAR(50) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    exit_code BIGINT,
+    error TEXT,
+    artifacts JSONB NOT NULL DEFAULT '[]',
+    log_truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    token_sha256 VARCHAR(64) NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+-- Per-user defaults applied to new sessions
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id VARCHAR(255) PRIMARY KEY,
+    privacy_default BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Keys proving user IDs, handed to whoever used an ID first (SHA-256 only)
+CREATE TABLE IF NOT EXISTS user_keys (
+    user_id VARCHAR(255) PRIMARY KEY,
+    key_sha256 VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Command history opt-out and retention (NULL keeps the server default)
+ALTER TABLE user_preferences ADD COLUMN IF NOT EXISTS history_enabled BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE user_preferences ADD COLUMN IF NOT EXISTS history_retention_days INT;
+
 -- Indexes for performance
 CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id);
 CREATE INDEX IF NOT EXISTS idx_sessions_status ON sessions(status);
@@ -92,6 +134,12 @@ CREATE INDEX IF NOT EXISTS idx_metrics_recorded ON container_metrics(recorded_at
 CREATE INDEX IF NOT EXISTS idx_rate_identifier ON rate_limits(identifier);
 CREATE INDEX IF NOT EXISTS idx_rate_window ON rate_limits(window_start);
 
+CREATE INDEX IF NOT EXISTS idx_history_session ON command_history(session_id);
+CREATE INDEX IF NOT EXISTS idx_history_user ON command_history(user_id, executed_at);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_user ON jobs(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_jobs_finished ON jobs(finished_at) WHERE finished_at IS NOT NULL;
+
 CREATE INDEX IF NOT EXISTS idx_security_session ON security_events(session_id);
 CREATE INDEX IF NOT EXISTS idx_security_user ON security_events(user_id);
 CREATE INDEX IF NOT EXISTS idx_security_severity ON security_events(severity);
@@ -0,0 +1,450 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Client for the Anyone (Tor-compatible) control protocol.
+//! Covers authentication, GETINFO, SIGNAL NEWNYM and asynchronous events.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+use tracing::debug;
+
+/// A complete reply: status code plus one entry per reply line
+///
+/// Data blocks (`250+key=` ... `.`) are folded into their line, joined by `\n`.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub code: u16,
+    pub lines: Vec<String>,
+}
+
+impl Reply {
+    pub fn is_ok(&self) -> bool {
+        self.code == 250
+    }
+}
+
+/// A relay hop in a circuit path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Relay {
+    pub fingerprint: String,
+    pub nickname: Option<String>,
+}
+
+/// One line of `GETINFO circuit-status` or a CIRC event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Circuit {
+    pub id: String,
+    pub status: String,
+    pub path: Vec<Relay>,
+    pub purpose: Option<String>,
+    pub build_flags: Vec<String>,
+    pub time_created: Option<String>,
+    /// SOCKS username the circuit is isolated to, if any
+    pub socks_username: Option<String>,
+}
+
+impl Circuit {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        let id = tokens.next()?.to_string();
+        let status = tokens.next()?.to_string();
+
+        let mut circuit = Circuit {
+            id,
+            status,
+            path: Vec::new(),
+            purpose: None,
+            build_flags: Vec::new(),
+            time_created: None,
+            socks_username: None,
+        };
+
+        for token in tokens {
+            match token.split_once('=') {
+                // The path is the only positional field and starts with `$`
+                _ if token.starts_with('$') => {
+                    circuit.path = token.split(',').map(Relay::parse).collect();
+                }
+                Some(("PURPOSE", value)) => circuit.purpose = Some(value.to_string()),
+                Some(("BUILD_FLAGS", value)) => {
+                    circuit.build_flags = value.split(',').map(String::from).collect();
+                }
+                Some(("TIME_CREATED", value)) => circuit.time_created = Some(value.to_string()),
+                Some(("SOCKS_USERNAME", value)) => {
+                    circuit.socks_username = Some(value.trim_matches('"').to_string());
+                }
+                _ => {}
+            }
+        }
+
+        Some(circuit)
+    }
+}
+
+impl Relay {
+    fn parse(hop: &str) -> Self {
+        let hop = hop.trim_start_matches('$');
+        match hop.split_once(['~', '=']) {
+            Some((fingerprint, nickname)) => Relay {
+                fingerprint: fingerprint.to_string(),
+                nickname: Some(nickname.to_string()),
+            },
+            None => Relay {
+                fingerprint: hop.to_string(),
+                nickname: None,
+            },
+        }
+    }
+}
+
+/// Bootstrap state from `status/bootstrap-phase` or a STATUS_CLIENT event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BootstrapPhase {
+    pub progress: u8,
+    pub tag: String,
+    pub summary: String,
+}
+
+impl BootstrapPhase {
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = &line[line.find("BOOTSTRAP")? + "BOOTSTRAP".len()..];
+        let args = parse_keywords(rest);
+        let get = |key: &str| args.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+
+        Some(BootstrapPhase {
+            progress: get("PROGRESS")?.parse().ok()?,
+            tag: get("TAG").unwrap_or_default(),
+            summary: get("SUMMARY").unwrap_or_default(),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress >= 100
+    }
+}
+
+/// Split `KEY=value KEY="quoted value"` arguments
+fn parse_keywords(input: &str) -> Vec<(String, String)> {
+    let mut args = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_string();
+        rest = &rest[eq + 1..];
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let value = quoted[..end].to_string();
+            rest = quoted.get(end + 1..).unwrap_or("");
+            value
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            let value = rest[..end].to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        args.push((key, value));
+        rest = rest.trim_start();
+    }
+
+    args
+}
+
+/// An authenticated control-port connection
+pub struct ControlClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    /// Asynchronous (650) events received while waiting for a reply
+    events: VecDeque<String>,
+}
+
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ControlClient {
+    pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        let stream = timeout(IO_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to control port {}", addr))?
+            .with_context(|| format!("Failed to connect to control port {}", addr))?;
+        let (read, write) = stream.into_split();
+
+        Ok(Self {
+            reader: BufReader::new(read),
+            writer: write,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Connect and authenticate using whatever method the daemon offers
+    pub async fn connect_authenticated(addr: SocketAddr, password: Option<&str>) -> Result<Self> {
+        let mut client = Self::connect(addr).await?;
+        client.authenticate(password).await?;
+        Ok(client)
+    }
+
+    /// Authenticate via PROTOCOLINFO: no auth, cookie file, or hashed password
+    pub async fn authenticate(&mut self, password: Option<&str>) -> Result<()> {
+        let info = self.command("PROTOCOLINFO 1").await?;
+        let auth_line = info
+            .lines
+            .iter()
+            .find(|line| line.starts_with("AUTH "))
+            .ok_or_else(|| anyhow!("PROTOCOLINFO did not list auth methods"))?;
+        let args = parse_keywords(&auth_line["AUTH ".len()..]);
+        let methods: Vec<&str> = args
+            .iter()
+            .find(|(k, _)| k == "METHODS")
+            .map(|(_, v)| v.split(',').collect())
+            .unwrap_or_default();
+        let cookie_file = args.iter().find(|(k, _)| k == "COOKIEFILE").map(|(_, v)| v.clone());
+
+        let credential = if methods.contains(&"NULL") {
+            String::new()
+        } else if let (true, Some(path)) = (methods.contains(&"COOKIE"), cookie_file) {
+            let cookie = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Failed to read control auth cookie {}", path))?;
+            format!(" {}", hex(&cookie))
+        } else if let (true, Some(password)) = (methods.contains(&"HASHEDPASSWORD"), password) {
+            format!(" \"{}\"", password.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            return Err(anyhow!("No usable control port auth method (offered: {})", methods.join(",")));
+        };
+
+        self.command(&format!("AUTHENTICATE{}", credential)).await?;
+        debug!("Authenticated to control port");
+        Ok(())
+    }
+
+    /// Send a command and return its reply, failing on non-250 codes
+    pub async fn command(&mut self, line: &str) -> Result<Reply> {
+        timeout(IO_TIMEOUT, self.writer.write_all(format!("{}\r\n", line).as_bytes()))
+            .await
+            .map_err(|_| anyhow!("Timed out sending control command"))??;
+
+        let reply = loop {
+            let reply = timeout(IO_TIMEOUT, self.read_reply())
+                .await
+                .map_err(|_| anyhow!("Timed out waiting for control reply"))??;
+            if reply.code == 650 {
+                self.events.extend(reply.lines);
+                continue;
+            }
+            break reply;
+        };
+
+        if !reply.is_ok() {
+            let verb = line.split_whitespace().next().unwrap_or(line);
+            return Err(anyhow!("{} failed: {} {}", verb, reply.code, reply.lines.join(" ")));
+        }
+        Ok(reply)
+    }
+
+    /// Value of a single GETINFO key
+    pub async fn getinfo(&mut self, key: &str) -> Result<String> {
+        let reply = self.command(&format!("GETINFO {}", key)).await?;
+        let prefix = format!("{}=", key);
+        reply
+            .lines
+            .iter()
+            .find_map(|line| line.strip_prefix(&prefix))
+            .map(|value| value.trim_start_matches('\n').to_string())
+            .ok_or_else(|| anyhow!("GETINFO {} returned no value", key))
+    }
+
+    /// Ask for new circuits for all future connections
+    pub async fn new_identity(&mut self) -> Result<()> {
+        self.command("SIGNAL NEWNYM").await.map(|_| ())
+    }
+
+    pub async fn circuits(&mut self) -> Result<Vec<Circuit>> {
+        let status = self.getinfo("circuit-status").await?;
+        Ok(status.lines().filter_map(Circuit::parse).collect())
+    }
+
+    pub async fn bootstrap_phase(&mut self) -> Result<BootstrapPhase> {
+        let phase = self.getinfo("status/bootstrap-phase").await?;
+        BootstrapPhase::parse(&phase).ok_or_else(|| anyhow!("Unrecognised bootstrap phase: {}", phase))
+    }
+
+    /// Subscribe to asynchronous events, e.g. `CIRC` or `STATUS_CLIENT`
+    pub async fn set_events(&mut self, events: &[&str]) -> Result<()> {
+        self.command(&format!("SETEVENTS {}", events.join(" "))).await.map(|_| ())
+    }
+
+    /// Next event line without the `650 ` prefix; waits until one arrives
+    pub async fn next_event(&mut self) -> Result<String> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let reply = self.read_reply().await?;
+            if reply.code == 650 {
+                self.events.extend(reply.lines);
+            }
+        }
+    }
+
+    async fn read_reply(&mut self) -> Result<Reply> {
+        let mut lines = Vec::new();
+
+        loop {
+            let line = self.read_line().await?;
+            let malformed = || anyhow!("Malformed control reply: {:?}", line);
+
+            let code: u16 = line.get(..3).ok_or_else(malformed)?.parse().map_err(|_| malformed())?;
+            let separator = *line.as_bytes().get(3).ok_or_else(malformed)?;
+            let mut text = line.get(4..).ok_or_else(malformed)?.to_string();
+
+            if separator == b'+' {
+                loop {
+                    let data = self.read_line().await?;
+                    if data == "." {
+                        break;
+                    }
+                    text.push('\n');
+                    // Leading dots are escaped by doubling
+                    text.push_str(data.strip_prefix('.').filter(|d| d.starts_with('.')).unwrap_or(&data));
+                }
+            }
+            lines.push(text);
+
+            if separator == b' ' {
+                return Ok(Reply { code, lines });
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("Control connection closed"));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Fake control port answering each expected command with a canned reply
+    async fn fake_server(script: Vec<(&'static str, &'static str)>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+
+            for (expected, response) in script {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                assert_eq!(line.trim_end(), expected);
+                write.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    const PROTOCOLINFO_NULL: &str =
+        "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250-VERSION Tor=\"0.4.8.9\"\r\n250 OK\r\n";
+
+    #[tokio::test]
+    async fn test_authenticate_and_newnym() {
+        let addr = fake_server(vec![
+            ("PROTOCOLINFO 1", PROTOCOLINFO_NULL),
+            ("AUTHENTICATE", "250 OK\r\n"),
+            ("SIGNAL NEWNYM", "250 OK\r\n"),
+        ]).await;
+
+        let mut client = ControlClient::connect_authenticated(addr, None).await.unwrap();
+        client.new_identity().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_short_and_non_ascii_replies_are_errors() {
+        for reply in ["25\r\n", "25\u{e9} OK\r\n", "250\u{e9}OK\r\n"] {
+            let addr = fake_server(vec![("PROTOCOLINFO 1", reply)]).await;
+            let err = ControlClient::connect_authenticated(addr, None).await.err().unwrap();
+            assert!(err.to_string().contains("Malformed control reply"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hashed_password_auth() {
+        let addr = fake_server(vec![
+            ("PROTOCOLINFO 1", "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=HASHEDPASSWORD\r\n250 OK\r\n"),
+            ("AUTHENTICATE \"s3cret\"", "515 Authentication failed\r\n"),
+        ]).await;
+
+        let err = ControlClient::connect_authenticated(addr, Some("s3cret")).await.err().unwrap();
+        assert!(err.to_string().contains("515"));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_status_data_block() {
+        let addr = fake_server(vec![
+            ("PROTOCOLINFO 1", PROTOCOLINFO_NULL),
+            ("AUTHENTICATE", "250 OK\r\n"),
+            (
+                "GETINFO circuit-status",
+                "250+circuit-status=\r\n\
+                 3 BUILT $AAAA~guard,$BBBB~middle,$CCCC~exit BUILD_FLAGS=NEED_CAPACITY PURPOSE=GENERAL TIME_CREATED=2025-01-01T00:00:00 SOCKS_USERNAME=\"noxterm-a\" SOCKS_PASSWORD=\"x\"\r\n\
+                 4 LAUNCHED PURPOSE=GENERAL\r\n\
+                 .\r\n250 OK\r\n",
+            ),
+        ]).await;
+
+        let mut client = ControlClient::connect_authenticated(addr, None).await.unwrap();
+        let circuits = client.circuits().await.unwrap();
+
+        assert_eq!(circuits.len(), 2);
+        assert_eq!(circuits[0].status, "BUILT");
+        assert_eq!(circuits[0].path.len(), 3);
+        assert_eq!(circuits[0].path[2].nickname.as_deref(), Some("exit"));
+        assert_eq!(circuits[0].socks_username.as_deref(), Some("noxterm-a"));
+        assert_eq!(circuits[1].path, Vec::new());
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_events() {
+        let addr = fake_server(vec![
+            ("PROTOCOLINFO 1", PROTOCOLINFO_NULL),
+            ("AUTHENTICATE", "250 OK\r\n"),
+            (
+                "GETINFO status/bootstrap-phase",
+                "250-status/bootstrap-phase=NOTICE BOOTSTRAP PROGRESS=50 TAG=loading_descriptors SUMMARY=\"Loading relay descriptors\"\r\n250 OK\r\n",
+            ),
+            (
+                "SETEVENTS STATUS_CLIENT",
+                "250 OK\r\n650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"\r\n",
+            ),
+        ]).await;
+
+        let mut client = ControlClient::connect_authenticated(addr, None).await.unwrap();
+        let phase = client.bootstrap_phase().await.unwrap();
+        assert_eq!(phase.progress, 50);
+        assert_eq!(phase.summary, "Loading relay descriptors");
+
+        client.set_events(&["STATUS_CLIENT"]).await.unwrap();
+        let event = client.next_event().await.unwrap();
+        let done = BootstrapPhase::parse(&event).unwrap();
+        assert!(done.is_done());
+        assert_eq!(done.tag, "done");
+    }
+}
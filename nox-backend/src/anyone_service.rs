@@ -1,23 +1,41 @@
-use std::process::{Command, Stdio};
+use std::collections::HashSet;
+use std::process::Stdio;
 use std::sync::Arc;
-use std::path::Path;
-use tokio::sync::{RwLock, Mutex};
-use tokio::time::{sleep, Duration, timeout};
-use tracing::{info, warn, debug};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{broadcast, RwLock, Mutex};
+use tokio::time::{sleep, Duration, Instant, timeout};
+use tracing::{info, warn, debug, error};
 use reqwest::Client;
-use tokio::process::{Child, Command as TokioCommand};
+use tokio::process::Child;
 use anyhow::{Result, Context};
-
-/// NOX Rust, Anyone Protocol service manager
-/// Cross-platform support for macOS, Linux, and Windows
+use std::net::SocketAddr;
+use crate::anyone_control::ControlClient;
+use crate::anyone_supervisor::{self, Backoff, SupervisorEvent};
+use crate::egress::dns::Lookup;
+use crate::privacy::{PrivacyBackend, ProxyEndpoint};
+use uuid::Uuid;
+
+/// NOX Rust, privacy network service manager
+/// Runs and supervises the configured backend (Anyone by default)
 #[derive(Clone)]
 pub struct AnyoneService {
-    process: Arc<Mutex<Option<Child>>>,
-    socks_port: u16,
-    control_port: u16,
+    /// The client process and the generation that spawned it
+    process: Arc<Mutex<Option<(u64, Child)>>>,
+    backend: Arc<dyn PrivacyBackend>,
     enabled: Arc<RwLock<bool>>,
     status: Arc<RwLock<ServiceStatus>>,
     client: Arc<RwLock<Option<Client>>>,
+    /// Sessions that need the daemon; it runs while this is non-empty
+    holders: Arc<Mutex<HashSet<Uuid>>>,
+    /// Held while the daemon is started or stopped for its holders
+    transition: Arc<Mutex<()>>,
+    /// Bumped on every start/stop so a stale supervisor knows to exit
+    generation: Arc<AtomicU64>,
+    /// Times the supervisor brought a crashed client back up
+    restarts: Arc<AtomicU64>,
+    /// Last warning or error the client logged
+    last_error: Arc<RwLock<Option<String>>>,
+    events: broadcast::Sender<SupervisorEvent>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -30,14 +48,19 @@ pub enum ServiceStatus {
 }
 
 impl AnyoneService {
-    pub fn new(socks_port: u16, control_port: u16) -> Self {
+    pub fn new(backend: Arc<dyn PrivacyBackend>) -> Self {
         Self {
             process: Arc::new(Mutex::new(None)),
-            socks_port,
-            control_port,
+            backend,
             enabled: Arc::new(RwLock::new(false)),
             status: Arc::new(RwLock::new(ServiceStatus::Stopped)),
             client: Arc::new(RwLock::new(None)),
+            holders: Arc::new(Mutex::new(HashSet::new())),
+            transition: Arc::new(Mutex::new(())),
+            generation: Arc::new(AtomicU64::new(0)),
+            restarts: Arc::new(AtomicU64::new(0)),
+            last_error: Arc::new(RwLock::new(None)),
+            events: broadcast::channel(16).0,
         }
     }
 
@@ -50,41 +73,24 @@ impl AnyoneService {
 
         *status = ServiceStatus::Starting;
         drop(status);
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
 
-        info!("Starting Anyone Protocol service on SOCKS port {}, Control port {}",
-              self.socks_port, self.control_port);
-
-        // Ensure Node.js is installed (auto-install if needed)
-        self.ensure_nodejs_installed().await?;
-
-        // Install Anyone client if not present
-        self.ensure_anyone_client_installed().await?;
-
-        // Create terms agreement file
-        self.create_terms_agreement().await?;
+        info!("Starting {} privacy backend (proxy {})", self.backend.name(), self.backend.proxy().addr);
 
-        // Start the Anyone client process
-        let mut process = self.process.lock().await;
-
-        if process.is_some() {
-            return Ok(()); // Race condition protection
+        if let Err(e) = self.launch(generation).await {
+            // A client left behind would be taken for a running one
+            self.kill_generation(generation).await;
+            *self.status.write().await = ServiceStatus::Error(e.to_string());
+            return Err(e);
         }
 
-        let child = self.spawn_anyone_process().await?;
-        *process = Some(child);
-        drop(process);
-
-        // Wait for service to be ready with timeout
-        self.wait_for_ready().await?;
-
-        // Initialize HTTP client with SOCKS proxy
-        self.initialize_proxy_client().await?;
-
         // Update status
         *self.enabled.write().await = true;
         *self.status.write().await = ServiceStatus::Running;
 
-        info!("✅ Anyone Protocol service started successfully");
+        tokio::spawn(self.clone().supervise(generation));
+
+        info!("✅ {} privacy backend started successfully", self.backend.name());
         Ok(())
     }
 
@@ -97,12 +103,14 @@ impl AnyoneService {
 
         *status = ServiceStatus::Stopping;
         drop(status);
+        // Deliberate stop: the supervisor must not restart the client
+        self.generation.fetch_add(1, Ordering::SeqCst);
 
         info!("Stopping Anyone Protocol service...");
 
         let mut process = self.process.lock().await;
 
-        if let Some(mut child) = process.take() {
+        if let Some((_, mut child)) = process.take() {
             // Graceful shutdown first
             if let Err(e) = child.kill().await {
                 warn!("Failed to kill Anyone process gracefully: {}", e);
@@ -133,511 +141,341 @@ impl AnyoneService {
         Ok(())
     }
 
-    /// Check if the service is currently enabled and running
-    pub async fn is_enabled(&self) -> bool {
-        *self.enabled.read().await
-    }
-
-    /// Get the current service status
-    pub async fn get_status(&self) -> ServiceStatus {
-        self.status.read().await.clone()
-    }
-
-    /// Get SOCKS port
-    pub fn get_socks_port(&self) -> u16 {
-        self.socks_port
-    }
-
-    /// Get control port
-    pub fn get_control_port(&self) -> u16 {
-        self.control_port
-    }
-
-    /// Get HTTP client configured for SOCKS proxy
-    pub async fn get_proxy_client(&self) -> Option<Client> {
-        self.client.read().await.clone()
-    }
-
-    pub async fn check_ports_available(&self) -> Result<()> {
-        use std::net::{TcpListener, SocketAddr};
-
-        let socks_addr: SocketAddr = format!("127.0.0.1:{}", self.socks_port).parse()?;
-        let control_addr: SocketAddr = format!("127.0.0.1:{}", self.control_port).parse()?;
-
-        // Check SOCKS port
-        match TcpListener::bind(socks_addr) {
-            Ok(_) => {},
-            Err(_) => {
-                return Err(anyhow::anyhow!("SOCKS port {} is already in use", self.socks_port));
+    /// Keep the daemon running on behalf of a session, starting it if needed
+    pub async fn acquire(&self, session_id: Uuid) -> Result<()> {
+        let holders = {
+            let mut holders = self.holders.lock().await;
+            holders.insert(session_id);
+            holders.len()
+        };
+        debug!("Session {} holds Anyone ({} holders)", session_id, holders);
+
+        // Starts and stops are serialized so a release cannot stop a daemon
+        // mid-acquire; the holder set stays free while the daemon starts
+        let _transition = self.transition.lock().await;
+        if !self.is_enabled().await {
+            if let Err(e) = self.check_ports_available().await {
+                // Ports may be held by a previous instance, try anyway
+                warn!("Port check failed: {}", e);
             }
-        }
-
-        // Check control port
-        match TcpListener::bind(control_addr) {
-            Ok(_) => {},
-            Err(_) => {
-                return Err(anyhow::anyhow!("Control port {} is already in use", self.control_port));
+            if let Err(e) = self.start().await {
+                self.holders.lock().await.remove(&session_id);
+                return Err(e);
             }
         }
-
         Ok(())
     }
 
-    /// Public method to ensure all prerequisites are installed
-    /// Called at startup to pre-install Node.js and Anyone SDK
-    pub async fn ensure_prerequisites(&self) -> Result<()> {
-        // Ensure Node.js is installed (auto-install if needed)
-        self.ensure_nodejs_installed().await?;
-
-        // Install Anyone client if needed
-        self.ensure_anyone_client_installed().await?;
-
-        Ok(())
-    }
-
-    // ========================================================================
-    // Cross-platform Node.js auto-installation
-    // ========================================================================
-
-    /// Check if Node.js is installed, if not, attempt to install it
-    async fn ensure_nodejs_installed(&self) -> Result<()> {
-        let (node_cmd, npm_cmd) = Self::get_node_commands();
-
-        // Check if Node.js is already installed
-        if let Ok(output) = Command::new(&node_cmd).args(["--version"]).output() {
-            if output.status.success() {
-                let version = String::from_utf8_lossy(&output.stdout);
-                info!("Node.js version: {}", version.trim());
-
-                // Also verify npm
-                if let Ok(npm_output) = Command::new(&npm_cmd).args(["--version"]).output() {
-                    if npm_output.status.success() {
-                        let npm_version = String::from_utf8_lossy(&npm_output.stdout);
-                        info!("npm version: {}", npm_version.trim());
-                        return Ok(());
-                    }
-                }
+    /// Drop a session's hold; the daemon stops once no session needs it
+    pub async fn release(&self, session_id: Uuid) -> Result<()> {
+        {
+            let mut holders = self.holders.lock().await;
+            if !holders.remove(&session_id) {
+                return Ok(());
+            }
+            debug!("Session {} released Anyone ({} holders)", session_id, holders.len());
+            if !holders.is_empty() {
+                return Ok(());
             }
         }
 
-        // Node.js not found - attempt to install
-        info!("Node.js not found. Attempting to install...");
-
-        if cfg!(target_os = "macos") {
-            self.install_nodejs_macos().await?;
-        } else if cfg!(target_os = "linux") {
-            self.install_nodejs_linux().await?;
-        } else if cfg!(target_os = "windows") {
-            self.install_nodejs_windows().await?;
-        } else {
-            return Err(anyhow::anyhow!(
-                "Unsupported platform. Please install Node.js manually from https://nodejs.org/"
-            ));
+        let _transition = self.transition.lock().await;
+        // Another session may have acquired it in the meantime
+        if !self.holders.lock().await.is_empty() {
+            return Ok(());
         }
+        info!("No sessions need Anyone any more, stopping it");
+        self.stop().await
+    }
 
-        // Verify installation
-        self.verify_nodejs_installation().await
+    /// Number of sessions currently holding the daemon
+    pub async fn holder_count(&self) -> usize {
+        self.holders.lock().await.len()
     }
 
-    /// Get platform-specific node/npm command names
-    fn get_node_commands() -> (String, String) {
-        if cfg!(target_os = "windows") {
-            ("node.exe".to_string(), "npm.cmd".to_string())
-        } else {
-            ("node".to_string(), "npm".to_string())
-        }
+    /// Times the client was restarted after crashing
+    pub fn restart_count(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
     }
 
-    /// Install Node.js on macOS using Homebrew
-    async fn install_nodejs_macos(&self) -> Result<()> {
-        // Check if Homebrew is installed
-        let brew_installed = Command::new("which")
-            .arg("brew")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false);
+    /// Crash and restart notifications from the supervisor
+    pub fn subscribe(&self) -> broadcast::Receiver<SupervisorEvent> {
+        self.events.subscribe()
+    }
 
-        if brew_installed {
-            info!("Installing Node.js via Homebrew...");
-            let status = Command::new("brew")
-                .args(["install", "node"])
-                .status();
+    fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
 
-            if status.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via Homebrew");
-                return Ok(());
-            }
-        }
+    /// Watch the client process started under `generation` and restart it
+    /// with exponential backoff whenever it exits on its own
+    async fn supervise(self, generation: u64) {
+        let mut backoff = Backoff::default();
+        let mut started_at = Instant::now();
 
-        // Try using the official installer via curl
-        info!("Installing Node.js via official script...");
-
-        // Check for nvm
-        let nvm_installed = Command::new("bash")
-            .args(["-c", "command -v nvm"])
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false);
-
-        if !nvm_installed {
-            // Install nvm first
-            info!("Installing nvm (Node Version Manager)...");
-            let nvm_install = Command::new("bash")
-                .args(["-c", "curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"])
-                .status();
-
-            if nvm_install.map(|s| s.success()).unwrap_or(false) {
-                info!("nvm installed. Installing Node.js...");
-
-                // Install latest LTS Node.js using nvm
-                let node_install = Command::new("bash")
-                    .args(["-c", "source ~/.nvm/nvm.sh && nvm install --lts && nvm use --lts"])
-                    .status();
-
-                if node_install.map(|s| s.success()).unwrap_or(false) {
-                    info!("✅ Node.js installed via nvm");
-                    return Ok(());
-                }
-            }
-        }
+        loop {
+            sleep(anyone_supervisor::POLL_INTERVAL).await;
 
-        // Fallback: download and run official pkg installer
-        warn!("Could not auto-install Node.js on macOS.");
-        Err(anyhow::anyhow!(
-            "Node.js auto-installation failed on macOS.\n\
-            Please install manually using one of these methods:\n\
-            1. Homebrew: brew install node\n\
-            2. Official installer: https://nodejs.org/\n\
-            3. nvm: curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"
-        ))
-    }
-
-    /// Install Node.js on Linux using package managers
-    async fn install_nodejs_linux(&self) -> Result<()> {
-        // Try NodeSource setup script (works on most distros)
-        info!("Installing Node.js via NodeSource...");
-
-        let nodesource_result = Command::new("bash")
-            .args(["-c", "curl -fsSL https://deb.nodesource.com/setup_lts.x | sudo -E bash -"])
-            .status();
-
-        if nodesource_result.map(|s| s.success()).unwrap_or(false) {
-            // Now install via apt
-            if Command::new("which").arg("apt-get").output().map(|o| o.status.success()).unwrap_or(false) {
-                let apt_result = Command::new("sudo")
-                    .args(["apt-get", "install", "-y", "nodejs"])
-                    .status();
-
-                if apt_result.map(|s| s.success()).unwrap_or(false) {
-                    info!("✅ Node.js installed via apt");
-                    return Ok(());
+            let exit = {
+                let mut process = self.process.lock().await;
+                if !self.is_current(generation) {
+                    return;
                 }
-            }
-        }
-
-        // Try apt directly (Ubuntu/Debian)
-        if Command::new("which").arg("apt-get").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via apt-get...");
-            let _ = Command::new("sudo").args(["apt-get", "update"]).status();
-            let apt_result = Command::new("sudo")
-                .args(["apt-get", "install", "-y", "nodejs", "npm"])
-                .status();
+                let Some((_, child)) = process.as_mut().filter(|(spawned, _)| *spawned == generation) else {
+                    return;
+                };
+                match child.try_wait() {
+                    Ok(Some(exit)) => {
+                        process.take();
+                        exit
+                    }
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("Failed to poll Anyone process: {}", e);
+                        continue;
+                    }
+                }
+            };
 
-            if apt_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via apt");
-                return Ok(());
+            let uptime = started_at.elapsed();
+            if uptime >= anyone_supervisor::STABLE_UPTIME {
+                backoff.reset();
             }
-        }
-
-        // Try dnf (Fedora/RHEL)
-        if Command::new("which").arg("dnf").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via dnf...");
-            let dnf_result = Command::new("sudo")
-                .args(["dnf", "install", "-y", "nodejs", "npm"])
-                .status();
+            let last_error = self.last_error.write().await.take();
+            error!(
+                "Anyone client exited unexpectedly ({}) after {}s{}",
+                exit,
+                uptime.as_secs(),
+                last_error.as_deref().map(|e| format!(": {}", e)).unwrap_or_default()
+            );
+
+            // Fail closed: privacy sessions lose egress until the client is back
+            *self.enabled.write().await = false;
+            *self.client.write().await = None;
+            *self.status.write().await = ServiceStatus::Error(format!("Anyone client exited ({})", exit));
+            let _ = self.events.send(SupervisorEvent::Exited {
+                code: exit.code(),
+                uptime_secs: uptime.as_secs(),
+                last_error,
+            });
+
+            let mut delay = backoff.next_delay();
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                info!("Restarting Anyone client in {}s (attempt {})", delay.as_secs(), attempt);
+                sleep(delay).await;
+                if !self.is_current(generation) {
+                    return;
+                }
 
-            if dnf_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via dnf");
-                return Ok(());
+                *self.status.write().await = ServiceStatus::Starting;
+                match self.relaunch(generation).await {
+                    Ok(()) if self.is_current(generation) => {
+                        self.restarts.fetch_add(1, Ordering::Relaxed);
+                        *self.enabled.write().await = true;
+                        *self.status.write().await = ServiceStatus::Running;
+                        info!("✅ Anyone client restarted (attempt {})", attempt);
+                        let _ = self.events.send(SupervisorEvent::Restarted { attempt });
+                        started_at = Instant::now();
+                        break;
+                    }
+                    // Stopped while we were relaunching; don't leave an orphan
+                    // but don't touch a client a newer start spawned either
+                    Ok(()) => {
+                        self.kill_generation(generation).await;
+                        return;
+                    }
+                    Err(e) => {
+                        self.kill_generation(generation).await;
+                        delay = backoff.next_delay();
+                        warn!("Anyone client restart attempt {} failed: {}", attempt, e);
+                        *self.status.write().await = ServiceStatus::Error(e.to_string());
+                        let _ = self.events.send(SupervisorEvent::RestartFailed {
+                            attempt,
+                            error: e.to_string(),
+                            retry_in: delay,
+                        });
+                    }
+                }
             }
         }
+    }
 
-        // Try yum (CentOS/older RHEL)
-        if Command::new("which").arg("yum").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via yum...");
-            let yum_result = Command::new("sudo")
-                .args(["yum", "install", "-y", "nodejs", "npm"])
-                .status();
-
-            if yum_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via yum");
-                return Ok(());
-            }
-        }
+    /// Check prerequisites, spawn the client and wait for its SOCKS port
+    async fn launch(&self, generation: u64) -> Result<()> {
+        // Install or check whatever the backend needs
+        self.check_prerequisites().await?;
 
-        // Try pacman (Arch)
-        if Command::new("which").arg("pacman").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via pacman...");
-            let pacman_result = Command::new("sudo")
-                .args(["pacman", "-S", "--noconfirm", "nodejs", "npm"])
-                .status();
+        // Start the backend's daemon, if it has one, replacing any left
+        // behind by a start that failed
+        self.kill_process().await;
+        let child = self.spawn_anyone_process().await?;
+        *self.process.lock().await = child.map(|child| (generation, child));
 
-            if pacman_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via pacman");
-                return Ok(());
-            }
-        }
+        // Wait for service to be ready with timeout
+        self.wait_for_ready().await?;
 
-        // Try zypper (openSUSE)
-        if Command::new("which").arg("zypper").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via zypper...");
-            let zypper_result = Command::new("sudo")
-                .args(["zypper", "install", "-y", "nodejs", "npm"])
-                .status();
+        // Initialize HTTP client with SOCKS proxy
+        self.initialize_proxy_client().await
+    }
 
-            if zypper_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via zypper");
-                return Ok(());
+    /// Spawn the client again for `generation` and wait for its SOCKS port.
+    /// The client is only stored while `generation` is current, so one
+    /// started or stopped meanwhile is never replaced.
+    async fn relaunch(&self, generation: u64) -> Result<()> {
+        let mut child = self.spawn_anyone_process().await?
+            .ok_or_else(|| anyhow::anyhow!("{} backend has no process to restart", self.backend.name()))?;
+        {
+            let mut process = self.process.lock().await;
+            if !self.is_current(generation) {
+                let _ = child.kill().await;
+                anyhow::bail!("{} backend was started or stopped during the restart", self.backend.name());
             }
+            *process = Some((generation, child));
         }
+        self.wait_for_ready().await?;
+        self.initialize_proxy_client().await
+    }
 
-        // Fallback: use nvm
-        info!("Trying nvm installation...");
-        let nvm_install = Command::new("bash")
-            .args(["-c", "curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash && source ~/.nvm/nvm.sh && nvm install --lts"])
-            .status();
-
-        if nvm_install.map(|s| s.success()).unwrap_or(false) {
-            info!("✅ Node.js installed via nvm");
-            return Ok(());
+    async fn kill_process(&self) {
+        if let Some((_, mut child)) = self.process.lock().await.take() {
+            let _ = child.kill().await;
         }
+    }
 
-        Err(anyhow::anyhow!(
-            "Node.js auto-installation failed on Linux.\n\
-            Please install manually using your package manager:\n\
-            - Debian/Ubuntu: sudo apt install nodejs npm\n\
-            - Fedora: sudo dnf install nodejs npm\n\
-            - Arch: sudo pacman -S nodejs npm\n\
-            - Or via nvm: curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"
-        ))
-    }
-
-    /// Install Node.js on Windows
-    async fn install_nodejs_windows(&self) -> Result<()> {
-        // Check common installation paths
-        let common_paths = [
-            r"C:\Program Files\nodejs\node.exe",
-            r"C:\Program Files (x86)\nodejs\node.exe",
-        ];
-
-        for path in &common_paths {
-            if Path::new(path).exists() {
-                info!("Found Node.js at: {}", path);
-                // It exists but might not be in PATH - try to add it
-                warn!("Node.js found but not in PATH. Please add to PATH or restart your terminal.");
-                return Ok(());
+    /// Kill the client only if `generation` spawned it
+    async fn kill_generation(&self, generation: u64) {
+        let mut process = self.process.lock().await;
+        if matches!(*process, Some((spawned, _)) if spawned == generation) {
+            if let Some((_, mut child)) = process.take() {
+                let _ = child.kill().await;
             }
         }
+    }
 
-        // Check if winget is available (Windows Package Manager)
-        if Command::new("winget").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via winget...");
-            let winget_result = Command::new("winget")
-                .args(["install", "--id", "OpenJS.NodeJS.LTS", "-e", "--silent"])
-                .status();
+    /// Check if the service is currently enabled and running
+    pub async fn is_enabled(&self) -> bool {
+        *self.enabled.read().await
+    }
 
-            if winget_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via winget");
-                info!("Please restart your terminal to use Node.js");
-                return Ok(());
-            }
-        }
+    /// Get the current service status
+    pub async fn get_status(&self) -> ServiceStatus {
+        self.status.read().await.clone()
+    }
 
-        // Check if Chocolatey is available
-        if Command::new("choco").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via Chocolatey...");
-            let choco_result = Command::new("choco")
-                .args(["install", "nodejs-lts", "-y"])
-                .status();
+    /// Name of the configured backend
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
 
-            if choco_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via Chocolatey");
-                info!("Please restart your terminal to use Node.js");
-                return Ok(());
-            }
-        }
+    /// Proxy that privacy traffic is forwarded to
+    pub fn proxy(&self) -> ProxyEndpoint {
+        self.backend.proxy()
+    }
 
-        // Check if Scoop is available
-        if Command::new("scoop").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
-            info!("Installing Node.js via Scoop...");
-            let scoop_result = Command::new("scoop")
-                .args(["install", "nodejs-lts"])
-                .status();
+    /// How privacy sessions' DNS queries are answered
+    pub fn dns(&self) -> Lookup {
+        self.backend.dns()
+    }
 
-            if scoop_result.map(|s| s.success()).unwrap_or(false) {
-                info!("✅ Node.js installed via Scoop");
-                return Ok(());
-            }
-        }
+    /// Control port, for backends that have one
+    pub fn control_addr(&self) -> Option<SocketAddr> {
+        self.backend.control_addr()
+    }
 
-        Err(anyhow::anyhow!(
-            "Node.js auto-installation failed on Windows.\n\
-            Please install manually using one of these methods:\n\
-            1. Official installer: https://nodejs.org/\n\
-            2. winget: winget install OpenJS.NodeJS.LTS\n\
-            3. Chocolatey: choco install nodejs-lts\n\
-            4. Scoop: scoop install nodejs-lts"
-        ))
+    /// Whether sessions get their own circuits through per-session SOCKS credentials
+    pub fn isolates_by_credentials(&self) -> bool {
+        self.backend.isolates_by_credentials()
     }
 
-    /// Verify Node.js installation after attempting to install
-    async fn verify_nodejs_installation(&self) -> Result<()> {
-        let (node_cmd, npm_cmd) = Self::get_node_commands();
+    /// Open an authenticated connection to the backend's control port
+    pub async fn control_client(&self, password: Option<&str>) -> Result<ControlClient> {
+        let addr = self.control_addr()
+            .ok_or_else(|| anyhow::anyhow!("The {} backend has no control port", self.backend.name()))?;
+        ControlClient::connect_authenticated(addr, password).await
+    }
 
-        // Give the system a moment to update PATH
-        sleep(Duration::from_secs(1)).await;
+    /// Get HTTP client configured for SOCKS proxy
+    pub async fn get_proxy_client(&self) -> Option<Client> {
+        self.client.read().await.clone()
+    }
 
-        let node_output = Command::new(&node_cmd)
-            .args(["--version"])
-            .output();
+    /// Check that the ports a local daemon would bind are free
+    pub async fn check_ports_available(&self) -> Result<()> {
+        use std::net::TcpListener;
 
-        match node_output {
-            Ok(output) if output.status.success() => {
-                let version = String::from_utf8_lossy(&output.stdout);
-                info!("✅ Node.js verified: {}", version.trim());
-            }
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Node.js installation could not be verified.\n\
-                    Please restart your terminal and try again, or install manually from https://nodejs.org/"
-                ));
-            }
+        if self.backend.command().is_none() {
+            return Ok(());
         }
 
-        let npm_output = Command::new(&npm_cmd)
-            .args(["--version"])
-            .output();
+        // Check SOCKS port
+        let socks_addr = self.backend.proxy().addr;
+        if TcpListener::bind(socks_addr).is_err() {
+            return Err(anyhow::anyhow!("SOCKS port {} is already in use", socks_addr.port()));
+        }
 
-        match npm_output {
-            Ok(output) if output.status.success() => {
-                let version = String::from_utf8_lossy(&output.stdout);
-                info!("✅ npm verified: {}", version.trim());
-            }
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "npm installation could not be verified.\n\
-                    Please restart your terminal and try again."
-                ));
+        // Check control port
+        if let Some(control_addr) = self.backend.control_addr() {
+            if TcpListener::bind(control_addr).is_err() {
+                return Err(anyhow::anyhow!("Control port {} is already in use", control_addr.port()));
             }
         }
 
         Ok(())
     }
 
-    // ========================================================================
-    // Anyone Protocol client installation
-    // ========================================================================
-
-    async fn ensure_anyone_client_installed(&self) -> Result<()> {
-        let (_, npm_cmd) = Self::get_node_commands();
-        let npx_cmd = if cfg!(target_os = "windows") { "npx.cmd" } else { "npx" };
-
-        // First try to check if it's globally installed
-        let check_output = Command::new(&npm_cmd)
-            .args(["list", "-g", "@anyone-protocol/anyone-client"])
-            .output();
-
-        if let Ok(output) = check_output {
-            if output.status.success() {
-                info!("Anyone Protocol client already installed globally");
-                return Ok(());
-            }
-        }
-
-        // Also check if npx can find it (might be cached or local)
-        let npx_check = Command::new(npx_cmd)
-            .args(["--yes", "@anyone-protocol/anyone-client", "--version"])
-            .output();
+    /// Public method to ensure all prerequisites are installed
+    /// Called at startup so the first privacy session doesn't wait on installs
+    pub async fn ensure_prerequisites(&self) -> Result<()> {
+        self.check_prerequisites().await
+    }
 
-        if let Ok(output) = npx_check {
-            if output.status.success() {
-                info!("Anyone Protocol client available via npx");
-                return Ok(());
+    /// Prepare the backend, leaving the remediation in the status on failure
+    async fn check_prerequisites(&self) -> Result<()> {
+        if let Err(e) = self.backend.prepare().await {
+            let mut status = self.status.write().await;
+            if *status != ServiceStatus::Running {
+                *status = ServiceStatus::Error(e.to_string());
             }
+            return Err(e);
         }
-
-        info!("Installing Anyone Protocol client globally...");
-        info!("Running: npm install -g @anyone-protocol/anyone-client");
-
-        let install_output = Command::new(&npm_cmd)
-            .args(["install", "-g", "@anyone-protocol/anyone-client"])
-            .output()
-            .context("Failed to execute npm install. Check npm permissions.")?;
-
-        if !install_output.status.success() {
-            let stderr = String::from_utf8_lossy(&install_output.stderr);
-            let stdout = String::from_utf8_lossy(&install_output.stdout);
-
-            // Provide helpful error message based on common issues
-            let help_msg = if stderr.contains("EACCES") || stderr.contains("permission denied") {
-                if cfg!(target_os = "windows") {
-                    "\n\nPermission denied. Try running as Administrator or use:\n  npm config set prefix %USERPROFILE%\\.npm-global"
-                } else {
-                    "\n\nPermission denied. Fix with one of these:\n  \
-                    1. Use nvm (recommended): https://github.com/nvm-sh/nvm\n  \
-                    2. Fix npm permissions: mkdir ~/.npm-global && npm config set prefix '~/.npm-global'\n  \
-                    3. Or run with sudo (not recommended): sudo npm install -g @anyone-protocol/anyone-client"
-                }
-            } else {
-                ""
-            };
-
-            return Err(anyhow::anyhow!(
-                "Failed to install Anyone Protocol client.\nstdout: {}\nstderr: {}{}",
-                stdout.trim(),
-                stderr.trim(),
-                help_msg
-            ));
-        }
-
-        info!("✅ Successfully installed Anyone Protocol client");
         Ok(())
     }
 
-    async fn create_terms_agreement(&self) -> Result<()> {
-        tokio::fs::write("terms-agreement", "agreed")
-            .await
-            .context("Failed to create terms-agreement file")?;
-        debug!("Created terms-agreement file");
-        Ok(())
-    }
-
-    async fn spawn_anyone_process(&self) -> Result<Child> {
-        let npx_cmd = if cfg!(target_os = "windows") { "npx.cmd" } else { "npx" };
+    async fn spawn_anyone_process(&self) -> Result<Option<Child>> {
+        let Some(mut command) = self.backend.command() else {
+            debug!("{} backend has no local process", self.backend.name());
+            return Ok(None);
+        };
 
-        info!("Starting Anyone Protocol client...");
-        info!("  SOCKS proxy: 127.0.0.1:{}", self.socks_port);
-        info!("  Control port: 127.0.0.1:{}", self.control_port);
+        info!("Starting {} client...", self.backend.name());
+        info!("  SOCKS proxy: {}", self.backend.proxy().addr);
+        if let Some(control_addr) = self.backend.control_addr() {
+            info!("  Control port: {}", control_addr);
+        }
 
-        let child = TokioCommand::new(npx_cmd)
-            .args([
-                "--yes",  // Auto-install if needed
-                "@anyone-protocol/anyone-client",
-                "-s", &self.socks_port.to_string(),
-                "-c", &self.control_port.to_string(),
-                "-v"
-            ])
+        let mut child = command
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .kill_on_drop(true)
             .spawn()
-            .context(format!(
-                "Failed to spawn Anyone client process.\n\
-                Make sure Node.js and npm are installed and in your PATH."
+            .with_context(|| format!(
+                "Failed to spawn {} client process. Make sure it is installed and in your PATH.",
+                self.backend.name()
             ))?;
 
-        debug!("Spawned Anyone client process with PID: {:?}", child.id());
-        Ok(child)
+        debug!("Spawned {} client process with PID: {:?}", self.backend.name(), child.id());
+
+        // Unread pipes fill up and stall the client, so always drain them
+        *self.last_error.write().await = None;
+        if let Some(stdout) = child.stdout.take() {
+            anyone_supervisor::drain(stdout, "stdout", self.last_error.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            anyone_supervisor::drain(stderr, "stderr", self.last_error.clone());
+        }
+        Ok(Some(child))
     }
 
     async fn wait_for_ready(&self) -> Result<()> {
@@ -662,9 +500,7 @@ impl AnyoneService {
         use std::net::TcpStream;
         use std::time::Duration as StdDuration;
 
-        let addr = format!("127.0.0.1:{}", self.socks_port)
-            .parse()
-            .context("Invalid SOCKS address")?;
+        let addr = self.backend.proxy().addr;
 
         TcpStream::connect_timeout(&addr, StdDuration::from_secs(1))
             .context("Failed to connect to SOCKS port")?;
@@ -673,7 +509,8 @@ impl AnyoneService {
     }
 
     async fn initialize_proxy_client(&self) -> Result<()> {
-        let proxy_url = format!("socks5://127.0.0.1:{}", self.socks_port);
+        let proxy = self.backend.proxy();
+        let proxy_url = proxy.client_url();
 
         let client = Client::builder()
             .proxy(reqwest::Proxy::all(&proxy_url)
@@ -683,16 +520,21 @@ impl AnyoneService {
             .context("Failed to build HTTP client")?;
 
         *self.client.write().await = Some(client);
-        debug!("Initialized HTTP client with SOCKS proxy: {}", proxy_url);
+        debug!("Initialized HTTP client with proxy: {}", proxy.addr);
         Ok(())
     }
 }
 
 impl Drop for AnyoneService {
     fn drop(&mut self) {
+        // Clones (such as the supervisor's) share the process; only the last one cleans up
+        if Arc::strong_count(&self.process) > 1 {
+            return;
+        }
+
         // Attempt cleanup but don't block
         if let Ok(mut process) = self.process.try_lock() {
-            if let Some(mut child) = process.take() {
+            if let Some((_, mut child)) = process.take() {
                 let _ = child.start_kill();
             }
         }
@@ -702,40 +544,132 @@ impl Drop for AnyoneService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::privacy::{AnyoneClient, UpstreamProxy};
+
+    /// Forwards to an upstream proxy but runs a stand-in daemon
+    struct Sleeper(UpstreamProxy);
+
+    #[async_trait::async_trait]
+    impl PrivacyBackend for Sleeper {
+        fn name(&self) -> &'static str {
+            "sleeper"
+        }
+
+        fn proxy(&self) -> ProxyEndpoint {
+            self.0.proxy()
+        }
+
+        fn dns(&self) -> Lookup {
+            self.0.dns()
+        }
+
+        fn command(&self) -> Option<tokio::process::Command> {
+            let mut command = tokio::process::Command::new("sleep");
+            command.arg("60");
+            Some(command)
+        }
+    }
+
+    fn anyone(socks_port: u16, control_port: u16) -> AnyoneService {
+        AnyoneService::new(Arc::new(AnyoneClient::new(socks_port, control_port, false)))
+    }
 
     #[tokio::test]
     async fn test_service_creation() {
-        let service = AnyoneService::new(9050, 9051);
-        assert_eq!(service.get_socks_port(), 9050);
-        assert_eq!(service.get_control_port(), 9051);
+        let service = anyone(9050, 9051);
+        assert_eq!(service.proxy().addr.port(), 9050);
+        assert_eq!(service.control_addr().map(|a| a.port()), Some(9051));
+        assert!(service.isolates_by_credentials());
         assert!(!service.is_enabled().await);
     }
 
     #[tokio::test]
-    async fn test_status_transitions() {
-        let service = AnyoneService::new(9052, 9053);
+    async fn test_upstream_backend_starts_against_local_stand_in() {
+        // Any listening socket will do: the upstream backend runs nothing itself
+        let stand_in = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = ProxyEndpoint::socks(stand_in.local_addr().unwrap());
+        let service = AnyoneService::new(Arc::new(UpstreamProxy::new(endpoint, "127.0.0.1:53".parse().unwrap())));
+
+        service.start().await.unwrap();
+        assert!(service.is_enabled().await);
+        assert_eq!(service.get_status().await, ServiceStatus::Running);
+        assert!(service.get_proxy_client().await.is_some());
+        assert!(service.control_client(None).await.is_err());
+
+        service.stop().await.unwrap();
+        assert!(!service.is_enabled().await);
+    }
 
-        assert_eq!(service.get_status().await, ServiceStatus::Stopped);
+    #[tokio::test]
+    async fn test_last_release_stops_the_backend() {
+        let stand_in = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = ProxyEndpoint::socks(stand_in.local_addr().unwrap());
+        let service = AnyoneService::new(Arc::new(UpstreamProxy::new(endpoint, "127.0.0.1:53".parse().unwrap())));
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        service.acquire(first).await.unwrap();
+        service.acquire(second).await.unwrap();
+        assert_eq!(service.holder_count().await, 2);
+
+        service.release(first).await.unwrap();
+        assert!(service.is_enabled().await);
+        service.release(second).await.unwrap();
+        assert_eq!(service.holder_count().await, 0);
+        assert!(!service.is_enabled().await);
+    }
 
-        // Test status updates (without actually starting)
-        *service.status.write().await = ServiceStatus::Starting;
-        assert_eq!(service.get_status().await, ServiceStatus::Starting);
+    #[tokio::test]
+    async fn test_start_replaces_client_left_by_failed_start() {
+        let stand_in = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = ProxyEndpoint::socks(stand_in.local_addr().unwrap());
+        let service = AnyoneService::new(Arc::new(UpstreamProxy::new(endpoint, "127.0.0.1:53".parse().unwrap())));
+        let leftover = tokio::process::Command::new("sleep").arg("60").kill_on_drop(true).spawn().unwrap();
+        *service.process.lock().await = Some((0, leftover));
+        *service.status.write().await = ServiceStatus::Error("timed out".to_string());
+
+        service.start().await.unwrap();
+        assert!(service.is_enabled().await);
+        assert_eq!(service.get_status().await, ServiceStatus::Running);
+        assert!(service.process.lock().await.is_none());
+        service.stop().await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_get_node_commands() {
-        let (node_cmd, npm_cmd) = AnyoneService::get_node_commands();
+    async fn test_stale_supervisor_leaves_newer_client_alone() {
+        let stand_in = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = ProxyEndpoint::socks(stand_in.local_addr().unwrap());
+        let service = AnyoneService::new(Arc::new(Sleeper(UpstreamProxy::new(endpoint, "127.0.0.1:53".parse().unwrap()))));
+        let newer = tokio::process::Command::new("sleep").arg("60").kill_on_drop(true).spawn().unwrap();
+        service.generation.store(2, Ordering::SeqCst);
+        *service.process.lock().await = Some((2, newer));
+
+        // A supervisor from generation 1 giving up must not kill it, and
+        // one still restarting must not replace it
+        service.kill_generation(1).await;
+        assert!(service.process.lock().await.is_some());
+        assert!(service.relaunch(1).await.is_err());
+        assert!(matches!(*service.process.lock().await, Some((2, _))));
+
+        service.kill_generation(2).await;
+        assert!(service.process.lock().await.is_none());
+    }
 
-        #[cfg(target_os = "windows")]
-        {
-            assert_eq!(node_cmd, "node.exe");
-            assert_eq!(npm_cmd, "npm.cmd");
-        }
+    #[tokio::test]
+    async fn test_release_of_unknown_session_is_noop() {
+        let service = anyone(9054, 9055);
+        service.release(Uuid::new_v4()).await.unwrap();
+        assert_eq!(service.holder_count().await, 0);
+        assert_eq!(service.get_status().await, ServiceStatus::Stopped);
+    }
 
-        #[cfg(not(target_os = "windows"))]
-        {
-            assert_eq!(node_cmd, "node");
-            assert_eq!(npm_cmd, "npm");
-        }
+    #[tokio::test]
+    async fn test_status_transitions() {
+        let service = anyone(9052, 9053);
+
+        assert_eq!(service.get_status().await, ServiceStatus::Stopped);
+
+        // Test status updates (without actually starting)
+        *service.status.write().await = ServiceStatus::Starting;
+        assert_eq!(service.get_status().await, ServiceStatus::Starting);
     }
 }
@@ -0,0 +1,185 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Supervision helpers for the Anyone client process: log draining, restart
+//! backoff and the events reported when the process dies or comes back.
+
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::RwLock;
+use tokio::time::Duration;
+use tracing::{debug, error, info, trace, warn};
+
+/// First restart delay after a crash
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between restart attempts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A process that stayed up this long crashed fresh, not in a loop
+pub const STABLE_UPTIME: Duration = Duration::from_secs(120);
+/// How often the supervisor checks whether the process is still alive
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Something the supervisor observed, for audit and security logging
+#[derive(Clone, Debug, PartialEq)]
+pub enum SupervisorEvent {
+    /// The process exited without being asked to
+    Exited {
+        code: Option<i32>,
+        uptime_secs: u64,
+        last_error: Option<String>,
+    },
+    /// A restart brought the service back up
+    Restarted { attempt: u32 },
+    /// A restart attempt failed; another follows after `retry_in`
+    RestartFailed {
+        attempt: u32,
+        error: String,
+        retry_in: Duration,
+    },
+}
+
+/// Severity of a client log line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Err,
+}
+
+/// Split a client log line (`Oct 18 10:00:00.000 [notice] Bootstrapped 5%`)
+/// into its level and message; lines without a level tag return `None`
+pub fn parse_log_line(line: &str) -> Option<(LogLevel, &str)> {
+    let start = line.find('[')?;
+    let end = start + line[start..].find(']')?;
+    let level = match &line[start + 1..end] {
+        "debug" => LogLevel::Debug,
+        "info" => LogLevel::Info,
+        "notice" => LogLevel::Notice,
+        "warn" => LogLevel::Warn,
+        "err" => LogLevel::Err,
+        _ => return None,
+    };
+    Some((level, line[end + 1..].trim()))
+}
+
+/// Forward a client output stream into `tracing`, remembering the last
+/// warning or error so a crash report can say why the process died
+pub fn drain<R>(reader: R, stream: &'static str, last_error: Arc<RwLock<Option<String>>>)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match parse_log_line(&line) {
+                        Some((LogLevel::Err, message)) => {
+                            error!(target: "anyone", "{}", message);
+                            *last_error.write().await = Some(message.to_string());
+                        }
+                        Some((LogLevel::Warn, message)) => {
+                            warn!(target: "anyone", "{}", message);
+                            *last_error.write().await = Some(message.to_string());
+                        }
+                        Some((LogLevel::Notice, message)) => info!(target: "anyone", "{}", message),
+                        Some((LogLevel::Info, message)) => debug!(target: "anyone", "{}", message),
+                        Some((LogLevel::Debug, message)) => trace!(target: "anyone", "{}", message),
+                        // npm and node chatter, or a stack trace on stderr
+                        None if stream == "stderr" => {
+                            warn!(target: "anyone", "{}", line);
+                            *last_error.write().await = Some(line);
+                        }
+                        None => debug!(target: "anyone", "{}", line),
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    debug!("Stopped reading Anyone {}: {}", stream, e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Exponential restart delay, doubling per failure up to `MAX_BACKOFF`
+#[derive(Debug)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { next: INITIAL_BACKOFF }
+    }
+}
+
+impl Backoff {
+    /// Delay before the next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = INITIAL_BACKOFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_line() {
+        assert_eq!(
+            parse_log_line("Oct 18 10:00:00.000 [notice] Bootstrapped 100% (done): Done"),
+            Some((LogLevel::Notice, "Bootstrapped 100% (done): Done"))
+        );
+        assert_eq!(
+            parse_log_line("Oct 18 10:00:00.000 [err] Could not bind to 127.0.0.1:9050"),
+            Some((LogLevel::Err, "Could not bind to 127.0.0.1:9050"))
+        );
+        assert_eq!(parse_log_line("npm WARN exec The following package was not found"), None);
+        assert_eq!(parse_log_line("[unknown] something"), None);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+        for _ in 0..10 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), MAX_BACKOFF);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_drain_records_last_error() {
+        let last_error = Arc::new(RwLock::new(None));
+        let output: &[u8] = b"Oct 18 10:00:00.000 [notice] Opening Socks listener\n\
+                               Oct 18 10:00:01.000 [warn] Clock skew detected\n\
+                               Oct 18 10:00:02.000 [notice] Bootstrapped 10%\n";
+        drain(output, "stdout", last_error.clone());
+
+        for _ in 0..50 {
+            if last_error.read().await.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(last_error.read().await.as_deref(), Some("Clock skew detected"));
+    }
+}
@@ -2,6 +2,7 @@
 // Copyright (c) 2025, NØNOS - NOXTERM 
 //! Configuration loading from environment variables
 
+use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
 use std::time::Duration;
@@ -31,6 +32,9 @@ impl Config {
                 })?;
 
         let environment = env_parse("NOXTERM_ENVIRONMENT", Environment::Development)?;
+        // Sessions have always been bridged; turning it off leaves them offline
+        let allow_networking = env_parse("NOXTERM_DOCKER_ALLOW_NETWORKING", true)?;
+        let default_network = if allow_networking { NetworkMode::Open } else { NetworkMode::None };
 
         Ok(Config {
             server: ServerConfig {
@@ -39,6 +43,8 @@ impl Config {
                 listen_addr,
                 environment,
                 graceful_shutdown_timeout_secs: env_parse("NOXTERM_SHUTDOWN_TIMEOUT", 30u64)?,
+                // Never on shared servers unless asked for
+                auto_install: env_parse("NOXTERM_AUTO_INSTALL", environment == Environment::Development)?,
             },
             docker: DockerConfig {
                 cpu_shares: env_parse("NOXTERM_DOCKER_CPU_SHARES", 512u64)?,
@@ -50,7 +56,13 @@ impl Config {
                 )?,
                 memory_swap_bytes: env_parse("NOXTERM_DOCKER_MEMORY_SWAP", -1i64)?,
                 pids_limit: env_parse("NOXTERM_DOCKER_PIDS_LIMIT", 100i64)?,
-                allow_networking: env_parse("NOXTERM_DOCKER_ALLOW_NETWORKING", false)?,
+                disk_limit_mb: env_parse("NOXTERM_DOCKER_DISK_LIMIT_MB", 2048i64)?,
+                disk_warn_percent: env_parse("NOXTERM_DOCKER_DISK_WARN_PERCENT", 90u8)?,
+                disk_check_interval_secs: env_parse("NOXTERM_DOCKER_DISK_CHECK_INTERVAL", 60u64)?,
+                allow_networking,
+                network_mode: env_parse("NOXTERM_DOCKER_NETWORK_MODE", default_network)?,
+                egress_network: env_or("NOXTERM_EGRESS_NETWORK", "noxterm-egress"),
+                egress_proxy_port: env_parse("NOXTERM_EGRESS_PROXY_PORT", 1080u16)?,
                 read_only_rootfs: env_parse("NOXTERM_DOCKER_READ_ONLY_ROOTFS", false)?,
                 container_user: env::var("NOXTERM_DOCKER_USER").ok(),
                 default_image: env_or("NOXTERM_DOCKER_DEFAULT_IMAGE", "ubuntu:22.04"),
@@ -69,6 +81,14 @@ impl Config {
                 socket_path: env::var("DOCKER_HOST")
                     .ok()
                     .or_else(|| env::var("NOXTERM_DOCKER_SOCKET").ok()),
+                runtime: env_parse("NOXTERM_DOCKER_RUNTIME", RuntimeClass::Runc)?,
+                runtime_policy: env_parse("NOXTERM_DOCKER_RUNTIME_POLICY", RuntimePolicy::Refuse)?,
+                image_runtimes: env_map("NOXTERM_DOCKER_IMAGE_RUNTIMES")?,
+                instance_id: env_or("NOXTERM_INSTANCE_ID", "default"),
+                hosts: env_map("NOXTERM_DOCKER_HOSTS")?,
+                tls_dir: env::var("NOXTERM_DOCKER_TLS_DIR").ok(),
+                host_memory_percent: env_parse("NOXTERM_DOCKER_HOST_MEMORY_PERCENT", 90u8)?,
+                allow_unlimited: env_parse("NOXTERM_DOCKER_ALLOW_UNLIMITED", false)?,
             },
             session: SessionConfig {
                 max_concurrent_sessions: env_parse("NOXTERM_MAX_SESSIONS", 100u32)?,
@@ -79,6 +99,8 @@ impl Config {
                 grace_period_secs: env_parse("NOXTERM_SESSION_GRACE_PERIOD", 300u64)?,
                 cleanup_interval_secs: env_parse("NOXTERM_CLEANUP_INTERVAL", 30u64)?,
                 health_check_interval_secs: env_parse("NOXTERM_HEALTH_CHECK_INTERVAL", 30u64)?,
+                exec_timeout_secs: env_parse("NOXTERM_EXEC_TIMEOUT", 60u64)?,
+                exec_max_timeout_secs: env_parse("NOXTERM_EXEC_MAX_TIMEOUT", 3600u64)?,
             },
             rate_limit: RateLimitConfig {
                 enabled: env_parse("NOXTERM_RATE_LIMIT_ENABLED", true)?,
@@ -109,6 +131,18 @@ impl Config {
                     vec!["127.0.0.1".to_string(), "::1".to_string()],
                 ),
                 audit_logging: env_parse("NOXTERM_AUDIT_LOGGING", true)?,
+                default_role: env_or("NOXTERM_DEFAULT_ROLE", "user"),
+                user_roles: env_map("NOXTERM_USER_ROLES")?,
+                user_keys: env_map("NOXTERM_USER_KEYS")?,
+                policy_path: env::var("NOXTERM_SECURITY_POLICY").ok().filter(|v| !v.is_empty()),
+                pty_policy: env_parse("NOXTERM_PTY_POLICY", PtyPolicyMode::Off)?,
+                command_logging: env_parse(
+                    "NOXTERM_COMMAND_LOGGING",
+                    env_parse("ENABLE_COMMAND_LOGGING", false)?,
+                )?,
+                pty_history: env_parse("NOXTERM_PTY_HISTORY", false)?,
+                history_retention_days: env_parse("NOXTERM_HISTORY_RETENTION_DAYS", 30u32)?,
+                history_inject_lines: env_parse("NOXTERM_HISTORY_INJECT_LINES", 1000u32)?,
             },
             observability: ObservabilityConfig {
                 log_level: env_or("NOXTERM_LOG_LEVEL", "info"),
@@ -119,9 +153,29 @@ impl Config {
             },
             anyone: AnyoneConfig {
                 enabled: env_parse("NOXTERM_ANYONE_ENABLED", true)?,
+                backend: env_parse("NOXTERM_PRIVACY_BACKEND", PrivacyBackendKind::Anyone)?,
                 socks_port: env_parse("NOXTERM_ANYONE_SOCKS_PORT", 9050u16)?,
                 control_port: env_parse("NOXTERM_ANYONE_CONTROL_PORT", 9051u16)?,
                 auto_start: env_parse("NOXTERM_ANYONE_AUTO_START", false)?,
+                control_password: env::var("NOXTERM_ANYONE_CONTROL_PASSWORD").ok(),
+                transparent: env_parse("NOXTERM_ANYONE_TRANSPARENT", true)?,
+                sidecar_image: env_or("NOXTERM_ANYONE_SIDECAR_IMAGE", "noxterm/netguard:latest"),
+                provisioning: env_parse("NOXTERM_ANYONE_PROVISIONING", AnyoneProvisioning::Auto)?,
+                client_path: env::var("NOXTERM_ANYONE_CLIENT_PATH").ok().filter(|v| !v.is_empty()),
+                client_sha256: env::var("NOXTERM_ANYONE_CLIENT_SHA256").ok().filter(|v| !v.is_empty()),
+                tor_binary: env_or("NOXTERM_TOR_BINARY", "tor"),
+                tor_data_dir: env_or("NOXTERM_TOR_DATA_DIR", "./tor-data"),
+                upstream_proxy: env::var("NOXTERM_PRIVACY_UPSTREAM").ok().filter(|v| !v.is_empty()),
+                upstream_dns: env::var("NOXTERM_PRIVACY_UPSTREAM_DNS").ok().filter(|v| !v.is_empty()),
+                echo_url: env_or("NOXTERM_PRIVACY_ECHO_URL", "https://check.en.anyone.tech/api/ip"),
+            },
+            tiers: load_tiers()?,
+            jobs: JobConfig {
+                artifact_dir: env_or("NOXTERM_JOB_ARTIFACT_DIR", "./job-artifacts"),
+                timeout_secs: env_parse("NOXTERM_JOB_TIMEOUT", 1800u64)?,
+                max_timeout_secs: env_parse("NOXTERM_JOB_MAX_TIMEOUT", 21600u64)?,
+                max_artifact_mb: env_parse("NOXTERM_JOB_MAX_ARTIFACT_MB", 512u64)?,
+                retention_hours: env_parse("NOXTERM_JOB_RETENTION_HOURS", 72u64)?,
             },
         })
     }
@@ -143,6 +197,33 @@ impl Config {
     }
 }
 
+/// Tiers named in `NOXTERM_TIERS`, each overridable via `NOXTERM_TIER_<NAME>`
+fn load_tiers() -> Result<TierConfig, ConfigError> {
+    let names = env_list(
+        "NOXTERM_TIERS",
+        vec!["small".to_string(), "medium".to_string(), "large".to_string()],
+    );
+
+    let mut tiers = Vec::with_capacity(names.len());
+    for name in names {
+        let key = format!("NOXTERM_TIER_{}", name.to_uppercase());
+        let spec = env::var(&key).unwrap_or_default();
+        let tier = ResourceTier::parse(&name.to_lowercase(), &spec).map_err(|reason| {
+            ConfigError::InvalidValue {
+                key: key.clone(),
+                value: spec.clone(),
+                reason,
+            }
+        })?;
+        tiers.push(tier);
+    }
+
+    Ok(TierConfig {
+        default_tier: env_or("NOXTERM_DEFAULT_TIER", "medium"),
+        tiers,
+    })
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config::from_env().unwrap_or_else(|_| panic!("Failed to load default configuration"))
@@ -167,6 +248,28 @@ where
     }
 }
 
+/// Parse a `key=value,key=value` list, e.g. `ubuntu:22.04=runsc,debian:12=kata`
+pub fn env_map<T>(key: &str) -> Result<HashMap<String, T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let mut map = HashMap::new();
+    for entry in env_list(key, Vec::new()) {
+        let (name, value) = entry.rsplit_once('=').ok_or_else(|| ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: entry.clone(),
+            reason: "Expected name=value".to_string(),
+        })?;
+        let parsed = value.trim().parse().map_err(|e| ConfigError::ParseError {
+            key: key.to_string(),
+            message: format!("{}: {}", name, e),
+        })?;
+        map.insert(name.trim().to_string(), parsed);
+    }
+    Ok(map)
+}
+
 pub fn env_list(key: &str, default: Vec<String>) -> Vec<String> {
     match env::var(key) {
         Ok(value) => value
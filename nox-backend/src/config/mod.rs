@@ -11,10 +11,11 @@ mod types;
 mod validation;
 
 pub use error::ConfigError;
-pub use loader::{env_list, env_or, env_parse};
+pub use loader::{env_list, env_map, env_or, env_parse};
 pub use types::{
-    AnyoneConfig, Config, DatabaseConfig, DockerConfig, Environment, ObservabilityConfig,
-    RateLimitConfig, SecurityConfig, ServerConfig, SessionConfig,
+    AnyoneConfig, AnyoneProvisioning, Config, DatabaseConfig, DockerConfig, DockerEndpoint, Environment, JobConfig, NetworkMode,
+    ObservabilityConfig, PrivacyBackendKind, PtyPolicyMode, RateLimitConfig, ResourceTier, RuntimeClass, RuntimePolicy, SecurityConfig, ServerConfig,
+    SessionConfig, TierConfig, MAX_HISTORY_RETENTION_DAYS,
 };
 
 #[cfg(test)]
@@ -46,4 +47,91 @@ mod tests {
         assert_eq!(Environment::Production.to_string(), "production");
         assert_eq!(Environment::Development.to_string(), "development");
     }
+
+    #[test]
+    fn test_runtime_class_parsing() {
+        assert_eq!("runc".parse::<RuntimeClass>().unwrap(), RuntimeClass::Runc);
+        assert_eq!("gvisor".parse::<RuntimeClass>().unwrap(), RuntimeClass::Runsc);
+        assert_eq!("kata".parse::<RuntimeClass>().unwrap(), RuntimeClass::Kata);
+        assert!("lxc".parse::<RuntimeClass>().is_err());
+        assert_eq!(RuntimeClass::Runsc.to_string(), "runsc");
+    }
+
+    #[test]
+    fn test_docker_endpoint_parsing() {
+        assert_eq!(
+            "unix:///var/run/docker.sock".parse::<DockerEndpoint>().unwrap(),
+            DockerEndpoint::Unix("/var/run/docker.sock".to_string())
+        );
+        assert_eq!(
+            "tcp://10.0.0.5:2376".parse::<DockerEndpoint>().unwrap(),
+            DockerEndpoint::Tcp("10.0.0.5:2376".to_string())
+        );
+        assert_eq!(
+            "ssh://deploy@edge-1:2222".parse::<DockerEndpoint>().unwrap(),
+            DockerEndpoint::Ssh {
+                destination: "ssh://deploy@edge-1:2222".to_string(),
+                socket: "/var/run/docker.sock".to_string(),
+            }
+        );
+        let rootless: DockerEndpoint = "ssh://edge-2/run/user/1000/docker.sock".parse().unwrap();
+        assert_eq!(rootless.to_string(), "ssh://edge-2/run/user/1000/docker.sock");
+        assert!(!rootless.is_local());
+        assert!("tcp://10.0.0.5".parse::<DockerEndpoint>().is_err());
+        assert!("npipe:////./pipe/docker_engine".parse::<DockerEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_runtime_policy_parsing() {
+        assert_eq!("fallback".parse::<RuntimePolicy>().unwrap(), RuntimePolicy::Fallback);
+        assert_eq!("refuse".parse::<RuntimePolicy>().unwrap(), RuntimePolicy::Refuse);
+        assert!("maybe".parse::<RuntimePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_network_mode_parsing() {
+        assert_eq!("none".parse::<NetworkMode>().unwrap(), NetworkMode::None);
+        assert_eq!("anyone-only".parse::<NetworkMode>().unwrap(), NetworkMode::AnyoneOnly);
+        assert_eq!("open".parse::<NetworkMode>().unwrap(), NetworkMode::Open);
+        assert!("vpn".parse::<NetworkMode>().is_err());
+    }
+
+    #[test]
+    fn test_privacy_backend_parsing() {
+        assert_eq!("anyone".parse::<PrivacyBackendKind>().unwrap(), PrivacyBackendKind::Anyone);
+        assert_eq!("Tor".parse::<PrivacyBackendKind>().unwrap(), PrivacyBackendKind::Tor);
+        assert_eq!("socks".parse::<PrivacyBackendKind>().unwrap(), PrivacyBackendKind::Upstream);
+        assert!("i2p".parse::<PrivacyBackendKind>().is_err());
+    }
+
+    #[test]
+    fn test_provisioning_parsing() {
+        assert_eq!("auto".parse::<AnyoneProvisioning>().unwrap(), AnyoneProvisioning::Auto);
+        assert_eq!("preinstalled".parse::<AnyoneProvisioning>().unwrap(), AnyoneProvisioning::Offline);
+        assert!("download".parse::<AnyoneProvisioning>().is_err());
+    }
+
+    #[test]
+    fn test_pty_policy_mode_parsing() {
+        assert_eq!("audit-only".parse::<PtyPolicyMode>().unwrap(), PtyPolicyMode::Audit);
+        assert_eq!("ENFORCE".parse::<PtyPolicyMode>().unwrap(), PtyPolicyMode::Enforce);
+        assert_eq!(PtyPolicyMode::Off.to_string(), "off");
+        assert!("block".parse::<PtyPolicyMode>().is_err());
+    }
+
+    #[test]
+    fn test_resource_tier_parsing() {
+        let tier = ResourceTier::parse("small", "memory_mb=256,roles=admin|trusted").unwrap();
+        assert_eq!(tier.memory_mb, 256);
+        assert_eq!(tier.pids_limit, 100);
+        assert!(tier.allows_role("admin"));
+        assert!(!tier.allows_role("user"));
+
+        let custom = ResourceTier::parse("gpu", "max_per_user=1").unwrap();
+        assert_eq!(custom.memory_mb, 1024);
+        assert_eq!(custom.max_per_user, Some(1));
+
+        assert!(ResourceTier::parse("small", "memory=1").is_err());
+        assert!(ResourceTier::parse("small", "memory_mb=lots").is_err());
+    }
 }
@@ -4,7 +4,8 @@
 //! Configuration type definitions
 //! All configuration structs and enums used throughout the application.
 
-use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
 /// Main application configuration
@@ -18,6 +19,8 @@ pub struct Config {
     pub security: SecurityConfig,
     pub observability: ObservabilityConfig,
     pub anyone: AnyoneConfig,
+    pub tiers: TierConfig,
+    pub jobs: JobConfig,
 }
 
 /// Server binding configuration
@@ -28,6 +31,9 @@ pub struct ServerConfig {
     pub listen_addr: SocketAddr,
     pub environment: Environment,
     pub graceful_shutdown_timeout_secs: u64,
+    /// Whether a missing Docker or Node.js may be installed, and a stopped
+    /// Docker started, with the system package manager and sudo
+    pub auto_install: bool,
 }
 
 /// Environment type
@@ -70,13 +76,311 @@ pub struct DockerConfig {
     pub memory_limit_bytes: u64,
     pub memory_swap_bytes: i64,
     pub pids_limit: i64,
+    pub disk_limit_mb: i64,
+    pub disk_warn_percent: u8,
+    pub disk_check_interval_secs: u64,
     pub allow_networking: bool,
+    pub network_mode: NetworkMode,
+    pub egress_network: String,
+    pub egress_proxy_port: u16,
     pub read_only_rootfs: bool,
     pub container_user: Option<String>,
     pub default_image: String,
     pub allowed_images: Vec<String>,
     pub stop_timeout_secs: u64,
     pub socket_path: Option<String>,
+    pub runtime: RuntimeClass,
+    pub runtime_policy: RuntimePolicy,
+    pub image_runtimes: HashMap<String, RuntimeClass>,
+    /// Labelled on every container this backend creates; instances sharing a
+    /// Docker host need distinct IDs
+    pub instance_id: String,
+    /// Docker hosts sessions are placed on, by name; empty means the local
+    /// daemon only
+    pub hosts: HashMap<String, DockerEndpoint>,
+    /// Holds `<host>/{ca,cert,key}.pem` for hosts reached over TCP; without
+    /// it TCP connections are unencrypted
+    pub tls_dir: Option<String>,
+    /// Share of a host's memory that sessions' limits may add up to
+    pub host_memory_percent: u8,
+    /// Start sessions without memory, CPU and pids limits on engines that
+    /// cannot enforce them (rootless on cgroup v1) instead of refusing them
+    pub allow_unlimited: bool,
+}
+
+impl DockerConfig {
+    /// Runtime class for an image, honouring per-template overrides
+    pub fn runtime_for_image(&self, image: &str) -> RuntimeClass {
+        self.image_runtimes
+            .get(image)
+            .copied()
+            .unwrap_or(self.runtime)
+    }
+}
+
+/// How a Docker daemon is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DockerEndpoint {
+    /// Socket on this machine
+    Unix(String),
+    /// `host:port` of a daemon listening on TCP
+    Tcp(String),
+    /// Daemon socket forwarded over SSH; `destination` is what `ssh` connects to
+    Ssh { destination: String, socket: String },
+}
+
+impl DockerEndpoint {
+    /// Daemon socket on remote hosts unless the URL names another
+    pub const DEFAULT_SOCKET: &'static str = "/var/run/docker.sock";
+
+    /// Whether containers run on this machine, next to the egress proxy
+    pub fn is_local(&self) -> bool {
+        matches!(self, DockerEndpoint::Unix(_))
+    }
+}
+
+impl FromStr for DockerEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix://") {
+            return Ok(DockerEndpoint::Unix(path.to_string()));
+        }
+        if let Some(addr) = s.strip_prefix("tcp://") {
+            if !addr.contains(':') {
+                return Err(format!("Expected tcp://host:port, got {}", s));
+            }
+            return Ok(DockerEndpoint::Tcp(addr.trim_end_matches('/').to_string()));
+        }
+        if let Some(rest) = s.strip_prefix("ssh://") {
+            let (authority, socket) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => (rest, Self::DEFAULT_SOCKET),
+            };
+            if authority.is_empty() {
+                return Err(format!("Expected ssh://[user@]host[:port][/socket], got {}", s));
+            }
+            return Ok(DockerEndpoint::Ssh {
+                destination: format!("ssh://{}", authority),
+                socket: socket.to_string(),
+            });
+        }
+        Err(format!("Unknown Docker endpoint {} (expected unix://, tcp:// or ssh://)", s))
+    }
+}
+
+impl std::fmt::Display for DockerEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DockerEndpoint::Unix(path) => write!(f, "unix://{}", path),
+            DockerEndpoint::Tcp(addr) => write!(f, "tcp://{}", addr),
+            DockerEndpoint::Ssh { destination, socket } => write!(f, "{}{}", destination, socket),
+        }
+    }
+}
+
+/// Network egress mode for a session container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkMode {
+    /// No network interface besides loopback
+    None,
+    /// Internal network whose only way out is the Anyone SOCKS proxy
+    AnyoneOnly,
+    /// Regular bridge networking
+    Open,
+}
+
+impl FromStr for NetworkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "offline" => Ok(NetworkMode::None),
+            "anyone-only" | "anyone" | "proxied" => Ok(NetworkMode::AnyoneOnly),
+            "open" | "bridge" => Ok(NetworkMode::Open),
+            _ => Err(format!("Unknown network mode: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkMode::None => write!(f, "none"),
+            NetworkMode::AnyoneOnly => write!(f, "anyone-only"),
+            NetworkMode::Open => write!(f, "open"),
+        }
+    }
+}
+
+/// Network that privacy sessions are routed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyBackendKind {
+    /// The Anyone Protocol npm client
+    Anyone,
+    /// A system `tor` binary
+    Tor,
+    /// An existing SOCKS5/HTTP proxy (NOXTERM_PRIVACY_UPSTREAM)
+    Upstream,
+}
+
+impl FromStr for PrivacyBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "anyone" => Ok(PrivacyBackendKind::Anyone),
+            "tor" => Ok(PrivacyBackendKind::Tor),
+            "upstream" | "socks" | "proxy" => Ok(PrivacyBackendKind::Upstream),
+            _ => Err(format!("Unknown privacy backend: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for PrivacyBackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivacyBackendKind::Anyone => write!(f, "anyone"),
+            PrivacyBackendKind::Tor => write!(f, "tor"),
+            PrivacyBackendKind::Upstream => write!(f, "upstream"),
+        }
+    }
+}
+
+/// How the Anyone client gets onto the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyoneProvisioning {
+    /// Install Node.js and the npm client on demand
+    Auto,
+    /// Only run a preinstalled client whose checksum is pinned; never install
+    Offline,
+}
+
+impl FromStr for AnyoneProvisioning {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(AnyoneProvisioning::Auto),
+            "offline" | "preinstalled" => Ok(AnyoneProvisioning::Offline),
+            _ => Err(format!("Unknown provisioning mode: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for AnyoneProvisioning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnyoneProvisioning::Auto => write!(f, "auto"),
+            AnyoneProvisioning::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+/// OCI runtime used to isolate session containers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuntimeClass {
+    Runc,
+    Runsc,
+    Kata,
+}
+
+impl RuntimeClass {
+    /// Names the runtime may be registered under in the Docker daemon
+    pub fn daemon_names(&self) -> &'static [&'static str] {
+        match self {
+            RuntimeClass::Runc => &["runc"],
+            RuntimeClass::Runsc => &["runsc", "gvisor"],
+            RuntimeClass::Kata => &["kata", "kata-runtime", "io.containerd.kata.v2"],
+        }
+    }
+}
+
+impl FromStr for RuntimeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "runc" | "default" | "" => Ok(RuntimeClass::Runc),
+            "runsc" | "gvisor" => Ok(RuntimeClass::Runsc),
+            "kata" | "kata-runtime" | "kata-containers" => Ok(RuntimeClass::Kata),
+            _ => Err(format!("Unknown runtime class: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeClass::Runc => write!(f, "runc"),
+            RuntimeClass::Runsc => write!(f, "runsc"),
+            RuntimeClass::Kata => write!(f, "kata"),
+        }
+    }
+}
+
+/// What to do when the requested runtime is not registered with the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimePolicy {
+    /// Start the session with runc and record the downgrade
+    Fallback,
+    /// Refuse to start the session
+    Refuse,
+}
+
+impl FromStr for RuntimePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fallback" => Ok(RuntimePolicy::Fallback),
+            "refuse" | "strict" => Ok(RuntimePolicy::Refuse),
+            _ => Err(format!("Unknown runtime policy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimePolicy::Fallback => write!(f, "fallback"),
+            RuntimePolicy::Refuse => write!(f, "refuse"),
+        }
+    }
+}
+
+/// How the command policy is applied to interactive PTY input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtyPolicyMode {
+    /// Keystrokes go straight to the shell
+    Off,
+    /// Lines are checked and logged but never blocked
+    Audit,
+    /// Denied lines are cancelled and warnings shown inline
+    Enforce,
+}
+
+impl FromStr for PtyPolicyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "disabled" => Ok(PtyPolicyMode::Off),
+            "audit" | "audit-only" => Ok(PtyPolicyMode::Audit),
+            "enforce" => Ok(PtyPolicyMode::Enforce),
+            _ => Err(format!("Unknown PTY policy mode: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for PtyPolicyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PtyPolicyMode::Off => write!(f, "off"),
+            PtyPolicyMode::Audit => write!(f, "audit"),
+            PtyPolicyMode::Enforce => write!(f, "enforce"),
+        }
+    }
 }
 
 /// Session management configuration
@@ -90,6 +394,10 @@ pub struct SessionConfig {
     pub grace_period_secs: u64,
     pub cleanup_interval_secs: u64,
     pub health_check_interval_secs: u64,
+    /// Timeout for exec API commands that do not set one
+    pub exec_timeout_secs: u64,
+    /// Longest timeout an exec API command may ask for
+    pub exec_max_timeout_secs: u64,
 }
 
 /// Rate limiting configuration
@@ -123,8 +431,38 @@ pub struct SecurityConfig {
     pub max_input_length: usize,
     pub trusted_proxies: Vec<String>,
     pub audit_logging: bool,
+    pub default_role: String,
+    pub user_roles: HashMap<String, String>,
+    /// SHA-256 (hex) of user keys set by the operator. Other user IDs are
+    /// claimed by whoever uses them first, so elevated roles need one.
+    pub user_keys: HashMap<String, String>,
+    /// TOML command policy; the shipped default is used when unset
+    pub policy_path: Option<String>,
+    /// Whether the policy also checks lines typed into the terminal
+    pub pty_policy: PtyPolicyMode,
+    /// Keep each user's command history; users may still opt out
+    pub command_logging: bool,
+    /// Also record lines typed into the terminal, rebuilt from keystrokes
+    pub pty_history: bool,
+    /// Days history is kept for users who have not set their own retention
+    pub history_retention_days: u32,
+    /// Most recent commands written to `~/.bash_history` in new containers
+    pub history_inject_lines: u32,
+}
+
+impl SecurityConfig {
+    /// Role assigned to a user, e.g. `user`, `trusted` or `admin`
+    pub fn role_for(&self, user_id: &str) -> &str {
+        self.user_roles
+            .get(user_id)
+            .map(String::as_str)
+            .unwrap_or(&self.default_role)
+    }
 }
 
+/// Longest command history retention, server-wide or per user
+pub const MAX_HISTORY_RETENTION_DAYS: u32 = 365;
+
 /// Observability configuration
 #[derive(Debug, Clone)]
 pub struct ObservabilityConfig {
@@ -135,11 +473,157 @@ pub struct ObservabilityConfig {
     pub tracing_enabled: bool,
 }
 
+/// Batch job configuration
+#[derive(Debug, Clone)]
+pub struct JobConfig {
+    /// Where job logs and collected artifacts are kept
+    pub artifact_dir: String,
+    /// Timeout for jobs that do not set one
+    pub timeout_secs: u64,
+    /// Longest timeout a job may ask for
+    pub max_timeout_secs: u64,
+    /// Most data collected per artifact path
+    pub max_artifact_mb: u64,
+    /// Hours a finished job's logs and artifacts are kept
+    pub retention_hours: u64,
+}
+
 /// Anyone Protocol configuration
 #[derive(Debug, Clone)]
 pub struct AnyoneConfig {
     pub enabled: bool,
+    pub backend: PrivacyBackendKind,
     pub socks_port: u16,
     pub control_port: u16,
     pub auto_start: bool,
+    /// Password for HASHEDPASSWORD control-port auth (cookie and no-auth need none)
+    pub control_password: Option<String>,
+    /// Force all anyone-only session traffic through a netguard sidecar
+    pub transparent: bool,
+    pub sidecar_image: String,
+    /// Anyone backend: install on demand, or run a pinned preinstalled client
+    pub provisioning: AnyoneProvisioning,
+    pub client_path: Option<String>,
+    /// Expected SHA-256 of `client_path`, or of its npm install when it is
+    /// inside `node_modules`, hex encoded
+    pub client_sha256: Option<String>,
+    /// Tor backend: binary and data directory
+    pub tor_binary: String,
+    pub tor_data_dir: String,
+    /// Upstream backend: `socks5://` or `http://` proxy URL
+    pub upstream_proxy: Option<String>,
+    /// Upstream backend: DNS server queried over TCP through the proxy,
+    /// as `ip` or `ip:port`
+    pub upstream_dns: Option<String>,
+    /// Endpoint that answers with the caller's public IP, used by the
+    /// privacy verification suite
+    pub echo_url: String,
+}
+
+impl AnyoneConfig {
+    /// `upstream_dns` as an address, port 53 when none is given
+    pub fn upstream_dns_server(&self) -> Result<Option<SocketAddr>, String> {
+        let Some(value) = self.upstream_dns.as_deref() else {
+            return Ok(None);
+        };
+        value
+            .parse::<SocketAddr>()
+            .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map(Some)
+            .map_err(|_| format!("'{}' is not an IP address or IP:port", value))
+    }
+}
+
+/// Resource tiers selectable at session creation
+#[derive(Debug, Clone)]
+pub struct TierConfig {
+    pub default_tier: String,
+    pub tiers: Vec<ResourceTier>,
+}
+
+impl TierConfig {
+    pub fn get(&self, name: &str) -> Option<&ResourceTier> {
+        self.tiers.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A named set of container limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceTier {
+    pub name: String,
+    pub memory_mb: i64,
+    pub cpu_percent: i64,
+    pub pids_limit: i64,
+    pub disk_mb: i64,
+    pub tmpfs_mb: i64,
+    /// Roles allowed to use this tier; empty means everyone
+    pub roles: Vec<String>,
+    /// Maximum concurrent sessions of this tier per user
+    pub max_per_user: Option<i64>,
+}
+
+impl ResourceTier {
+    /// Built-in small/medium/large tiers
+    pub fn builtin(name: &str) -> Option<Self> {
+        let tier = |memory_mb, cpu_percent, pids_limit, disk_mb, roles: &[&str], max_per_user| Self {
+            name: name.to_string(),
+            memory_mb,
+            cpu_percent,
+            pids_limit,
+            disk_mb,
+            tmpfs_mb: 256,
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            max_per_user,
+        };
+
+        match name {
+            "small" => Some(tier(512, 50, 100, 1024, &[], None)),
+            "medium" => Some(tier(1024, 100, 200, 2048, &[], None)),
+            "large" => Some(tier(4096, 200, 500, 8192, &["trusted", "admin"], Some(1))),
+            _ => None,
+        }
+    }
+
+    /// Parse `memory_mb=512,cpu_percent=50,roles=trusted|admin,...` on top of a base tier
+    pub fn parse(name: &str, spec: &str) -> Result<Self, String> {
+        let mut tier = Self::builtin(name).unwrap_or_else(|| Self {
+            name: name.to_string(),
+            ..Self::builtin("medium").expect("medium tier is built in")
+        });
+
+        for field in spec.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value, got '{}'", field))?;
+            let number = || {
+                value
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|e| format!("{}: {}", key, e))
+            };
+
+            match key.trim() {
+                "memory_mb" => tier.memory_mb = number()?,
+                "cpu_percent" => tier.cpu_percent = number()?,
+                "pids_limit" => tier.pids_limit = number()?,
+                "disk_mb" => tier.disk_mb = number()?,
+                "tmpfs_mb" => tier.tmpfs_mb = number()?,
+                "max_per_user" => tier.max_per_user = Some(number()?),
+                "roles" => {
+                    tier.roles = value
+                        .split('|')
+                        .map(|r| r.trim().to_string())
+                        .filter(|r| !r.is_empty())
+                        .collect()
+                }
+                other => return Err(format!("Unknown tier field '{}'", other)),
+            }
+        }
+
+        Ok(tier)
+    }
+
+    pub fn allows_role(&self, role: &str) -> bool {
+        self.roles.is_empty() || self.roles.iter().any(|r| r == role)
+    }
 }
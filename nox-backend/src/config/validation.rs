@@ -6,7 +6,7 @@
 use tracing::warn;
 
 use super::error::ConfigError;
-use super::types::{Config, Environment};
+use super::types::{AnyoneProvisioning, Config, DockerEndpoint, Environment, NetworkMode, PrivacyBackendKind, MAX_HISTORY_RETENTION_DAYS};
 
 impl Config {
     pub fn validate(&self) -> Result<(), ConfigError> {
@@ -18,6 +18,14 @@ impl Config {
             });
         }
 
+        if self.server.graceful_shutdown_timeout_secs == 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_SHUTDOWN_TIMEOUT".to_string(),
+                value: "0".to_string(),
+                reason: "Shutdown timeout cannot be 0".to_string(),
+            });
+        }
+
         if self.docker.memory_limit_bytes < 64 * 1024 * 1024 {
             return Err(ConfigError::InvalidValue {
                 key: "NOXTERM_DOCKER_MEMORY_LIMIT".to_string(),
@@ -26,6 +34,22 @@ impl Config {
             });
         }
 
+        if self.docker.disk_warn_percent == 0 || self.docker.disk_warn_percent > 100 {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_DOCKER_DISK_WARN_PERCENT".to_string(),
+                value: self.docker.disk_warn_percent.to_string(),
+                reason: "Disk warning threshold must be between 1 and 100".to_string(),
+            });
+        }
+
+        if self.docker.disk_check_interval_secs == 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_DOCKER_DISK_CHECK_INTERVAL".to_string(),
+                value: "0".to_string(),
+                reason: "Disk check interval cannot be 0".to_string(),
+            });
+        }
+
         if self.session.max_sessions_per_ip == 0 {
             return Err(ConfigError::InvalidValue {
                 key: "NOXTERM_MAX_SESSIONS_PER_IP".to_string(),
@@ -34,6 +58,58 @@ impl Config {
             });
         }
 
+        if self.session.exec_timeout_secs == 0 || self.session.exec_timeout_secs > self.session.exec_max_timeout_secs {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_EXEC_TIMEOUT".to_string(),
+                value: self.session.exec_timeout_secs.to_string(),
+                reason: format!(
+                    "Exec timeout must be between 1 and NOXTERM_EXEC_MAX_TIMEOUT ({})",
+                    self.session.exec_max_timeout_secs
+                ),
+            });
+        }
+
+        if self.jobs.timeout_secs == 0 || self.jobs.timeout_secs > self.jobs.max_timeout_secs {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_JOB_TIMEOUT".to_string(),
+                value: self.jobs.timeout_secs.to_string(),
+                reason: format!(
+                    "Job timeout must be between 1 and NOXTERM_JOB_MAX_TIMEOUT ({})",
+                    self.jobs.max_timeout_secs
+                ),
+            });
+        }
+
+        let retention = self.security.history_retention_days;
+        if retention == 0 || retention > MAX_HISTORY_RETENTION_DAYS {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_HISTORY_RETENTION_DAYS".to_string(),
+                value: retention.to_string(),
+                reason: format!("History retention must be between 1 and {} days", MAX_HISTORY_RETENTION_DAYS),
+            });
+        }
+
+        for (user_id, key_sha256) in &self.security.user_keys {
+            if key_sha256.len() != 64 || !key_sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(ConfigError::InvalidValue {
+                    key: "NOXTERM_USER_KEYS".to_string(),
+                    value: user_id.clone(),
+                    reason: "Expected the key's SHA-256 as 64 hex digits".to_string(),
+                });
+            }
+        }
+
+        // Without an operator-set key the role goes to whoever claims the ID first
+        for (user_id, role) in &self.security.user_roles {
+            if *role != self.security.default_role && !self.security.user_keys.contains_key(user_id) {
+                return Err(ConfigError::InvalidValue {
+                    key: "NOXTERM_USER_ROLES".to_string(),
+                    value: format!("{}={}", user_id, role),
+                    reason: "Users with a role other than the default need a key in NOXTERM_USER_KEYS".to_string(),
+                });
+            }
+        }
+
         if self.docker.allowed_images.is_empty() {
             return Err(ConfigError::InvalidValue {
                 key: "NOXTERM_DOCKER_ALLOWED_IMAGES".to_string(),
@@ -42,6 +118,102 @@ impl Config {
             });
         }
 
+        let instance_id = &self.docker.instance_id;
+        if instance_id.is_empty()
+            || instance_id.len() > 63
+            || !instance_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_INSTANCE_ID".to_string(),
+                value: instance_id.clone(),
+                reason: "Must be 1-63 letters, digits, '.', '_' or '-'".to_string(),
+            });
+        }
+
+        if self.docker.host_memory_percent == 0 || self.docker.host_memory_percent > 100 {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_DOCKER_HOST_MEMORY_PERCENT".to_string(),
+                value: self.docker.host_memory_percent.to_string(),
+                reason: "Must be between 1 and 100".to_string(),
+            });
+        }
+
+        if self.docker.network_mode == NetworkMode::Open && !self.docker.allow_networking {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_DOCKER_NETWORK_MODE".to_string(),
+                value: self.docker.network_mode.to_string(),
+                reason: "Open networking is disabled by NOXTERM_DOCKER_ALLOW_NETWORKING".to_string(),
+            });
+        }
+
+        if self.anyone.backend == PrivacyBackendKind::Upstream && self.anyone.upstream_proxy.is_none() {
+            return Err(ConfigError::MissingRequired {
+                key: "NOXTERM_PRIVACY_UPSTREAM".to_string(),
+            });
+        }
+
+        // Privacy sessions resolve through the backend; an upstream proxy has
+        // no RESOLVE extension, so it needs a DNS server to tunnel queries to
+        if self.anyone.backend == PrivacyBackendKind::Upstream {
+            match self.anyone.upstream_dns_server() {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    return Err(ConfigError::MissingRequired {
+                        key: "NOXTERM_PRIVACY_UPSTREAM_DNS".to_string(),
+                    });
+                }
+                Err(reason) => {
+                    return Err(ConfigError::InvalidValue {
+                        key: "NOXTERM_PRIVACY_UPSTREAM_DNS".to_string(),
+                        value: self.anyone.upstream_dns.clone().unwrap_or_default(),
+                        reason,
+                    });
+                }
+            }
+        }
+
+        if self.anyone.backend == PrivacyBackendKind::Anyone && self.anyone.provisioning == AnyoneProvisioning::Offline {
+            if self.anyone.client_path.is_none() {
+                return Err(ConfigError::MissingRequired {
+                    key: "NOXTERM_ANYONE_CLIENT_PATH".to_string(),
+                });
+            }
+            match self.anyone.client_sha256 {
+                None => {
+                    return Err(ConfigError::MissingRequired {
+                        key: "NOXTERM_ANYONE_CLIENT_SHA256".to_string(),
+                    });
+                }
+                Some(ref sha256) if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    return Err(ConfigError::InvalidValue {
+                        key: "NOXTERM_ANYONE_CLIENT_SHA256".to_string(),
+                        value: sha256.clone(),
+                        reason: "Expected a 64-character hex SHA-256 digest".to_string(),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        let echo_url = &self.anyone.echo_url;
+        if !(echo_url.starts_with("http://") || echo_url.starts_with("https://"))
+            || echo_url.chars().any(|c| c.is_whitespace() || c == '\'')
+        {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_PRIVACY_ECHO_URL".to_string(),
+                value: echo_url.clone(),
+                reason: "Expected an http:// or https:// URL without quotes or spaces".to_string(),
+            });
+        }
+
+        if self.tiers.get(&self.tiers.default_tier).is_none() {
+            return Err(ConfigError::InvalidValue {
+                key: "NOXTERM_DEFAULT_TIER".to_string(),
+                value: self.tiers.default_tier.clone(),
+                reason: "Default tier must be listed in NOXTERM_TIERS".to_string(),
+            });
+        }
+
         if self.server.environment == Environment::Production {
             if !self.security.validate_commands {
                 warn!("Command validation is disabled in production!");
@@ -55,6 +227,15 @@ impl Config {
             if !self.docker.read_only_rootfs {
                 warn!("Read-only root filesystem is disabled in production");
             }
+            let plain_tcp = self.docker.hosts.iter()
+                .find(|(_, endpoint)| matches!(endpoint, DockerEndpoint::Tcp(_)));
+            if let (Some((name, endpoint)), None) = (plain_tcp, &self.docker.tls_dir) {
+                return Err(ConfigError::InvalidValue {
+                    key: "NOXTERM_DOCKER_HOSTS".to_string(),
+                    value: format!("{}={}", name, endpoint),
+                    reason: "TCP Docker hosts need NOXTERM_DOCKER_TLS_DIR in production".to_string(),
+                });
+            }
             if !self.security.audit_logging {
                 warn!("Audit logging is disabled in production");
             }
@@ -0,0 +1,306 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! What the container engine behind a Docker API socket supports.
+//!
+//! Podman and rootless Docker serve the same API as Docker, but some
+//! `HostConfig` options behave differently or are rejected there. The
+//! engine is detected from `/version` and `/info`, and session containers
+//! are created with only the options it honours.
+
+use super::storage;
+use anyhow::Result;
+use bollard::models::{SystemInfo, SystemInfoCgroupVersionEnum};
+use bollard::system::Version;
+use bollard::Docker;
+use serde::Serialize;
+
+/// Docker release that added `host-gateway` to `--add-host`
+const DOCKER_HOST_GATEWAY: (u32, u32) = (20, 10);
+/// Podman release that added `host-gateway` to `--add-host`
+const PODMAN_HOST_GATEWAY: (u32, u32) = (5, 3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Docker,
+    Podman,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Kind::Docker => write!(f, "Docker"),
+            Kind::Podman => write!(f, "Podman"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    pub kind: Kind,
+    pub version: String,
+    /// The engine runs as an unprivileged user
+    pub rootless: bool,
+    /// The engine runs in a VM (Docker Desktop, Colima, OrbStack, a Podman
+    /// machine), so its bridge networks are not on this machine
+    pub vm: bool,
+    /// `host.docker.internal:host-gateway` is accepted in `extra_hosts`
+    pub host_gateway: bool,
+    /// The storage driver can cap the writable layer with `storage_opt`
+    pub storage_size: bool,
+    /// Memory, CPU and pids limits are enforced. Rootless engines need
+    /// cgroup v2 for that
+    pub resource_limits: bool,
+    /// Bridge gateways are addresses on this machine, so the egress proxy
+    /// and DNS resolver can listen on them. Rootless engines keep their
+    /// networks inside a namespace of their own, VM engines inside the VM
+    pub egress_routing: bool,
+    /// Runtime used when `HostConfig.runtime` is left unset
+    pub default_runtime: Option<String>,
+}
+
+impl Capabilities {
+    pub fn from_daemon(version: &Version, info: &SystemInfo) -> Self {
+        let podman = version
+            .components
+            .iter()
+            .flatten()
+            .find(|component| component.name.to_lowercase().contains("podman"));
+        let (kind, release) = match podman {
+            Some(component) => (Kind::Podman, component.version.clone()),
+            None => (Kind::Docker, version.version.clone().unwrap_or_default()),
+        };
+
+        let rootless = info
+            .security_options
+            .iter()
+            .flatten()
+            .any(|option| option.split(',').any(|field| field == "name=rootless"));
+        let cgroup_v2 = info.cgroup_version == Some(SystemInfoCgroupVersionEnum::_2);
+        let vm = runs_in_vm(info);
+
+        let driver = info.driver.clone().unwrap_or_default();
+        let backing_fs = info.driver_status.iter().flatten().find_map(|pair| match pair.as_slice() {
+            [key, value] if key == "Backing Filesystem" => Some(value.as_str()),
+            _ => None,
+        });
+
+        let host_gateway_since = match kind {
+            Kind::Docker => DOCKER_HOST_GATEWAY,
+            Kind::Podman => PODMAN_HOST_GATEWAY,
+        };
+
+        Self {
+            kind,
+            host_gateway: parse_version(&release).is_some_and(|v| v >= host_gateway_since),
+            version: release,
+            rootless,
+            vm,
+            // Project quotas cannot be set from a user namespace
+            storage_size: !rootless && storage::supports_size_opt(&driver, backing_fs),
+            resource_limits: !rootless || cgroup_v2,
+            egress_routing: !rootless && !vm,
+            default_runtime: info.default_runtime.clone(),
+        }
+    }
+
+    /// `HostConfig.runtime` for a resolved runtime. runc is left to the
+    /// engine's default where that is another runc-compatible runtime, as
+    /// Podman's crun is and runc is often not installed alongside it
+    pub fn runtime_name(&self, daemon_name: &str) -> Option<String> {
+        if daemon_name == "runc" && self.kind == Kind::Podman {
+            return None;
+        }
+        Some(daemon_name.to_string())
+    }
+
+    pub fn describe(&self) -> String {
+        let mode = match (self.rootless, self.vm) {
+            (true, _) => " (rootless)",
+            (false, true) => " (VM)",
+            (false, false) => "",
+        };
+        format!("{} {}{}", self.kind, self.version, mode)
+    }
+
+    /// Why sessions cannot be routed through the egress proxy here
+    pub fn egress_limitation(&self) -> Option<&'static str> {
+        match (self.rootless, self.vm) {
+            (true, _) => Some("rootless engines keep their networks in a user namespace"),
+            (false, true) => Some("the engine runs in a VM, so its network gateways are not on this machine"),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Docker Desktop, OrbStack, Colima and Lima (Rancher Desktop) name
+/// themselves in `/info`
+fn runs_in_vm(info: &SystemInfo) -> bool {
+    let os = info.operating_system.as_deref().unwrap_or_default();
+    let name = info.name.as_deref().unwrap_or_default();
+    os.contains("Docker Desktop")
+        || os.contains("OrbStack")
+        || name == "colima"
+        || name.starts_with("colima-")
+        || name.starts_with("lima-")
+}
+
+/// Ask the daemon what it supports
+pub async fn detect(docker: &Docker) -> Result<Capabilities> {
+    let version = docker.version().await?;
+    let info = docker.info().await?;
+    Ok(Capabilities::from_daemon(&version, &info))
+}
+
+/// Major and minor of a version like `24.0.7` or `5.3.1-dev`
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim_start_matches('v').split(['.', '-', '+']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().and_then(|minor| minor.parse().ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+/// Docker API sockets to probe on Linux, rootful before rootless
+pub fn linux_sockets(home: &str, runtime_dir: Option<&str>) -> Vec<String> {
+    let mut sockets = vec![
+        "/var/run/docker.sock".to_string(),
+        "/run/docker.sock".to_string(),
+    ];
+    if let Some(dir) = runtime_dir {
+        // Rootless Docker, then rootless Podman's API service
+        sockets.push(format!("{}/docker.sock", dir));
+        sockets.push(format!("{}/podman/podman.sock", dir));
+    }
+    sockets.push("/run/podman/podman.sock".to_string());
+    sockets.push(format!("{}/.docker/run/docker.sock", home));
+    sockets
+}
+
+/// Docker API sockets to probe on macOS, including a Podman machine's
+pub fn macos_sockets(home: &str) -> Vec<String> {
+    vec![
+        "/var/run/docker.sock".to_string(),
+        format!("{}/.docker/run/docker.sock", home),
+        "/Users/Shared/docker/docker.sock".to_string(),
+        format!("{}/.orbstack/run/docker.sock", home),
+        format!("{}/.colima/default/docker.sock", home),
+        format!("{}/.local/share/containers/podman/machine/podman.sock", home),
+        format!("{}/.local/share/containers/podman/machine/podman-machine-default/podman.sock", home),
+    ]
+}
+
+/// `$XDG_RUNTIME_DIR`, or `/run/user/<uid>` where a service manager left
+/// it unset
+pub fn runtime_dir() -> Option<String> {
+    if let Ok(dir) = std::env::var("XDG_RUNTIME_DIR") {
+        if !dir.is_empty() {
+            return Some(dir);
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(proc_self) = std::fs::metadata("/proc/self") {
+            return Some(format!("/run/user/{}", proc_self.uid()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::system::VersionComponents;
+
+    fn daemon(components: &[(&str, &str)], security: &[&str], cgroup: SystemInfoCgroupVersionEnum) -> (Version, SystemInfo) {
+        let version = Version {
+            version: Some(components.first().map_or("24.0.7", |(_, v)| *v).to_string()),
+            components: Some(
+                components
+                    .iter()
+                    .map(|(name, version)| VersionComponents {
+                        name: name.to_string(),
+                        version: version.to_string(),
+                        details: None,
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        let info = SystemInfo {
+            driver: Some("overlay2".to_string()),
+            driver_status: Some(vec![vec!["Backing Filesystem".to_string(), "xfs".to_string()]]),
+            security_options: Some(security.iter().map(|s| s.to_string()).collect()),
+            cgroup_version: Some(cgroup),
+            default_runtime: Some("runc".to_string()),
+            ..Default::default()
+        };
+        (version, info)
+    }
+
+    #[test]
+    fn test_rootful_docker() {
+        let (version, info) = daemon(&[("Engine", "24.0.7")], &["name=seccomp,profile=builtin"], SystemInfoCgroupVersionEnum::_2);
+        let caps = Capabilities::from_daemon(&version, &info);
+        assert_eq!(caps.kind, Kind::Docker);
+        assert!(!caps.rootless);
+        assert!(caps.host_gateway && caps.storage_size && caps.resource_limits && caps.egress_routing);
+        assert_eq!(caps.runtime_name("runc").as_deref(), Some("runc"));
+    }
+
+    #[test]
+    fn test_rootless_podman() {
+        let (version, info) = daemon(
+            &[("Podman Engine", "4.9.3"), ("Conmon", "2.1.10")],
+            &["name=seccomp,profile=default", "name=rootless"],
+            SystemInfoCgroupVersionEnum::_2,
+        );
+        let caps = Capabilities::from_daemon(&version, &info);
+        assert_eq!(caps.kind, Kind::Podman);
+        assert_eq!(caps.describe(), "Podman 4.9.3 (rootless)");
+        assert!(!caps.host_gateway);
+        assert!(!caps.storage_size);
+        assert!(!caps.egress_routing);
+        assert!(caps.resource_limits);
+        assert_eq!(caps.runtime_name("runc"), None);
+        assert_eq!(caps.runtime_name("runsc").as_deref(), Some("runsc"));
+        assert!(caps.egress_limitation().is_some());
+
+        let (version, info) = daemon(&[("Podman Engine", "5.3.1")], &["name=rootless"], SystemInfoCgroupVersionEnum::_1);
+        let caps = Capabilities::from_daemon(&version, &info);
+        assert!(caps.host_gateway);
+        assert!(!caps.resource_limits);
+    }
+
+    #[test]
+    fn test_vm_engines() {
+        let (version, mut info) = daemon(&[("Engine", "27.3.1")], &[], SystemInfoCgroupVersionEnum::_2);
+        info.operating_system = Some("Docker Desktop".to_string());
+        let caps = Capabilities::from_daemon(&version, &info);
+        assert!(caps.vm && !caps.egress_routing);
+        assert_eq!(caps.describe(), "Docker 27.3.1 (VM)");
+
+        info.operating_system = Some("Ubuntu 24.04 LTS".to_string());
+        info.name = Some("colima".to_string());
+        assert!(!Capabilities::from_daemon(&version, &info).egress_routing);
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("24.0.7"), Some((24, 0)));
+        assert_eq!(parse_version("20.10.21"), Some((20, 10)));
+        assert_eq!(parse_version("5.3.1-dev"), Some((5, 3)));
+        assert_eq!(parse_version("v27"), Some((27, 0)));
+        assert_eq!(parse_version("dev"), None);
+    }
+
+    #[test]
+    fn test_linux_sockets() {
+        let sockets = linux_sockets("/home/alice", Some("/run/user/1000"));
+        assert_eq!(sockets[0], "/var/run/docker.sock");
+        assert!(sockets.contains(&"/run/user/1000/docker.sock".to_string()));
+        assert!(sockets.contains(&"/run/user/1000/podman/podman.sock".to_string()));
+        assert!(!linux_sockets("/home/alice", None).iter().any(|s| s.starts_with("/run/user")));
+    }
+}
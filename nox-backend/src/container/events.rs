@@ -0,0 +1,347 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Docker events for this instance's session containers.
+//!
+//! The watcher follows Docker's event stream filtered to the instance label,
+//! so a crashed or OOM-killed container is noticed as it happens rather than
+//! at the next health poll. Docker reports an OOM kill as `oom`, then `kill`
+//! and `die`; the tracker folds those into one `Stop` with its cause.
+//!
+//! Containers from before labels, which startup reconciliation may still
+//! adopt, carry neither the instance nor the session label. The watcher never
+//! sees them; their stops are only noticed by the lifecycle health poll.
+
+use super::labels;
+use crate::anyone_supervisor::Backoff;
+use bollard::models::EventMessage;
+use bollard::system::EventsOptions;
+use bollard::Docker;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Exit code of a process killed by SIGKILL, which is how the OOM killer ends it
+const SIGKILL_EXIT_CODE: i64 = 137;
+
+/// Why a session container stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cause {
+    /// Killed by the kernel for exceeding its memory limit
+    OomKilled,
+    /// Sent a signal through Docker, e.g. `docker kill` or a stop
+    Killed { signal: Option<String> },
+    /// Its main process exited on its own
+    Exited,
+}
+
+impl Cause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cause::OomKilled => "oom_killed",
+            Cause::Killed { .. } => "killed",
+            Cause::Exited => "exited",
+        }
+    }
+}
+
+/// A session container that stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    pub session_id: Uuid,
+    pub container_id: String,
+    pub exit_code: Option<i64>,
+    pub cause: Cause,
+}
+
+impl Stop {
+    /// Explanation shown to the session's user
+    pub fn message(&self) -> String {
+        match &self.cause {
+            Cause::OomKilled => {
+                "Your container ran out of memory and was stopped. Pick a larger tier or use less memory.".to_string()
+            }
+            Cause::Killed { signal: Some(signal) } => format!("Your container was stopped (signal {}).", signal),
+            Cause::Killed { signal: None } => "Your container was stopped.".to_string(),
+            Cause::Exited => match self.exit_code {
+                Some(code) => format!("Your container exited with code {}.", code),
+                None => "Your container exited.".to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A process in the container was OOM-killed; the container may live on
+    Oom { session_id: Uuid, container_id: String },
+    Stopped(Stop),
+    /// Result of the image's HEALTHCHECK: healthy, unhealthy or starting
+    Health { session_id: Uuid, container_id: String, status: String },
+}
+
+/// What was seen of a container before it died
+#[derive(Debug, Default)]
+struct Pending {
+    oom: bool,
+    signal: Option<String>,
+}
+
+/// Turns raw Docker events into `Event`s, remembering `oom` and `kill` until
+/// the `die` they lead to
+#[derive(Debug, Default)]
+pub struct Tracker {
+    pending: HashMap<String, Pending>,
+}
+
+impl Tracker {
+    pub fn observe(&mut self, message: &EventMessage) -> Option<Event> {
+        let actor = message.actor.as_ref()?;
+        let container_id = actor.id.clone()?;
+        let attributes = actor.attributes.as_ref()?;
+        // The netguard sidecar goes down with its session container
+        if labels::is_netguard(attributes) {
+            return None;
+        }
+        let session_id = labels::session_id(attributes)?;
+
+        match message.action.as_deref()? {
+            "oom" => {
+                self.pending.entry(container_id.clone()).or_default().oom = true;
+                Some(Event::Oom { session_id, container_id })
+            }
+            "kill" => {
+                self.pending.entry(container_id).or_default().signal = attributes.get("signal").cloned();
+                None
+            }
+            "die" => {
+                let pending = self.pending.remove(&container_id).unwrap_or_default();
+                let exit_code = attributes.get("exitCode").and_then(|code| code.parse().ok());
+                // A process OOM-killed earlier does not explain a later clean exit
+                let cause = if pending.oom && exit_code == Some(SIGKILL_EXIT_CODE) {
+                    Cause::OomKilled
+                } else if pending.signal.is_some() {
+                    Cause::Killed { signal: pending.signal }
+                } else {
+                    Cause::Exited
+                };
+                Some(Event::Stopped(Stop { session_id, container_id, exit_code, cause }))
+            }
+            action => {
+                let status = action.strip_prefix("health_status:")?.trim().to_string();
+                Some(Event::Health { session_id, container_id, status })
+            }
+        }
+    }
+}
+
+/// Where a reconnect resumes: Docker replays events from `since` onwards,
+/// so those at the last nanosecond seen are remembered and skipped
+#[derive(Debug, Default)]
+struct Cursor {
+    time_nano: i64,
+    seen: HashSet<(String, String)>,
+}
+
+impl Cursor {
+    /// Whether an event is new, moving the cursor past it
+    fn advance(&mut self, message: &EventMessage) -> bool {
+        let Some(time_nano) = message.time_nano else {
+            return true;
+        };
+        let key = (
+            message.actor.as_ref().and_then(|actor| actor.id.clone()).unwrap_or_default(),
+            message.action.clone().unwrap_or_default(),
+        );
+        if time_nano < self.time_nano {
+            return false;
+        }
+        if time_nano > self.time_nano {
+            self.time_nano = time_nano;
+            self.seen.clear();
+        }
+        self.seen.insert(key)
+    }
+
+    /// `since` for the events request, as `seconds.nanoseconds`
+    fn since(&self) -> Option<String> {
+        (self.time_nano > 0).then(|| format!("{}.{:09}", self.time_nano / 1_000_000_000, self.time_nano % 1_000_000_000))
+    }
+}
+
+/// Follows the event stream and fans events out to subscribers
+pub struct Watcher {
+    events: broadcast::Sender<Event>,
+    oom_kills: AtomicU64,
+}
+
+impl Watcher {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            events: broadcast::channel(64).0,
+            oom_kills: AtomicU64::new(0),
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// OOM kills seen in session containers since startup
+    pub fn oom_kills(&self) -> u64 {
+        self.oom_kills.load(Ordering::Relaxed)
+    }
+
+    /// Follow Docker's events for `instance_id`'s containers, reconnecting
+    /// with backoff and resuming from the last event seen
+    pub async fn run(self: Arc<Self>, docker: Arc<Docker>, instance_id: String) {
+        let mut tracker = Tracker::default();
+        let mut backoff = Backoff::default();
+        let mut cursor = Cursor::default();
+
+        loop {
+            let mut filters = labels::instance_filter(&instance_id);
+            filters.insert("type".to_string(), vec!["container".to_string()]);
+            filters.insert("event".to_string(), ["oom", "kill", "die", "health_status"].map(String::from).to_vec());
+            let mut stream = docker.events(Some(EventsOptions {
+                since: cursor.since(),
+                filters,
+                ..Default::default()
+            }));
+            info!("Watching Docker events for instance {}", instance_id);
+
+            while let Some(message) = stream.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Docker event stream failed: {}", e);
+                        break;
+                    }
+                };
+                backoff.reset();
+                if !cursor.advance(&message) {
+                    continue;
+                }
+
+                let Some(event) = tracker.observe(&message) else {
+                    continue;
+                };
+                if let Event::Oom { .. } = event {
+                    self.oom_kills.fetch_add(1, Ordering::Relaxed);
+                }
+                debug!("Container event: {:?}", event);
+                let _ = self.events.send(event);
+            }
+
+            let delay = backoff.next_delay();
+            warn!("Docker event stream ended, reconnecting in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Wait for the stop of `container_id` among `events`
+pub async fn stopped(events: &mut broadcast::Receiver<Event>, container_id: &str) -> Option<Stop> {
+    loop {
+        match events.recv().await {
+            Ok(Event::Stopped(stop)) if stop.container_id == container_id => return Some(stop),
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::EventActor;
+
+    fn message(action: &str, session_id: Uuid, extra: &[(&str, &str)]) -> EventMessage {
+        let mut attributes = HashMap::from([(labels::SESSION_ID.to_string(), session_id.to_string())]);
+        attributes.extend(extra.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        EventMessage {
+            action: Some(action.to_string()),
+            actor: Some(EventActor { id: Some("c1".to_string()), attributes: Some(attributes) }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_oom_kill_folds_into_stop() {
+        let id = Uuid::new_v4();
+        let mut tracker = Tracker::default();
+        assert_eq!(
+            tracker.observe(&message("oom", id, &[])),
+            Some(Event::Oom { session_id: id, container_id: "c1".to_string() })
+        );
+        assert_eq!(tracker.observe(&message("kill", id, &[("signal", "9")])), None);
+
+        let Some(Event::Stopped(stop)) = tracker.observe(&message("die", id, &[("exitCode", "137")])) else {
+            panic!("expected a stop");
+        };
+        assert_eq!(stop.cause, Cause::OomKilled);
+        assert_eq!(stop.exit_code, Some(137));
+        assert!(tracker.pending.is_empty());
+    }
+
+    #[test]
+    fn test_stop_causes() {
+        let id = Uuid::new_v4();
+        let mut tracker = Tracker::default();
+
+        tracker.observe(&message("kill", id, &[("signal", "15")]));
+        let Some(Event::Stopped(stop)) = tracker.observe(&message("die", id, &[("exitCode", "143")])) else {
+            panic!("expected a stop");
+        };
+        assert_eq!(stop.cause, Cause::Killed { signal: Some("15".to_string()) });
+
+        // An earlier OOM kill of a child does not explain a clean exit
+        tracker.observe(&message("oom", id, &[]));
+        let Some(Event::Stopped(stop)) = tracker.observe(&message("die", id, &[("exitCode", "0")])) else {
+            panic!("expected a stop");
+        };
+        assert_eq!(stop.cause, Cause::Exited);
+        assert_eq!(stop.message(), "Your container exited with code 0.");
+    }
+
+    #[test]
+    fn test_cursor_skips_replayed_events() {
+        let id = Uuid::new_v4();
+        let at = |action: &str, time_nano: i64| EventMessage { time_nano: Some(time_nano), ..message(action, id, &[]) };
+        let mut cursor = Cursor::default();
+        assert_eq!(cursor.since(), None);
+
+        assert!(cursor.advance(&at("oom", 1_500_000_000)));
+        assert!(cursor.advance(&at("kill", 1_500_000_000)));
+        assert_eq!(cursor.since().as_deref(), Some("1.500000000"));
+
+        // A reconnect replays from that nanosecond
+        assert!(!cursor.advance(&at("oom", 1_500_000_000)));
+        assert!(!cursor.advance(&at("kill", 1_500_000_000)));
+        assert!(!cursor.advance(&at("oom", 1_000_000_000)));
+        assert!(cursor.advance(&at("die", 1_500_000_000)));
+        assert!(cursor.advance(&at("oom", 2_000_000_000)));
+    }
+
+    #[test]
+    fn test_health_and_ignored_events() {
+        let id = Uuid::new_v4();
+        let mut tracker = Tracker::default();
+        assert_eq!(
+            tracker.observe(&message("health_status: unhealthy", id, &[])),
+            Some(Event::Health { session_id: id, container_id: "c1".to_string(), status: "unhealthy".to_string() })
+        );
+        assert_eq!(tracker.observe(&message("start", id, &[])), None);
+
+        let sidecar = message("die", id, &[(labels::ROLE, labels::Role::Netguard.as_str())]);
+        assert_eq!(tracker.observe(&sidecar), None);
+
+        let mut unlabelled = message("die", id, &[]);
+        unlabelled.actor.as_mut().unwrap().attributes = Some(HashMap::new());
+        assert_eq!(tracker.observe(&unlabelled), None);
+    }
+}
@@ -0,0 +1,331 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Non-interactive commands run inside containers: short shell snippets the
+//! backend runs itself, and argument vectors submitted through the exec API
+//! with their output streamed as it arrives.
+
+use anyhow::{anyhow, Result};
+use bollard::container::LogOutput;
+use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
+use bollard::Docker;
+use futures::StreamExt;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Output kept per stream by [`Captured`]; the rest is dropped
+pub const MAX_CAPTURE: usize = 4 * 1024 * 1024;
+
+/// Reports the shell's PID on stderr, then becomes the command so the PID
+/// can be killed on timeout
+const PID_WRAPPER: &str = "echo $$ >&2; exec \"$@\"";
+
+/// Output of a finished command
+#[derive(Debug, Clone)]
+pub struct ExecOutput {
+    pub exit_code: Option<i64>,
+    pub output: String,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Run `sh -c <command>` and collect stdout and stderr
+pub async fn run(docker: &Docker, container_id: &str, command: &str, timeout: Duration) -> Result<ExecOutput> {
+    let exec = docker.create_exec(
+        container_id,
+        CreateExecOptions {
+            cmd: Some(vec!["/bin/sh", "-c", command]),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        },
+    ).await?;
+
+    let StartExecResults::Attached { mut output, .. } = docker
+        .start_exec(&exec.id, Some(StartExecOptions { detach: false, ..Default::default() }))
+        .await?
+    else {
+        return Err(anyhow!("Exec unexpectedly detached"));
+    };
+
+    let collect = async {
+        let mut text = String::new();
+        while let Some(chunk) = output.next().await {
+            match chunk? {
+                LogOutput::StdOut { message } | LogOutput::StdErr { message } => {
+                    text.push_str(&String::from_utf8_lossy(&message));
+                }
+                _ => {}
+            }
+        }
+        Ok::<_, bollard::errors::Error>(text)
+    };
+
+    let text = tokio::time::timeout(timeout, collect)
+        .await
+        .map_err(|_| anyhow!("Command timed out after {}s: {}", timeout.as_secs(), command))??;
+
+    let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+
+    Ok(ExecOutput { exit_code, output: text })
+}
+
+/// A command run without a terminal
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub argv: Vec<String>,
+    /// `NAME=value` pairs added to the environment
+    pub env: Vec<String>,
+    pub cwd: Option<String>,
+    /// Written to stdin, which is then closed
+    pub stdin: Option<Vec<u8>>,
+    pub timeout: Duration,
+}
+
+/// How a command ended
+#[derive(Debug, Clone, Serialize)]
+pub struct Exit {
+    /// None when the command was killed for timing out
+    pub exit_code: Option<i64>,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+}
+
+/// Output of a running command, ending with exactly one `Exit`
+#[derive(Debug, Clone)]
+pub enum Event {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    Exit(Exit),
+}
+
+/// Output of a finished command with stdout and stderr kept apart
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+    /// Set when either stream went over [`MAX_CAPTURE`]
+    pub truncated: bool,
+    pub exit_code: Option<i64>,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+}
+
+impl Captured {
+    /// Drain a command's events
+    pub async fn collect(mut events: mpsc::Receiver<Event>) -> Self {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut captured = Captured::default();
+        while let Some(event) = events.recv().await {
+            let (buffer, chunk) = match event {
+                Event::Stdout(chunk) => (&mut stdout, chunk),
+                Event::Stderr(chunk) => (&mut stderr, chunk),
+                Event::Exit(exit) => {
+                    captured.exit_code = exit.exit_code;
+                    captured.timed_out = exit.timed_out;
+                    captured.duration_ms = exit.duration_ms;
+                    break;
+                }
+            };
+            let room = MAX_CAPTURE.saturating_sub(buffer.len());
+            captured.truncated |= chunk.len() > room;
+            buffer.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        }
+        captured.stdout = String::from_utf8_lossy(&stdout).into_owned();
+        captured.stderr = String::from_utf8_lossy(&stderr).into_owned();
+        captured
+    }
+}
+
+/// Start `command` and stream its output. The command runs to completion or
+/// timeout even if the receiver is dropped.
+pub async fn spawn(docker: Docker, container_id: &str, command: Command) -> Result<mpsc::Receiver<Event>> {
+    if command.argv.is_empty() {
+        return Err(anyhow!("Command has no arguments"));
+    }
+
+    let mut cmd = vec!["/bin/sh".to_string(), "-c".to_string(), PID_WRAPPER.to_string(), "sh".to_string()];
+    cmd.extend(command.argv.iter().cloned());
+
+    let exec = docker.create_exec(
+        container_id,
+        CreateExecOptions {
+            cmd: Some(cmd),
+            env: Some(command.env.clone()),
+            working_dir: command.cwd.clone(),
+            attach_stdin: Some(command.stdin.is_some()),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            tty: Some(false),
+            ..Default::default()
+        },
+    ).await?;
+
+    let StartExecResults::Attached { mut output, mut input } = docker
+        .start_exec(&exec.id, Some(StartExecOptions { detach: false, ..Default::default() }))
+        .await?
+    else {
+        return Err(anyhow!("Exec unexpectedly detached"));
+    };
+
+    if let Some(stdin) = command.stdin {
+        tokio::spawn(async move {
+            if let Err(e) = input.write_all(&stdin).await {
+                debug!("Exec stdin write ended early: {}", e);
+            }
+            let _ = input.shutdown().await;
+        });
+    }
+
+    let (tx, rx) = mpsc::channel(64);
+    let container_id = container_id.to_string();
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let deadline = tokio::time::sleep(command.timeout);
+        tokio::pin!(deadline);
+
+        let mut pid = PidLine::default();
+        let mut timed_out = false;
+        loop {
+            tokio::select! {
+                chunk = output.next() => match chunk {
+                    Some(Ok(LogOutput::StdOut { message })) => {
+                        let _ = tx.send(Event::Stdout(message.to_vec())).await;
+                    }
+                    Some(Ok(LogOutput::StdErr { message })) => {
+                        if let Some(rest) = pid.feed(&message) {
+                            let _ = tx.send(Event::Stderr(rest)).await;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        warn!("Exec output stream failed in {}: {}", container_id, e);
+                        break;
+                    }
+                    None => break,
+                },
+                _ = &mut deadline => {
+                    timed_out = true;
+                    break;
+                }
+            }
+        }
+
+        let exit_code = if timed_out {
+            if let Some(pid) = pid.pid {
+                let kill = format!("kill -KILL {}", pid);
+                if let Err(e) = run(&docker, &container_id, &kill, Duration::from_secs(5)).await {
+                    warn!("Could not kill timed out exec in {}: {}", container_id, e);
+                }
+            }
+            None
+        } else {
+            match docker.inspect_exec(&exec.id).await {
+                Ok(inspect) => inspect.exit_code,
+                Err(e) => {
+                    warn!("Could not read exec exit code in {}: {}", container_id, e);
+                    None
+                }
+            }
+        };
+
+        let _ = tx.send(Event::Exit(Exit {
+            exit_code,
+            timed_out,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })).await;
+    });
+
+    Ok(rx)
+}
+
+/// Write `content` to `path` inside the container, creating its directory
+pub async fn write_file(docker: &Docker, container_id: &str, path: &str, content: Vec<u8>, executable: bool) -> Result<()> {
+    let script = r#"mkdir -p "$(dirname "$1")" && cat > "$1" && if [ "$2" = 1 ]; then chmod +x "$1"; fi"#;
+    let command = Command {
+        argv: vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            script.to_string(),
+            "sh".to_string(),
+            path.to_string(),
+            if executable { "1" } else { "0" }.to_string(),
+        ],
+        env: Vec::new(),
+        cwd: None,
+        stdin: Some(content),
+        timeout: Duration::from_secs(30),
+    };
+
+    let captured = Captured::collect(spawn(docker.clone(), container_id, command).await?).await;
+    match captured.exit_code {
+        Some(0) => Ok(()),
+        _ if captured.timed_out => Err(anyhow!("Timed out writing {}", path)),
+        _ => Err(anyhow!("Could not write {}: {}", path, captured.stderr.trim())),
+    }
+}
+
+/// Splits the wrapper's PID line off the start of stderr
+#[derive(Debug, Default)]
+struct PidLine {
+    pid: Option<u32>,
+    done: bool,
+    pending: Vec<u8>,
+}
+
+impl PidLine {
+    /// Stderr left to forward once the PID line has been consumed
+    fn feed(&mut self, chunk: &[u8]) -> Option<Vec<u8>> {
+        if self.done {
+            return Some(chunk.to_vec());
+        }
+        self.pending.extend_from_slice(chunk);
+        let end = self.pending.iter().position(|&b| b == b'\n')?;
+        self.done = true;
+        self.pid = std::str::from_utf8(&self.pending[..end]).ok().and_then(|line| line.trim().parse().ok());
+        let rest = self.pending.split_off(end + 1);
+        (!rest.is_empty()).then_some(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_line_is_split_from_stderr() {
+        let mut pid = PidLine::default();
+        assert_eq!(pid.feed(b"12"), None);
+        assert_eq!(pid.feed(b"34\nwarn"), Some(b"warn".to_vec()));
+        assert_eq!(pid.pid, Some(1234));
+        assert_eq!(pid.feed(b"ing\n"), Some(b"ing\n".to_vec()));
+
+        let mut pid = PidLine::default();
+        assert_eq!(pid.feed(b"7\n"), None);
+        assert_eq!(pid.pid, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_collect_keeps_streams_apart() {
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(Event::Stdout(b"out\n".to_vec())).await.unwrap();
+        tx.send(Event::Stderr(b"err\n".to_vec())).await.unwrap();
+        tx.send(Event::Stdout(vec![b'x'; MAX_CAPTURE])).await.unwrap();
+        tx.send(Event::Exit(Exit { exit_code: Some(3), timed_out: false, duration_ms: 5 })).await.unwrap();
+
+        let captured = Captured::collect(rx).await;
+        assert!(captured.stdout.starts_with("out\nxx"));
+        assert_eq!(captured.stdout.len(), MAX_CAPTURE);
+        assert_eq!(captured.stderr, "err\n");
+        assert!(captured.truncated);
+        assert_eq!(captured.exit_code, Some(3));
+    }
+}
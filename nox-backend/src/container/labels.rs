@@ -0,0 +1,159 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Docker labels recording which NOXTERM instance and session own a
+//! container. Every container the backend creates carries them, and the
+//! backend only ever lists, adopts or removes containers labelled with its
+//! own instance ID, so several instances can share one Docker host.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const SESSION_ID: &str = "noxterm.session_id";
+pub const USER_ID: &str = "noxterm.user_id";
+pub const INSTANCE_ID: &str = "noxterm.instance_id";
+pub const CREATED_AT: &str = "noxterm.created_at";
+pub const TIER: &str = "noxterm.tier";
+/// Image the session was started from
+pub const TEMPLATE: &str = "noxterm.template";
+/// What the container is to its session, see `Role`
+pub const ROLE: &str = "noxterm.role";
+
+/// Prefix of every session container's name
+pub const NAME_PREFIX: &str = "noxterm-session-";
+
+/// Characters of the session ID kept in names from before labels
+const LEGACY_NAME_ID_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The container a session's shell runs in
+    Session,
+    /// Firewall sidecar sharing the session container's network
+    Netguard,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Session => "session",
+            Role::Netguard => "netguard",
+        }
+    }
+}
+
+/// Who a container belongs to
+#[derive(Debug, Clone)]
+pub struct Owner<'a> {
+    pub instance_id: &'a str,
+    pub session_id: Uuid,
+    pub user_id: &'a str,
+    pub tier: &'a str,
+    pub template: &'a str,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Owner<'_> {
+    pub fn labels(&self, role: Role) -> HashMap<String, String> {
+        HashMap::from([
+            (INSTANCE_ID.to_string(), self.instance_id.to_string()),
+            (SESSION_ID.to_string(), self.session_id.to_string()),
+            (USER_ID.to_string(), self.user_id.to_string()),
+            (TIER.to_string(), self.tier.to_string()),
+            (TEMPLATE.to_string(), self.template.to_string()),
+            (CREATED_AT.to_string(), self.created_at.to_rfc3339()),
+            (ROLE.to_string(), role.as_str().to_string()),
+        ])
+    }
+}
+
+/// `list_containers` filter matching every container an instance created
+pub fn instance_filter(instance_id: &str) -> HashMap<String, Vec<String>> {
+    HashMap::from([("label".to_string(), vec![format!("{}={}", INSTANCE_ID, instance_id)])])
+}
+
+/// The session a container's labels name, if any
+pub fn session_id(labels: &HashMap<String, String>) -> Option<Uuid> {
+    labels.get(SESSION_ID).and_then(|id| id.parse().ok())
+}
+
+/// The instance a container's labels name; None for containers from
+/// before labels
+pub fn instance_id(labels: &HashMap<String, String>) -> Option<&str> {
+    labels.get(INSTANCE_ID).map(String::as_str)
+}
+
+pub fn is_netguard(labels: &HashMap<String, String>) -> bool {
+    labels.get(ROLE).map(String::as_str) == Some(Role::Netguard.as_str())
+}
+
+/// Container name for a session
+pub fn container_name(session_id: Uuid) -> String {
+    format!("{}{}", NAME_PREFIX, session_id.simple())
+}
+
+/// Container name a session got before labels, with only the first 12
+/// characters of its ID
+pub fn legacy_container_name(session_id: Uuid) -> String {
+    format!("{}{}", NAME_PREFIX, &session_id.simple().to_string()[..LEGACY_NAME_ID_LEN])
+}
+
+/// Whether a container name (as Docker reports it, with or without the
+/// leading `/`) belongs to this session. Containers from before labels can
+/// only be matched this way.
+pub fn name_matches(name: &str, session_id: Uuid) -> bool {
+    let name = name.trim_start_matches('/');
+    name == container_name(session_id) || name == legacy_container_name(session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(session_id: Uuid) -> Owner<'static> {
+        Owner {
+            instance_id: "eu-1",
+            session_id,
+            user_id: "alice",
+            tier: "small",
+            template: "ubuntu:22.04",
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_container_name() {
+        let id: Uuid = "0f3c2a9e-41b7-4d2e-9a61-2f0c8b7d5e14".parse().unwrap();
+        assert_eq!(container_name(id), "noxterm-session-0f3c2a9e41b74d2e9a612f0c8b7d5e14");
+        assert!(name_matches("/noxterm-session-0f3c2a9e41b74d2e9a612f0c8b7d5e14", id));
+        assert!(name_matches("/noxterm-session-0f3c2a9e41b7", id));
+        assert!(!name_matches("/noxterm-session-0f3c2a9e41b8", id));
+    }
+
+    #[test]
+    fn test_owner_labels() {
+        let id = Uuid::new_v4();
+        let labels = owner(id).labels(Role::Session);
+        assert_eq!(session_id(&labels), Some(id));
+        assert_eq!(instance_id(&labels), Some("eu-1"));
+        assert_eq!(labels[USER_ID], "alice");
+        assert!(!is_netguard(&labels));
+        assert!(is_netguard(&owner(id).labels(Role::Netguard)));
+    }
+
+    #[test]
+    fn test_unlabelled() {
+        assert_eq!(session_id(&HashMap::new()), None);
+        assert_eq!(instance_id(&HashMap::new()), None);
+
+        let garbled = HashMap::from([(SESSION_ID.to_string(), "not-a-uuid".to_string())]);
+        assert_eq!(session_id(&garbled), None);
+    }
+
+    #[test]
+    fn test_instance_filter() {
+        let filter = instance_filter("eu-1");
+        assert_eq!(filter["label"], vec!["noxterm.instance_id=eu-1".to_string()]);
+    }
+}
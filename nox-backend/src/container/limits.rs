@@ -0,0 +1,91 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Translation of stored resource limits into Docker host settings.
+
+use super::storage;
+use crate::config::ResourceTier;
+use crate::db::ResourceLimits;
+use bollard::container::UpdateContainerOptions;
+use bollard::models::HostConfig;
+
+/// CFS period used for all session containers (100ms)
+const CPU_PERIOD: i64 = 100_000;
+
+/// Resource limits described by a tier
+pub fn from_tier(tier: &ResourceTier) -> ResourceLimits {
+    ResourceLimits {
+        memory_mb: tier.memory_mb,
+        cpu_percent: tier.cpu_percent,
+        pids_limit: tier.pids_limit,
+        disk_mb: tier.disk_mb,
+        tmpfs_mb: tier.tmpfs_mb,
+    }
+}
+
+fn memory_bytes(limits: &ResourceLimits) -> i64 {
+    limits.memory_mb.saturating_mul(1024 * 1024)
+}
+
+/// `cpu_percent` of 100 is one full CPU
+fn cpu_quota(limits: &ResourceLimits) -> i64 {
+    limits.cpu_percent.saturating_mul(CPU_PERIOD / 100)
+}
+
+/// Limit fields of a `HostConfig`; combine with struct update syntax
+pub fn host_config(limits: &ResourceLimits) -> HostConfig {
+    HostConfig {
+        memory: Some(memory_bytes(limits)),
+        memory_swap: Some(memory_bytes(limits)),
+        cpu_quota: Some(cpu_quota(limits)),
+        cpu_period: Some(CPU_PERIOD),
+        pids_limit: Some(limits.pids_limit),
+        tmpfs: Some(storage::tmpfs(limits.tmpfs_mb)),
+        ..Default::default()
+    }
+}
+
+/// Limits that can be changed on a running container
+///
+/// Disk and tmpfs sizes are fixed at creation; the disk watcher picks up the
+/// new quota from the session row instead.
+pub fn update_options(limits: &ResourceLimits) -> UpdateContainerOptions<String> {
+    UpdateContainerOptions {
+        memory: Some(memory_bytes(limits)),
+        memory_swap: Some(memory_bytes(limits)),
+        cpu_quota: Some(cpu_quota(limits)),
+        cpu_period: Some(CPU_PERIOD),
+        pids_limit: Some(limits.pids_limit),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_config_from_limits() {
+        let limits = ResourceLimits {
+            memory_mb: 1024,
+            cpu_percent: 150,
+            pids_limit: 200,
+            disk_mb: 2048,
+            tmpfs_mb: 64,
+        };
+        let host = host_config(&limits);
+        assert_eq!(host.memory, Some(1024 * 1024 * 1024));
+        assert_eq!(host.cpu_quota, Some(150_000));
+        assert_eq!(host.cpu_period, Some(100_000));
+        assert_eq!(host.pids_limit, Some(200));
+        assert!(host.tmpfs.unwrap()["/tmp"].contains("size=64m"));
+    }
+
+    #[test]
+    fn test_from_tier() {
+        let tier = ResourceTier::builtin("large").unwrap();
+        let limits = from_tier(&tier);
+        assert_eq!(limits.memory_mb, 4096);
+        assert_eq!(limits.disk_mb, 8192);
+    }
+}
@@ -0,0 +1,17 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! NOXTERM Container Sandbox
+//! Isolation settings applied to session containers before they are created.
+
+pub mod engine;
+pub mod events;
+pub mod exec;
+pub mod labels;
+pub mod limits;
+pub mod network;
+pub mod runtime;
+pub mod storage;
+pub mod transparent;
+
+pub use runtime::ResolvedRuntime;
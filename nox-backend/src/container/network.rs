@@ -0,0 +1,60 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Docker networks used for session egress control.
+
+use anyhow::{anyhow, Result};
+use bollard::network::{CreateNetworkOptions, InspectNetworkOptions};
+use bollard::Docker;
+use std::net::IpAddr;
+use tracing::info;
+
+/// Make sure the internal egress network exists and return its gateway address
+///
+/// The network is created with `internal: true`, so containers attached to it
+/// have no route out; the only reachable address is the host-side gateway,
+/// where the backend's egress proxy listens.
+pub async fn ensure_egress_network(docker: &Docker, name: &str) -> Result<IpAddr> {
+    let network = match docker.inspect_network(name, None::<InspectNetworkOptions<String>>).await {
+        Ok(network) => network,
+        Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => {
+            info!("Creating internal egress network {}", name);
+            docker.create_network(CreateNetworkOptions {
+                name,
+                driver: "bridge",
+                internal: true,
+                check_duplicate: true,
+                ..Default::default()
+            }).await?;
+            docker.inspect_network(name, None::<InspectNetworkOptions<String>>).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if network.internal != Some(true) {
+        return Err(anyhow!("Network {} exists but is not internal", name));
+    }
+
+    network
+        .ipam
+        .and_then(|ipam| ipam.config)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|config| config.gateway)
+        .find_map(|gateway| gateway.parse::<IpAddr>().ok())
+        .ok_or_else(|| anyhow!("Network {} has no gateway address", name))
+}
+
+/// Address of a container on the given network
+pub async fn container_ip(docker: &Docker, container_id: &str, network: &str) -> Result<IpAddr> {
+    let details = docker.inspect_container(container_id, None).await?;
+
+    details
+        .network_settings
+        .and_then(|settings| settings.networks)
+        .and_then(|mut networks| networks.remove(network))
+        .and_then(|endpoint| endpoint.ip_address)
+        .filter(|ip| !ip.is_empty())
+        .and_then(|ip| ip.parse().ok())
+        .ok_or_else(|| anyhow!("Container {} has no address on network {}", container_id, network))
+}
@@ -0,0 +1,140 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! OCI runtime selection (runc, gVisor, Kata) per session.
+
+use crate::config::{RuntimeClass, RuntimePolicy};
+use anyhow::Result;
+use bollard::Docker;
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Runtime chosen for a session after checking the daemon
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedRuntime {
+    /// Runtime class asked for by config or template
+    pub requested: String,
+    /// Runtime class actually used
+    pub runtime: String,
+    /// Name passed to Docker in `HostConfig.runtime`
+    pub daemon_name: String,
+    /// True when the requested runtime was unavailable and runc was used instead
+    pub fell_back: bool,
+}
+
+/// List the runtimes registered with the Docker daemon
+pub async fn registered_runtimes(docker: &Docker) -> Result<Vec<String>> {
+    let info = docker.info().await?;
+    let mut names: Vec<String> = info
+        .runtimes
+        .map(|runtimes| runtimes.into_keys().collect())
+        .unwrap_or_default();
+
+    // Older daemons omit runc from the runtime list even though it is always present
+    if !names.iter().any(|n| n == "runc") {
+        names.push("runc".to_string());
+    }
+
+    Ok(names)
+}
+
+/// Pick a runtime from the registered list according to policy
+pub fn select(
+    requested: RuntimeClass,
+    policy: RuntimePolicy,
+    registered: &[String],
+) -> Result<ResolvedRuntime> {
+    if let Some(name) = requested
+        .daemon_names()
+        .iter()
+        .find(|name| registered.iter().any(|r| r == *name))
+    {
+        return Ok(ResolvedRuntime {
+            requested: requested.to_string(),
+            runtime: requested.to_string(),
+            daemon_name: name.to_string(),
+            fell_back: false,
+        });
+    }
+
+    match policy {
+        RuntimePolicy::Fallback => {
+            warn!(
+                "Runtime {} is not registered with Docker (available: {:?}), falling back to runc",
+                requested, registered
+            );
+            Ok(ResolvedRuntime {
+                requested: requested.to_string(),
+                runtime: RuntimeClass::Runc.to_string(),
+                daemon_name: "runc".to_string(),
+                fell_back: true,
+            })
+        }
+        RuntimePolicy::Refuse => Err(anyhow::anyhow!(
+            "Container runtime '{}' is not registered with the Docker daemon (available: {})",
+            requested,
+            registered.join(", ")
+        )),
+    }
+}
+
+/// Verify the requested runtime against the daemon and resolve it
+pub async fn resolve(
+    docker: &Docker,
+    requested: RuntimeClass,
+    policy: RuntimePolicy,
+) -> Result<ResolvedRuntime> {
+    // runc never needs a daemon round-trip
+    if requested == RuntimeClass::Runc {
+        return select(requested, policy, &["runc".to_string()]);
+    }
+
+    let registered = registered_runtimes(docker).await?;
+    let resolved = select(requested, policy, &registered)?;
+    info!("Using container runtime {} ({})", resolved.runtime, resolved.daemon_name);
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_registered_runtime() {
+        let resolved = select(
+            RuntimeClass::Runsc,
+            RuntimePolicy::Refuse,
+            &registered(&["runc", "runsc"]),
+        )
+        .unwrap();
+        assert_eq!(resolved.daemon_name, "runsc");
+        assert!(!resolved.fell_back);
+    }
+
+    #[test]
+    fn test_select_kata_alias() {
+        let resolved = select(
+            RuntimeClass::Kata,
+            RuntimePolicy::Refuse,
+            &registered(&["runc", "kata-runtime"]),
+        )
+        .unwrap();
+        assert_eq!(resolved.runtime, "kata");
+        assert_eq!(resolved.daemon_name, "kata-runtime");
+    }
+
+    #[test]
+    fn test_select_fallback_and_refuse() {
+        let available = registered(&["runc"]);
+
+        let resolved = select(RuntimeClass::Runsc, RuntimePolicy::Fallback, &available).unwrap();
+        assert_eq!(resolved.daemon_name, "runc");
+        assert!(resolved.fell_back);
+
+        assert!(select(RuntimeClass::Runsc, RuntimePolicy::Refuse, &available).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Disk quotas for session containers: writable-layer size and tmpfs caps.
+
+use std::collections::HashMap;
+
+/// Mount points backed by a size-capped tmpfs
+const TMPFS_MOUNTS: &[&str] = &["/tmp", "/var/tmp"];
+
+/// Whether a storage driver honours `--storage-opt size=`
+///
+/// overlay2 only supports it on an xfs backing filesystem mounted with `pquota`;
+/// the mount option is not reported, so xfs is treated as supported and creation
+/// is retried without the option if the daemon rejects it.
+pub fn supports_size_opt(driver: &str, backing_fs: Option<&str>) -> bool {
+    match driver {
+        "overlay2" | "overlay" => backing_fs.is_some_and(|fs| fs.eq_ignore_ascii_case("xfs")),
+        "btrfs" | "zfs" | "devicemapper" | "windowsfilter" => true,
+        _ => false,
+    }
+}
+
+/// `HostConfig.storage_opt` capping the container's writable layer
+pub fn storage_opt(disk_mb: i64) -> HashMap<String, String> {
+    HashMap::from([("size".to_string(), format!("{}M", disk_mb))])
+}
+
+/// `HostConfig.tmpfs` mounts, each capped at `tmpfs_mb`
+pub fn tmpfs(tmpfs_mb: i64) -> HashMap<String, String> {
+    TMPFS_MOUNTS
+        .iter()
+        .map(|mount| {
+            (
+                mount.to_string(),
+                format!("rw,nosuid,nodev,size={}m", tmpfs_mb),
+            )
+        })
+        .collect()
+}
+
+/// True when container creation failed because the driver rejected `storage_opt`
+pub fn is_storage_opt_rejected(err: &bollard::errors::Error) -> bool {
+    let message = err.to_string().to_lowercase();
+    message.contains("storage-opt") || message.contains("storage opt")
+}
+
+/// Disk usage state relative to the session quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaState {
+    Within,
+    Near,
+    Exceeded,
+}
+
+/// Compare writable-layer usage against a quota
+pub fn check_quota(size_rw_bytes: i64, quota_mb: i64, warn_percent: u8) -> QuotaState {
+    let quota_bytes = quota_mb.saturating_mul(1024 * 1024);
+    if quota_bytes <= 0 {
+        return QuotaState::Within;
+    }
+
+    if size_rw_bytes > quota_bytes {
+        QuotaState::Exceeded
+    } else if size_rw_bytes.saturating_mul(100) >= quota_bytes.saturating_mul(warn_percent as i64) {
+        QuotaState::Near
+    } else {
+        QuotaState::Within
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports_size_opt() {
+        assert!(supports_size_opt("overlay2", Some("xfs")));
+        assert!(!supports_size_opt("overlay2", Some("extfs")));
+        assert!(!supports_size_opt("overlay2", None));
+        assert!(supports_size_opt("btrfs", None));
+        assert!(!supports_size_opt("vfs", None));
+    }
+
+    #[test]
+    fn test_tmpfs_mounts() {
+        let mounts = tmpfs(256);
+        assert_eq!(mounts.get("/tmp").unwrap(), "rw,nosuid,nodev,size=256m");
+        assert!(mounts.contains_key("/var/tmp"));
+    }
+
+    #[test]
+    fn test_check_quota() {
+        let mb = 1024 * 1024;
+        assert_eq!(check_quota(100 * mb, 1024, 90), QuotaState::Within);
+        assert_eq!(check_quota(950 * mb, 1024, 90), QuotaState::Near);
+        assert_eq!(check_quota(1100 * mb, 1024, 90), QuotaState::Exceeded);
+        assert_eq!(check_quota(1100 * mb, 0, 90), QuotaState::Within);
+    }
+}
@@ -0,0 +1,218 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Transparent proxying for privacy sessions.
+//!
+//! A netguard sidecar joins the session's network and PID namespaces, redirects
+//! every outgoing TCP connection through redsocks to the egress proxy and drops
+//! everything else. Sharing the PID namespace means the sidecar dies with the
+//! session container, and the session itself never holds NET_ADMIN.
+
+use super::exec;
+use crate::egress::dns::DNS_PORT;
+use crate::egress::EgressProxy;
+use anyhow::{anyhow, Result};
+use bollard::container::{Config, CreateContainerOptions, StartContainerOptions};
+use bollard::image::CreateImageOptions;
+use bollard::models::HostConfig;
+use bollard::Docker;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Port redsocks listens on inside the session's network namespace
+pub const REDSOCKS_PORT: u16 = 12345;
+
+/// Marker the sidecar writes once its firewall rules are in place
+const SIDECAR_READY: &str = "/run/netguard.ready";
+
+/// Marker the session's startup command waits for before running setup
+pub const NETWORK_READY_MARKER: &str = "/tmp/.noxterm-net-ready";
+
+/// redsocks configuration forwarding redirected connections to a SOCKS5 proxy
+pub fn redsocks_config(proxy: SocketAddr) -> String {
+    format!(
+        "base {{\n  log_debug = off;\n  log_info = on;\n  log = stderr;\n  daemon = off;\n  redirector = iptables;\n}}\n\
+         redsocks {{\n  local_ip = 127.0.0.1;\n  local_port = {};\n  ip = {};\n  port = {};\n  type = socks5;\n}}\n",
+        REDSOCKS_PORT,
+        proxy.ip(),
+        proxy.port(),
+    )
+}
+
+/// Firewall rules: TCP is redirected to redsocks, only redsocks may reach the
+/// proxy, DNS may only go to the anonymous resolver, and all other traffic
+/// (UDP, ICMP, IPv6) is dropped
+pub fn firewall_rules(proxy: SocketAddr, resolver: IpAddr) -> Vec<String> {
+    let (ip, port) = (proxy.ip(), proxy.port());
+    vec![
+        "iptables -t nat -N NOXTERM".to_string(),
+        "iptables -t nat -A NOXTERM -d 127.0.0.0/8 -j RETURN".to_string(),
+        format!("iptables -t nat -A NOXTERM -p tcp -d {} --dport {} -j RETURN", ip, port),
+        format!("iptables -t nat -A NOXTERM -p tcp -j REDIRECT --to-ports {}", REDSOCKS_PORT),
+        "iptables -t nat -A OUTPUT -p tcp -j NOXTERM".to_string(),
+        "iptables -A OUTPUT -o lo -j ACCEPT".to_string(),
+        format!("iptables -A OUTPUT -p tcp -d {} --dport {} -j ACCEPT", ip, port),
+        format!("iptables -A OUTPUT -p udp -d {} --dport {} -j ACCEPT", resolver, DNS_PORT),
+        "iptables -A OUTPUT -j DROP".to_string(),
+        "ip6tables -A OUTPUT -o lo -j ACCEPT".to_string(),
+        "ip6tables -A OUTPUT -j DROP".to_string(),
+    ]
+}
+
+/// Sidecar entrypoint: write the config, apply rules, then run redsocks
+pub fn sidecar_script(proxy: SocketAddr, resolver: IpAddr) -> String {
+    format!(
+        "set -e\ncat > /etc/redsocks.conf <<'CONF'\n{}CONF\n{}\ntouch {}\nexec redsocks -c /etc/redsocks.conf\n",
+        redsocks_config(proxy),
+        firewall_rules(proxy, resolver).join("\n"),
+        SIDECAR_READY,
+    )
+}
+
+/// Name of the sidecar belonging to a session container
+pub fn sidecar_name(container_name: &str) -> String {
+    format!("{}-netguard", container_name)
+}
+
+/// Start the netguard sidecar for a running session container, labelled
+/// with the session's owner labels
+pub async fn start_sidecar(
+    docker: &Docker,
+    container_id: &str,
+    container_name: &str,
+    image: &str,
+    labels: HashMap<String, String>,
+    proxy: SocketAddr,
+    resolver: IpAddr,
+) -> Result<String> {
+    ensure_image(docker, image).await?;
+
+    let name = sidecar_name(container_name);
+    let config = Config {
+        image: Some(image.to_string()),
+        entrypoint: Some(vec!["/bin/sh".to_string(), "-c".to_string()]),
+        cmd: Some(vec![sidecar_script(proxy, resolver)]),
+        labels: Some(labels),
+        host_config: Some(HostConfig {
+            auto_remove: Some(true),
+            network_mode: Some(format!("container:{}", container_id)),
+            pid_mode: Some(format!("container:{}", container_id)),
+            cap_drop: Some(vec!["ALL".to_string()]),
+            cap_add: Some(vec!["NET_ADMIN".to_string(), "NET_RAW".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let sidecar_id = docker
+        .create_container(Some(CreateContainerOptions { name: name.as_str(), platform: None }), config)
+        .await?
+        .id;
+    docker.start_container(&sidecar_id, None::<StartContainerOptions<String>>).await?;
+
+    for _ in 0..50 {
+        let ready = exec::run(docker, &sidecar_id, &format!("test -f {}", SIDECAR_READY), Duration::from_secs(5)).await;
+        if ready.is_ok_and(|out| out.success()) {
+            info!("Netguard sidecar {} ready", name);
+            return Ok(sidecar_id);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    let _ = docker.stop_container(&sidecar_id, None).await;
+    Err(anyhow!("Netguard sidecar {} did not apply its firewall rules", name))
+}
+
+async fn ensure_image(docker: &Docker, image: &str) -> Result<()> {
+    if docker.inspect_image(image).await.is_ok() {
+        return Ok(());
+    }
+
+    info!("Pulling netguard image {}", image);
+    let mut stream = docker.create_image(
+        Some(CreateImageOptions { from_image: image, ..Default::default() }),
+        None,
+        None,
+    );
+    while let Some(result) = stream.next().await {
+        result.map_err(|e| anyhow!(
+            "Netguard image {} unavailable ({}); build it from nox-backend/docker/netguard",
+            image, e
+        ))?;
+    }
+    Ok(())
+}
+
+/// Confirm that a session cannot reach the network except through the proxy
+///
+/// UDP must be refused locally (except DNS to the resolver), and a TCP connection to a documentation-range
+/// address must show up at the egress proxy as coming from this session.
+pub async fn leak_check(
+    docker: &Docker,
+    container_id: &str,
+    session_id: Uuid,
+    egress: &EgressProxy,
+) -> Result<()> {
+    let udp = exec::run(
+        docker,
+        container_id,
+        "bash -c 'echo probe > /dev/udp/192.0.2.1/53' 2>/dev/null && echo udp-sent",
+        Duration::from_secs(10),
+    ).await?;
+    if udp.output.contains("udp-sent") {
+        return Err(anyhow!("Leak check failed: UDP egress is not blocked"));
+    }
+
+    let probe = SocketAddr::from((Ipv4Addr::new(192, 0, 2, rand_octet()), 9));
+    let observed = egress.expect_probe(probe).await;
+    let _ = exec::run(
+        docker,
+        container_id,
+        &format!("timeout 5 bash -c '</dev/tcp/{}/{}' 2>/dev/null", probe.ip(), probe.port()),
+        Duration::from_secs(10),
+    ).await;
+
+    match tokio::time::timeout(Duration::from_secs(5), observed).await {
+        Ok(Ok(seen)) if seen == session_id => Ok(()),
+        Ok(Ok(seen)) => Err(anyhow!("Leak check failed: probe arrived from session {}", seen)),
+        _ => {
+            egress.cancel_probe(probe).await;
+            warn!("TCP probe from session {} never reached the egress proxy", session_id);
+            Err(anyhow!("Leak check failed: TCP traffic is not routed through the proxy"))
+        }
+    }
+}
+
+fn rand_octet() -> u8 {
+    // Spread concurrent probes over TEST-NET-1 (1..=254)
+    (Uuid::new_v4().as_bytes()[0] % 254) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firewall_rules_only_allow_proxy() {
+        let proxy: SocketAddr = "172.30.0.1:1080".parse().unwrap();
+        let rules = firewall_rules(proxy, proxy.ip());
+
+        assert!(rules.contains(&"iptables -A OUTPUT -p tcp -d 172.30.0.1 --dport 1080 -j ACCEPT".to_string()));
+        assert!(rules.contains(&"iptables -A OUTPUT -p udp -d 172.30.0.1 --dport 53 -j ACCEPT".to_string()));
+        assert_eq!(rules.iter().filter(|r| r.ends_with("-j DROP")).count(), 2);
+        // Drop rules must come last so nothing slips past them
+        assert!(rules.iter().position(|r| r == "iptables -A OUTPUT -j DROP").unwrap() > 5);
+    }
+
+    #[test]
+    fn test_sidecar_script_runs_redsocks_last() {
+        let script = sidecar_script("172.30.0.1:1080".parse().unwrap(), "172.30.0.1".parse().unwrap());
+        assert!(script.contains("ip = 172.30.0.1;"));
+        assert!(script.contains(&format!("local_port = {};", REDSOCKS_PORT)));
+        assert!(script.trim_end().ends_with("exec redsocks -c /etc/redsocks.conf"));
+    }
+}
@@ -17,12 +17,15 @@ pub enum EventType {
     SessionConnected,
     SessionDisconnected,
     SessionTerminated,
+    SessionResized,
     ContainerStarted,
     ContainerStopped,
     CommandExecuted,
     SecurityViolation,
     RateLimitExceeded,
     AuthAttempt,
+    PrivacyServiceExited,
+    PrivacyServiceRestarted,
 }
 
 impl std::fmt::Display for EventType {
@@ -32,12 +35,15 @@ impl std::fmt::Display for EventType {
             EventType::SessionConnected => write!(f, "session_connected"),
             EventType::SessionDisconnected => write!(f, "session_disconnected"),
             EventType::SessionTerminated => write!(f, "session_terminated"),
+            EventType::SessionResized => write!(f, "session_resized"),
             EventType::ContainerStarted => write!(f, "container_started"),
             EventType::ContainerStopped => write!(f, "container_stopped"),
             EventType::CommandExecuted => write!(f, "command_executed"),
             EventType::SecurityViolation => write!(f, "security_violation"),
             EventType::RateLimitExceeded => write!(f, "rate_limit_exceeded"),
             EventType::AuthAttempt => write!(f, "auth_attempt"),
+            EventType::PrivacyServiceExited => write!(f, "privacy_service_exited"),
+            EventType::PrivacyServiceRestarted => write!(f, "privacy_service_restarted"),
         }
     }
 }
@@ -5,23 +5,26 @@
 use super::pool::DbPool;
 use tracing::info;
 
-pub async fn run_all(pool: &DbPool) -> Result<CleanupStats, sqlx::Error> {
+/// `history_retention_days` applies to users who have not set their own
+pub async fn run_all(pool: &DbPool, history_retention_days: u32) -> Result<CleanupStats, sqlx::Error> {
     let expired_sessions = cleanup_expired_sessions(pool).await?;
     let old_rate_limits = cleanup_old_rate_limits(pool).await?;
     let old_metrics = cleanup_old_metrics(pool).await?;
     let old_audit_logs = cleanup_old_audit_logs(pool).await?;
+    let old_history = cleanup_old_history(pool, history_retention_days).await?;
 
     let stats = CleanupStats {
         expired_sessions,
         old_rate_limits,
         old_metrics,
         old_audit_logs,
+        old_history,
     };
 
     if stats.total() > 0 {
         info!(
-            "Cleanup completed: {} expired sessions, {} rate limits, {} metrics, {} audit logs",
-            expired_sessions, old_rate_limits, old_metrics, old_audit_logs
+            "Cleanup completed: {} expired sessions, {} rate limits, {} metrics, {} audit logs, {} history entries",
+            expired_sessions, old_rate_limits, old_metrics, old_audit_logs, old_history
         );
     }
 
@@ -83,16 +86,34 @@ async fn cleanup_old_audit_logs(pool: &DbPool) -> Result<i64, sqlx::Error> {
     Ok(result.rows_affected() as i64)
 }
 
+async fn cleanup_old_history(pool: &DbPool, default_days: u32) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM command_history h
+        WHERE h.executed_at < NOW() - make_interval(days => COALESCE(
+            (SELECT p.history_retention_days FROM user_preferences p WHERE p.user_id = h.user_id),
+            $1
+        ))
+        "#,
+    )
+    .bind(default_days as i32)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() as i64)
+}
+
 #[derive(Debug, Clone)]
 pub struct CleanupStats {
     pub expired_sessions: i64,
     pub old_rate_limits: i64,
     pub old_metrics: i64,
     pub old_audit_logs: i64,
+    pub old_history: i64,
 }
 
 impl CleanupStats {
     pub fn total(&self) -> i64 {
-        self.expired_sessions + self.old_rate_limits + self.old_metrics + self.old_audit_logs
+        self.expired_sessions + self.old_rate_limits + self.old_metrics + self.old_audit_logs + self.old_history
     }
 }
@@ -0,0 +1,36 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! User keys. A user ID belongs to whoever first created a session or job
+//! with it; only the SHA-256 of the key handed out then is stored.
+
+use super::pool::DbPool;
+use tracing::debug;
+
+/// SHA-256 of the key claiming this user ID, if it has been claimed
+pub async fn get_user_key(pool: &DbPool, user_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT key_sha256 FROM user_keys WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|(key_sha256,)| key_sha256))
+}
+
+/// Claim an unclaimed user ID; false if someone else already has
+pub async fn claim_user(pool: &DbPool, user_id: &str, key_sha256: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO user_keys (user_id, key_sha256)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(key_sha256)
+    .execute(pool)
+    .await?;
+
+    debug!("Claim of user {}: {}", user_id, result.rows_affected() == 1);
+    Ok(result.rows_affected() == 1)
+}
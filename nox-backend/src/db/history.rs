@@ -0,0 +1,180 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Command history: what each user ran, across sessions, and how it ended.
+
+use super::pool::DbPool;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Where a recorded command came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Exec,
+    /// A line typed into the terminal, rebuilt from keystrokes
+    Pty,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Exec => write!(f, "exec"),
+            Source::Pty => write!(f, "pty"),
+        }
+    }
+}
+
+/// A command to record
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    pub session_id: Uuid,
+    pub user_id: &'a str,
+    pub command: &'a str,
+    pub source: Source,
+    pub exit_code: Option<i64>,
+    pub timed_out: bool,
+    pub duration_ms: Option<u64>,
+}
+
+/// A recorded command
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub session_id: Option<Uuid>,
+    pub command: String,
+    pub source: String,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration_ms: Option<i64>,
+    pub executed_at: DateTime<Utc>,
+}
+
+pub async fn record(pool: &DbPool, entry: &Entry<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO command_history
+        (session_id, user_id, command, source, exit_code, timed_out, duration_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(entry.session_id)
+    .bind(entry.user_id)
+    .bind(entry.command)
+    .bind(entry.source.to_string())
+    .bind(entry.exit_code.map(|code| code as i32))
+    .bind(entry.timed_out)
+    .bind(entry.duration_ms.map(|ms| ms as i64))
+    .execute(pool)
+    .await?;
+
+    debug!("Recorded {} command for session {}", entry.source, entry.session_id);
+    Ok(())
+}
+
+/// A user's commands containing `query`, newest first
+pub async fn search(
+    pool: &DbPool,
+    user_id: &str,
+    query: Option<&str>,
+    limit: i64,
+) -> Result<Vec<HistoryEntry>, sqlx::Error> {
+    let pattern = query.map(|q| format!("%{}%", escape_like(q)));
+    sqlx::query_as::<_, HistoryEntry>(
+        r#"
+        SELECT id, session_id, command, source, exit_code, timed_out, duration_ms, executed_at
+        FROM command_history
+        WHERE user_id = $1
+        AND ($2::TEXT IS NULL OR command ILIKE $2)
+        ORDER BY executed_at DESC, id DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// A user's most recent commands, oldest first, for seeding a shell's history
+pub async fn recent_commands(pool: &DbPool, user_id: &str, limit: i64) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT command FROM (
+            SELECT command, executed_at, id
+            FROM command_history
+            WHERE user_id = $1
+            ORDER BY executed_at DESC, id DESC
+            LIMIT $2
+        ) recent
+        ORDER BY executed_at, id
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|(command,)| command).collect())
+}
+
+/// Forget everything a user has run
+pub async fn clear(pool: &DbPool, user_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM command_history WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    debug!("Cleared {} history entries for {}", result.rows_affected(), user_id);
+    Ok(result.rows_affected())
+}
+
+/// Commands as a `~/.bash_history` file. Multi-line commands are left out
+/// since bash would read each of their lines as a separate entry.
+pub fn bash_history(commands: &[String]) -> String {
+    let mut file = String::new();
+    for command in commands.iter().map(|c| c.trim()) {
+        if command.is_empty() || command.contains('\n') {
+            continue;
+        }
+        file.push_str(command);
+        file.push('\n');
+    }
+    file
+}
+
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("git log"), "git log");
+        assert_eq!(escape_like(r"50%_a\b"), r"50\%\_a\\b");
+    }
+
+    #[test]
+    fn test_bash_history_skips_multiline_commands() {
+        let commands = vec![
+            "ls -la".to_string(),
+            "cat <<EOF\nhi\nEOF".to_string(),
+            "  ".to_string(),
+            "make test ".to_string(),
+        ];
+        assert_eq!(bash_history(&commands), "ls -la\nmake test\n");
+    }
+}
@@ -0,0 +1,148 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Batch job records, so status survives the job's container and a restart.
+
+use super::pool::DbPool;
+use crate::jobs::{JobInfo, JobStatus};
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use sqlx::FromRow;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Debug, FromRow)]
+struct JobRow {
+    id: Uuid,
+    session_id: Uuid,
+    user_id: String,
+    image: String,
+    tier: String,
+    status: String,
+    exit_code: Option<i64>,
+    error: Option<String>,
+    artifacts: JsonValue,
+    log_truncated: bool,
+    token_sha256: String,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+impl From<JobRow> for JobInfo {
+    fn from(row: JobRow) -> Self {
+        JobInfo {
+            id: row.id,
+            session_id: row.session_id,
+            user_id: row.user_id,
+            image: row.image,
+            tier: row.tier,
+            // Unknown statuses come from jobs interrupted by a restart
+            status: row.status.parse().unwrap_or(JobStatus::Failed),
+            exit_code: row.exit_code,
+            error: row.error,
+            artifacts: serde_json::from_value(row.artifacts).unwrap_or_default(),
+            log_truncated: row.log_truncated,
+            token_sha256: row.token_sha256,
+            created_at: row.created_at,
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+        }
+    }
+}
+
+/// Insert or update a job
+pub async fn save(pool: &DbPool, job: &JobInfo) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO jobs
+        (id, session_id, user_id, image, tier, status, exit_code, error, artifacts, log_truncated,
+         token_sha256, created_at, started_at, finished_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (id)
+        DO UPDATE SET status = EXCLUDED.status,
+                      exit_code = EXCLUDED.exit_code,
+                      error = EXCLUDED.error,
+                      artifacts = EXCLUDED.artifacts,
+                      log_truncated = EXCLUDED.log_truncated,
+                      started_at = EXCLUDED.started_at,
+                      finished_at = EXCLUDED.finished_at
+        "#,
+    )
+    .bind(job.id)
+    .bind(job.session_id)
+    .bind(&job.user_id)
+    .bind(&job.image)
+    .bind(&job.tier)
+    .bind(job.status.to_string())
+    .bind(job.exit_code)
+    .bind(&job.error)
+    .bind(serde_json::to_value(&job.artifacts).unwrap_or_default())
+    .bind(job.log_truncated)
+    .bind(&job.token_sha256)
+    .bind(job.created_at)
+    .bind(job.started_at)
+    .bind(job.finished_at)
+    .execute(pool)
+    .await?;
+
+    debug!("Saved job {} ({})", job.id, job.status);
+    Ok(())
+}
+
+pub async fn get(pool: &DbPool, id: Uuid) -> Result<Option<JobInfo>, sqlx::Error> {
+    let row = sqlx::query_as::<_, JobRow>("SELECT * FROM jobs WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(JobInfo::from))
+}
+
+pub async fn list_by_user(pool: &DbPool, user_id: &str, limit: i64) -> Result<Vec<JobInfo>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, JobRow>(
+        r#"
+        SELECT * FROM jobs
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(JobInfo::from).collect())
+}
+
+/// Mark jobs left unfinished by a restart as failed; their containers are gone
+pub async fn fail_interrupted(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE jobs
+        SET status = 'failed', error = 'Interrupted by a server restart', finished_at = NOW()
+        WHERE finished_at IS NULL
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Forget jobs that finished more than `hours` ago
+pub async fn delete_finished_before(pool: &DbPool, hours: u64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM jobs
+        WHERE finished_at IS NOT NULL
+        AND finished_at < NOW() - make_interval(hours => $1)
+        "#,
+    )
+    .bind(hours as i32)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -6,8 +6,12 @@
 
 pub mod audit;
 pub mod cleanup;
+pub mod credentials;
+pub mod history;
+pub mod jobs;
 pub mod metrics;
 mod pool;
+pub mod preferences;
 pub mod rate_limits;
 pub mod security;
 pub mod sessions;
@@ -0,0 +1,86 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Per-user defaults for new sessions and command history settings.
+
+use super::pool::DbPool;
+use serde::Serialize;
+use tracing::debug;
+
+/// Whether new sessions for this user default to privacy mode
+pub async fn get_privacy_default(pool: &DbPool, user_id: &str) -> Result<Option<bool>, sqlx::Error> {
+    let row: Option<(bool,)> = sqlx::query_as(
+        "SELECT privacy_default FROM user_preferences WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(enabled,)| enabled))
+}
+
+pub async fn set_privacy_default(pool: &DbPool, user_id: &str, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO user_preferences (user_id, privacy_default, updated_at)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (user_id)
+        DO UPDATE SET privacy_default = EXCLUDED.privacy_default, updated_at = NOW()
+        "#,
+    )
+    .bind(user_id)
+    .bind(enabled)
+    .execute(pool)
+    .await?;
+
+    debug!("Set privacy default for {} to {}", user_id, enabled);
+    Ok(())
+}
+
+/// A user's command history settings
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HistoryPreferences {
+    pub enabled: bool,
+    /// None keeps the server's retention
+    pub retention_days: Option<i32>,
+}
+
+impl Default for HistoryPreferences {
+    fn default() -> Self {
+        Self { enabled: true, retention_days: None }
+    }
+}
+
+pub async fn get_history(pool: &DbPool, user_id: &str) -> Result<HistoryPreferences, sqlx::Error> {
+    let row: Option<(bool, Option<i32>)> = sqlx::query_as(
+        "SELECT history_enabled, history_retention_days FROM user_preferences WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row
+        .map(|(enabled, retention_days)| HistoryPreferences { enabled, retention_days })
+        .unwrap_or_default())
+}
+
+pub async fn set_history(pool: &DbPool, user_id: &str, preferences: HistoryPreferences) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO user_preferences (user_id, history_enabled, history_retention_days, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_id)
+        DO UPDATE SET history_enabled = EXCLUDED.history_enabled,
+                      history_retention_days = EXCLUDED.history_retention_days,
+                      updated_at = NOW()
+        "#,
+    )
+    .bind(user_id)
+    .bind(preferences.enabled)
+    .bind(preferences.retention_days)
+    .execute(pool)
+    .await?;
+
+    debug!("Set history preferences for {}: {:?}", user_id, preferences);
+    Ok(())
+}
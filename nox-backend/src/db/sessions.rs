@@ -49,6 +49,20 @@ pub struct ResourceLimits {
     pub memory_mb: i64,
     pub cpu_percent: i64,
     pub pids_limit: i64,
+    /// Writable-layer quota in MB
+    #[serde(default = "default_disk_mb")]
+    pub disk_mb: i64,
+    /// Size cap for each tmpfs mount in MB
+    #[serde(default = "default_tmpfs_mb")]
+    pub tmpfs_mb: i64,
+}
+
+fn default_disk_mb() -> i64 {
+    2048
+}
+
+fn default_tmpfs_mb() -> i64 {
+    256
 }
 
 impl Default for ResourceLimits {
@@ -57,6 +71,8 @@ impl Default for ResourceLimits {
             memory_mb: 512,
             cpu_percent: 50,
             pids_limit: 100,
+            disk_mb: default_disk_mb(),
+            tmpfs_mb: default_tmpfs_mb(),
         }
     }
 }
@@ -198,6 +214,24 @@ pub async fn set_container(
     Ok(())
 }
 
+/// Point a session at a container found after a restart, keeping its status
+pub async fn adopt_container(
+    pool: &DbPool,
+    id: Uuid,
+    container_id: &str,
+    container_name: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sessions SET container_id = $1, container_name = $2 WHERE id = $3")
+        .bind(container_id)
+        .bind(container_name)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    debug!("Adopted container {} for session {}", container_id, id);
+    Ok(())
+}
+
 pub async fn mark_disconnected(
     pool: &DbPool,
     id: Uuid,
@@ -209,7 +243,7 @@ pub async fn mark_disconnected(
         SET status = 'disconnected',
             disconnected_at = NOW(),
             expires_at = NOW() + ($1 || ' seconds')::INTERVAL
-        WHERE id = $2
+        WHERE id = $2 AND status != 'terminated'
         "#,
     )
     .bind(grace_period_secs.to_string())
@@ -224,6 +258,33 @@ pub async fn mark_disconnected(
     Ok(())
 }
 
+pub async fn set_resource_limits(
+    pool: &DbPool,
+    id: Uuid,
+    limits: &ResourceLimits,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sessions SET resource_limits = $1 WHERE id = $2")
+        .bind(serde_json::to_value(limits).unwrap_or_default())
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    debug!("Updated resource limits for session {}", id);
+    Ok(())
+}
+
+/// Merge keys into the session's metadata document
+pub async fn merge_metadata(pool: &DbPool, id: Uuid, metadata: JsonValue) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sessions SET metadata = COALESCE(metadata, '{}'::JSONB) || $1 WHERE id = $2")
+        .bind(metadata)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    debug!("Updated metadata for session {}", id);
+    Ok(())
+}
+
 pub async fn clear_disconnection(pool: &DbPool, id: Uuid) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
@@ -271,6 +332,50 @@ pub async fn get_expired(pool: &DbPool) -> Result<Vec<DbSession>, sqlx::Error> {
     .await
 }
 
+/// Every session of an instance that is not terminated, oldest first.
+/// Sessions from before the instance was recorded count for every instance
+pub async fn list_live(pool: &DbPool, instance_id: &str) -> Result<Vec<DbSession>, sqlx::Error> {
+    sqlx::query_as::<_, DbSession>(
+        r#"
+        SELECT * FROM sessions
+        WHERE status != 'terminated'
+        AND COALESCE(metadata->>'instance_id', $1) = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(instance_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Running sessions of an instance, newest first. Sessions from before the
+/// instance was recorded count for every instance
+pub async fn list_running(pool: &DbPool, instance_id: &str, limit: i64) -> Result<Vec<DbSession>, sqlx::Error> {
+    sqlx::query_as::<_, DbSession>(
+        r#"
+        SELECT * FROM sessions
+        WHERE status = 'running'
+        AND COALESCE(metadata->>'instance_id', $1) = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(instance_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// IDs of every session of an instance that is not terminated
+pub async fn live_ids(pool: &DbPool, instance_id: &str) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT id FROM sessions WHERE status != 'terminated' AND COALESCE(metadata->>'instance_id', $1) = $1",
+    )
+    .bind(instance_id)
+    .fetch_all(pool)
+    .await
+}
+
 pub async fn touch(pool: &DbPool, id: Uuid) -> Result<(), sqlx::Error> {
     sqlx::query("UPDATE sessions SET last_activity = NOW() WHERE id = $1")
         .bind(id)
@@ -362,5 +467,17 @@ mod tests {
         let limits = ResourceLimits::default();
         assert_eq!(limits.memory_mb, 512);
         assert_eq!(limits.pids_limit, 100);
+        assert_eq!(limits.disk_mb, 2048);
+    }
+
+    #[test]
+    fn test_resource_limits_legacy_json() {
+        let limits: ResourceLimits = serde_json::from_value(serde_json::json!({
+            "memory_mb": 1024, "cpu_percent": 100, "pids_limit": 200
+        }))
+        .unwrap();
+        assert_eq!(limits.memory_mb, 1024);
+        assert_eq!(limits.disk_mb, 2048);
+        assert_eq!(limits.tmpfs_mb, 256);
     }
 }
@@ -0,0 +1,282 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Domain and CIDR allowlists for session egress.
+
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Ranges only the host should reach: unspecified, loopback, private and
+/// link-local (which holds cloud metadata services)
+const HOST_ONLY_V4: &[(u32, u8)] = &[
+    (0x0000_0000, 8),
+    (0x7f00_0000, 8),
+    (0x0a00_0000, 8),
+    (0xac10_0000, 12),
+    (0xc0a8_0000, 16),
+    (0xa9fe_0000, 16),
+];
+
+/// `::` and `::1`, unique local, link-local and IPv4-mapped addresses
+const HOST_ONLY_V6: &[(u128, u8)] = &[
+    (0, 127),
+    (0xfc00 << 112, 7),
+    (0xfe80 << 112, 10),
+    (0xffff << 32, 96),
+];
+
+/// Whether `ip` is off the host and its private networks, so a session may
+/// be connected to it
+pub fn is_public(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        ip => ip,
+    };
+    !reaches_host(ip, if ip.is_ipv4() { 32 } else { 128 })
+}
+
+/// Whether a network overlaps any host-only range
+fn reaches_host(network: IpAddr, prefix: u8) -> bool {
+    match network {
+        IpAddr::V4(net) => HOST_ONLY_V4
+            .iter()
+            .any(|&(range, bits)| prefix_matches(range as u128, u32::from(net) as u128, prefix.min(bits), 32)),
+        IpAddr::V6(net) => HOST_ONLY_V6
+            .iter()
+            .any(|&(range, bits)| prefix_matches(range, u128::from(net), prefix.min(bits), 128)),
+    }
+}
+
+/// A single allowlist entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// `example.com` matches the domain and all of its subdomains
+    Domain(String),
+    /// `*.example.com` matches subdomains only
+    Subdomains(String),
+    /// `203.0.113.0/24`, `2001:db8::/32` or a bare public address
+    Cidr { network: IpAddr, prefix: u8 },
+}
+
+impl Rule {
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let entry = entry.trim().trim_end_matches('.').to_lowercase();
+        if entry.is_empty() {
+            return Err("Empty allowlist entry".to_string());
+        }
+
+        if let Some((addr, prefix)) = entry.split_once('/') {
+            let network: IpAddr = addr
+                .parse()
+                .map_err(|_| format!("Invalid network address in '{}'", entry))?;
+            let prefix: u8 = prefix
+                .parse()
+                .map_err(|_| format!("Invalid prefix length in '{}'", entry))?;
+            let max = if network.is_ipv4() { 32 } else { 128 };
+            if prefix > max {
+                return Err(format!("Prefix length {} too long in '{}'", prefix, entry));
+            }
+            return Rule::cidr(network, prefix, &entry);
+        }
+
+        if let Ok(network) = entry.parse::<IpAddr>() {
+            let prefix = if network.is_ipv4() { 32 } else { 128 };
+            return Rule::cidr(network, prefix, &entry);
+        }
+
+        let (domain, rule): (&str, fn(String) -> Rule) = match entry.strip_prefix("*.") {
+            Some(rest) => (rest, Rule::Subdomains),
+            None => (entry.as_str(), Rule::Domain),
+        };
+
+        let valid = domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+        if !valid {
+            return Err(format!("Invalid domain '{}'", entry));
+        }
+        if domain == "localhost" || domain.ends_with(".localhost") {
+            return Err(host_only(&entry));
+        }
+
+        Ok(rule(domain.to_string()))
+    }
+
+    fn cidr(network: IpAddr, prefix: u8, entry: &str) -> Result<Self, String> {
+        if reaches_host(network, prefix) {
+            return Err(host_only(entry));
+        }
+        Ok(Rule::Cidr { network, prefix })
+    }
+
+    fn matches_domain(&self, host: &str) -> bool {
+        match self {
+            Rule::Domain(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+            Rule::Subdomains(domain) => host.ends_with(&format!(".{}", domain)),
+            Rule::Cidr { .. } => false,
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        let Rule::Cidr { network, prefix } = self else {
+            return false;
+        };
+
+        match (network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(*net) as u128, u32::from(ip) as u128, *prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(*net), u128::from(ip), *prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn host_only(entry: &str) -> String {
+    format!(
+        "'{}' reaches the host or a private network (loopback, link-local, private or unique local addresses); \
+         allowlists may only name public destinations",
+        entry
+    )
+}
+
+fn prefix_matches(network: u128, ip: u128, prefix: u8, bits: u32) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix as u32;
+    (network >> shift) == (ip >> shift)
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Domain(domain) => write!(f, "{}", domain),
+            Rule::Subdomains(domain) => write!(f, "*.{}", domain),
+            Rule::Cidr { network, prefix } => write!(f, "{}/{}", network, prefix),
+        }
+    }
+}
+
+impl Serialize for Rule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Destinations a session may connect to; empty means unrestricted
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Allowlist {
+    rules: Vec<Rule>,
+}
+
+impl Allowlist {
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, String> {
+        let rules = entries
+            .iter()
+            .map(|e| Rule::parse(e.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn has_cidr_rules(&self) -> bool {
+        self.rules.iter().any(|r| matches!(r, Rule::Cidr { .. }))
+    }
+
+    pub fn allows_domain(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.is_empty() || self.rules.iter().any(|r| r.matches_domain(&host))
+    }
+
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        self.is_empty() || self.rules.iter().any(|r| r.matches_ip(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(Rule::parse("Example.COM").unwrap(), Rule::Domain("example.com".to_string()));
+        assert_eq!(
+            Rule::parse("*.example.com").unwrap(),
+            Rule::Subdomains("example.com".to_string())
+        );
+        assert!(matches!(Rule::parse("100.0.0.0/8").unwrap(), Rule::Cidr { prefix: 8, .. }));
+        assert!(matches!(Rule::parse("1.2.3.4").unwrap(), Rule::Cidr { prefix: 32, .. }));
+        assert!(Rule::parse("10.0.0.0/33").is_err());
+        assert!(Rule::parse("bad domain").is_err());
+        assert!(Rule::parse("").is_err());
+    }
+
+    #[test]
+    fn test_host_only_rules_are_rejected() {
+        for entry in [
+            "127.0.0.1/32",
+            "127.0.0.1",
+            "localhost",
+            "*.localhost",
+            "169.254.169.254",
+            "10.0.0.0/8",
+            "172.20.0.0/16",
+            "192.168.1.0/24",
+            "0.0.0.0/0",
+            "96.0.0.0/3",
+            "::1",
+            "::/0",
+            "fd00::/8",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(Rule::parse(entry).is_err(), "entry: {}", entry);
+        }
+        assert!(Rule::parse("172.32.0.0/16").is_ok());
+        assert!(Rule::parse("2001:db8::/32").is_ok());
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(is_public("1.1.1.1".parse().unwrap()));
+        assert!(is_public("2606:4700::1111".parse().unwrap()));
+        assert!(is_public("::ffff:1.1.1.1".parse().unwrap()));
+        for ip in ["127.0.0.1", "0.0.0.0", "10.1.2.3", "172.31.255.255", "192.168.0.1", "169.254.169.254", "::", "::1", "fc00::1", "fe80::1", "::ffff:10.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "ip: {}", ip);
+        }
+    }
+
+    #[test]
+    fn test_domain_matching() {
+        let list = Allowlist::parse(&["github.com", "*.pypi.org"]).unwrap();
+        assert!(list.allows_domain("github.com"));
+        assert!(list.allows_domain("api.github.com"));
+        assert!(!list.allows_domain("evilgithub.com"));
+        assert!(list.allows_domain("files.pypi.org"));
+        assert!(!list.allows_domain("pypi.org"));
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let list = Allowlist::parse(&["198.51.100.0/24", "2001:db8::/32"]).unwrap();
+        assert!(list.allows_ip("198.51.100.20".parse().unwrap()));
+        assert!(!list.allows_ip("203.0.113.1".parse().unwrap()));
+        assert!(list.allows_ip("2001:db8::1".parse().unwrap()));
+        assert!(!list.allows_ip("2001:db9::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_empty_allowlist_is_unrestricted() {
+        let list = Allowlist::default();
+        assert!(list.allows_domain("anything.example"));
+        assert!(list.allows_ip("8.8.8.8".parse().unwrap()));
+    }
+}
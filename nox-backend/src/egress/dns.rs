@@ -0,0 +1,439 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! DNS resolver for privacy sessions.
+//! Answers A queries on the egress gateway through the privacy backend: Anyone
+//! and Tor resolve with the SOCKS RESOLVE extension, other proxies carry a
+//! DNS-over-TCP query to a configured server. Names never reach a resolver
+//! outside the network.
+
+use super::socks::{self, Credentials, Target};
+use super::{EgressProxy, Upstream};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{oneshot, Mutex};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Docker's `--dns` option only takes an address, so the resolver must use 53
+pub const DNS_PORT: u16 = 53;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+const ANSWER_TTL: u32 = 60;
+
+/// A single-question DNS query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub id: u16,
+    pub recursion_desired: bool,
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+    /// Raw question section, echoed back in the response
+    question: Vec<u8>,
+}
+
+/// Parse a query packet; anything else (responses, multiple questions) is rejected
+pub fn parse_query(packet: &[u8]) -> Option<Query> {
+    if packet.len() < 12 {
+        return None;
+    }
+
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let is_response = packet[2] & 0x80 != 0;
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if is_response || qdcount != 1 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = 12;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers never appear in a well-formed question
+        if len > 63 {
+            return None;
+        }
+        let label = packet.get(pos..pos + len)?;
+        labels.push(String::from_utf8(label.to_vec()).ok()?);
+        pos += len;
+    }
+
+    let fixed = packet.get(pos..pos + 4)?;
+    Some(Query {
+        id,
+        recursion_desired: packet[2] & 0x01 != 0,
+        name: labels.join(".").to_lowercase(),
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        question: packet[12..pos + 4].to_vec(),
+    })
+}
+
+/// Build a response to `query` with the given rcode and A records
+pub fn build_response(query: &Query, rcode: u8, answers: &[Ipv4Addr]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(12 + query.question.len() + answers.len() * 16);
+    packet.extend_from_slice(&query.id.to_be_bytes());
+    // QR=1, opcode 0, RD echoed; RA=1 with the rcode
+    packet.push(0x80 | u8::from(query.recursion_desired));
+    packet.push(0x80 | (rcode & 0x0f));
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0]);
+    packet.extend_from_slice(&query.question);
+
+    for addr in answers {
+        // Name is a pointer to the question at offset 12
+        packet.extend_from_slice(&[0xc0, 0x0c]);
+        packet.extend_from_slice(&TYPE_A.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+        packet.extend_from_slice(&4u16.to_be_bytes());
+        packet.extend_from_slice(&addr.octets());
+    }
+
+    packet
+}
+
+/// Build a recursive A query for `name`; `None` when a label is empty or too long
+pub fn build_query(id: u16, name: &str) -> Option<Vec<u8>> {
+    let mut packet = Vec::with_capacity(18 + name.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    // RD=1, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_A.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+/// First A record in the response to query `id`; NXDOMAIN is `NotFound`
+pub fn parse_answer(packet: &[u8], id: u16) -> io::Result<Option<Ipv4Addr>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "Malformed DNS response");
+    if packet.len() < 12 || u16::from_be_bytes([packet[0], packet[1]]) != id || packet[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    match packet[3] & 0x0f {
+        0 => {}
+        RCODE_NXDOMAIN => return Err(io::Error::new(io::ErrorKind::NotFound, "No such name")),
+        rcode => return Err(io::Error::other(format!("DNS server answered with rcode {}", rcode))),
+    }
+
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    let ancount = u16::from_be_bytes([packet[6], packet[7]]);
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(packet, pos).ok_or_else(malformed)? + 4;
+    }
+    for _ in 0..ancount {
+        pos = skip_name(packet, pos).ok_or_else(malformed)?;
+        let fixed = packet.get(pos..pos + 10).ok_or_else(malformed)?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        pos += 10;
+        let data = packet.get(pos..pos + rdlength).ok_or_else(malformed)?;
+        if rtype == TYPE_A && rdlength == 4 {
+            return Ok(Some(Ipv4Addr::new(data[0], data[1], data[2], data[3])));
+        }
+        pos += rdlength;
+    }
+    Ok(None)
+}
+
+/// Offset just past the name at `pos`, which may end in a compression pointer
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len if len <= 63 => pos += 1 + len as usize,
+            _ => return None,
+        }
+    }
+}
+
+/// Resolve a name with a DNS-over-TCP query to `server`, carried through the
+/// proxy's CONNECT so the query leaves from the proxy's network
+pub async fn resolve_tunnelled(
+    proxy: Upstream,
+    server: SocketAddr,
+    name: &str,
+    auth: Option<&Credentials>,
+) -> io::Result<IpAddr> {
+    if proxy == Upstream::Direct {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "DNS is only tunnelled through a proxy"));
+    }
+    // Random id, so a stale or forged answer is not taken for this one
+    let id = Uuid::new_v4().as_u128() as u16;
+    let query = build_query(id, name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid DNS name"))?;
+
+    let exchange = async {
+        let mut stream = super::open_via(proxy, &Target::Ip(server), auth).await?;
+        stream.write_all(&(query.len() as u16).to_be_bytes()).await?;
+        stream.write_all(&query).await?;
+        let len = stream.read_u16().await? as usize;
+        let mut response = vec![0; len];
+        stream.read_exact(&mut response).await?;
+        Ok::<_, io::Error>(response)
+    };
+    let response = tokio::time::timeout(Duration::from_secs(30), exchange)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Tunnelled DNS query timed out"))??;
+
+    match parse_answer(&response, id)? {
+        Some(addr) => Ok(IpAddr::V4(addr)),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "No A record")),
+    }
+}
+
+/// How names are looked up through the privacy backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    /// Tor's SOCKS RESOLVE extension, spoken by Anyone and Tor
+    SocksResolve(SocketAddr),
+    /// DNS over TCP to `server`, through the proxy's CONNECT
+    Tunnel { proxy: Upstream, server: SocketAddr },
+}
+
+impl Lookup {
+    pub async fn resolve(&self, name: &str, auth: Option<&Credentials>) -> io::Result<IpAddr> {
+        match *self {
+            Lookup::SocksResolve(proxy) => resolve_via(proxy, name, auth).await,
+            Lookup::Tunnel { proxy, server } => resolve_tunnelled(proxy, server, name, auth).await,
+        }
+    }
+}
+
+impl std::fmt::Display for Lookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lookup::SocksResolve(proxy) => write!(f, "SOCKS RESOLVE at {}", proxy),
+            Lookup::Tunnel { proxy, server } => write!(f, "{} through {:?}", server, proxy),
+        }
+    }
+}
+
+/// Resolve a name through a SOCKS5 proxy that supports RESOLVE
+pub async fn resolve_via(proxy: SocketAddr, name: &str, auth: Option<&Credentials>) -> io::Result<IpAddr> {
+    let mut stream = tokio::time::timeout(Duration::from_secs(10), TcpStream::connect(proxy))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SOCKS proxy connect timed out"))??;
+
+    tokio::time::timeout(Duration::from_secs(30), socks::resolve(&mut stream, name, auth))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SOCKS RESOLVE timed out"))?
+}
+
+/// UDP resolver listening on the egress network gateway
+pub struct DnsResolver {
+    lookup: Lookup,
+    /// Source of per-session isolation credentials, keyed by container address
+    egress: Arc<EgressProxy>,
+    listening: Mutex<Option<SocketAddr>>,
+    /// Names used by leak tests, answered with the querying address
+    watches: Mutex<HashMap<String, oneshot::Sender<IpAddr>>>,
+}
+
+impl DnsResolver {
+    pub fn new(lookup: Lookup, egress: Arc<EgressProxy>) -> Self {
+        Self {
+            lookup,
+            egress,
+            listening: Mutex::new(None),
+            watches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn lookup(&self) -> Lookup {
+        self.lookup
+    }
+
+    pub async fn listening_on(&self) -> Option<SocketAddr> {
+        *self.listening.lock().await
+    }
+
+    /// Start answering queries on the gateway if not already running
+    pub async fn ensure_listening(self: &Arc<Self>, gateway: IpAddr) -> io::Result<SocketAddr> {
+        let mut listening = self.listening.lock().await;
+        if let Some(addr) = *listening {
+            return Ok(addr);
+        }
+
+        let socket = Arc::new(UdpSocket::bind((gateway, DNS_PORT)).await.map_err(|e| super::gateway_bind_error(gateway, e))?);
+        let addr = socket.local_addr()?;
+        info!("Anonymous DNS resolver listening on {} (via {})", addr, self.lookup);
+
+        let resolver = self.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = match socket.recv_from(&mut buf).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        warn!("DNS resolver receive failed: {}", e);
+                        continue;
+                    }
+                };
+
+                let Some(query) = parse_query(&buf[..len]) else {
+                    debug!("Ignoring malformed DNS packet from {}", peer);
+                    continue;
+                };
+
+                let resolver = resolver.clone();
+                let socket = socket.clone();
+                tokio::spawn(async move {
+                    let response = resolver.answer(&query, peer.ip()).await;
+                    let _ = socket.send_to(&response, peer).await;
+                });
+            }
+        });
+
+        *listening = Some(addr);
+        Ok(addr)
+    }
+
+    /// Report the address that next asks for `name`
+    pub async fn watch(&self, name: &str) -> oneshot::Receiver<IpAddr> {
+        let (tx, rx) = oneshot::channel();
+        self.watches.lock().await.insert(name.to_lowercase(), tx);
+        rx
+    }
+
+    pub async fn unwatch(&self, name: &str) {
+        self.watches.lock().await.remove(&name.to_lowercase());
+    }
+
+    async fn answer(&self, query: &Query, client: IpAddr) -> Vec<u8> {
+        if let Some(watch) = self.watches.lock().await.remove(&query.name) {
+            let _ = watch.send(client);
+            return build_response(query, RCODE_NXDOMAIN, &[]);
+        }
+
+        match (query.qtype, query.qclass) {
+            (TYPE_A, CLASS_IN) => {}
+            // Lookups only yield IPv4; an empty answer makes clients fall back to A
+            (TYPE_AAAA, CLASS_IN) => return build_response(query, 0, &[]),
+            _ => return build_response(query, RCODE_NOTIMP, &[]),
+        }
+
+        // Lookups share the session's circuits rather than a common one, and
+        // carry the upstream login where the backend needs one
+        let isolation = self.egress.isolation_for(client).await;
+        match self.lookup.resolve(&query.name, isolation.as_ref()).await {
+            Ok(IpAddr::V4(addr)) => build_response(query, 0, &[addr]),
+            Ok(IpAddr::V6(_)) => build_response(query, 0, &[]),
+            Err(e) if matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound) => {
+                debug!("Could not resolve {}: {}", query.name, e);
+                build_response(query, RCODE_NXDOMAIN, &[])
+            }
+            Err(e) => {
+                warn!("DNS resolution via {} failed for {}: {}", self.lookup, query.name, e);
+                build_response(query, RCODE_SERVFAIL, &[])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_packet(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query(&query_packet("Example.COM", TYPE_A)).unwrap();
+        assert_eq!(query.id, 0x1234);
+        assert_eq!(query.name, "example.com");
+        assert_eq!(query.qtype, TYPE_A);
+        assert!(query.recursion_desired);
+
+        assert!(parse_query(&[0u8; 5]).is_none());
+        let mut response = query_packet("example.com", TYPE_A);
+        response[2] |= 0x80;
+        assert!(parse_query(&response).is_none());
+    }
+
+    #[test]
+    fn test_build_response_with_answer() {
+        let packet = query_packet("example.com", TYPE_A);
+        let query = parse_query(&packet).unwrap();
+        let response = build_response(&query, 0, &[Ipv4Addr::new(93, 184, 216, 34)]);
+
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        assert_eq!(response[2] & 0x80, 0x80);
+        assert_eq!(response[3] & 0x0f, 0);
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 1);
+        assert_eq!(&response[response.len() - 4..], &[93, 184, 216, 34]);
+    }
+
+    #[test]
+    fn test_build_response_error_code() {
+        let query = parse_query(&query_packet("missing.example", TYPE_A)).unwrap();
+        let response = build_response(&query, RCODE_NXDOMAIN, &[]);
+        assert_eq!(response[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(response.len(), packet_len("missing.example"));
+    }
+
+    #[test]
+    fn test_parse_answer_to_built_query() {
+        let packet = build_query(0x4242, "example.com.").unwrap();
+        let query = parse_query(&packet).unwrap();
+        assert_eq!(query.name, "example.com");
+
+        let addr = Ipv4Addr::new(93, 184, 216, 34);
+        let response = build_response(&query, 0, &[addr]);
+        assert_eq!(parse_answer(&response, 0x4242).unwrap(), Some(addr));
+        assert_eq!(parse_answer(&build_response(&query, 0, &[]), 0x4242).unwrap(), None);
+
+        let missing = build_response(&query, RCODE_NXDOMAIN, &[]);
+        assert_eq!(parse_answer(&missing, 0x4242).unwrap_err().kind(), io::ErrorKind::NotFound);
+        // Answers to another query are not trusted
+        assert!(parse_answer(&response, 0x4243).is_err());
+        assert!(build_query(1, "bad..name").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tunnelled_lookup_needs_a_proxy() {
+        let server = "9.9.9.9:53".parse().unwrap();
+        let err = resolve_tunnelled(Upstream::Direct, server, "example.com", None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    fn packet_len(name: &str) -> usize {
+        query_packet(name, TYPE_A).len()
+    }
+}
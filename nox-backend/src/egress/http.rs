@@ -0,0 +1,119 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! HTTP CONNECT client for forwarding egress through an upstream HTTP proxy.
+
+use super::socks::{Credentials, Target};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Longest response header accepted from the proxy
+const MAX_RESPONSE_HEADER: usize = 8192;
+
+/// Ask the proxy on `stream` to open a tunnel to `target`
+pub async fn connect<S>(stream: &mut S, target: &Target, auth: Option<&Credentials>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(auth) = auth {
+        let token = base64(format!("{}:{}", auth.username, auth.password).as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing after the header is consumed
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_RESPONSE_HEADER {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Upstream proxy response header too long"));
+        }
+        header.push(stream.read_u8().await?);
+    }
+
+    let status_line = String::from_utf8_lossy(&header);
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed upstream proxy response"))?;
+
+    match status {
+        200..=299 => Ok(()),
+        407 => Err(io::Error::new(io::ErrorKind::PermissionDenied, "Upstream proxy rejected credentials")),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("Upstream proxy replied {} for {}", status, target),
+        )),
+    }
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64(b"a"), "YQ==");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+
+    #[tokio::test]
+    async fn test_connect_sends_credentials_and_accepts_200() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let n = server.read(&mut buf).await.unwrap();
+            server.write_all(b"HTTP/1.1 200 Connection established\r\n\r\ntunnel").await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        });
+
+        let auth = Credentials {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        };
+        connect(&mut client, &Target::Domain("example.com".to_string(), 443), Some(&auth)).await.unwrap();
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+
+        // Tunnel bytes are left for the caller
+        let mut rest = [0u8; 6];
+        client.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"tunnel");
+    }
+
+    #[tokio::test]
+    async fn test_connect_reports_refusal() {
+        let (mut client, mut server) = duplex(1024);
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            let _ = server.read(&mut buf).await;
+            server.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n").await.unwrap();
+        });
+
+        let err = connect(&mut client, &Target::Domain("example.com".to_string(), 443), None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,340 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! NOXTERM Egress Proxy
+//! SOCKS5 proxy that is the only way out of the internal egress network.
+//! Each container address is registered with a policy deciding where its
+//! connections go (directly or through Anyone) and which destinations it may reach.
+
+pub mod allowlist;
+pub mod dns;
+pub mod http;
+pub mod socks;
+
+pub use allowlist::Allowlist;
+
+use socks::Target;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How long a new container may connect before its policy is registered
+const REGISTRATION_GRACE: Duration = Duration::from_secs(5);
+
+/// Where allowed connections are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upstream {
+    /// Connect from the host (open mode with an allowlist)
+    Direct,
+    /// Forward through a SOCKS5 proxy such as the local Anyone client
+    Socks(SocketAddr),
+    /// Forward through an HTTP proxy with CONNECT
+    Http(SocketAddr),
+}
+
+/// Egress rules for one session container
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    pub session_id: Uuid,
+    pub upstream: Upstream,
+    pub allowlist: Allowlist,
+    /// Credentials sent upstream: per-session ones isolate circuits, or the
+    /// upstream proxy's own login
+    pub isolation: Option<socks::Credentials>,
+}
+
+impl EgressPolicy {
+    /// Resolve a target to an address allowed by this policy
+    ///
+    /// Domains are only resolved locally for direct upstreams with CIDR rules;
+    /// with a proxy upstream the name must match a domain rule, so no DNS query
+    /// ever leaves the host outside the proxy.
+    async fn authorize(&self, target: &Target) -> Result<Target, u8> {
+        match target {
+            Target::Ip(addr) => {
+                if self.allowlist.allows_ip(addr.ip()) {
+                    Ok(target.clone())
+                } else {
+                    Err(socks::REPLY_NOT_ALLOWED)
+                }
+            }
+            Target::Domain(host, port) => {
+                if self.allowlist.allows_domain(host) {
+                    return Ok(target.clone());
+                }
+                if self.upstream != Upstream::Direct || !self.allowlist.has_cidr_rules() {
+                    return Err(socks::REPLY_NOT_ALLOWED);
+                }
+
+                let addrs = tokio::net::lookup_host((host.as_str(), *port))
+                    .await
+                    .map_err(|_| socks::REPLY_HOST_UNREACHABLE)?;
+                addrs
+                    .into_iter()
+                    .find(|addr| self.allowlist.allows_ip(addr.ip()))
+                    .map(Target::Ip)
+                    .ok_or(socks::REPLY_NOT_ALLOWED)
+            }
+        }
+    }
+
+    async fn open(&self, target: &Target) -> io::Result<TcpStream> {
+        open_via(self.upstream, target, self.isolation.as_ref()).await
+    }
+}
+
+/// Connect to `target` through an upstream, authenticating with `auth`
+pub(crate) async fn open_via(upstream: Upstream, target: &Target, auth: Option<&socks::Credentials>) -> io::Result<TcpStream> {
+    match upstream {
+        Upstream::Direct => {
+            let addrs: Vec<SocketAddr> = match target {
+                Target::Ip(addr) => vec![*addr],
+                Target::Domain(host, port) => tokio::net::lookup_host((host.as_str(), *port)).await?.collect(),
+            };
+            // Every address is checked and only those are dialled, so a name
+            // cannot be rebound to the host after it was allowed
+            if let Some(addr) = addrs.iter().find(|addr| !allowlist::is_public(addr.ip())) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is on the host or a private network", addr.ip()),
+                ));
+            }
+            TcpStream::connect(addrs.as_slice()).await
+        }
+        Upstream::Socks(proxy) => {
+            let mut stream = TcpStream::connect(proxy).await?;
+            socks::connect(&mut stream, target, auth).await?;
+            Ok(stream)
+        }
+        Upstream::Http(proxy) => {
+            let mut stream = TcpStream::connect(proxy).await?;
+            http::connect(&mut stream, target, auth).await?;
+            Ok(stream)
+        }
+    }
+}
+
+/// Binding a bridge gateway fails where the engine keeps its networks
+/// elsewhere, as Docker Desktop, Colima and OrbStack do inside their VM
+pub(crate) fn gateway_bind_error(gateway: IpAddr, e: io::Error) -> io::Error {
+    if e.kind() != io::ErrorKind::AddrNotAvailable {
+        return e;
+    }
+    io::Error::new(
+        e.kind(),
+        format!(
+            "egress network gateway {} is not an address on this machine; the Docker engine likely runs in a VM \
+             (Docker Desktop, Colima, OrbStack), where only open networking is available",
+            gateway
+        ),
+    )
+}
+
+/// Backend-managed egress proxy shared by all sessions
+pub struct EgressProxy {
+    port: u16,
+    policies: RwLock<HashMap<IpAddr, EgressPolicy>>,
+    listening: Mutex<Option<SocketAddr>>,
+    /// Leak-check probe targets waiting to be seen, answered with the sender's session
+    probes: Mutex<HashMap<SocketAddr, oneshot::Sender<Uuid>>>,
+}
+
+impl EgressProxy {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            policies: RwLock::new(HashMap::new()),
+            listening: Mutex::new(None),
+            probes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start listening on the egress network gateway if not already running
+    pub async fn ensure_listening(self: &Arc<Self>, gateway: IpAddr) -> io::Result<SocketAddr> {
+        let mut listening = self.listening.lock().await;
+        if let Some(addr) = *listening {
+            return Ok(addr);
+        }
+
+        let listener = TcpListener::bind((gateway, self.port)).await.map_err(|e| gateway_bind_error(gateway, e))?;
+        let addr = listener.local_addr()?;
+        info!("Egress proxy listening on {}", addr);
+
+        let proxy = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let proxy = proxy.clone();
+                        tokio::spawn(async move { proxy.handle(stream, peer).await });
+                    }
+                    Err(e) => warn!("Egress proxy accept failed: {}", e),
+                }
+            }
+        });
+
+        *listening = Some(addr);
+        Ok(addr)
+    }
+
+    /// Apply a policy to connections from a container address
+    pub async fn register(&self, container_ip: IpAddr, policy: EgressPolicy) {
+        debug!("Registering egress policy for session {} at {}", policy.session_id, container_ip);
+        self.policies.write().await.insert(container_ip, policy);
+    }
+
+    /// Isolation credentials of the session registered at a container address
+    pub async fn isolation_for(&self, container_ip: IpAddr) -> Option<socks::Credentials> {
+        self.policies.read().await.get(&container_ip).and_then(|policy| policy.isolation.clone())
+    }
+
+    /// Drop all policies belonging to a session
+    pub async fn unregister(&self, session_id: Uuid) {
+        self.policies.write().await.retain(|_, policy| policy.session_id != session_id);
+    }
+
+    /// Report which session next asks for `target`; the connection itself is refused
+    pub async fn expect_probe(&self, target: SocketAddr) -> oneshot::Receiver<Uuid> {
+        let (tx, rx) = oneshot::channel();
+        self.probes.lock().await.insert(target, tx);
+        rx
+    }
+
+    pub async fn cancel_probe(&self, target: SocketAddr) {
+        self.probes.lock().await.remove(&target);
+    }
+
+    /// Policy for a peer, waiting briefly for a container that has just started
+    async fn policy_for(&self, ip: IpAddr) -> Option<EgressPolicy> {
+        let deadline = tokio::time::Instant::now() + REGISTRATION_GRACE;
+        loop {
+            if let Some(policy) = self.policies.read().await.get(&ip) {
+                return Some(policy.clone());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream, peer: SocketAddr) {
+        // Unknown peers get nothing, not even a handshake
+        let Some(policy) = self.policy_for(peer.ip()).await else {
+            debug!("Egress connection from unregistered address {}", peer);
+            return;
+        };
+
+        let target = match socks::accept(&mut stream).await {
+            Ok(target) => target,
+            Err(e) => {
+                debug!("Egress handshake from {} failed: {}", peer, e);
+                return;
+            }
+        };
+
+        if let Target::Ip(addr) = target {
+            if let Some(probe) = self.probes.lock().await.remove(&addr) {
+                let _ = probe.send(policy.session_id);
+                let _ = socks::reply(&mut stream, socks::REPLY_NOT_ALLOWED).await;
+                return;
+            }
+        }
+
+        let destination = match policy.authorize(&target).await {
+            Ok(destination) => destination,
+            Err(code) => {
+                warn!("Egress to {} denied for session {}", target, policy.session_id);
+                let _ = socks::reply(&mut stream, code).await;
+                return;
+            }
+        };
+
+        let mut upstream = match policy.open(&destination).await {
+            Ok(upstream) => upstream,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                warn!("Egress to {} denied for session {}: {}", target, policy.session_id, e);
+                let _ = socks::reply(&mut stream, socks::REPLY_NOT_ALLOWED).await;
+                return;
+            }
+            Err(e) => {
+                debug!("Egress to {} failed for session {}: {}", target, policy.session_id, e);
+                let _ = socks::reply(&mut stream, socks::REPLY_HOST_UNREACHABLE).await;
+                return;
+            }
+        };
+
+        if socks::reply(&mut stream, socks::REPLY_SUCCEEDED).await.is_ok() {
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(upstream: Upstream, entries: &[&str]) -> EgressPolicy {
+        EgressPolicy {
+            session_id: Uuid::new_v4(),
+            upstream,
+            allowlist: Allowlist::parse(entries).unwrap(),
+            isolation: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socks_upstream_never_resolves_unlisted_domains() {
+        let upstream = Upstream::Socks("127.0.0.1:9050".parse().unwrap());
+        let policy = policy(upstream, &["github.com", "198.51.100.0/24"]);
+
+        let allowed = Target::Domain("api.github.com".to_string(), 443);
+        assert_eq!(policy.authorize(&allowed).await, Ok(allowed.clone()));
+
+        let denied = Target::Domain("localhost".to_string(), 80);
+        assert_eq!(policy.authorize(&denied).await, Err(socks::REPLY_NOT_ALLOWED));
+    }
+
+    #[tokio::test]
+    async fn test_ip_targets_checked_against_cidrs() {
+        let policy = policy(Upstream::Direct, &["198.51.100.0/24"]);
+
+        let allowed = Target::Ip("198.51.100.1:80".parse().unwrap());
+        assert!(policy.authorize(&allowed).await.is_ok());
+
+        let denied = Target::Ip("1.1.1.1:80".parse().unwrap());
+        assert_eq!(policy.authorize(&denied).await, Err(socks::REPLY_NOT_ALLOWED));
+    }
+
+    #[tokio::test]
+    async fn test_direct_upstream_refuses_host_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        for target in [
+            Target::Ip(SocketAddr::from(([127, 0, 0, 1], port))),
+            // A name is checked by what it resolves to, as a rebinding one would
+            Target::Domain("localhost".to_string(), port),
+        ] {
+            let err = open_via(Upstream::Direct, &target, None).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "target: {}", target);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unregister_removes_session_policies() {
+        let proxy = EgressProxy::new(0);
+        let p = policy(Upstream::Direct, &[]);
+        let session_id = p.session_id;
+
+        proxy.register("172.30.0.2".parse().unwrap(), p).await;
+        proxy.unregister(session_id).await;
+        assert!(proxy.policies.read().await.is_empty());
+    }
+}
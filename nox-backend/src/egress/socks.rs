@@ -0,0 +1,364 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Minimal SOCKS5 (RFC 1928) server handshake and client connect.
+//! Only CONNECT is supported, so UDP never leaves a session through the proxy.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USER_PASS: u8 = 0x02;
+const USER_PASS_VERSION: u8 = 0x01;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+/// Tor extension: resolve a hostname, returned as the bound address
+const CMD_RESOLVE: u8 = 0xf0;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Reply codes sent back to the client
+pub const REPLY_SUCCEEDED: u8 = 0x00;
+pub const REPLY_NOT_ALLOWED: u8 = 0x02;
+pub const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+
+/// Username/password sent upstream (RFC 1929)
+///
+/// Anyone, like Tor, isolates streams by SOCKS credentials (IsolateSOCKSAuth),
+/// so a distinct pair per session keeps sessions on separate circuits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Fresh isolation credentials for a session
+    pub fn for_session(session_id: uuid::Uuid) -> Self {
+        Self {
+            username: format!("noxterm-{}", session_id.simple()),
+            password: uuid::Uuid::new_v4().simple().to_string(),
+        }
+    }
+}
+
+/// Destination requested by a SOCKS client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl Target {
+    pub fn port(&self) -> u16 {
+        match self {
+            Target::Ip(addr) => addr.port(),
+            Target::Domain(_, port) => *port,
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Ip(addr) => write!(f, "{}", addr),
+            Target::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Run the server side of the handshake and return the requested target
+///
+/// Anything other than a no-auth CONNECT is answered with an error reply.
+pub async fn accept<S>(stream: &mut S) -> io::Result<Target>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    if header[0] != VERSION {
+        return Err(protocol_error("Not a SOCKS5 client"));
+    }
+
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTH) {
+        stream.write_all(&[VERSION, NO_ACCEPTABLE_METHODS]).await?;
+        return Err(protocol_error("Client does not offer no-auth"));
+    }
+    stream.write_all(&[VERSION, NO_AUTH]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    if request[0] != VERSION {
+        return Err(protocol_error("Bad request version"));
+    }
+
+    let target = read_address(stream, request[3]).await?;
+
+    if request[1] != CMD_CONNECT {
+        reply(stream, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Err(protocol_error("Only CONNECT is supported"));
+    }
+
+    Ok(target)
+}
+
+/// Send a reply with an unspecified bound address
+pub async fn reply<S>(stream: &mut S, code: u8) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_all(&[VERSION, code, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await
+}
+
+/// Ask an upstream SOCKS5 proxy to CONNECT to a target
+///
+/// Domains are passed through unresolved so name resolution happens upstream.
+pub async fn connect<S>(stream: &mut S, target: &Target, auth: Option<&Credentials>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    request(stream, CMD_CONNECT, target, auth).await.map(|_| ())
+}
+
+/// Ask an upstream proxy supporting the RESOLVE extension for a host's address
+pub async fn resolve<S>(stream: &mut S, host: &str, auth: Option<&Credentials>) -> io::Result<IpAddr>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match request(stream, CMD_RESOLVE, &Target::Domain(host.to_string(), 0), auth).await? {
+        Target::Ip(addr) => Ok(addr.ip()),
+        Target::Domain(..) => Err(protocol_error("RESOLVE returned a hostname")),
+    }
+}
+
+/// Client handshake followed by one request; returns the bound address
+async fn request<S>(stream: &mut S, cmd: u8, target: &Target, auth: Option<&Credentials>) -> io::Result<Target>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let method = if auth.is_some() { USER_PASS } else { NO_AUTH };
+    stream.write_all(&[VERSION, 1, method]).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [VERSION, method] {
+        return Err(protocol_error("Upstream proxy refused the auth method"));
+    }
+
+    if let Some(auth) = auth {
+        let username = u8::try_from(auth.username.len()).map_err(|_| protocol_error("Username too long"))?;
+        let password = u8::try_from(auth.password.len()).map_err(|_| protocol_error("Password too long"))?;
+
+        let mut packet = vec![USER_PASS_VERSION, username];
+        packet.extend_from_slice(auth.username.as_bytes());
+        packet.push(password);
+        packet.extend_from_slice(auth.password.as_bytes());
+        stream.write_all(&packet).await?;
+
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await?;
+        if status[1] != 0x00 {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Upstream proxy rejected credentials"));
+        }
+    }
+
+    let mut packet = vec![VERSION, cmd, 0x00];
+    write_address(&mut packet, target)?;
+    stream.write_all(&packet).await?;
+
+    let mut response = [0u8; 4];
+    stream.read_exact(&mut response).await?;
+    // Drain the bound address regardless of the outcome
+    let bound = read_address(stream, response[3]).await?;
+
+    if response[1] != REPLY_SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("Upstream proxy replied {:#04x} for {}", response[1], target),
+        ));
+    }
+
+    Ok(bound)
+}
+
+async fn read_address<S>(stream: &mut S, atyp: u8) -> io::Result<Target>
+where
+    S: AsyncRead + Unpin,
+{
+    let target = match atyp {
+        ATYP_IPV4 => {
+            let mut buf = [0u8; 6];
+            stream.read_exact(&mut buf).await?;
+            let ip = Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]);
+            Target::Ip(SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([buf[4], buf[5]])))
+        }
+        ATYP_IPV6 => {
+            let mut buf = [0u8; 18];
+            stream.read_exact(&mut buf).await?;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&buf[..16]);
+            let ip = Ipv6Addr::from(octets);
+            Target::Ip(SocketAddr::new(IpAddr::V6(ip), u16::from_be_bytes([buf[16], buf[17]])))
+        }
+        ATYP_DOMAIN => {
+            let len = stream.read_u8().await? as usize;
+            let mut host = vec![0u8; len];
+            stream.read_exact(&mut host).await?;
+            let port = stream.read_u16().await?;
+            let host = String::from_utf8(host).map_err(|_| protocol_error("Domain is not UTF-8"))?;
+            Target::Domain(host, port)
+        }
+        _ => return Err(protocol_error("Unknown address type")),
+    };
+
+    Ok(target)
+}
+
+fn write_address(buf: &mut Vec<u8>, target: &Target) -> io::Result<()> {
+    match target {
+        Target::Ip(SocketAddr::V4(addr)) => {
+            buf.push(ATYP_IPV4);
+            buf.extend_from_slice(&addr.ip().octets());
+        }
+        Target::Ip(SocketAddr::V6(addr)) => {
+            buf.push(ATYP_IPV6);
+            buf.extend_from_slice(&addr.ip().octets());
+        }
+        Target::Domain(host, _) => {
+            let len = u8::try_from(host.len()).map_err(|_| protocol_error("Domain too long"))?;
+            buf.push(ATYP_DOMAIN);
+            buf.push(len);
+            buf.extend_from_slice(host.as_bytes());
+        }
+    }
+    buf.extend_from_slice(&target.port().to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_client_server_handshake() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let target = Target::Domain("example.com".to_string(), 443);
+
+        let expected = target.clone();
+        let server_task = tokio::spawn(async move {
+            let requested = accept(&mut server).await.unwrap();
+            assert_eq!(requested, expected);
+            reply(&mut server, REPLY_SUCCEEDED).await.unwrap();
+        });
+
+        connect(&mut client, &target, None).await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refused_reply_is_an_error() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let target = Target::Ip("10.1.2.3:22".parse().unwrap());
+
+        let server_task = tokio::spawn(async move {
+            accept(&mut server).await.unwrap();
+            reply(&mut server, REPLY_NOT_ALLOWED).await.unwrap();
+        });
+
+        assert!(connect(&mut client, &target, None).await.is_err());
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_returns_bound_address() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+
+        let server_task = tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            server.read_exact(&mut greeting).await.unwrap();
+            server.write_all(&[VERSION, NO_AUTH]).await.unwrap();
+
+            let mut header = [0u8; 4];
+            server.read_exact(&mut header).await.unwrap();
+            assert_eq!(header[1], CMD_RESOLVE);
+            let requested = read_address(&mut server, header[3]).await.unwrap();
+            assert_eq!(requested, Target::Domain("example.com".to_string(), 0));
+
+            server.write_all(&[VERSION, REPLY_SUCCEEDED, 0, ATYP_IPV4, 93, 184, 216, 34, 0, 0]).await.unwrap();
+        });
+
+        let ip = resolve(&mut client, "example.com", None).await.unwrap();
+        assert_eq!(ip, "93.184.216.34".parse::<IpAddr>().unwrap());
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_sends_isolation_credentials() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let auth = Credentials {
+            username: "noxterm-a".to_string(),
+            password: "secret".to_string(),
+        };
+
+        let server_task = tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            server.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [VERSION, 1, USER_PASS]);
+            server.write_all(&[VERSION, USER_PASS]).await.unwrap();
+
+            let mut sub = [0u8; 2];
+            server.read_exact(&mut sub).await.unwrap();
+            let mut username = vec![0u8; sub[1] as usize];
+            server.read_exact(&mut username).await.unwrap();
+            let len = server.read_u8().await.unwrap();
+            let mut password = vec![0u8; len as usize];
+            server.read_exact(&mut password).await.unwrap();
+            assert_eq!((username.as_slice(), password.as_slice()), (&b"noxterm-a"[..], &b"secret"[..]));
+            server.write_all(&[USER_PASS_VERSION, 0x00]).await.unwrap();
+
+            let mut header = [0u8; 4];
+            server.read_exact(&mut header).await.unwrap();
+            read_address(&mut server, header[3]).await.unwrap();
+            reply(&mut server, REPLY_SUCCEEDED).await.unwrap();
+        });
+
+        let target = Target::Domain("example.com".to_string(), 80);
+        connect(&mut client, &target, Some(&auth)).await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[test]
+    fn test_session_credentials_are_distinct() {
+        let a = Credentials::for_session(uuid::Uuid::new_v4());
+        let b = Credentials::for_session(uuid::Uuid::new_v4());
+        assert_ne!(a.username, b.username);
+        assert_ne!(a.password, b.password);
+    }
+
+    #[tokio::test]
+    async fn test_udp_associate_is_rejected() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+
+        let server_task = tokio::spawn(async move { accept(&mut server).await });
+
+        client.write_all(&[VERSION, 1, NO_AUTH]).await.unwrap();
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        client.write_all(&[VERSION, 0x03, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 53]).await.unwrap();
+        let mut response = [0u8; 10];
+        client.read_exact(&mut response).await.unwrap();
+
+        assert_eq!(response[1], REPLY_COMMAND_NOT_SUPPORTED);
+        assert!(server_task.await.unwrap().is_err());
+    }
+}
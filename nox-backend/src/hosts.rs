@@ -0,0 +1,475 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Docker hosts that sessions are placed on.
+//!
+//! Each host is reached through its own client: a local socket, TCP (with
+//! TLS when certificates are configured) or a socket forwarded over SSH.
+//! Hosts are pinged and measured periodically, and `place` picks a healthy
+//! host with room for a new session's limits. A session stays on the host
+//! it was placed on, recorded as `host` in its metadata.
+
+use crate::config::{DockerConfig, DockerEndpoint};
+use crate::container::engine::Capabilities;
+use anyhow::{anyhow, Context, Result};
+use bollard::Docker;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+/// Name of the host when none are configured, and of sessions from before
+/// multi-host placement
+pub const DEFAULT_HOST: &str = "local";
+
+const CONNECT_TIMEOUT_SECS: u64 = 120;
+
+/// How long an SSH tunnel may take to open its local socket
+const TUNNEL_START: Duration = Duration::from_secs(10);
+
+/// Health and size of a host as of the last check
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Status {
+    pub healthy: bool,
+    pub memory_bytes: i64,
+    pub cpus: i64,
+    pub containers_running: i64,
+    /// What the engine supports, once it has answered
+    pub engine: Option<Capabilities>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub checked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub struct Host {
+    pub name: String,
+    /// None for the auto-detected local daemon
+    pub endpoint: Option<DockerEndpoint>,
+    pub docker: Arc<Docker>,
+    status: RwLock<Status>,
+    /// `ssh` process forwarding the daemon socket, for SSH hosts
+    tunnel: Mutex<Option<Child>>,
+}
+
+impl Host {
+    /// Whether containers here can reach the egress proxy and DNS resolver,
+    /// which listen on this machine
+    pub fn is_local(&self) -> bool {
+        self.endpoint.as_ref().is_none_or(DockerEndpoint::is_local)
+    }
+
+    pub async fn status(&self) -> Status {
+        self.status.read().await.clone()
+    }
+
+    /// Ping the daemon and record its size, reopening a dead SSH tunnel first
+    pub async fn refresh(&self) {
+        if let Some(DockerEndpoint::Ssh { destination, socket }) = &self.endpoint {
+            let mut tunnel = self.tunnel.lock().await;
+            let exited = match tunnel.as_mut() {
+                Some(child) => child.try_wait().ok().flatten().is_some(),
+                None => true,
+            };
+            if exited {
+                warn!("SSH tunnel to Docker host {} is down, reopening", self.name);
+                match open_tunnel(&self.name, destination, socket).await {
+                    Ok((child, _)) => *tunnel = Some(child),
+                    Err(e) => {
+                        *tunnel = None;
+                        self.mark_unhealthy(e.to_string()).await;
+                        return;
+                    }
+                }
+            }
+        }
+
+        let checked = async {
+            self.docker.ping().await?;
+            let version = self.docker.version().await?;
+            let info = self.docker.info().await?;
+            Ok::<_, bollard::errors::Error>((version, info))
+        };
+        match tokio::time::timeout(Duration::from_secs(10), checked).await {
+            Ok(Ok((version, info))) => {
+                let engine = Capabilities::from_daemon(&version, &info);
+                let mut status = self.status.write().await;
+                if !status.healthy {
+                    info!("Docker host {} is healthy ({})", self.name, engine.describe());
+                }
+                *status = Status {
+                    healthy: true,
+                    memory_bytes: info.mem_total.unwrap_or(0),
+                    cpus: info.ncpu.unwrap_or(0),
+                    containers_running: info.containers_running.unwrap_or(0),
+                    engine: Some(engine),
+                    error: None,
+                    checked_at: Some(chrono::Utc::now()),
+                };
+            }
+            Ok(Err(e)) => self.mark_unhealthy(e.to_string()).await,
+            Err(_) => self.mark_unhealthy("timed out".to_string()).await,
+        }
+    }
+
+    async fn mark_unhealthy(&self, error: String) {
+        let mut status = self.status.write().await;
+        if status.healthy || status.checked_at.is_none() {
+            warn!("Docker host {} is unhealthy: {}", self.name, error);
+        }
+        status.healthy = false;
+        status.error = Some(error);
+        status.checked_at = Some(chrono::Utc::now());
+    }
+}
+
+pub struct HostPool {
+    /// The first host also serves sessions from before multi-host placement
+    hosts: Vec<Arc<Host>>,
+}
+
+impl HostPool {
+    /// A pool of just the auto-detected local daemon
+    pub fn local(docker: Docker) -> Self {
+        Self { hosts: vec![Arc::new(Host::new(DEFAULT_HOST, None, docker, None))] }
+    }
+
+    /// Connect to every configured host. Clients connect lazily, so only an
+    /// unusable endpoint (missing socket, certificates or SSH tunnel) fails
+    /// here; unreachable daemons show up as unhealthy on `refresh`
+    pub async fn connect(config: &DockerConfig) -> Result<Self> {
+        let mut names: Vec<&String> = config.hosts.keys().collect();
+        // The default host, when configured, serves sessions from before
+        // multi-host placement
+        names.sort_by_key(|name| (name.as_str() != DEFAULT_HOST, name.as_str()));
+
+        let mut hosts = Vec::new();
+        for name in names {
+            let endpoint = &config.hosts[name];
+            let (docker, tunnel) = connect_endpoint(name, endpoint, config.tls_dir.as_deref())
+                .await
+                .with_context(|| format!("Docker host {} ({})", name, endpoint))?;
+            info!("Docker host {} at {}", name, endpoint);
+            hosts.push(Arc::new(Host::new(name, Some(endpoint.clone()), docker, tunnel)));
+        }
+        if hosts.is_empty() {
+            return Err(anyhow!("No Docker hosts configured"));
+        }
+        Ok(Self { hosts })
+    }
+
+    pub fn all(&self) -> &[Arc<Host>] {
+        &self.hosts
+    }
+
+    pub fn primary(&self) -> &Arc<Host> {
+        &self.hosts[0]
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Host>> {
+        self.hosts.iter().find(|host| host.name == name)
+    }
+
+    /// Client for the host a session runs on. Hosts dropped from the
+    /// configuration fall back to the primary, whose lookups then miss
+    pub fn docker_for(&self, host: &str) -> Arc<Docker> {
+        match self.get(host) {
+            Some(host) => host.docker.clone(),
+            None => {
+                warn!("Unknown Docker host {}, using {}", host, self.primary().name);
+                self.primary().docker.clone()
+            }
+        }
+    }
+
+    pub async fn refresh(&self) {
+        futures::future::join_all(self.hosts.iter().map(|host| host.refresh())).await;
+    }
+
+    /// Re-check every host forever
+    pub async fn run_health_checks(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.refresh().await;
+        }
+    }
+
+    /// Every host with its capacity and what sessions already reserve on it
+    pub async fn candidates(&self, committed: &HashMap<String, Load>, memory_percent: u8) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for host in &self.hosts {
+            let status = host.status().await;
+            candidates.push(Candidate {
+                name: host.name.clone(),
+                healthy: status.healthy,
+                // Rootless and VM engines' networks are out of the egress proxy's reach
+                local: host.is_local() && status.engine.as_ref().is_none_or(|engine| engine.egress_routing),
+                limits: status.engine.as_ref().is_none_or(|engine| engine.resource_limits),
+                capacity: Load {
+                    memory_mb: status.memory_bytes / (1024 * 1024) * i64::from(memory_percent) / 100,
+                    cpu_percent: status.cpus * 100,
+                },
+                committed: committed.get(&host.name).copied().unwrap_or_default(),
+            });
+        }
+        candidates
+    }
+}
+
+impl Host {
+    fn new(name: &str, endpoint: Option<DockerEndpoint>, docker: Docker, tunnel: Option<Child>) -> Self {
+        Self {
+            name: name.to_string(),
+            endpoint,
+            docker: Arc::new(docker),
+            status: RwLock::new(Status::default()),
+            tunnel: Mutex::new(tunnel),
+        }
+    }
+}
+
+/// The host recorded in a session's metadata
+pub fn host_of(metadata: &serde_json::Value) -> Option<&str> {
+    metadata.get("host").and_then(|host| host.as_str())
+}
+
+async fn connect_endpoint(
+    name: &str,
+    endpoint: &DockerEndpoint,
+    tls_dir: Option<&str>,
+) -> Result<(Docker, Option<Child>)> {
+    let version = bollard::API_DEFAULT_VERSION;
+    match endpoint {
+        DockerEndpoint::Unix(path) => Ok((Docker::connect_with_unix(path, CONNECT_TIMEOUT_SECS, version)?, None)),
+        DockerEndpoint::Tcp(addr) => {
+            let addr = format!("tcp://{}", addr);
+            let docker = match tls_dir {
+                Some(dir) => {
+                    let certs = tls_certs(Path::new(dir), name);
+                    Docker::connect_with_ssl(
+                        &addr,
+                        &certs.join("key.pem"),
+                        &certs.join("cert.pem"),
+                        &certs.join("ca.pem"),
+                        CONNECT_TIMEOUT_SECS,
+                        version,
+                    )?
+                }
+                None => {
+                    warn!("Docker host {} is reached over unencrypted TCP", name);
+                    Docker::connect_with_http(&addr, CONNECT_TIMEOUT_SECS, version)?
+                }
+            };
+            Ok((docker, None))
+        }
+        DockerEndpoint::Ssh { destination, socket } => {
+            let (tunnel, local) = open_tunnel(name, destination, socket).await?;
+            let docker = Docker::connect_with_unix(&local.to_string_lossy(), CONNECT_TIMEOUT_SECS, version)?;
+            Ok((docker, Some(tunnel)))
+        }
+    }
+}
+
+/// Certificates for a host: `<dir>/<host>/` when it exists, else `<dir>`
+fn tls_certs(dir: &Path, name: &str) -> PathBuf {
+    let per_host = dir.join(name);
+    if per_host.is_dir() {
+        per_host
+    } else {
+        dir.to_path_buf()
+    }
+}
+
+/// Forward a remote daemon socket to a local one with `ssh -L`
+async fn open_tunnel(name: &str, destination: &str, socket: &str) -> Result<(Child, PathBuf)> {
+    let local = std::env::temp_dir().join(format!("noxterm-docker-{}.sock", name));
+    let _ = tokio::fs::remove_file(&local).await;
+
+    let mut child = Command::new("ssh")
+        .args(["-nNT", "-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes", "-o", "ServerAliveInterval=15"])
+        .arg("-L")
+        .arg(format!("{}:{}", local.display(), socket))
+        .arg(destination)
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run ssh")?;
+
+    let deadline = tokio::time::Instant::now() + TUNNEL_START;
+    while tokio::time::Instant::now() < deadline {
+        if local.exists() {
+            return Ok((child, local));
+        }
+        if let Some(status) = child.try_wait()? {
+            return Err(anyhow!("ssh to {} exited with {}", destination, status));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let _ = child.kill().await;
+    Err(anyhow!("ssh to {} did not open {} within {:?}", destination, local.display(), TUNNEL_START))
+}
+
+/// Memory and CPU, as session limits count them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Load {
+    pub memory_mb: i64,
+    /// 100 per core
+    pub cpu_percent: i64,
+}
+
+impl std::ops::AddAssign for Load {
+    fn add_assign(&mut self, other: Self) {
+        self.memory_mb += other.memory_mb;
+        self.cpu_percent += other.cpu_percent;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub name: String,
+    pub healthy: bool,
+    /// Its containers can reach the egress proxy and DNS resolver
+    pub local: bool,
+    /// Its engine enforces memory, CPU and pids limits
+    pub limits: bool,
+    pub capacity: Load,
+    /// What sessions already placed here may use
+    pub committed: Load,
+}
+
+impl Candidate {
+    /// Memory is reserved in full; CPU may be overcommitted
+    fn fits(&self, request: &Request) -> bool {
+        self.healthy
+            && (self.local || !request.needs_local)
+            && (self.limits || !request.needs_limits)
+            && self.committed.memory_mb + request.load.memory_mb <= self.capacity.memory_mb
+    }
+
+    /// Share of memory and CPU left after placing the request, the scarcer
+    /// of the two counting most
+    fn headroom(&self, request: &Request) -> f64 {
+        let left = |capacity: i64, used: i64| {
+            if capacity <= 0 {
+                return 0.0;
+            }
+            (capacity - used) as f64 / capacity as f64
+        };
+        let memory = left(self.capacity.memory_mb, self.committed.memory_mb + request.load.memory_mb);
+        let cpu = left(self.capacity.cpu_percent, self.committed.cpu_percent + request.load.cpu_percent);
+        memory.min(cpu)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request<'a> {
+    pub load: Load,
+    /// Sessions using the egress proxy, DNS resolver or host privacy proxy
+    /// must run on this machine
+    pub needs_local: bool,
+    /// Refuse hosts that would run the session without resource limits
+    pub needs_limits: bool,
+    /// Host to prefer while it has room, e.g. where the user's other
+    /// sessions run
+    pub affinity: Option<&'a str>,
+}
+
+/// Pick the host for a new session: the affinity host if it fits, else the
+/// one left with the most headroom
+pub fn place(candidates: &[Candidate], request: &Request) -> Option<String> {
+    let fitting = candidates.iter().filter(|c| c.fits(request));
+    if let Some(preferred) = fitting.clone().find(|c| Some(c.name.as_str()) == request.affinity) {
+        return Some(preferred.name.clone());
+    }
+    fitting
+        .max_by(|a, b| {
+            a.headroom(request)
+                .total_cmp(&b.headroom(request))
+                // Ties go to the earlier host
+                .then_with(|| b.name.cmp(&a.name))
+        })
+        .map(|c| c.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, memory_mb: i64, committed_mb: i64) -> Candidate {
+        Candidate {
+            name: name.to_string(),
+            healthy: true,
+            local: true,
+            limits: true,
+            capacity: Load { memory_mb, cpu_percent: 400 },
+            committed: Load { memory_mb: committed_mb, cpu_percent: 0 },
+        }
+    }
+
+    fn request(memory_mb: i64) -> Request<'static> {
+        Request { load: Load { memory_mb, cpu_percent: 100 }, needs_local: false, needs_limits: true, affinity: None }
+    }
+
+    #[test]
+    fn test_places_on_most_headroom() {
+        let hosts = vec![candidate("a", 8192, 6144), candidate("b", 8192, 1024), candidate("c", 4096, 0)];
+        assert_eq!(place(&hosts, &request(1024)), Some("b".to_string()));
+        // Only a host with the memory free can take it
+        assert_eq!(place(&hosts, &request(6000)), Some("b".to_string()));
+        assert_eq!(place(&hosts, &request(8000)), None);
+    }
+
+    #[test]
+    fn test_affinity_while_it_fits() {
+        let hosts = vec![candidate("a", 8192, 6144), candidate("b", 8192, 0)];
+        let mut sticky = request(1024);
+        sticky.affinity = Some("a");
+        assert_eq!(place(&hosts, &sticky), Some("a".to_string()));
+
+        sticky.load.memory_mb = 4096;
+        assert_eq!(place(&hosts, &sticky), Some("b".to_string()));
+    }
+
+    #[test]
+    fn test_skips_unhealthy_and_remote_for_local_sessions() {
+        let mut down = candidate("a", 16384, 0);
+        down.healthy = false;
+        let mut remote = candidate("b", 16384, 0);
+        remote.local = false;
+        let hosts = vec![down, remote, candidate("c", 4096, 2048)];
+
+        assert_eq!(place(&hosts, &request(1024)), Some("b".to_string()));
+        let mut private = request(1024);
+        private.needs_local = true;
+        assert_eq!(place(&hosts, &private), Some("c".to_string()));
+    }
+
+    #[test]
+    fn test_skips_unlimited_hosts_unless_allowed() {
+        let mut unlimited = candidate("a", 16384, 0);
+        unlimited.limits = false;
+        let hosts = vec![unlimited, candidate("b", 4096, 0)];
+
+        assert_eq!(place(&hosts, &request(1024)), Some("b".to_string()));
+        let mut allowed = request(1024);
+        allowed.needs_limits = false;
+        assert_eq!(place(&hosts, &allowed), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_cpu_counts_toward_headroom() {
+        let mut busy = candidate("a", 8192, 0);
+        busy.committed.cpu_percent = 350;
+        let hosts = vec![busy, candidate("b", 8192, 2048)];
+        assert_eq!(place(&hosts, &request(512)), Some("b".to_string()));
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(host_of(&serde_json::json!({ "host": "edge-1" })), Some("edge-1"));
+        assert_eq!(host_of(&serde_json::json!({})), None);
+    }
+}
@@ -0,0 +1,382 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Batch jobs: a script run to completion in a throwaway container.
+//!
+//! While a job runs its output is buffered in a `JobLog` that late listeners
+//! can replay before following along. Once it finishes, the output and any
+//! collected artifacts live in the `JobStore` directory until retention
+//! removes them; the container itself is gone by then.
+
+use crate::container::exec::Event;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Output kept in memory per job; the rest is dropped
+pub const MAX_LOG: usize = 4 * 1024 * 1024;
+
+/// Most input files and artifact paths per job
+pub const MAX_PATHS: usize = 32;
+
+/// Where the script is written inside the container
+pub const SCRIPT_PATH: &str = "/tmp/noxterm-job/script";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for its container
+    Starting,
+    Running,
+    /// Collecting artifacts after the script exited
+    Collecting,
+    Succeeded,
+    Failed,
+    TimedOut,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::TimedOut | JobStatus::Cancelled)
+    }
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Starting => write!(f, "starting"),
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Collecting => write!(f, "collecting"),
+            JobStatus::Succeeded => write!(f, "succeeded"),
+            JobStatus::Failed => write!(f, "failed"),
+            JobStatus::TimedOut => write!(f, "timed_out"),
+            JobStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starting" => Ok(JobStatus::Starting),
+            "running" => Ok(JobStatus::Running),
+            "collecting" => Ok(JobStatus::Collecting),
+            "succeeded" => Ok(JobStatus::Succeeded),
+            "failed" => Ok(JobStatus::Failed),
+            "timed_out" => Ok(JobStatus::TimedOut),
+            "cancelled" => Ok(JobStatus::Cancelled),
+            _ => Err(format!("Unknown job status '{}'", s)),
+        }
+    }
+}
+
+/// A file written into the container before the script runs
+#[derive(Debug, Clone, Deserialize)]
+pub struct InputFile {
+    pub path: String,
+    pub content: String,
+    #[serde(default)]
+    pub executable: bool,
+}
+
+/// A path collected after the script exits, as a tar archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    pub path: String,
+    pub size_bytes: u64,
+    /// Why the path could not be collected
+    pub error: Option<String>,
+}
+
+/// What is known about a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: Uuid,
+    pub user_id: String,
+    /// Session holding the job's container
+    pub session_id: Uuid,
+    pub image: String,
+    pub tier: String,
+    pub status: JobStatus,
+    pub exit_code: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Indexed by position in the request's `artifacts`
+    pub artifacts: Vec<Artifact>,
+    /// Set when output went over [`MAX_LOG`]
+    pub log_truncated: bool,
+    /// SHA-256 of the token handed out with the job; empty for none
+    #[serde(skip)]
+    pub token_sha256: String,
+}
+
+/// Check a path given for an input file or artifact
+pub fn validate_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err(format!("'{}' is not an absolute path", path));
+    }
+    if path.contains('\0') {
+        return Err("Paths cannot contain null bytes".to_string());
+    }
+    if path.split('/').any(|part| part == "..") {
+        return Err(format!("'{}' leaves its directory with '..'", path));
+    }
+    if path.trim_end_matches('/').is_empty() {
+        return Err("The root directory cannot be used".to_string());
+    }
+    Ok(())
+}
+
+/// A running job's output, replayable by late listeners
+#[derive(Debug)]
+pub struct JobLog {
+    events: Vec<Event>,
+    bytes: usize,
+    truncated: bool,
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for JobLog {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            bytes: 0,
+            truncated: false,
+            tx: broadcast::channel(256).0,
+        }
+    }
+}
+
+impl JobLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: Event) {
+        let _ = self.tx.send(event.clone());
+        let chunk = match event {
+            Event::Stdout(ref chunk) | Event::Stderr(ref chunk) => chunk,
+            Event::Exit(_) => {
+                self.events.push(event);
+                return;
+            }
+        };
+
+        let room = MAX_LOG.saturating_sub(self.bytes);
+        if chunk.len() > room {
+            self.truncated = true;
+        }
+        if room == 0 {
+            return;
+        }
+        let kept = chunk.len().min(room);
+        self.bytes += kept;
+        self.events.push(match event {
+            Event::Stdout(chunk) => Event::Stdout(chunk[..kept].to_vec()),
+            Event::Stderr(chunk) => Event::Stderr(chunk[..kept].to_vec()),
+            exit => exit,
+        });
+    }
+
+    /// Output so far, and a receiver for what comes after it
+    pub fn subscribe(&self) -> (Vec<Event>, broadcast::Receiver<Event>) {
+        (self.events.clone(), self.tx.subscribe())
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Stdout and stderr as kept
+    pub fn streams(&self) -> (Vec<u8>, Vec<u8>) {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        for event in &self.events {
+            match event {
+                Event::Stdout(chunk) => stdout.extend_from_slice(chunk),
+                Event::Stderr(chunk) => stderr.extend_from_slice(chunk),
+                Event::Exit(_) => {}
+            }
+        }
+        (stdout, stderr)
+    }
+}
+
+/// Finished jobs' output and artifacts on disk, one directory per job
+#[derive(Debug, Clone)]
+pub struct JobStore {
+    root: PathBuf,
+    max_artifact_bytes: u64,
+}
+
+impl JobStore {
+    pub fn new(root: impl Into<PathBuf>, max_artifact_mb: u64) -> Self {
+        Self {
+            root: root.into(),
+            max_artifact_bytes: max_artifact_mb * 1024 * 1024,
+        }
+    }
+
+    fn dir(&self, job_id: Uuid) -> PathBuf {
+        self.root.join(job_id.to_string())
+    }
+
+    /// Tar archive of the job's `index`th artifact path
+    pub fn artifact_path(&self, job_id: Uuid, index: usize) -> PathBuf {
+        self.dir(job_id).join(format!("artifact-{}.tar", index))
+    }
+
+    /// `stdout.log` or `stderr.log`
+    pub fn log_path(&self, job_id: Uuid, stream: &str) -> PathBuf {
+        self.dir(job_id).join(format!("{}.log", stream))
+    }
+
+    pub async fn save_logs(&self, job_id: Uuid, stdout: &[u8], stderr: &[u8]) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(self.dir(job_id)).await?;
+        tokio::fs::write(self.log_path(job_id, "stdout"), stdout).await?;
+        tokio::fs::write(self.log_path(job_id, "stderr"), stderr).await
+    }
+
+    /// Write an artifact archive, giving up once it passes the size limit
+    pub async fn save_artifact<S, B, E>(&self, job_id: Uuid, index: usize, mut archive: S) -> Result<u64, String>
+    where
+        S: Stream<Item = Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        E: std::fmt::Display,
+    {
+        let path = self.artifact_path(job_id, index);
+        let result = async {
+            tokio::fs::create_dir_all(self.dir(job_id)).await.map_err(|e| e.to_string())?;
+            let mut file = tokio::fs::File::create(&path).await.map_err(|e| e.to_string())?;
+            let mut size = 0u64;
+            while let Some(chunk) = archive.next().await {
+                let chunk = chunk.map_err(|e| e.to_string())?;
+                size += chunk.as_ref().len() as u64;
+                if size > self.max_artifact_bytes {
+                    return Err(format!("Larger than {} MB", self.max_artifact_bytes / 1024 / 1024));
+                }
+                file.write_all(chunk.as_ref()).await.map_err(|e| e.to_string())?;
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+            Ok(size)
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+        result
+    }
+
+    pub async fn remove(&self, job_id: Uuid) {
+        if let Err(e) = tokio::fs::remove_dir_all(self.dir(job_id)).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove files of job {}: {}", job_id, e);
+            }
+        }
+    }
+
+    /// Remove job directories last written more than `age` ago
+    pub async fn purge_older_than(&self, age: Duration) -> usize {
+        let Some(cutoff) = SystemTime::now().checked_sub(age) else {
+            return 0;
+        };
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Cannot read job directory {}: {}", self.root.display(), e);
+                }
+                return 0;
+            }
+        };
+
+        let mut removed = 0;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(job_id) = entry.file_name().to_string_lossy().parse::<Uuid>() else {
+                continue;
+            };
+            let modified = entry.metadata().await.and_then(|m| m.modified());
+            if matches!(modified, Ok(modified) if modified < cutoff) {
+                self.remove(job_id).await;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            debug!("Removed files of {} expired jobs", removed);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::exec::Exit;
+
+    #[test]
+    fn test_validate_path() {
+        assert!(validate_path("/root/out").is_ok());
+        assert!(validate_path("/root/build/").is_ok());
+        assert!(validate_path("out").is_err());
+        assert!(validate_path("/root/../etc").is_err());
+        assert!(validate_path("/").is_err());
+        assert!(validate_path("/a\0b").is_err());
+    }
+
+    #[test]
+    fn test_status_round_trips() {
+        for status in [JobStatus::Starting, JobStatus::TimedOut, JobStatus::Succeeded] {
+            assert_eq!(status.to_string().parse::<JobStatus>(), Ok(status));
+        }
+        assert!(JobStatus::Cancelled.is_finished());
+        assert!(!JobStatus::Collecting.is_finished());
+    }
+
+    #[test]
+    fn test_log_replays_and_caps_output() {
+        let mut log = JobLog::new();
+        log.push(Event::Stdout(b"hello\n".to_vec()));
+        let (replay, mut rx) = log.subscribe();
+        assert_eq!(replay.len(), 1);
+
+        log.push(Event::Stderr(vec![b'x'; MAX_LOG]));
+        log.push(Event::Stdout(b"dropped".to_vec()));
+        log.push(Event::Exit(Exit { exit_code: Some(0), timed_out: false, duration_ms: 1 }));
+        assert!(log.truncated());
+
+        let (stdout, stderr) = log.streams();
+        assert_eq!(stdout, b"hello\n");
+        assert_eq!(stderr.len(), MAX_LOG - 6);
+        // Listeners still see everything
+        assert!(matches!(rx.try_recv(), Ok(Event::Stderr(chunk)) if chunk.len() == MAX_LOG));
+    }
+
+    #[tokio::test]
+    async fn test_artifact_over_limit_is_discarded() {
+        let store = JobStore::new(std::env::temp_dir().join(format!("noxterm-jobs-{}", Uuid::new_v4())), 1);
+        let job_id = Uuid::new_v4();
+
+        let small = futures::stream::iter(vec![Ok::<_, String>(vec![1u8; 10])]);
+        assert_eq!(store.save_artifact(job_id, 0, small).await, Ok(10));
+
+        let big = futures::stream::iter(vec![Ok::<_, String>(vec![0u8; 1024 * 1024]), Ok(vec![0u8; 1])]);
+        assert!(store.save_artifact(job_id, 1, big).await.is_err());
+        assert!(store.artifact_path(job_id, 0).exists());
+        assert!(!store.artifact_path(job_id, 1).exists());
+
+        assert_eq!(store.purge_older_than(Duration::ZERO).await, 1);
+        assert!(!store.artifact_path(job_id, 0).exists());
+        let _ = tokio::fs::remove_dir_all(&store.root).await;
+    }
+}
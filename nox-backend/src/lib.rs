@@ -1,15 +1,27 @@
 // NOXTERM Library
 // This file enables the backend to be used as a library
 
+pub mod anyone_control;
 pub mod anyone_service;
+pub mod anyone_supervisor;
+pub mod config;
+pub mod container;
 pub mod db;
+pub mod doctor;
+pub mod egress;
+pub mod hosts;
+pub mod jobs;
 pub mod lifecycle;
+pub mod privacy;
+pub mod reconcile;
 pub mod security;
+pub mod shutdown;
 
 pub use anyone_service::{AnyoneService, ServiceStatus};
+pub use config::Config;
 pub use db::DbPool;
 pub use lifecycle::{LifecycleConfig, LifecycleManager, ContainerHealth};
-pub use security::{validate_input, ValidationResult, Severity};
+pub use security::{validate_with, Policy, ValidationResult, Severity};
 
 // Re-export commonly used types
 pub use anyhow::{Result, Context};
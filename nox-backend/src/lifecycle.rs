@@ -2,14 +2,18 @@
 //!
 //! Background tasks for container cleanup, health monitoring, and session management.
 
+use crate::container::labels;
+use crate::container::storage::{self, QuotaState};
 use crate::db::{self, DbPool};
+use crate::hosts::{self, HostPool};
+use crate::jobs::JobStore;
 use bollard::container::{InspectContainerOptions, StatsOptions, StopContainerOptions};
 use bollard::Docker;
 use futures_util::StreamExt;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio::time::interval;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
@@ -27,6 +31,20 @@ pub struct LifecycleConfig {
     pub metrics_interval_secs: u64,
     /// Maximum containers per user
     pub max_containers_per_user: i64,
+    /// Interval for disk quota checks in seconds
+    pub disk_check_interval_secs: u64,
+    /// Default writable-layer quota in MB for sessions without stored limits
+    pub disk_quota_mb: i64,
+    /// Usage percentage of the quota at which a warning is logged
+    pub disk_warn_percent: u8,
+    /// Days command history is kept for users without their own retention
+    pub history_retention_days: u32,
+    /// Directory holding finished jobs' logs and artifacts
+    pub job_artifact_dir: String,
+    /// Hours a finished job is kept
+    pub job_retention_hours: u64,
+    /// Only containers labelled with this instance ID are ever removed
+    pub instance_id: String,
 }
 
 impl Default for LifecycleConfig {
@@ -37,6 +55,13 @@ impl Default for LifecycleConfig {
             health_check_interval_secs: 30,
             metrics_interval_secs: 15,
             max_containers_per_user: 3,
+            disk_check_interval_secs: 60,
+            disk_quota_mb: 2048,
+            disk_warn_percent: 90,
+            history_retention_days: 30,
+            job_artifact_dir: "./job-artifacts".to_string(),
+            job_retention_hours: 72,
+            instance_id: "default".to_string(),
         }
     }
 }
@@ -57,24 +82,40 @@ pub struct ContainerHealth {
 
 /// Lifecycle manager for handling background tasks
 pub struct LifecycleManager {
-    docker: Docker,
+    /// Docker hosts; each session's container lives on the one in its metadata
+    hosts: Arc<HostPool>,
     db_pool: DbPool,
     config: LifecycleConfig,
     /// Cache of active container health statuses
     health_cache: Arc<RwLock<HashMap<Uuid, ContainerHealth>>>,
+    /// Sessions this manager terminated, for the server to drop what it
+    /// still holds for them
+    reaped: broadcast::Sender<Uuid>,
 }
 
 impl LifecycleManager {
     /// Create a new lifecycle manager
-    pub fn new(docker: Docker, db_pool: DbPool, config: LifecycleConfig) -> Self {
+    pub fn new(hosts: Arc<HostPool>, db_pool: DbPool, config: LifecycleConfig) -> Self {
         Self {
-            docker,
+            hosts,
             db_pool,
             config,
             health_cache: Arc::new(RwLock::new(HashMap::new())),
+            reaped: broadcast::channel(256).0,
         }
     }
 
+    /// IDs of sessions terminated here: expired, over their disk quota or
+    /// whose container was removed as an orphan
+    pub fn subscribe(&self) -> broadcast::Receiver<Uuid> {
+        self.reaped.subscribe()
+    }
+
+    async fn reap(&self, session_id: Uuid) {
+        self.health_cache.write().await.remove(&session_id);
+        let _ = self.reaped.send(session_id);
+    }
+
     /// Start all background tasks
     pub async fn start(self: Arc<Self>) {
         info!("Starting lifecycle management background tasks");
@@ -83,6 +124,7 @@ impl LifecycleManager {
         let health_manager = self.clone();
         let metrics_manager = self.clone();
         let orphan_manager = self.clone();
+        let disk_manager = self.clone();
 
         // Spawn cleanup task
         tokio::spawn(async move {
@@ -104,6 +146,11 @@ impl LifecycleManager {
             orphan_manager.run_orphan_detection_task().await;
         });
 
+        // Spawn disk quota watcher
+        tokio::spawn(async move {
+            disk_manager.run_disk_quota_task().await;
+        });
+
         info!("Lifecycle management tasks started");
     }
 
@@ -126,7 +173,7 @@ impl LifecycleManager {
 
                         // Stop and remove container if exists
                         if let Some(container_id) = &session.container_id {
-                            if let Err(e) = self.stop_container(container_id).await {
+                            if let Err(e) = self.stop_container(&self.docker_for(&session), container_id).await {
                                 warn!("Failed to stop container {}: {}", container_id, e);
                             }
                         }
@@ -150,8 +197,7 @@ impl LifecycleManager {
                         )
                         .await;
 
-                        // Remove from health cache
-                        self.health_cache.write().await.remove(&session.id);
+                        self.reap(session.id).await;
                     }
                 }
                 Err(e) => {
@@ -160,10 +206,24 @@ impl LifecycleManager {
             }
 
             // Run database cleanup
-            if let Err(e) = db::cleanup::run_all(&self.db_pool).await {
+            if let Err(e) = db::cleanup::run_all(&self.db_pool, self.config.history_retention_days).await {
                 error!("Database cleanup failed: {}", e);
             }
+
+            self.cleanup_finished_jobs().await;
+        }
+    }
+
+    /// Remove finished jobs past retention, with their logs and artifacts
+    async fn cleanup_finished_jobs(&self) {
+        let retention = Duration::from_secs(self.config.job_retention_hours * 3600);
+        match db::jobs::delete_finished_before(&self.db_pool, self.config.job_retention_hours).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Removed {} expired jobs", deleted),
+            Err(e) => error!("Failed to remove expired jobs: {}", e),
         }
+        // Directories are only written until the job finishes
+        JobStore::new(&self.config.job_artifact_dir, 0).purge_older_than(retention).await;
     }
 
     /// Health check task - monitors container status
@@ -174,12 +234,13 @@ impl LifecycleManager {
             ticker.tick().await;
             debug!("Running health check task");
 
-            // Get all running sessions
-            match db::sessions::list(&self.db_pool, None, Some("running"), 1000).await {
+            // Get this instance's running sessions
+            match db::sessions::list_running(&self.db_pool, &self.config.instance_id, 1000).await {
                 Ok(sessions) => {
                     for session in sessions {
                         if let Some(container_id) = &session.container_id {
-                            match self.check_container_health(container_id, session.id).await {
+                            let docker = self.docker_for(&session);
+                            match self.check_container_health(&docker, container_id, session.id).await {
                                 Ok(health) => {
                                     // Update health cache
                                     self.health_cache.write().await.insert(session.id, health);
@@ -191,7 +252,7 @@ impl LifecycleManager {
                                     );
 
                                     // Container might have crashed - check if it exists
-                                    if let Ok(false) = self.container_exists(container_id).await {
+                                    if let Ok(false) = self.container_exists(&docker, container_id).await {
                                         warn!(
                                             "Container {} no longer exists, marking session {} as disconnected",
                                             container_id, session.id
@@ -261,7 +322,8 @@ impl LifecycleManager {
         }
     }
 
-    /// Orphan container detection - finds and removes containers not tracked in DB
+    /// Orphan container detection - finds and removes this instance's
+    /// containers whose session is no longer live in the DB
     async fn run_orphan_detection_task(&self) {
         // Run less frequently
         let mut ticker = interval(Duration::from_secs(300)); // Every 5 minutes
@@ -270,43 +332,202 @@ impl LifecycleManager {
             ticker.tick().await;
             debug!("Running orphan container detection");
 
-            // List all noxterm containers
-            match self.list_noxterm_containers().await {
-                Ok(container_ids) => {
-                    for container_id in container_ids {
-                        // Check if this container is tracked in DB
-                        let is_tracked = self.is_container_tracked(&container_id).await;
-
-                        if !is_tracked {
-                            warn!(
-                                "Found orphan container {}, scheduling for removal",
-                                container_id
-                            );
-
-                            // Stop and remove orphan container
-                            if let Err(e) = self.stop_container(&container_id).await {
-                                error!("Failed to remove orphan container {}: {}", container_id, e);
-                            } else {
-                                info!("Removed orphan container {}", container_id);
+            let live: HashSet<Uuid> = match db::sessions::live_ids(&self.db_pool, &self.config.instance_id).await {
+                Ok(ids) => ids.into_iter().collect(),
+                Err(e) => {
+                    error!("Failed to list live sessions: {}", e);
+                    continue;
+                }
+            };
+
+            // List all containers labelled for this instance, on every host
+            for host in self.hosts.all() {
+                match self.list_noxterm_containers(&host.docker).await {
+                    Ok(containers) => {
+                        for (container_id, session_id) in containers {
+                            let is_tracked = session_id.is_some_and(|id| live.contains(&id));
+
+                            if !is_tracked {
+                                warn!(
+                                    "Found orphan container {} on {}, scheduling for removal",
+                                    container_id, host.name
+                                );
+
+                                // Stop and remove orphan container
+                                if let Err(e) = self.stop_container(&host.docker, &container_id).await {
+                                    error!("Failed to remove orphan container {}: {}", container_id, e);
+                                } else {
+                                    info!("Removed orphan container {}", container_id);
+                                }
+                                if let Some(session_id) = session_id {
+                                    self.reap(session_id).await;
+                                }
                             }
                         }
                     }
+                    Err(e) => {
+                        error!("Failed to list containers on {}: {}", host.name, e);
+                    }
                 }
+            }
+        }
+    }
+
+    /// Disk quota watcher - measures writable-layer size and terminates sessions over quota
+    async fn run_disk_quota_task(&self) {
+        let mut ticker = interval(Duration::from_secs(self.config.disk_check_interval_secs));
+        // Sessions already warned about, so the warning is recorded once per
+        // crossing of the threshold rather than on every check
+        let mut near_quota: HashSet<Uuid> = HashSet::new();
+
+        loop {
+            ticker.tick().await;
+            debug!("Running disk quota check");
+
+            let sessions = match db::sessions::list_running(&self.db_pool, &self.config.instance_id, 1000).await {
+                Ok(sessions) => sessions,
                 Err(e) => {
-                    error!("Failed to list containers: {}", e);
+                    error!("Failed to get running sessions for disk check: {}", e);
+                    continue;
+                }
+            };
+
+            let running: HashSet<Uuid> = sessions.iter().map(|session| session.id).collect();
+            near_quota.retain(|id| running.contains(id));
+
+            for session in sessions {
+                let Some(container_id) = &session.container_id else {
+                    continue;
+                };
+
+                let docker = self.docker_for(&session);
+                let size_rw = match self.container_disk_usage(&docker, container_id).await {
+                    Ok(Some(size)) => size,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        debug!("Disk usage check failed for container {}: {}", container_id, e);
+                        continue;
+                    }
+                };
+
+                let quota_mb = self.disk_quota_for(&session);
+                match storage::check_quota(size_rw, quota_mb, self.config.disk_warn_percent) {
+                    QuotaState::Within => {
+                        near_quota.remove(&session.id);
+                    }
+                    QuotaState::Near => {
+                        if !near_quota.insert(session.id) {
+                            continue;
+                        }
+                        warn!(
+                            "Session {} is near its disk quota: {} MB of {} MB",
+                            session.id,
+                            size_rw / (1024 * 1024),
+                            quota_mb
+                        );
+
+                        let _ = db::security::log_event(
+                            &self.db_pool,
+                            Some(session.id),
+                            &session.user_id,
+                            "disk_quota_warning",
+                            db::security::Severity::Warning,
+                            Some(&format!(
+                                "Writable layer at {} MB of {} MB quota",
+                                size_rw / (1024 * 1024),
+                                quota_mb
+                            )),
+                            None,
+                            None,
+                        )
+                        .await;
+                    }
+                    QuotaState::Exceeded => {
+                        near_quota.remove(&session.id);
+                        warn!(
+                            "Session {} exceeded its disk quota ({} MB > {} MB), terminating",
+                            session.id,
+                            size_rw / (1024 * 1024),
+                            quota_mb
+                        );
+
+                        if let Err(e) = self.stop_container(&docker, container_id).await {
+                            error!("Failed to stop over-quota container {}: {}", container_id, e);
+                        }
+
+                        if let Err(e) = db::sessions::terminate(&self.db_pool, session.id).await {
+                            error!("Failed to terminate session {}: {}", session.id, e);
+                        }
+
+                        let _ = db::security::log_event(
+                            &self.db_pool,
+                            Some(session.id),
+                            &session.user_id,
+                            "disk_quota_exceeded",
+                            db::security::Severity::Critical,
+                            Some(&format!(
+                                "Writable layer reached {} MB, quota is {} MB",
+                                size_rw / (1024 * 1024),
+                                quota_mb
+                            )),
+                            None,
+                            None,
+                        )
+                        .await;
+
+                        let _ = db::audit::log(
+                            &self.db_pool,
+                            Some(session.id),
+                            &session.user_id,
+                            db::audit::EventType::SessionTerminated,
+                            Some(serde_json::json!({
+                                "reason": "disk_quota_exceeded",
+                                "size_rw": size_rw,
+                                "quota_mb": quota_mb
+                            })),
+                            None,
+                            None,
+                        )
+                        .await;
+
+                        self.reap(session.id).await;
+                    }
                 }
             }
         }
     }
 
+    /// Size of a container's writable layer in bytes
+    async fn container_disk_usage(&self, docker: &Docker, container_id: &str) -> Result<Option<i64>, anyhow::Error> {
+        let inspect = docker
+            .inspect_container(container_id, Some(InspectContainerOptions { size: true }))
+            .await?;
+        Ok(inspect.size_rw)
+    }
+
+    /// Client for the host a session's container runs on
+    fn docker_for(&self, session: &db::DbSession) -> Arc<Docker> {
+        self.hosts.docker_for(hosts::host_of(&session.metadata).unwrap_or(&self.hosts.primary().name))
+    }
+
+    /// Disk quota for a session, from its stored resource limits
+    fn disk_quota_for(&self, session: &db::DbSession) -> i64 {
+        session
+            .resource_limits
+            .get("disk_mb")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(self.config.disk_quota_mb)
+    }
+
     /// Check health of a specific container
     async fn check_container_health(
         &self,
+        docker: &Docker,
         container_id: &str,
         session_id: Uuid,
     ) -> Result<ContainerHealth, anyhow::Error> {
         // Get container stats
-        let mut stats_stream = self.docker.stats(
+        let mut stats_stream = docker.stats(
             container_id,
             Some(StatsOptions {
                 stream: false,
@@ -372,9 +593,8 @@ impl LifecycleManager {
     }
 
     /// Check if container exists
-    async fn container_exists(&self, container_id: &str) -> Result<bool, anyhow::Error> {
-        match self
-            .docker
+    async fn container_exists(&self, docker: &Docker, container_id: &str) -> Result<bool, anyhow::Error> {
+        match docker
             .inspect_container(container_id, None::<InspectContainerOptions>)
             .await
         {
@@ -387,10 +607,9 @@ impl LifecycleManager {
     }
 
     /// Stop and remove a container gracefully
-    pub async fn stop_container(&self, container_id: &str) -> Result<(), anyhow::Error> {
+    pub async fn stop_container(&self, docker: &Docker, container_id: &str) -> Result<(), anyhow::Error> {
         // Try graceful stop first (SIGTERM)
-        let stop_result = self
-            .docker
+        let stop_result = docker
             .stop_container(
                 container_id,
                 Some(StopContainerOptions { t: 10 }), // 10 second timeout
@@ -410,13 +629,12 @@ impl LifecycleManager {
             Err(e) => {
                 warn!("Graceful stop failed for {}, forcing: {}", container_id, e);
                 // Force kill
-                let _ = self.docker.kill_container::<String>(container_id, None).await;
+                let _ = docker.kill_container::<String>(container_id, None).await;
             }
         }
 
         // Remove container
-        match self
-            .docker
+        match docker
             .remove_container(
                 container_id,
                 Some(bollard::container::RemoveContainerOptions {
@@ -440,46 +658,29 @@ impl LifecycleManager {
         }
     }
 
-    /// List all noxterm session containers (not infrastructure like postgres)
-    async fn list_noxterm_containers(&self) -> Result<Vec<String>, anyhow::Error> {
+    /// List this instance's containers with the session each is labelled
+    /// for. Infrastructure (noxterm-postgres) and other instances' sessions
+    /// carry no matching label and are never returned
+    async fn list_noxterm_containers(&self, docker: &Docker) -> Result<Vec<(String, Option<Uuid>)>, anyhow::Error> {
         use bollard::container::ListContainersOptions;
-        use std::collections::HashMap;
 
-        let mut filters = HashMap::new();
-        // Only target session containers (noxterm-session-*), not infrastructure (noxterm-postgres)
-        filters.insert("name", vec!["noxterm-session-"]);
-
-        let containers = self
-            .docker
+        let containers = docker
             .list_containers(Some(ListContainersOptions {
                 all: true,
-                filters,
+                filters: labels::instance_filter(&self.config.instance_id),
                 ..Default::default()
             }))
             .await?;
 
         Ok(containers
-            .iter()
-            .filter_map(|c| c.id.clone())
+            .into_iter()
+            .filter_map(|c| {
+                let session_id = c.labels.as_ref().and_then(labels::session_id);
+                Some((c.id?, session_id))
+            })
             .collect())
     }
 
-    /// Check if a container is tracked in the database
-    async fn is_container_tracked(&self, container_id: &str) -> bool {
-        // Query database for this container ID
-        let result: Result<Option<(i64,)>, _> = sqlx::query_as(
-            "SELECT 1 FROM sessions WHERE container_id = $1 AND status != 'terminated' LIMIT 1",
-        )
-        .bind(container_id)
-        .fetch_optional(&self.db_pool)
-        .await;
-
-        match result {
-            Ok(Some(_)) => true,
-            _ => false,
-        }
-    }
-
     /// Get cached health status for a session
     pub async fn get_health(&self, session_id: Uuid) -> Option<ContainerHealth> {
         self.health_cache.read().await.get(&session_id).cloned()
@@ -495,6 +696,11 @@ impl LifecycleManager {
         self.health_cache.write().await.remove(&session_id);
     }
 
+    /// How long disconnected sessions stay reattachable
+    pub fn grace_period_secs(&self) -> i64 {
+        self.config.grace_period_secs
+    }
+
     /// Check if user can create more containers
     pub async fn can_create_container(&self, user_id: &str) -> Result<bool, anyhow::Error> {
         let count = db::sessions::count_active_by_user(&self.db_pool, user_id).await?;
@@ -516,5 +722,6 @@ mod tests {
         let config = LifecycleConfig::default();
         assert_eq!(config.grace_period_secs, 300);
         assert_eq!(config.max_containers_per_user, 3);
+        assert_eq!(config.disk_quota_mb, 2048);
     }
 }
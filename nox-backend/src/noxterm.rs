@@ -20,24 +20,26 @@ use tower_http::cors::CorsLayer;
 use tracing::{info, warn, error, debug};
 use uuid::Uuid;
 
-mod anyone_service;
-mod db;
-mod lifecycle;
-mod security;
+use noxterm::{
+    anyone_control, anyone_service, anyone_supervisor, config, container, db, doctor, egress, hosts, jobs, lifecycle,
+    privacy, reconcile, security, shutdown,
+};
 
 use anyone_service::AnyoneService;
+use anyone_supervisor::SupervisorEvent;
 use db::DbPool;
 use lifecycle::{LifecycleConfig, LifecycleManager};
 use security::{
     validate_user_id, validate_image_name, extract_client_ip,
-    validate_input, sanitize_container_name, Severity as SecuritySeverity,
+    validate_with, sanitize_container_name, Severity as SecuritySeverity,
 };
 
 // Re-import sqlx for query execution in handlers
 use sqlx;
 
-/// Cross-platform Docker connection with automatic setup
-async fn connect_docker() -> Result<Docker> {
+/// Cross-platform Docker connection, starting or installing Docker when
+/// `auto_install` allows it
+async fn connect_docker(auto_install: bool) -> Result<Docker> {
     // Check for explicit DOCKER_HOST environment variable first
     if let Ok(docker_host) = std::env::var("DOCKER_HOST") {
         info!("Using DOCKER_HOST: {}", docker_host);
@@ -47,25 +49,16 @@ async fn connect_docker() -> Result<Docker> {
 
     let home = std::env::var("HOME").unwrap_or_default();
 
-    // Platform-specific socket paths to try
+    // Platform-specific socket paths to try, including rootless Docker and
+    // Podman's Docker-compatible API
     let socket_paths: Vec<String> = if cfg!(target_os = "macos") {
-        vec![
-            "/var/run/docker.sock".to_string(),
-            format!("{}/.docker/run/docker.sock", home),
-            "/Users/Shared/docker/docker.sock".to_string(),
-            format!("{}/.orbstack/run/docker.sock", home),
-            format!("{}/.colima/default/docker.sock", home),
-        ]
+        container::engine::macos_sockets(&home)
     } else if cfg!(target_os = "windows") {
         vec![
             "npipe:////./pipe/docker_engine".to_string(),
         ]
     } else {
-        vec![
-            "/var/run/docker.sock".to_string(),
-            "/run/docker.sock".to_string(),
-            format!("{}/.docker/run/docker.sock", home),
-        ]
+        container::engine::linux_sockets(&home, container::engine::runtime_dir().as_deref())
     };
 
     // First attempt: try to connect to existing Docker
@@ -73,6 +66,14 @@ async fn connect_docker() -> Result<Docker> {
         return Ok(docker);
     }
 
+    if !auto_install {
+        return Err(anyhow::anyhow!(
+            "Failed to connect to Docker.\n\n{}\n\n\
+            Automatic installation is off (NOXTERM_AUTO_INSTALL). Run `noxterm-backend doctor` to check the setup.",
+            doctor::docker_install_hint()
+        ));
+    }
+
     // No Docker running - try to start or install it
     info!("Docker not running. Attempting to start or install...");
 
@@ -156,23 +157,27 @@ async fn connect_docker() -> Result<Docker> {
         return Ok(docker);
     }
 
-    // Platform-specific error message
-    let install_instructions = if cfg!(target_os = "macos") {
-        "macOS: Run 'brew install colima docker && colima start'"
-    } else if cfg!(target_os = "linux") {
-        "Linux: Run 'curl -fsSL https://get.docker.com | sudo sh && sudo systemctl start docker'"
-    } else if cfg!(target_os = "windows") {
-        "Windows: Download and install Docker Desktop from https://docker.com/products/docker-desktop"
-    } else {
-        "Please install Docker for your platform from https://docker.com"
-    };
-
     Err(anyhow::anyhow!(
         "Failed to connect to Docker.\n\n{}\n\nAfter installation, restart NOXTERM.",
-        install_instructions
+        doctor::docker_install_hint()
     ))
 }
 
+/// Connect to the configured Docker hosts, or to the local daemon when none
+/// are configured
+async fn connect_hosts(settings: &config::Config, auto_install: bool) -> Result<hosts::HostPool> {
+    if !settings.docker.hosts.is_empty() {
+        return hosts::HostPool::connect(&settings.docker).await;
+    }
+
+    let docker = connect_docker(auto_install).await?;
+    let version = docker.version().await
+        .map_err(|e| anyhow::anyhow!("Docker daemon not responding. Is Docker running?\nError: {}", e))?;
+    info!("✅ Docker connected successfully");
+    info!("Docker version: {}", version.version.unwrap_or_else(|| "unknown".to_string()));
+    Ok(hosts::HostPool::local(docker))
+}
+
 fn try_connect_docker(socket_paths: &[String]) -> Option<Docker> {
     for socket_path in socket_paths {
         if socket_path.is_empty() {
@@ -471,16 +476,36 @@ async fn install_and_start_colima() -> Result<bool> {
 struct AppState {
     /// In-memory session cache (for fast access, backed by PostgreSQL)
     sessions: Arc<RwLock<HashMap<Uuid, Session>>>,
-    /// Docker client
-    docker: Arc<Docker>,
+    /// Docker hosts sessions are placed on
+    hosts: Arc<hosts::HostPool>,
     /// Application configuration
     config: AppConfig,
+    /// NOXTERM_* settings (container sandbox, sessions, privacy)
+    settings: Arc<config::Config>,
     /// Anyone Protocol service for privacy mode
     anyone_service: Arc<AnyoneService>,
+    /// SOCKS proxy enforcing per-session egress on the internal network
+    egress: Arc<egress::EgressProxy>,
+    /// Resolver answering privacy sessions' DNS through Anyone
+    dns: Arc<egress::dns::DnsResolver>,
+    /// Per-user privacy defaults when no database is configured
+    privacy_defaults: Arc<RwLock<HashMap<String, bool>>>,
+    /// SHA-256 of claimed user keys when no database is configured
+    user_keys: Arc<RwLock<HashMap<String, String>>>,
+    /// Batch jobs that are running, and finished ones when there is no database
+    jobs: Arc<RwLock<HashMap<Uuid, Arc<Job>>>>,
+    /// Logs and artifacts of finished jobs
+    job_store: jobs::JobStore,
     /// PostgreSQL connection pool (optional - falls back to in-memory if unavailable)
     db_pool: Option<DbPool>,
     /// Lifecycle manager for container cleanup and health monitoring
     lifecycle_manager: Option<Arc<LifecycleManager>>,
+    /// Command policy checked before input reaches a session
+    policy: Arc<security::Policy>,
+    /// Set once shutdown starts; attached terminals and jobs hold it open
+    drain: Arc<shutdown::Drain>,
+    /// Docker events (crashes, OOM kills, health) of this instance's containers
+    container_events: Arc<container::events::Watcher>,
 }
 
 #[derive(Clone, Debug)]
@@ -498,6 +523,55 @@ struct Session {
     container_name: Option<String>,
     created_at: chrono::DateTime<chrono::Utc>,
     container_image: String,
+    /// OCI runtime the container was started with
+    runtime: Option<String>,
+    /// Resource tier selected at creation
+    tier: String,
+    resource_limits: db::ResourceLimits,
+    /// Docker host the session was placed on
+    host: String,
+    network: SessionNetwork,
+    /// Whether this session's traffic goes through Anyone
+    privacy: bool,
+    /// SOCKS credentials keeping this session on its own Anyone circuits
+    #[serde(skip)]
+    socks_auth: egress::socks::Credentials,
+    /// SHA-256 of the token handed to the session's creator
+    #[serde(skip)]
+    token_sha256: String,
+    /// Set when the session only holds a batch job's container
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<Uuid>,
+}
+
+/// Network egress settings chosen at creation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SessionNetwork {
+    /// none, anyone-only or open
+    mode: String,
+    /// Domains and CIDRs the session may reach; empty means unrestricted
+    allowlist: Vec<String>,
+}
+
+impl SessionNetwork {
+    fn mode(&self) -> config::NetworkMode {
+        // Validated at creation; anything unreadable gets no network at all
+        self.mode.parse().unwrap_or(config::NetworkMode::None)
+    }
+}
+
+/// A session container that has been created and started
+struct StartedContainer {
+    id: String,
+    name: String,
+    runtime: container::ResolvedRuntime,
+    network: config::NetworkMode,
+    /// SOCKS URL of the egress proxy when traffic is forced through it
+    egress_proxy: Option<String>,
+    /// Whether a netguard sidecar redirects all TCP through the proxy
+    transparent: bool,
+    /// Whether this is a container adopted from before a restart
+    resumed: bool,
 }
 
 
@@ -505,6 +579,19 @@ struct Session {
 struct CreateSessionRequest {
     user_id: String,
     container_image: Option<String>,
+    /// Resource tier name (defaults to the configured default tier)
+    tier: Option<String>,
+    /// Network egress mode: none, anyone-only or open
+    network: Option<String>,
+    /// Domains (`example.com`, `*.example.com`) and CIDRs the session may reach
+    allowlist: Option<Vec<String>>,
+    /// Route this session through Anyone (defaults to the user's preference)
+    privacy: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ResizeSessionRequest {
+    tier: String,
 }
 
 #[derive(Serialize)]
@@ -512,14 +599,25 @@ struct CreateSessionResponse {
     session_id: Uuid,
     websocket_url: String,
     status: String,
+    /// Proves ownership of the session; only returned here
+    session_token: String,
+    /// Proves the user ID from now on; only returned when this request
+    /// claimed it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_key: Option<String>,
 }
 
 #[derive(Serialize)]
 struct PrivacyStatusResponse {
+    /// The user's default for new sessions
     enabled: bool,
     socks_port: Option<u16>,
     control_port: Option<u16>,
     status: String,
+    /// Privacy network in use: anyone, tor or upstream
+    backend: String,
+    /// Sessions currently keeping the Anyone daemon running
+    active_sessions: usize,
 }
 
 #[derive(Serialize)]
@@ -529,9 +627,15 @@ struct PrivacyResponse {
     message: String,
 }
 
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
+async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    // Load balancers stop routing here as soon as shutdown starts
+    let (code, status) = if state.drain.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "healthy")
+    };
+    (code, Json(serde_json::json!({
+        "status": status,
         "service": "noxterm-production",
         "version": env!("CARGO_PKG_VERSION"),
         "build_time": env!("BUILD_TIME"),
@@ -542,16 +646,33 @@ async fn health_check() -> impl IntoResponse {
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs()
-    }))
+    })))
+}
+
+/// Refuse new sessions and jobs once shutdown has started
+fn reject_if_draining(state: &AppState) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if !state.drain.is_draining() {
+        return Ok(());
+    }
+    Err((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "error": "Server is shutting down",
+            "details": "Retry once NOXTERM has restarted"
+        })),
+    ))
 }
 
 // Create session endpoint with validation and database persistence
 async fn create_session(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Json(payload): Json<CreateSessionRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    reject_if_draining(&state)?;
+
     // Extract client IP for rate limiting and audit
     let xff = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok());
     let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
@@ -606,6 +727,7 @@ async fn create_session(
             })),
         ));
     }
+    let unclaimed = authorize_new_work(&state, &payload.user_id, &headers, &params).await?;
 
     // Validate container image if provided
     let container_image = payload.container_image.unwrap_or_else(|| "ubuntu:22.04".to_string());
@@ -620,11 +742,219 @@ async fn create_session(
         ));
     }
 
+    // Resolve the resource tier for this user
+    let tier_name = payload.tier.clone().unwrap_or_else(|| state.settings.tiers.default_tier.clone());
+    let tier = authorize_tier(&state, &payload.user_id, &tier_name, None).await?;
+    let resource_limits = container::limits::from_tier(&tier);
+
+    let privacy = match payload.privacy {
+        Some(privacy) => privacy,
+        None => privacy_default(&state, &payload.user_id).await,
+    };
+    let network = resolve_session_network(&state, payload.network.as_deref(), payload.allowlist.clone(), privacy).await?;
+    let privacy = privacy || network.mode() == config::NetworkMode::AnyoneOnly;
+
+    check_container_limit(&state, &payload.user_id).await?;
+    let host = place_session(&state, &payload.user_id, &resource_limits, privacy, &network).await?;
+
+    let session_id = Uuid::new_v4();
+
+    hold_anyone(&state, session_id, privacy, &network).await?;
+
+    let (session_token, token_sha256) = new_token();
+    let session = Session {
+        id: session_id,
+        user_id: payload.user_id.clone(),
+        status: "created".to_string(),
+        container_id: None,
+        container_name: None,
+        created_at: chrono::Utc::now(),
+        container_image: container_image.clone(),
+        runtime: None,
+        tier: tier.name.clone(),
+        resource_limits: resource_limits.clone(),
+        network: network.clone(),
+        privacy,
+        socks_auth: egress::socks::Credentials::for_session(session_id),
+        token_sha256,
+        job_id: None,
+        host: host.clone(),
+    };
+
+    let websocket_url = format!("ws://{}:{}/ws/{}", state.config.host, state.config.port, session_id);
+
+    let audit = serde_json::json!({
+        "container_image": container_image,
+        "websocket_url": websocket_url,
+        "tier": tier.name,
+        "resource_limits": resource_limits,
+        "network": network,
+        "privacy": privacy,
+        "host": host
+    });
+    register_session(&state, session.clone(), audit, client_ip.as_deref(), user_agent.as_deref()).await;
+    // Claimed only now, so a request that fails does not lose the ID
+    let user_key = if unclaimed { Some(claim_user(&state, &session).await?) } else { None };
+
+    info!("Created session {} for user {}", session_id, payload.user_id);
+
+    let response = CreateSessionResponse {
+        session_id,
+        websocket_url,
+        status: "created".to_string(),
+        session_token,
+        user_key,
+    };
+
+    Ok(Json(response))
+}
+
+/// A session, job or user token and the SHA-256 kept of it
+fn new_token() -> (String, String) {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let sha256 = token_sha256(&token);
+    (token, sha256)
+}
+
+fn token_sha256(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Session token sent as `Authorization: Bearer` or, where headers cannot
+/// be set, as `?token=`
+fn presented_token<'a>(headers: &'a HeaderMap, params: &'a HashMap<String, String>) -> Option<&'a str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| params.get("token").map(String::as_str))
+}
+
+/// The session, if the request carries its token
+async fn authorize_session(
+    state: &AppState,
+    session_id: Uuid,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<Session, (StatusCode, Json<serde_json::Value>)> {
+    let Some(session) = state.sessions.read().await.get(&session_id).cloned() else {
+        return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Session not found" }))));
+    };
+    match presented_token(headers, params) {
+        Some(token) if !session.token_sha256.is_empty() && token_sha256(token) == session.token_sha256 => Ok(session),
+        _ => {
+            warn!("Denied access to session {} without its token", session_id);
+            Err((StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "Session token required" }))))
+        }
+    }
+}
+
+/// SHA-256 of the key proving a user ID: set by the operator, or handed to
+/// whoever claimed the ID. None while it is unclaimed.
+async fn user_key_sha256(state: &AppState, user_id: &str) -> Result<Option<String>, (StatusCode, Json<serde_json::Value>)> {
+    if let Some(pinned) = state.settings.security.user_keys.get(user_id) {
+        return Ok(Some(pinned.to_ascii_lowercase()));
+    }
+    match state.db_pool {
+        Some(ref pool) => db::credentials::get_user_key(pool, user_id).await.map_err(user_key_failed),
+        None => Ok(state.user_keys.read().await.get(user_id).cloned()),
+    }
+}
+
+fn user_key_failed(e: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    error!("User key query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({
+            "error": "User key query failed",
+            "details": e.to_string()
+        })),
+    )
+}
+
+/// Refuse unless the request carries the user's key
+async fn authorize_user(
+    state: &AppState,
+    user_id: &str,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if let (Some(expected), Some(token)) = (user_key_sha256(state, user_id).await?, presented_token(headers, params)) {
+        if token_sha256(token) == expected {
+            return Ok(());
+        }
+    }
+    warn!("Denied access to user {}'s data without the user key", user_id);
+    Err((
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "error": "User key required",
+            "details": "Pass the user_key returned when this user ID was first used"
+        })),
+    ))
+}
+
+/// Whether a request creating sessions or jobs as `user_id` is the first to
+/// use it. A claimed ID needs its key; an unclaimed one is claimed with
+/// `claim_user` once the request has succeeded.
+async fn authorize_new_work(
+    state: &AppState,
+    user_id: &str,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<bool, (StatusCode, Json<serde_json::Value>)> {
+    if user_key_sha256(state, user_id).await?.is_none() {
+        return Ok(true);
+    }
+    authorize_user(state, user_id, headers, params).await?;
+    Ok(false)
+}
+
+/// Claim an unclaimed user ID with a new key, returned to hand out. Fails
+/// if another request claimed it first, in which case `session` is torn down.
+async fn claim_user(state: &AppState, session: &Session) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    let (key, sha256) = new_token();
+    let claimed = match state.db_pool {
+        Some(ref pool) => db::credentials::claim_user(pool, &session.user_id, &sha256).await.map_err(user_key_failed),
+        None => match state.user_keys.write().await.entry(session.user_id.clone()) {
+            std::collections::hash_map::Entry::Occupied(_) => Ok(false),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(sha256);
+                Ok(true)
+            }
+        },
+    };
+    match claimed {
+        Ok(true) => {
+            info!("User ID {} claimed", session.user_id);
+            Ok(key)
+        }
+        Ok(false) => {
+            teardown_session(state, session, "user_id_claimed").await;
+            warn!("User ID {} was claimed by another request", session.user_id);
+            Err((
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": "User key required",
+                    "details": "This user ID was just claimed by another request"
+                })),
+            ))
+        }
+        Err(e) => {
+            teardown_session(state, session, "user_id_claim_failed").await;
+            Err(e)
+        }
+    }
+}
+
+/// Refuse a new container when the user is at the per-user limit
+async fn check_container_limit(state: &AppState, user_id: &str) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
     // Check container limit if lifecycle manager is available
     if let Some(ref lifecycle) = state.lifecycle_manager {
-        match lifecycle.can_create_container(&payload.user_id).await {
+        match lifecycle.can_create_container(user_id).await {
             Ok(false) => {
-                warn!("User {} at container limit", payload.user_id);
+                warn!("User {} at container limit", user_id);
                 return Err((
                     StatusCode::TOO_MANY_REQUESTS,
                     Json(serde_json::json!({
@@ -642,50 +972,136 @@ async fn create_session(
         }
     }
 
-    let session_id = Uuid::new_v4();
-    let session = Session {
-        id: session_id,
-        user_id: payload.user_id.clone(),
-        status: "created".to_string(),
-        container_id: None,
-        container_name: None,
-        created_at: chrono::Utc::now(),
-        container_image: container_image.clone(),
+    Ok(())
+}
+
+/// Pick the Docker host a new session's container will run on
+async fn place_session(
+    state: &AppState,
+    user_id: &str,
+    limits: &db::ResourceLimits,
+    privacy: bool,
+    network: &SessionNetwork,
+) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    let mut committed: HashMap<String, hosts::Load> = HashMap::new();
+    let mut affinity = None;
+    for session in state.sessions.read().await.values() {
+        *committed.entry(session.host.clone()).or_default() += hosts::Load {
+            memory_mb: session.resource_limits.memory_mb,
+            cpu_percent: session.resource_limits.cpu_percent,
+        };
+        if session.user_id == user_id {
+            affinity = Some(session.host.clone());
+        }
+    }
+
+    let candidates = state.hosts.candidates(&committed, state.settings.docker.host_memory_percent).await;
+    let request = hosts::Request {
+        load: hosts::Load { memory_mb: limits.memory_mb, cpu_percent: limits.cpu_percent },
+        // The egress proxy, DNS resolver and Anyone proxy only listen here
+        needs_local: privacy || network.mode() != config::NetworkMode::Open || !network.allowlist.is_empty(),
+        needs_limits: !state.settings.docker.allow_unlimited,
+        affinity: affinity.as_deref(),
     };
+    hosts::place(&candidates, &request).ok_or_else(|| {
+        warn!("No Docker host has room for a session of user {} ({} MB)", user_id, limits.memory_mb);
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "error": "No Docker host has room",
+                "details": "All Docker hosts are full, unreachable, cannot route this session's network or cannot enforce its resource limits. Try a smaller tier or retry later."
+            })),
+        )
+    })
+}
 
-    let websocket_url = format!("ws://{}:{}/ws/{}", state.config.host, state.config.port, session_id);
+/// What the engine on a session's host supports, as of its last health
+/// check or asked now if it has not answered one yet
+async fn host_engine(state: &AppState, host: &str, docker: &Docker) -> Result<container::engine::Capabilities> {
+    if let Some(host) = state.hosts.get(host) {
+        if let Some(engine) = host.status().await.engine {
+            return Ok(engine);
+        }
+    }
+    container::engine::detect(docker).await
+}
+
+/// Docker client for the host a session's container runs on
+async fn session_docker(state: &AppState, session_id: Uuid) -> Arc<Docker> {
+    match state.sessions.read().await.get(&session_id) {
+        Some(session) => state.hosts.docker_for(&session.host),
+        None => state.hosts.primary().docker.clone(),
+    }
+}
+
+/// Each privacy session keeps the daemon up until it is torn down
+async fn hold_anyone(
+    state: &AppState,
+    session_id: Uuid,
+    privacy: bool,
+    network: &SessionNetwork,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if privacy && network.mode() != config::NetworkMode::None {
+        if let Err(e) = state.anyone_service.acquire(session_id).await {
+            error!("Failed to start Anyone for session {}: {}", session_id, e);
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({
+                    "error": "Anyone network unavailable",
+                    "details": e.to_string()
+                })),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Persist a new session and add it to the cache
+async fn register_session(
+    state: &AppState,
+    session: Session,
+    audit: serde_json::Value,
+    client_ip: Option<&str>,
+    user_agent: Option<&str>,
+) {
+    let session_id = session.id;
 
     // Persist to database if available
     if let Some(ref pool) = state.db_pool {
-        let resource_limits = db::ResourceLimits {
-            memory_mb: 1024,
-            cpu_percent: 100,
-            pids_limit: 200,
-        };
+        let mut metadata = serde_json::json!({
+            "tier": session.tier,
+            "network": session.network,
+            "privacy": session.privacy,
+            "host": session.host,
+            "token_sha256": session.token_sha256,
+            "instance_id": state.settings.docker.instance_id
+        });
+        if let Some(job_id) = session.job_id {
+            metadata["job_id"] = serde_json::json!(job_id);
+        }
 
         if let Err(e) = db::sessions::create(
             pool,
             session_id,
-            &payload.user_id,
-            &container_image,
-            Some(resource_limits),
+            &session.user_id,
+            &session.container_image,
+            Some(session.resource_limits.clone()),
         ).await {
             error!("Failed to persist session to database: {}", e);
             // Continue with in-memory storage
+        } else if let Err(e) = db::sessions::merge_metadata(pool, session_id, metadata).await {
+            error!("Failed to store tier, network, privacy and host for session {}: {}", session_id, e);
         }
 
         // Log audit event
         let _ = db::audit::log(
             pool,
             Some(session_id),
-            &payload.user_id,
+            &session.user_id,
             db::audit::EventType::SessionCreated,
-            Some(serde_json::json!({
-                "container_image": container_image,
-                "websocket_url": websocket_url
-            })),
-            client_ip.as_deref(),
-            user_agent.as_deref(),
+            Some(audit),
+            client_ip,
+            user_agent,
         )
         .await;
     }
@@ -695,190 +1111,561 @@ async fn create_session(
         let mut sessions = state.sessions.write().await;
         sessions.insert(session_id, session);
     }
+}
 
-    info!("Created session {} for user {}", session_id, payload.user_id);
+/// Check that a tier exists and the user may run another session on it
+async fn authorize_tier(
+    state: &AppState,
+    user_id: &str,
+    tier_name: &str,
+    exclude_session: Option<Uuid>,
+) -> Result<config::ResourceTier, (StatusCode, Json<serde_json::Value>)> {
+    let Some(tier) = state.settings.tiers.get(tier_name) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "Unknown resource tier",
+                "details": format!("Tier '{}' is not configured", tier_name),
+                "available_tiers": state.settings.tiers.tiers.iter().map(|t| &t.name).collect::<Vec<_>>()
+            })),
+        ));
+    };
 
-    let response = CreateSessionResponse {
-        session_id,
-        websocket_url,
-        status: "created".to_string(),
+    let role = state.settings.security.role_for(user_id);
+    if !tier.allows_role(role) {
+        warn!("User {} (role {}) denied tier {}", user_id, role, tier.name);
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({
+                "error": "Resource tier not allowed",
+                "details": format!("Role '{}' may not use tier '{}'", role, tier.name)
+            })),
+        ));
+    }
+
+    if let Some(max) = tier.max_per_user {
+        let in_use = state.sessions.read().await
+            .values()
+            .filter(|s| s.user_id == user_id && s.tier == tier.name && Some(s.id) != exclude_session)
+            .count() as i64;
+
+        if in_use >= max {
+            return Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(serde_json::json!({
+                    "error": "Resource tier quota reached",
+                    "details": format!("At most {} '{}' session(s) per user", max, tier.name),
+                    "max_per_user": max
+                })),
+            ));
+        }
+    }
+
+    Ok(tier.clone())
+}
+
+/// Validate the requested egress mode and allowlist for a new session
+async fn resolve_session_network(
+    state: &AppState,
+    mode: Option<&str>,
+    allowlist: Option<Vec<String>>,
+    privacy: bool,
+) -> Result<SessionNetwork, (StatusCode, Json<serde_json::Value>)> {
+    let mode = match mode {
+        Some(mode) => mode.parse::<config::NetworkMode>().map_err(|e| (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "Invalid network mode",
+                "details": e
+            })),
+        ))?,
+        // Privacy sessions without an explicit mode get no direct egress
+        None if privacy => config::NetworkMode::AnyoneOnly,
+        None => state.settings.docker.network_mode,
     };
 
-    Ok(Json(response))
+    if mode == config::NetworkMode::Open && !state.settings.docker.allow_networking {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({
+                "error": "Network mode not allowed",
+                "details": "Open networking is disabled on this server"
+            })),
+        ));
+    }
+
+    let allowlist = allowlist.unwrap_or_default();
+    if !allowlist.is_empty() {
+        if mode == config::NetworkMode::None {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "Invalid allowlist",
+                    "details": "An allowlist needs a network mode other than none"
+                })),
+            ));
+        }
+
+        if let Err(e) = egress::Allowlist::parse(&allowlist) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "Invalid allowlist",
+                    "details": e
+                })),
+            ));
+        }
+    }
+
+    Ok(SessionNetwork {
+        mode: mode.to_string(),
+        allowlist,
+    })
 }
 
-// Get session endpoint
+// Get session endpoint; needs the session token
 async fn get_session(
     State(state): State<AppState>,
     Path(session_id): Path<Uuid>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let sessions = state.sessions.read().await;
-    
-    match sessions.get(&session_id) {
-        Some(session) => Ok(Json(session.clone())),
-        None => {
-            warn!("Session {} not found", session_id);
-            Err(StatusCode::NOT_FOUND)
-        }
-    }
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let session = authorize_session(&state, session_id, &headers, &params).await?;
+    Ok(Json(session))
 }
 
-// List sessions endpoint
+// List a user's sessions; needs `?user_id=` and the user key
 async fn list_sessions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<HashMap<String, String>>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user_id = params.get("user_id").map(String::as_str).unwrap_or_default();
+    authorize_user(&state, user_id, &headers, &params).await?;
+
     let sessions = state.sessions.read().await;
-    let user_id = params.get("user_id");
-    
     let filtered_sessions: Vec<&Session> = sessions
         .values()
-        .filter(|session| {
-            user_id.map_or(true, |uid| &session.user_id == uid)
-        })
+        .filter(|session| session.user_id == user_id)
         .collect();
 
-    Json(serde_json::json!({
+    Ok(Json(serde_json::json!({
         "sessions": filtered_sessions,
         "count": filtered_sessions.len()
-    }))
+    })))
 }
 
 // Privacy control endpoints
 
-// Enable privacy mode (start Anyone service)
-async fn enable_privacy(
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    info!("Enabling privacy mode...");
-
-    // Check if ports are available before starting
-    if let Err(e) = state.anyone_service.check_ports_available().await {
-        warn!("Port check failed: {}", e);
-        // Ports may be in use by a previous instance, continue anyway
-    }
-
-    match state.anyone_service.start().await {
-        Ok(_) => {
-            let socks_port = state.anyone_service.get_socks_port();
-            info!("Privacy mode enabled successfully on SOCKS port {}", socks_port);
-            
-            let response = PrivacyResponse {
-                status: "enabled".to_string(),
-                socks_port: Some(socks_port),
-                message: "Anyone Protocol network activated".to_string(),
-            };
-            Ok(Json(response))
-        }
-        Err(e) => {
-            error!("Failed to enable privacy mode: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+/// Privacy default for a user's new sessions
+async fn privacy_default(state: &AppState, user_id: &str) -> bool {
+    if let Some(ref pool) = state.db_pool {
+        match db::preferences::get_privacy_default(pool, user_id).await {
+            Ok(enabled) => return enabled.unwrap_or(false),
+            Err(e) => error!("Failed to load privacy default for {}: {}", user_id, e),
         }
     }
+
+    state.privacy_defaults.read().await.get(user_id).copied().unwrap_or(false)
 }
 
-// Disable privacy mode (stop Anyone service)
-async fn disable_privacy(
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    info!("Disabling privacy mode...");
-    
-    match state.anyone_service.stop().await {
-        Ok(_) => {
-            info!("Privacy mode disabled successfully");
-            
-            let response = PrivacyResponse {
-                status: "disabled".to_string(),
-                socks_port: None,
-                message: "Anyone Protocol network deactivated".to_string(),
-            };
-            Ok(Json(response))
+/// Record a user's privacy default; running sessions keep their own
+/// setting. Only the user may change it, with their user key
+async fn set_privacy_default(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    enabled: bool,
+) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    let user_id = match params.get("user_id") {
+        Some(user_id) if validate_user_id(user_id) => user_id.clone(),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "Invalid user_id",
+                    "details": "Privacy mode is set per user; pass a valid user_id"
+                })),
+            ));
         }
-        Err(e) => {
-            error!("Failed to disable privacy mode: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    authorize_user(state, &user_id, headers, params).await?;
+
+    if let Some(ref pool) = state.db_pool {
+        if let Err(e) = db::preferences::set_privacy_default(pool, &user_id, enabled).await {
+            error!("Failed to store privacy default for {}: {}", user_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": "Failed to store privacy default",
+                    "details": e.to_string()
+                })),
+            ));
         }
     }
+    state.privacy_defaults.write().await.insert(user_id.clone(), enabled);
+
+    info!("Privacy default for {} set to {}", user_id, enabled);
+    Ok(user_id)
 }
 
-// Get privacy status
-async fn privacy_status(
+// Enable privacy mode for the user's new sessions
+async fn enable_privacy(
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    let enabled = state.anyone_service.is_enabled().await;
-    let service_status = state.anyone_service.get_status().await;
-
-    let response = PrivacyStatusResponse {
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    set_privacy_default(&state, &headers, &params, true).await?;
+
+    Ok(Json(PrivacyResponse {
+        status: "enabled".to_string(),
+        socks_port: Some(state.anyone_service.proxy().addr.port()),
+        message: format!("New sessions will be routed through the {} network", state.anyone_service.backend_name()),
+    }))
+}
+
+// Disable privacy mode for the user's new sessions
+async fn disable_privacy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    set_privacy_default(&state, &headers, &params, false).await?;
+
+    Ok(Json(PrivacyResponse {
+        status: "disabled".to_string(),
+        socks_port: None,
+        message: "New sessions will use direct networking; existing sessions are unchanged".to_string(),
+    }))
+}
+
+// Get the shared daemon's status, and with `?user_id=` and that user's key
+// their privacy default
+async fn privacy_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let enabled = match params.get("user_id") {
+        Some(user_id) => {
+            authorize_user(&state, user_id, &headers, &params).await?;
+            privacy_default(&state, user_id).await
+        }
+        None => false,
+    };
+    let running = state.anyone_service.is_enabled().await;
+    let service_status = state.anyone_service.get_status().await;
+
+    let response = PrivacyStatusResponse {
         enabled,
-        socks_port: if enabled { Some(state.anyone_service.get_socks_port()) } else { None },
-        control_port: if enabled { Some(state.anyone_service.get_control_port()) } else { None },
+        socks_port: if running { Some(state.anyone_service.proxy().addr.port()) } else { None },
+        control_port: if running { state.anyone_service.control_addr().map(|addr| addr.port()) } else { None },
         status: format!("{:?}", service_status),
+        backend: state.anyone_service.backend_name().to_string(),
+        active_sessions: state.anyone_service.holder_count().await,
     };
 
-    Json(response)
+    Ok(Json(response))
+}
+
+/// Authenticated control-port connection to the running Anyone client
+async fn anyone_control(
+    state: &AppState,
+) -> Result<anyone_control::ControlClient, (StatusCode, Json<serde_json::Value>)> {
+    if !state.anyone_service.is_enabled().await {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "success": false,
+                "error": "Privacy mode not enabled"
+            })),
+        ));
+    }
+
+    if state.anyone_service.control_addr().is_none() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "success": false,
+                "error": "Not supported by privacy backend",
+                "details": format!("The {} backend has no control port", state.anyone_service.backend_name())
+            })),
+        ));
+    }
+
+    state.anyone_service
+        .control_client(state.settings.anyone.control_password.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Anyone control port unavailable: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(serde_json::json!({
+                    "success": false,
+                    "error": "Control port unavailable",
+                    "details": e.to_string()
+                })),
+            )
+        })
+}
+
+fn control_error(e: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(serde_json::json!({
+            "success": false,
+            "error": e.to_string()
+        })),
+    )
+}
+
+// Switch to fresh circuits for all new connections. This affects every
+// privacy session, so it takes an admin's `?user_id=` and user key
+async fn new_privacy_identity(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user_id = params.get("user_id").map(String::as_str).unwrap_or_default();
+    authorize_user(&state, user_id, &headers, &params).await?;
+    if state.settings.security.role_for(user_id) != "admin" {
+        warn!("Denied new Anyone identity to non-admin {}", user_id);
+        return Err((StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "Admin role required" }))));
+    }
+
+    let mut control = anyone_control(&state).await?;
+    control.new_identity().await.map_err(control_error)?;
+
+    info!("🔄 Requested new Anyone identity (NEWNYM) for {}", user_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "New circuits will be used for new connections"
+    })))
+}
+
+// List the Anyone client's circuits
+async fn list_privacy_circuits(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut control = anyone_control(&state).await?;
+    let mut circuits = control.circuits().await.map_err(control_error)?;
+
+    // Which session owns a circuit is only shown to that session's owner
+    for circuit in &mut circuits {
+        circuit.socks_username = None;
+    }
+
+    Ok(Json(serde_json::json!({
+        "circuits": circuits,
+        "count": circuits.len()
+    })))
+}
+
+// Stream bootstrap progress as server-sent events until the client is ready
+async fn privacy_bootstrap_stream(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+
+    let mut control = anyone_control(&state).await?;
+    let initial = control.bootstrap_phase().await.map_err(control_error)?;
+    if !initial.is_done() {
+        control.set_events(&["STATUS_CLIENT"]).await.map_err(control_error)?;
+    }
+
+    // Some(phase) is ready to send; None means wait for the next event.
+    // The stream ends once a completed phase has been sent.
+    let stream = futures::stream::unfold(
+        Some((control, Some(initial))),
+        |state| async move {
+            let (mut control, pending) = state?;
+            let phase = match pending {
+                Some(phase) => phase,
+                None => loop {
+                    match control.next_event().await {
+                        Ok(event) => {
+                            if let Some(phase) = anyone_control::BootstrapPhase::parse(&event) {
+                                break phase;
+                            }
+                        }
+                        Err(e) => {
+                            debug!("Bootstrap event stream ended: {}", e);
+                            return None;
+                        }
+                    }
+                },
+            };
+
+            let event = Event::default().event("bootstrap").json_data(&phase).ok()?;
+            let next = (!phase.is_done()).then_some((control, None));
+            Some((Ok::<_, std::convert::Infallible>(event), next))
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Circuits carrying a session's traffic; only the holder of the session's
+// token may see them
+async fn get_session_circuits(
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let session = authorize_session(&state, session_id, &headers, &params).await?;
+
+    let mut control = anyone_control(&state).await?;
+    let circuits: Vec<_> = control.circuits().await
+        .map_err(control_error)?
+        .into_iter()
+        .filter(|c| c.socks_username.as_deref() == Some(session.socks_auth.username.as_str()))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "isolation_key": session.socks_auth.username,
+        "circuits": circuits,
+        "count": circuits.len()
+    })))
 }
 
-// Test privacy connection by making a request through the proxy
+// Verify privacy. Without a session this checks the host's proxy; with
+// `session_id` and its token it runs the leak-check suite, DNS included,
+// inside that session's container
 async fn test_privacy_connection(
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    // Check if privacy is enabled
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let echo = privacy::EchoEndpoint::new(state.settings.anyone.echo_url.clone());
+
+    let Some(session_id) = params.get("session_id") else {
+        return Ok(Json(host_privacy_check(&state, &echo).await));
+    };
+    let session_id = Uuid::parse_str(session_id).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "Invalid session_id" })),
+        )
+    })?;
+
+    let session = authorize_session(&state, session_id, &headers, &params).await?;
+    let Some(ref container_id) = session.container_id else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "Session has no running container",
+                "details": "Connect to the session before verifying it"
+            })),
+        ));
+    };
+
+    let network = session.network.mode();
+    let proxy_url = session_proxy_url(&state, &session);
+
+    let docker = state.hosts.docker_for(&session.host);
+    let verifier = privacy::verify::Verifier {
+        docker: &docker,
+        dns: &state.dns,
+        echo: &echo,
+    };
+    let report = verifier
+        .run(&privacy::verify::SessionTarget {
+            session_id,
+            container_id,
+            network,
+            proxy_url,
+        })
+        .await;
+
+    if report.leak_detected {
+        let failed: Vec<&str> = report.checks.iter()
+            .filter(|c| c.status == privacy::verify::CheckStatus::Fail)
+            .map(|c| c.name)
+            .collect();
+        warn!("Privacy verification found leaks in session {}: {}", session_id, failed.join(", "));
+        if let Some(ref pool) = state.db_pool {
+            let _ = db::security::log_event(
+                pool,
+                Some(session_id),
+                &session.user_id,
+                "privacy_leak_detected",
+                db::security::Severity::Warning,
+                Some(&format!("Failed checks: {}", failed.join(", "))),
+                None,
+                None,
+            ).await;
+        }
+    }
+
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+/// Proxy a bridged privacy session's commands must be pointed at; other
+/// sessions get their routing from the container's network
+fn session_proxy_url(state: &AppState, session: &Session) -> Option<String> {
+    let bridged = session.network.mode() == config::NetworkMode::Open && egress_upstream(state, session).is_none();
+    (session.privacy && bridged).then(|| {
+        let isolation = state.anyone_service.isolates_by_credentials().then_some(&session.socks_auth);
+        state.anyone_service.proxy().container_url(isolation)
+    })
+}
+
+/// Compare the host's public IP with the one seen through the privacy
+/// proxy, and check that names resolve through it
+async fn host_privacy_check(state: &AppState, echo: &privacy::EchoEndpoint) -> serde_json::Value {
     if !state.anyone_service.is_enabled().await {
-        return Ok(Json(serde_json::json!({
+        return serde_json::json!({
             "success": false,
             "error": "Privacy mode not enabled",
-            "ip": null
-        })));
+            "exit_ip": null
+        });
     }
 
-    // Get the proxy client
-    let client = match state.anyone_service.get_proxy_client().await {
-        Some(c) => c,
-        None => {
-            return Ok(Json(serde_json::json!({
-                "success": false,
-                "error": "Proxy client not initialized",
-                "ip": null
-            })));
-        }
+    let Some(client) = state.anyone_service.get_proxy_client().await else {
+        return serde_json::json!({
+            "success": false,
+            "error": "Proxy client not initialized",
+            "exit_ip": null
+        });
     };
 
-    // Test connection by checking IP through the Anyone network
-    match client.get("https://check.en.anyone.tech/api/ip")
-        .timeout(std::time::Duration::from_secs(30))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            match response.json::<serde_json::Value>().await {
-                Ok(data) => {
-                    let is_anyone = data.get("IsAnyone").and_then(|v| v.as_bool()).unwrap_or(false);
-                    let ip = data.get("IP").and_then(|v| v.as_str()).unwrap_or("unknown");
-
-                    Ok(Json(serde_json::json!({
-                        "success": true,
-                        "is_anonymous": is_anyone,
-                        "exit_ip": ip,
-                        "message": if is_anyone { "Traffic routed through Anyone Protocol network" } else { "Connected but not through Anyone exit" }
-                    })))
-                }
-                Err(e) => {
-                    Ok(Json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to parse response: {}", e),
-                        "ip": null
-                    })))
+    let host_ip = echo.fetch(&reqwest::Client::new()).await.ok();
+    let mut result = match echo.fetch(&client).await {
+        Ok(exit_ip) => {
+            let is_anonymous = host_ip.is_some_and(|host| host != exit_ip);
+            serde_json::json!({
+                "success": true,
+                "is_anonymous": is_anonymous,
+                "exit_ip": exit_ip,
+                "host_ip": host_ip,
+                "message": match host_ip {
+                    Some(_) if is_anonymous => "Traffic leaves through the privacy network",
+                    Some(_) => "Traffic through the proxy exits from the host IP",
+                    None => "Could not reach the echo endpoint directly to compare",
                 }
-            }
-        }
-        Err(e) => {
-            Ok(Json(serde_json::json!({
-                "success": false,
-                "error": format!("Request failed: {}", e),
-                "ip": null
-            })))
+            })
         }
-    }
+        Err(e) => serde_json::json!({
+            "success": false,
+            "error": format!("Echo request through the proxy failed: {}", e),
+            "exit_ip": null
+        }),
+    };
+
+    let anonymous_resolution = match echo.host() {
+        Some(host) => match state.dns.lookup().resolve(&host, state.anyone_service.proxy().credentials.as_ref()).await {
+            Ok(ip) => serde_json::json!({ "success": true, "name": host, "address": ip }),
+            Err(e) => serde_json::json!({ "success": false, "name": host, "error": e.to_string() }),
+        },
+        None => serde_json::json!({ "success": false, "error": "Echo URL has no host name" }),
+    };
+    result["echo_url"] = serde_json::json!(echo.url());
+    result["resolver"] = serde_json::json!(state.dns.listening_on().await);
+    result["anonymous_resolution"] = anonymous_resolution;
+    result
 }
 
 // ==================== Phase 2 Endpoints ====================
@@ -952,16 +1739,39 @@ async fn terminate_session(
         }
     };
 
+    teardown_session(&state, &session, "user_requested").await;
+
+    // A job whose container is gone can only end as cancelled
+    if let Some(job_id) = session.job_id {
+        if let Some(job) = state.jobs.read().await.get(&job_id) {
+            job.cancel.notify_one();
+        }
+    }
+
+    info!("Session {} terminated successfully", session_id);
+
+    Ok(Json(serde_json::json!({
+        "status": "terminated",
+        "session_id": session_id
+    })))
+}
+
+/// Stop a session's container and release everything held for it
+async fn teardown_session(state: &AppState, session: &Session, reason: &str) {
+    let session_id = session.id;
+    release_session(state, session_id).await;
+
     // Stop container if exists
     if let Some(ref container_id) = session.container_id {
+        let docker = state.hosts.docker_for(&session.host);
         if let Some(ref lifecycle) = state.lifecycle_manager {
-            if let Err(e) = lifecycle.stop_container(container_id).await {
+            if let Err(e) = lifecycle.stop_container(&docker, container_id).await {
                 warn!("Failed to stop container {}: {}", container_id, e);
             }
         } else {
             // Direct Docker stop
-            let _ = state.docker.stop_container(container_id, None).await;
-            let _ = state.docker.remove_container(container_id, None).await;
+            let _ = docker.stop_container(container_id, None).await;
+            let _ = docker.remove_container(container_id, None).await;
         }
     }
 
@@ -978,30 +1788,118 @@ async fn terminate_session(
             &session.user_id,
             db::audit::EventType::SessionTerminated,
             Some(serde_json::json!({
-                "reason": "user_requested"
+                "reason": reason
             })),
             None,
             None,
         )
         .await;
     }
+}
+
+/// Drop what the server holds for a session: its cache entry, egress
+/// routes and hold on the privacy daemon. Every way a session ends comes
+/// through here, including the lifecycle manager's reaping
+async fn release_session(state: &AppState, session_id: Uuid) -> Option<Session> {
+    // Dropped from the cache first so the event handler knows a stop of
+    // the container that follows is intended
+    let session = state.sessions.write().await.remove(&session_id);
+
+    state.egress.unregister(session_id).await;
+    if let Err(e) = state.anyone_service.release(session_id).await {
+        warn!("Failed to release Anyone for session {}: {}", session_id, e);
+    }
+    if let Some(ref lifecycle) = state.lifecycle_manager {
+        lifecycle.remove_from_cache(session_id).await;
+    }
+    session
+}
+
+/// Release sessions the lifecycle manager terminated: expired, over their
+/// disk quota or with an orphaned container
+async fn release_reaped_sessions(state: AppState, mut reaped: tokio::sync::broadcast::Receiver<Uuid>) {
+    loop {
+        match reaped.recv().await {
+            Ok(session_id) => {
+                if release_session(&state, session_id).await.is_some() {
+                    info!("Released session {} terminated by the lifecycle manager", session_id);
+                }
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} terminated sessions", missed);
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+// Move a session to another resource tier, resizing its container live;
+// takes the session's token
+async fn resize_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Json(payload): Json<ResizeSessionRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let session = authorize_session(&state, session_id, &headers, &params).await?;
+
+    let tier = authorize_tier(&state, &session.user_id, &payload.tier, Some(session_id)).await?;
+    let resource_limits = container::limits::from_tier(&tier);
+
+    if let Some(ref container_id) = session.container_id {
+        if let Err(e) = state.hosts.docker_for(&session.host)
+            .update_container(container_id, container::limits::update_options(&resource_limits))
+            .await
+        {
+            error!("Failed to resize container {}: {}", container_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": "Failed to resize container",
+                    "details": e.to_string()
+                })),
+            ));
+        }
+    }
 
-    // Remove from in-memory cache
     {
         let mut sessions = state.sessions.write().await;
-        sessions.remove(&session_id);
+        if let Some(session) = sessions.get_mut(&session_id) {
+            session.tier = tier.name.clone();
+            session.resource_limits = resource_limits.clone();
+        }
     }
 
-    // Remove from lifecycle health cache
-    if let Some(ref lifecycle) = state.lifecycle_manager {
-        lifecycle.remove_from_cache(session_id).await;
+    if let Some(ref pool) = state.db_pool {
+        if let Err(e) = db::sessions::set_resource_limits(pool, session_id, &resource_limits).await {
+            error!("Failed to store resource limits for session {}: {}", session_id, e);
+        }
+        let _ = db::sessions::merge_metadata(pool, session_id, serde_json::json!({ "tier": tier.name })).await;
+
+        let _ = db::audit::log(
+            pool,
+            Some(session_id),
+            &session.user_id,
+            db::audit::EventType::SessionResized,
+            Some(serde_json::json!({
+                "from_tier": session.tier,
+                "to_tier": tier.name,
+                "resource_limits": resource_limits
+            })),
+            None,
+            None,
+        ).await;
     }
 
-    info!("Session {} terminated successfully", session_id);
+    info!("Session {} moved from tier {} to {}", session_id, session.tier, tier.name);
 
     Ok(Json(serde_json::json!({
-        "status": "terminated",
-        "session_id": session_id
+        "status": "resized",
+        "session_id": session_id,
+        "tier": tier.name,
+        "resource_limits": resource_limits,
+        "live": session.container_id.is_some()
     })))
 }
 
@@ -1057,7 +1955,17 @@ async fn get_session_metrics(
 async fn detailed_health_check(
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    let docker_ok = state.docker.ping().await.is_ok();
+    let mut docker_ok = false;
+    let mut docker_hosts = Vec::new();
+    for host in state.hosts.all() {
+        let status = host.status().await;
+        docker_ok |= status.healthy;
+        docker_hosts.push(serde_json::json!({
+            "name": host.name,
+            "endpoint": host.endpoint.as_ref().map(|e| e.to_string()),
+            "status": status
+        }));
+    }
     let db_ok = if let Some(ref pool) = state.db_pool {
         sqlx::query("SELECT 1")
             .fetch_one(pool)
@@ -1080,6 +1988,7 @@ async fn detailed_health_check(
         "git_hash": env!("GIT_HASH"),
         "components": {
             "docker": docker_ok,
+            "docker_hosts": docker_hosts,
             "database": db_ok,
             "anyone_protocol": format!("{:?}", anyone_status)
         },
@@ -1131,12 +2040,20 @@ async fn prometheus_metrics(
          noxterm_memory_usage_bytes {}\n\
          # HELP noxterm_privacy_enabled Privacy mode status (1=enabled, 0=disabled)\n\
          # TYPE noxterm_privacy_enabled gauge\n\
-         noxterm_privacy_enabled {}\n",
+         noxterm_privacy_enabled {}\n\
+         # HELP noxterm_anyone_restarts_total Times the Anyone client was restarted after crashing\n\
+         # TYPE noxterm_anyone_restarts_total counter\n\
+         noxterm_anyone_restarts_total {}\n\
+         # HELP noxterm_container_oom_kills_total Processes OOM-killed in session containers\n\
+         # TYPE noxterm_container_oom_kills_total counter\n\
+         noxterm_container_oom_kills_total {}\n",
         active_sessions,
         container_count,
         total_cpu,
         total_memory,
-        if anyone_enabled { 1 } else { 0 }
+        if anyone_enabled { 1 } else { 0 },
+        state.anyone_service.restart_count(),
+        state.container_events.oom_kills()
     );
 
     (
@@ -1152,6 +2069,7 @@ async fn reattach_session(
     Path(session_id): Path<Uuid>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     info!("Reattach request for session {}", session_id);
+    reject_if_draining(&state)?;
 
     // Check if session exists in database
     if let Some(ref pool) = state.db_pool {
@@ -1466,7 +2384,7 @@ async fn get_user_active_sessions(
     Err(StatusCode::NOT_FOUND)
 }
 
-// Validate command input (security check endpoint)
+// Validate command input against the command policy (security check endpoint)
 async fn validate_command(
     State(state): State<AppState>,
     Path(session_id): Path<Uuid>,
@@ -1474,23 +2392,57 @@ async fn validate_command(
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     body: String,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let validation = validate_input(&body);
+    // The policy is evaluated for the session owner's role
+    let mut user_id = state.sessions.read().await.get(&session_id).map(|s| s.user_id.clone());
+    if user_id.is_none() {
+        if let Some(ref pool) = state.db_pool {
+            if let Ok(Some(session)) = db::sessions::get_by_id(pool, session_id).await {
+                user_id = Some(session.user_id);
+            }
+        }
+    }
+    let user_id = user_id.unwrap_or_else(|| "unknown".to_string());
 
     // Extract client info for logging
     let xff = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok());
     let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
     let client_ip = extract_client_ip(xff, real_ip, Some(&addr.to_string()));
 
-    if !validation.is_safe {
-        // Log security event to database
-        if let Some(ref pool) = state.db_pool {
-            // Get user_id from session
-            let user_id = if let Ok(Some(session)) = db::sessions::get_by_id(pool, session_id).await {
-                session.user_id
-            } else {
-                "unknown".to_string()
-            };
+    let warning = enforce_policy(&state, Some(session_id), None, &user_id, &body, client_ip.as_deref()).await?;
+
+    let mut response = serde_json::json!({
+        "allowed": true,
+        "command": body
+    });
+    if let Some(warning) = warning {
+        response["warning"] = warning;
+    }
+    Ok(Json(response))
+}
+
+/// Check a command line against the policy for its owner's role, logging
+/// anything flagged. Denials become a 403; warnings are returned.
+///
+/// Job scripts are checked before their session exists, so they are logged
+/// without a session and with the job ID in the details
+async fn enforce_policy(
+    state: &AppState,
+    session_id: Option<Uuid>,
+    job_id: Option<Uuid>,
+    user_id: &str,
+    command: &str,
+    client_ip: Option<&str>,
+) -> Result<Option<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    if !state.settings.security.validate_commands {
+        return Ok(None);
+    }
 
+    let role = state.settings.security.role_for(user_id).to_string();
+    let validation = validate_with(&state.policy, command, &role);
+
+    let flagged = !validation.is_safe || validation.severity != SecuritySeverity::Safe;
+    if flagged && state.settings.security.log_security_events {
+        if let Some(ref pool) = state.db_pool {
             let severity = match validation.severity {
                 SecuritySeverity::Critical => db::security::Severity::Critical,
                 SecuritySeverity::Warning => db::security::Severity::Warning,
@@ -1499,45 +2451,1027 @@ async fn validate_command(
 
             let _ = db::security::log_event(
                 pool,
-                Some(session_id),
-                &user_id,
-                "command_blocked",
+                session_id,
+                user_id,
+                if validation.is_safe { "command_flagged" } else { "command_blocked" },
                 severity,
                 validation.reason.as_deref(),
-                Some(&body),
-                client_ip.as_deref(),
+                Some(command),
+                client_ip,
             ).await;
 
-            // Also log audit event
-            let _ = db::audit::log(
-                pool,
-                Some(session_id),
-                &user_id,
-                db::audit::EventType::SecurityViolation,
-                Some(serde_json::json!({
-                    "blocked_command": body,
+            if !validation.is_safe {
+                let mut details = serde_json::json!({
+                    "blocked_command": command,
+                    "rule": validation.rule,
                     "reason": validation.reason,
                     "severity": format!("{:?}", validation.severity)
-                })),
-                client_ip.as_deref(),
-                None,
-            ).await;
+                });
+                if let Some(job_id) = job_id {
+                    details["job_id"] = serde_json::json!(job_id);
+                }
+                let _ = db::audit::log(
+                    pool,
+                    session_id,
+                    user_id,
+                    db::audit::EventType::SecurityViolation,
+                    Some(details),
+                    client_ip,
+                    None,
+                ).await;
+            }
+        }
+    }
+
+    if !validation.is_safe {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({
+                "allowed": false,
+                "rule": validation.rule,
+                "reason": validation.reason,
+                "severity": format!("{:?}", validation.severity),
+                "blocked_pattern": validation.blocked_pattern,
+                "role": role
+            })),
+        ));
+    }
+
+    Ok(flagged.then(|| serde_json::json!({
+        "rule": validation.rule,
+        "reason": validation.reason,
+        "command": validation.blocked_pattern
+    })))
+}
+
+#[derive(Deserialize)]
+struct ExecRequest {
+    argv: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    cwd: Option<String>,
+    stdin: Option<String>,
+    timeout_secs: Option<u64>,
+}
+
+/// An exec request that passed token, limit and policy checks
+struct PreparedExec {
+    user_id: String,
+    docker: Arc<Docker>,
+    container_id: String,
+    command_line: String,
+    command: container::exec::Command,
+    warning: Option<serde_json::Value>,
+}
+
+/// First name in `env` that is not a valid variable name
+fn invalid_env_name(env: &HashMap<String, String>) -> Option<&String> {
+    env.keys().find(|name| {
+        name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Environment for a command run without a terminal, with the session's
+/// proxy settings and the caller's variables on top
+fn exec_env(state: &AppState, session: &Session, extra: &HashMap<String, String>) -> Vec<String> {
+    let mut env = vec![
+        "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
+        "HOME=/root".to_string(),
+        "LANG=en_US.UTF-8".to_string(),
+        "DEBIAN_FRONTEND=noninteractive".to_string(),
+    ];
+    if let Some(proxy_url) = session_proxy_url(state, session) {
+        env.push(format!("ALL_PROXY={}", proxy_url));
+        env.push(format!("all_proxy={}", proxy_url));
+        env.push("NOXTERM_PRIVACY=enabled".to_string());
+    }
+    env.extend(extra.iter().map(|(name, value)| format!("{}={}", name, value)));
+    env
+}
+
+async fn prepare_exec(
+    state: &AppState,
+    session_id: Uuid,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    addr: SocketAddr,
+    request: ExecRequest,
+) -> Result<PreparedExec, (StatusCode, Json<serde_json::Value>)> {
+    let bad_request = |error: &str, details: String| {
+        (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": error, "details": details })))
+    };
+
+    let session = authorize_session(state, session_id, headers, params).await?;
+    let Some(container_id) = session.container_id.clone() else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "Session has no running container",
+                "details": "Connect to the session before running commands in it"
+            })),
+        ));
+    };
+
+    if request.argv.first().is_none_or(|program| program.is_empty()) {
+        return Err(bad_request("Invalid argv", "argv must name a program".to_string()));
+    }
+    if request.argv.iter().any(|arg| arg.contains('\0')) {
+        return Err(bad_request("Invalid argv", "Arguments cannot contain null bytes".to_string()));
+    }
+    if let Some(name) = invalid_env_name(&request.env) {
+        return Err(bad_request("Invalid env", format!("'{}' is not a variable name", name)));
+    }
+
+    let limits = &state.settings.session;
+    let timeout_secs = request.timeout_secs.unwrap_or(limits.exec_timeout_secs);
+    if timeout_secs == 0 || timeout_secs > limits.exec_max_timeout_secs {
+        return Err(bad_request(
+            "Invalid timeout",
+            format!("timeout_secs must be between 1 and {}", limits.exec_max_timeout_secs),
+        ));
+    }
+
+    let xff = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok());
+    let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
+    let client_ip = extract_client_ip(xff, real_ip, Some(&addr.to_string()));
+
+    let command_line = security::shell::join(&request.argv);
+    let warning = enforce_policy(state, Some(session_id), None, &session.user_id, &command_line, client_ip.as_deref()).await?;
+
+    let env = exec_env(state, &session, &request.env);
+
+    Ok(PreparedExec {
+        docker: state.hosts.docker_for(&session.host),
+        user_id: session.user_id,
+        container_id,
+        command_line,
+        command: container::exec::Command {
+            argv: request.argv,
+            env,
+            cwd: request.cwd.or_else(|| Some("/root".to_string())),
+            stdin: request.stdin.map(String::into_bytes),
+            timeout: std::time::Duration::from_secs(timeout_secs),
+        },
+        warning,
+    })
+}
+
+async fn record_exec(state: &AppState, session_id: Uuid, prepared: &PreparedExec, exit: &container::exec::Exit) {
+    if exit.timed_out {
+        warn!("Exec in session {} timed out after {}ms: {}", session_id, exit.duration_ms, prepared.command_line);
+    }
+    if !history_enabled(state, &prepared.user_id).await {
+        return;
+    }
+    if let Some(ref pool) = state.db_pool {
+        let entry = db::history::Entry {
+            session_id,
+            user_id: &prepared.user_id,
+            command: &prepared.command_line,
+            source: db::history::Source::Exec,
+            exit_code: exit.exit_code,
+            timed_out: exit.timed_out,
+            duration_ms: Some(exit.duration_ms),
+        };
+        if let Err(e) = db::history::record(pool, &entry).await {
+            warn!("Failed to record command history for session {}: {}", session_id, e);
+        }
+    }
+}
+
+fn exec_failed(e: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({
+            "error": "Failed to run command",
+            "details": e.to_string()
+        })),
+    )
+}
+
+// Run a command without a terminal and return its exit code and output;
+// takes the session's token
+async fn exec_command(
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<ExecRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let prepared = prepare_exec(&state, session_id, &headers, &params, addr, request).await?;
+    let events = container::exec::spawn((*prepared.docker).clone(), &prepared.container_id, prepared.command.clone())
+        .await
+        .map_err(exec_failed)?;
+
+    let captured = container::exec::Captured::collect(events).await;
+    let exit = container::exec::Exit {
+        exit_code: captured.exit_code,
+        timed_out: captured.timed_out,
+        duration_ms: captured.duration_ms,
+    };
+    record_exec(&state, session_id, &prepared, &exit).await;
+
+    let mut response = serde_json::json!({
+        "session_id": session_id,
+        "command": prepared.command_line,
+    });
+    if let (Some(fields), serde_json::Value::Object(result)) = (response.as_object_mut(), serde_json::json!(captured)) {
+        fields.extend(result);
+    }
+    if let Some(warning) = prepared.warning {
+        response["warning"] = warning;
+    }
+    Ok(Json(response))
+}
+
+// Same as exec_command, streaming `stdout`, `stderr` and a final `exit`
+// event as server-sent events. The command keeps running if the client
+// disconnects.
+async fn exec_command_stream(
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<ExecRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+    use container::exec::Event as ExecEvent;
+
+    let prepared = prepare_exec(&state, session_id, &headers, &params, addr, request).await?;
+    let mut events = container::exec::spawn((*prepared.docker).clone(), &prepared.container_id, prepared.command.clone())
+        .await
+        .map_err(exec_failed)?;
+
+    // History is recorded here so it is written even without a listener
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    let warning = prepared.warning.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let ExecEvent::Exit(ref exit) = event {
+                record_exec(&state, session_id, &prepared, exit).await;
+            }
+            let _ = tx.send(event).await;
+        }
+    });
+
+    let start = Event::default()
+        .event("start")
+        .json_data(serde_json::json!({ "session_id": session_id, "warning": warning }))
+        .ok();
+    let stream = futures::stream::unfold((rx, start), |(mut rx, start)| async move {
+        if let Some(start) = start {
+            return Some((Ok::<_, std::convert::Infallible>(start), (rx, None)));
+        }
+        let event = rx.recv().await?;
+        Some((Ok(exec_sse_event(&event)), (rx, None)))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// `stdout`, `stderr` or `exit` server-sent event for command output
+fn exec_sse_event(event: &container::exec::Event) -> axum::response::sse::Event {
+    use axum::response::sse::Event;
+    use container::exec::Event as ExecEvent;
+
+    let event = match event {
+        ExecEvent::Stdout(chunk) => Event::default()
+            .event("stdout")
+            .json_data(serde_json::json!({ "data": String::from_utf8_lossy(chunk) })),
+        ExecEvent::Stderr(chunk) => Event::default()
+            .event("stderr")
+            .json_data(serde_json::json!({ "data": String::from_utf8_lossy(chunk) })),
+        ExecEvent::Exit(exit) => Event::default().event("exit").json_data(exit),
+    };
+    event.unwrap_or_default()
+}
+
+/// Whether commands run by this user go into their history: command logging
+/// must be on and the user must not have opted out
+async fn history_enabled(state: &AppState, user_id: &str) -> bool {
+    let Some(ref pool) = state.db_pool else {
+        return false;
+    };
+    if !state.settings.security.command_logging {
+        return false;
+    }
+    match db::preferences::get_history(pool, user_id).await {
+        Ok(preferences) => preferences.enabled,
+        Err(e) => {
+            // Fail closed: a user who opted out must not be recorded
+            error!("Failed to load history preferences for {}: {}", user_id, e);
+            false
+        }
+    }
+}
+
+fn history_unavailable() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "error": "Command history unavailable",
+            "details": "Command history is stored in the database; set DATABASE_URL"
+        })),
+    )
+}
+
+fn history_failed(e: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    error!("Command history query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({
+            "error": "Command history query failed",
+            "details": e.to_string()
+        })),
+    )
+}
+
+fn invalid_user_id() -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "Invalid user_id" })))
+}
+
+// Search a user's command history across sessions (?q=substring&limit=N)
+async fn get_user_history(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !validate_user_id(&user_id) {
+        return Err(invalid_user_id());
+    }
+    authorize_user(&state, &user_id, &headers, &params).await?;
+    let Some(ref pool) = state.db_pool else {
+        return Err(history_unavailable());
+    };
+
+    let query = params.get("q").map(String::as_str).filter(|q| !q.is_empty());
+    let limit: i64 = params.get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+
+    let history = db::history::search(pool, &user_id, query, limit).await.map_err(history_failed)?;
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "query": query,
+        "history": history,
+        "count": history.len()
+    })))
+}
+
+// Delete a user's command history
+async fn clear_user_history(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !validate_user_id(&user_id) {
+        return Err(invalid_user_id());
+    }
+    authorize_user(&state, &user_id, &headers, &params).await?;
+    let Some(ref pool) = state.db_pool else {
+        return Err(history_unavailable());
+    };
+
+    let deleted = db::history::clear(pool, &user_id).await.map_err(history_failed)?;
+    info!("Cleared command history for {} ({} entries)", user_id, deleted);
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "deleted": deleted
+    })))
+}
+
+fn history_settings_json(state: &AppState, user_id: &str, preferences: db::preferences::HistoryPreferences) -> serde_json::Value {
+    let security = &state.settings.security;
+    serde_json::json!({
+        "user_id": user_id,
+        "enabled": preferences.enabled,
+        "retention_days": preferences.retention_days,
+        "effective_retention_days": preferences.retention_days.map_or(security.history_retention_days, |days| days as u32),
+        "command_logging": security.command_logging,
+        "pty_history": security.pty_history
+    })
+}
+
+// A user's history opt-out and retention
+async fn get_history_settings(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !validate_user_id(&user_id) {
+        return Err(invalid_user_id());
+    }
+    authorize_user(&state, &user_id, &headers, &params).await?;
+    let Some(ref pool) = state.db_pool else {
+        return Err(history_unavailable());
+    };
+
+    let preferences = db::preferences::get_history(pool, &user_id).await.map_err(history_failed)?;
+    Ok(Json(history_settings_json(&state, &user_id, preferences)))
+}
+
+#[derive(Deserialize)]
+struct HistorySettingsRequest {
+    enabled: bool,
+    /// Days to keep history; omit for the server default
+    retention_days: Option<u32>,
+}
+
+// Opt out of (or back into) command history and set its retention. Opting
+// out also deletes what was already recorded.
+async fn update_history_settings(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Json(request): Json<HistorySettingsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !validate_user_id(&user_id) {
+        return Err(invalid_user_id());
+    }
+    authorize_user(&state, &user_id, &headers, &params).await?;
+    let Some(ref pool) = state.db_pool else {
+        return Err(history_unavailable());
+    };
+
+    if let Some(days) = request.retention_days {
+        if days == 0 || days > config::MAX_HISTORY_RETENTION_DAYS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "Invalid retention_days",
+                    "details": format!("retention_days must be between 1 and {}", config::MAX_HISTORY_RETENTION_DAYS)
+                })),
+            ));
+        }
+    }
+
+    let preferences = db::preferences::HistoryPreferences {
+        enabled: request.enabled,
+        retention_days: request.retention_days.map(|days| days as i32),
+    };
+    db::preferences::set_history(pool, &user_id, preferences).await.map_err(history_failed)?;
+
+    if !preferences.enabled {
+        let deleted = db::history::clear(pool, &user_id).await.map_err(history_failed)?;
+        info!("{} opted out of command history ({} entries deleted)", user_id, deleted);
+    }
+
+    Ok(Json(history_settings_json(&state, &user_id, preferences)))
+}
+
+/// A batch job tracked while it runs
+struct Job {
+    info: RwLock<jobs::JobInfo>,
+    log: parking_lot::Mutex<jobs::JobLog>,
+    cancel: tokio::sync::Notify,
+}
+
+#[derive(Deserialize)]
+struct CreateJobRequest {
+    user_id: String,
+    image: Option<String>,
+    /// Run with its shebang interpreter, or /bin/sh without one
+    script: String,
+    #[serde(default)]
+    files: Vec<jobs::InputFile>,
+    /// Paths collected as tar archives once the script exits
+    #[serde(default)]
+    artifacts: Vec<String>,
+    tier: Option<String>,
+    network: Option<String>,
+    allowlist: Option<Vec<String>>,
+    privacy: Option<bool>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    timeout_secs: Option<u64>,
+}
+
+/// What the runner needs once the job's session exists
+struct JobSpec {
+    script: String,
+    files: Vec<jobs::InputFile>,
+    artifacts: Vec<String>,
+    env: HashMap<String, String>,
+    timeout: std::time::Duration,
+}
+
+// Submit a script to run in a fresh container; poll /api/jobs/:id for the
+// result with the returned job token
+async fn create_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(request): Json<CreateJobRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    reject_if_draining(&state)?;
+
+    let bad_request = |error: &str, details: String| {
+        (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": error, "details": details })))
+    };
+
+    if !validate_user_id(&request.user_id) {
+        return Err(invalid_user_id());
+    }
+    let unclaimed = authorize_new_work(&state, &request.user_id, &headers, &params).await?;
+    let image = request.image.clone().unwrap_or_else(|| state.settings.docker.default_image.clone());
+    if !validate_image_name(&image) {
+        return Err(bad_request("Invalid container image", "Container image name contains invalid characters".to_string()));
+    }
+    if request.script.trim().is_empty() {
+        return Err(bad_request("Invalid script", "script cannot be empty".to_string()));
+    }
+    if request.files.len() > jobs::MAX_PATHS || request.artifacts.len() > jobs::MAX_PATHS {
+        return Err(bad_request("Too many paths", format!("At most {} files and {} artifacts", jobs::MAX_PATHS, jobs::MAX_PATHS)));
+    }
+    for path in request.files.iter().map(|f| &f.path).chain(&request.artifacts) {
+        jobs::validate_path(path).map_err(|e| bad_request("Invalid path", e))?;
+    }
+    if let Some(name) = invalid_env_name(&request.env) {
+        return Err(bad_request("Invalid env", format!("'{}' is not a variable name", name)));
+    }
+    let limits = &state.settings.jobs;
+    let timeout_secs = request.timeout_secs.unwrap_or(limits.timeout_secs);
+    if timeout_secs == 0 || timeout_secs > limits.max_timeout_secs {
+        return Err(bad_request(
+            "Invalid timeout",
+            format!("timeout_secs must be between 1 and {}", limits.max_timeout_secs),
+        ));
+    }
+
+    let tier_name = request.tier.clone().unwrap_or_else(|| state.settings.tiers.default_tier.clone());
+    let tier = authorize_tier(&state, &request.user_id, &tier_name, None).await?;
+    let privacy = match request.privacy {
+        Some(privacy) => privacy,
+        None => privacy_default(&state, &request.user_id).await,
+    };
+    let network = resolve_session_network(&state, request.network.as_deref(), request.allowlist.clone(), privacy).await?;
+    let privacy = privacy || network.mode() == config::NetworkMode::AnyoneOnly;
+
+    check_container_limit(&state, &request.user_id).await?;
+    let resource_limits = container::limits::from_tier(&tier);
+    let host = place_session(&state, &request.user_id, &resource_limits, privacy, &network).await?;
+
+    let session_id = Uuid::new_v4();
+    let job_id = Uuid::new_v4();
+
+    let xff = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok());
+    let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
+    let client_ip = extract_client_ip(xff, real_ip, Some(&addr.to_string()));
+    let user_agent = headers.get("user-agent").and_then(|v| v.to_str().ok());
+
+    // The whole script is checked, as it would be typed into a shell
+    enforce_policy(&state, None, Some(job_id), &request.user_id, &request.script, client_ip.as_deref()).await?;
+
+    hold_anyone(&state, session_id, privacy, &network).await?;
+
+    let session = Session {
+        id: session_id,
+        user_id: request.user_id.clone(),
+        status: "created".to_string(),
+        container_id: None,
+        container_name: None,
+        created_at: chrono::Utc::now(),
+        container_image: image.clone(),
+        runtime: None,
+        tier: tier.name.clone(),
+        resource_limits: resource_limits.clone(),
+        network: network.clone(),
+        privacy,
+        socks_auth: egress::socks::Credentials::for_session(session_id),
+        // Jobs are looked after with their job token, never a session token
+        token_sha256: String::new(),
+        job_id: Some(job_id),
+        host: host.clone(),
+    };
+    let audit = serde_json::json!({
+        "container_image": image,
+        "job_id": job_id,
+        "tier": tier.name,
+        "resource_limits": resource_limits,
+        "network": network,
+        "privacy": privacy,
+        "host": host
+    });
+    register_session(&state, session.clone(), audit, client_ip.as_deref(), user_agent).await;
+    let user_key = if unclaimed { Some(claim_user(&state, &session).await?) } else { None };
+
+    let (job_token, token_sha256) = new_token();
+    let info = jobs::JobInfo {
+        id: job_id,
+        user_id: request.user_id.clone(),
+        session_id,
+        image,
+        tier: tier.name.clone(),
+        status: jobs::JobStatus::Starting,
+        exit_code: None,
+        error: None,
+        created_at: chrono::Utc::now(),
+        started_at: None,
+        finished_at: None,
+        artifacts: Vec::new(),
+        log_truncated: false,
+        token_sha256,
+    };
+    if let Some(ref pool) = state.db_pool {
+        if let Err(e) = db::jobs::save(pool, &info).await {
+            error!("Failed to persist job {}: {}", job_id, e);
+        }
+    }
+
+    let job = Arc::new(Job {
+        info: RwLock::new(info),
+        log: parking_lot::Mutex::new(jobs::JobLog::new()),
+        cancel: tokio::sync::Notify::new(),
+    });
+    state.jobs.write().await.insert(job_id, job.clone());
+
+    let spec = JobSpec {
+        script: request.script,
+        files: request.files,
+        artifacts: request.artifacts,
+        env: request.env,
+        timeout: std::time::Duration::from_secs(timeout_secs),
+    };
+    tokio::spawn(run_job(state.clone(), job, session_id, spec));
+
+    info!("Created job {} for user {} (session {})", job_id, request.user_id, session_id);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({
+            "job_id": job_id,
+            "session_id": session_id,
+            "job_token": job_token,
+            "user_key": user_key,
+            "status": jobs::JobStatus::Starting,
+            "status_url": format!("/api/jobs/{}", job_id),
+            "logs_url": format!("/api/jobs/{}/logs", job_id)
+        })),
+    ))
+}
+
+/// Start the job's container, run the script, collect artifacts and tear
+/// everything down again
+async fn run_job(state: AppState, job: Arc<Job>, session_id: Uuid, spec: JobSpec) {
+    let _active = state.drain.track();
+    let job_id = job.info.read().await.id;
+    let outcome = tokio::select! {
+        outcome = run_job_steps(&state, &job, session_id, &spec) => outcome,
+        _ = job.cancel.notified() => Err((jobs::JobStatus::Cancelled, "Cancelled".to_string())),
+    };
+
+    let (stdout, stderr) = job.log.lock().streams();
+    if let Err(e) = state.job_store.save_logs(job_id, &stdout, &stderr).await {
+        warn!("Failed to save logs of job {}: {}", job_id, e);
+    }
+
+    let session = state.sessions.read().await.get(&session_id).cloned();
+    if let Some(session) = session {
+        // Cancelled while the container was being created: it has a name
+        // but the session never heard its ID
+        if session.container_id.is_none() {
+            let docker = state.hosts.docker_for(&session.host);
+            let options = bollard::container::RemoveContainerOptions { force: true, ..Default::default() };
+            let _ = docker.remove_container(&container::labels::container_name(session_id), Some(options)).await;
+        }
+        teardown_session(&state, &session, "job_finished").await;
+    }
+
+    let finished = {
+        let mut info = job.info.write().await;
+        match outcome {
+            Ok(status) => info.status = status,
+            Err((status, error)) => {
+                info.status = status;
+                info.error = Some(error);
+            }
+        }
+        info.log_truncated = job.log.lock().truncated();
+        info.finished_at = Some(chrono::Utc::now());
+        info.clone()
+    };
+    info!("Job {} finished: {} (exit code {:?})", job_id, finished.status, finished.exit_code);
+
+    // Finished jobs are served from the database when there is one, and
+    // otherwise from memory until their files expire
+    let saved = match state.db_pool {
+        Some(ref pool) => match db::jobs::save(pool, &finished).await {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to persist job {}: {}", job_id, e);
+                false
+            }
+        },
+        None => false,
+    };
+    if saved {
+        state.jobs.write().await.remove(&job_id);
+        return;
+    }
+    let retention = std::time::Duration::from_secs(state.settings.jobs.retention_hours * 3600);
+    tokio::spawn(async move {
+        tokio::time::sleep(retention).await;
+        state.jobs.write().await.remove(&job_id);
+    });
+}
+
+/// The job's status when it ends on its own, or a status and error
+async fn run_job_steps(
+    state: &AppState,
+    job: &Job,
+    session_id: Uuid,
+    spec: &JobSpec,
+) -> Result<jobs::JobStatus, (jobs::JobStatus, String)> {
+    let failed = |e: String| (jobs::JobStatus::Failed, e);
+
+    let docker = session_docker(state, session_id).await;
+    let started = start_container(&docker, session_id, state).await
+        .map_err(|e| failed(format!("Container start failed: {}", e)))?;
+    record_container_started(state, session_id, &started).await;
+    let container_id = started.id;
+
+    let script_argv = if spec.script.starts_with("#!") {
+        vec![jobs::SCRIPT_PATH.to_string()]
+    } else {
+        vec!["/bin/sh".to_string(), jobs::SCRIPT_PATH.to_string()]
+    };
+    container::exec::write_file(&docker, &container_id, jobs::SCRIPT_PATH, spec.script.clone().into_bytes(), true)
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+    for file in &spec.files {
+        container::exec::write_file(&docker, &container_id, &file.path, file.content.clone().into_bytes(), file.executable)
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+    }
+
+    let env = match state.sessions.read().await.get(&session_id) {
+        Some(session) => exec_env(state, session, &spec.env),
+        None => return Err(failed("Session was terminated".to_string())),
+    };
+    let command = container::exec::Command {
+        argv: script_argv,
+        env,
+        cwd: Some("/root".to_string()),
+        stdin: None,
+        timeout: spec.timeout,
+    };
+
+    {
+        let mut info = job.info.write().await;
+        info.status = jobs::JobStatus::Running;
+        info.started_at = Some(chrono::Utc::now());
+    }
+    let mut events = container::exec::spawn((*docker).clone(), &container_id, command).await
+        .map_err(|e| failed(format!("Failed to run script: {}", e)))?;
+
+    let mut exit = None;
+    while let Some(event) = events.recv().await {
+        if let container::exec::Event::Exit(ref ended) = event {
+            exit = Some(ended.clone());
+        }
+        job.log.lock().push(event);
+    }
+    let exit = exit.ok_or_else(|| failed("Script output ended without an exit status".to_string()))?;
+    job.info.write().await.exit_code = exit.exit_code;
+
+    job.info.write().await.status = jobs::JobStatus::Collecting;
+    for (index, path) in spec.artifacts.iter().enumerate() {
+        let archive = docker.download_from_container(
+            &container_id,
+            Some(bollard::container::DownloadFromContainerOptions { path: path.clone() }),
+        );
+        let artifact = match state.job_store.save_artifact(job.info.read().await.id, index, archive).await {
+            Ok(size_bytes) => jobs::Artifact { path: path.clone(), size_bytes, error: None },
+            Err(e) => jobs::Artifact { path: path.clone(), size_bytes: 0, error: Some(e) },
+        };
+        job.info.write().await.artifacts.push(artifact);
+    }
+
+    if exit.timed_out {
+        Err((jobs::JobStatus::TimedOut, format!("Timed out after {}s", spec.timeout.as_secs())))
+    } else if exit.exit_code == Some(0) {
+        Ok(jobs::JobStatus::Succeeded)
+    } else {
+        Ok(jobs::JobStatus::Failed)
+    }
+}
+
+/// A job's current record: running ones from memory, finished ones from
+/// the database
+async fn find_job(state: &AppState, job_id: Uuid) -> Result<jobs::JobInfo, (StatusCode, Json<serde_json::Value>)> {
+    if let Some(job) = state.jobs.read().await.get(&job_id).cloned() {
+        return Ok(job.info.read().await.clone());
+    }
+    if let Some(ref pool) = state.db_pool {
+        if let Some(info) = db::jobs::get(pool, job_id).await.map_err(history_failed)? {
+            return Ok(info);
+        }
+    }
+    Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Job not found" }))))
+}
+
+/// Only the holder of the job's token may see or cancel it
+fn authorize_job(
+    info: &jobs::JobInfo,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    action: &str,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match presented_token(headers, params) {
+        Some(token) if !info.token_sha256.is_empty() && token_sha256(token) == info.token_sha256 => Ok(()),
+        _ => {
+            warn!("Denied {} job {} without its token", action, info.id);
+            Err((StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "Job token required" }))))
         }
+    }
+}
+
+// Job status, exit code and artifacts
+async fn get_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let info = find_job(&state, job_id).await?;
+    authorize_job(&info, &headers, &params, "reading")?;
+    Ok(Json(info))
+}
 
+// Cancel a job; its container is removed and artifacts are not collected
+async fn cancel_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let info = find_job(&state, job_id).await?;
+    authorize_job(&info, &headers, &params, "cancelling")?;
+    if info.status.is_finished() {
         return Err((
-            StatusCode::FORBIDDEN,
-            Json(serde_json::json!({
-                "allowed": false,
-                "reason": validation.reason,
-                "severity": format!("{:?}", validation.severity),
-                "blocked_pattern": validation.blocked_pattern
-            })),
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": "Job already finished", "status": info.status })),
         ));
     }
 
+    if let Some(job) = state.jobs.read().await.get(&job_id) {
+        job.cancel.notify_one();
+    }
+    info!("Cancelling job {}", job_id);
     Ok(Json(serde_json::json!({
-        "allowed": true,
-        "command": body
+        "job_id": job_id,
+        "status": "cancelling"
+    })))
+}
+
+// Job output as server-sent events: what was written so far, then new
+// output until the script exits
+async fn job_logs(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    use axum::response::sse::{KeepAlive, Sse};
+    use container::exec::Event as ExecEvent;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let info = find_job(&state, job_id).await?;
+    authorize_job(&info, &headers, &params, "reading logs of")?;
+
+    let running = state.jobs.read().await.get(&job_id).cloned();
+    let (replay, follow) = match running {
+        Some(job) if job.info.read().await.finished_at.is_none() => {
+            let (replay, rx) = job.log.lock().subscribe();
+            let exited = replay.iter().any(|event| matches!(event, ExecEvent::Exit(_)));
+            (replay, (!exited).then_some(rx))
+        }
+        _ => {
+            let info = find_job(&state, job_id).await?;
+            let stdout = tokio::fs::read(state.job_store.log_path(job_id, "stdout")).await.unwrap_or_default();
+            let stderr = tokio::fs::read(state.job_store.log_path(job_id, "stderr")).await.unwrap_or_default();
+            let exit = container::exec::Exit {
+                exit_code: info.exit_code,
+                timed_out: info.status == jobs::JobStatus::TimedOut,
+                duration_ms: match (info.started_at, info.finished_at) {
+                    (Some(started), Some(finished)) => (finished - started).num_milliseconds().max(0) as u64,
+                    _ => 0,
+                },
+            };
+            (vec![ExecEvent::Stdout(stdout), ExecEvent::Stderr(stderr), ExecEvent::Exit(exit)], None)
+        }
+    };
+
+    let stream = futures::stream::unfold((replay.into_iter(), follow), |(mut replay, mut follow)| async move {
+        if let Some(event) = replay.next() {
+            return Some((Ok::<_, std::convert::Infallible>(exec_sse_event(&event)), (replay, follow)));
+        }
+        loop {
+            match follow.as_mut()?.recv().await {
+                Ok(event) => {
+                    let done = matches!(event, ExecEvent::Exit(_));
+                    return Some((Ok(exec_sse_event(&event)), (replay, if done { None } else { follow })));
+                }
+                // Slow listeners miss output rather than hold up the job
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Download an artifact as a tar archive; `index` is its position in the
+// job's `artifacts`
+async fn download_job_artifact(
+    State(state): State<AppState>,
+    Path((job_id, index)): Path<(Uuid, usize)>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    use tokio::io::AsyncReadExt;
+
+    let info = find_job(&state, job_id).await?;
+    authorize_job(&info, &headers, &params, "downloading artifacts of")?;
+    let not_found = |details: String| {
+        (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Artifact not found", "details": details })))
+    };
+    let artifact = info.artifacts.get(index).ok_or_else(|| not_found(format!("Job has {} artifacts", info.artifacts.len())))?;
+    if let Some(ref error) = artifact.error {
+        return Err(not_found(error.clone()));
+    }
+    let file = tokio::fs::File::open(state.job_store.artifact_path(job_id, index)).await
+        .map_err(|e| not_found(e.to_string()))?;
+
+    let body = futures::stream::unfold(file, |mut file| async move {
+        let mut chunk = vec![0u8; 64 * 1024];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok::<_, std::io::Error>(chunk), file))
+            }
+            Err(e) => Some((Err(e), file)),
+        }
+    });
+
+    let name = artifact.path.trim_end_matches('/').rsplit('/').next().unwrap_or("artifact");
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "application/x-tar".to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.tar\"", sanitize_container_name(name))),
+        ],
+        axum::body::Body::from_stream(body),
+    ))
+}
+
+// A user's jobs, newest first
+async fn list_user_jobs(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !validate_user_id(&user_id) {
+        return Err(invalid_user_id());
+    }
+    authorize_user(&state, &user_id, &headers, &params).await?;
+    let limit: i64 = params.get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(50)
+        .clamp(1, 500);
+
+    let mut jobs: HashMap<Uuid, jobs::JobInfo> = HashMap::new();
+    if let Some(ref pool) = state.db_pool {
+        for info in db::jobs::list_by_user(pool, &user_id, limit).await.map_err(history_failed)? {
+            jobs.insert(info.id, info);
+        }
+    }
+    for job in state.jobs.read().await.values() {
+        let info = job.info.read().await;
+        if info.user_id == user_id {
+            jobs.insert(info.id, info.clone());
+        }
+    }
+
+    let mut jobs: Vec<_> = jobs.into_values().collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+    jobs.truncate(limit as usize);
+    Ok(Json(serde_json::json!({
+        "user_id": user_id,
+        "jobs": jobs,
+        "count": jobs.len()
     })))
 }
 
@@ -1643,16 +3577,17 @@ async fn websocket_handler(
     ws: WebSocketUpgrade,
     Path(session_id): Path<Uuid>,
     State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
     info!("WebSocket connection request for session {}", session_id);
-    
-    // Check if session exists
-    {
-        let sessions = state.sessions.read().await;
-        if !sessions.contains_key(&session_id) {
-            warn!("WebSocket connection rejected - session {} not found", session_id);
-            return (StatusCode::NOT_FOUND, "Session not found").into_response();
-        }
+    if state.drain.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+
+    // Browsers cannot set headers on a WebSocket, so the token comes as `?token=`
+    if let Err(rejection) = authorize_session(&state, session_id, &headers, &params).await {
+        return rejection.into_response();
     }
 
     ws.on_upgrade(move |socket| handle_websocket(socket, session_id, state))
@@ -1662,17 +3597,24 @@ async fn pty_websocket_handler(
     ws: WebSocketUpgrade,
     Path(session_id): Path<Uuid>,
     State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
     info!("PTY WebSocket connection request for session {}", session_id);
-    
-    let sessions = state.sessions.read().await;
-    if !sessions.contains_key(&session_id) {
-        error!("Session {} not found for PTY WebSocket", session_id);
-        return (StatusCode::NOT_FOUND, "Session not found").into_response();
+    if state.drain.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+
+    if let Err(rejection) = authorize_session(&state, session_id, &headers, &params).await {
+        return rejection.into_response();
     }
-    drop(sessions);
 
-    ws.on_upgrade(move |socket| handle_pty_websocket(socket, session_id, state))
+    let xff = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok());
+    let real_ip = headers.get("x-real-ip").and_then(|v| v.to_str().ok());
+    let client_ip = extract_client_ip(xff, real_ip, Some(&addr.to_string()));
+
+    ws.on_upgrade(move |socket| handle_pty_websocket(socket, session_id, state, client_ip))
 }
 
 async fn handle_websocket(
@@ -1684,23 +3626,20 @@ async fn handle_websocket(
     use futures::{SinkExt, StreamExt};
 
     info!("WebSocket connected for session {}", session_id);
-    
+    let _active = state.drain.track();
+    let mut notices = state.drain.subscribe();
+    let mut container_events = state.container_events.subscribe();
+
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
     // Start a Docker container with exec
-    let container_id = match start_container(&state.docker, session_id, &state).await {
-        Ok((container_id, container_name)) => {
-            info!("Started container {} for session {}", container_name, session_id);
-            
-            // Update session
-            {
-                let mut sessions = state.sessions.write().await;
-                if let Some(session) = sessions.get_mut(&session_id) {
-                    session.container_id = Some(container_id.clone());
-                    session.container_name = Some(container_name.clone());
-                    session.status = "running".to_string();
-                }
-            }
+    let docker = session_docker(&state, session_id).await;
+    let container_id = match start_container(&docker, session_id, &state).await {
+        Ok(started) => {
+            info!("Started container {} for session {}", started.name, session_id);
+
+            record_container_started(&state, session_id, &started).await;
+            let StartedContainer { id: container_id, name: container_name, runtime, network, .. } = started;
             
             // Send container ready message with working terminal
             if let Err(e) = ws_sender.send(Message::Text(
@@ -1709,6 +3648,8 @@ async fn handle_websocket(
                     "session_id": session_id,
                     "container_id": container_id,
                     "container_name": container_name,
+                    "runtime": runtime.runtime,
+                    "network": network.to_string(),
                     "message": "🐳 Container started! Terminal ready for commands.",
                     "timestamp": chrono::Utc::now()
                 }).to_string()
@@ -1761,10 +3702,39 @@ async fn handle_websocket(
 
     loop {
         // Use timeout to allow periodic keepalive checks
-        let msg = tokio::time::timeout(
-            std::time::Duration::from_secs(30),
-            ws_receiver.next()
-        ).await;
+        let msg = tokio::select! {
+            msg = tokio::time::timeout(std::time::Duration::from_secs(30), ws_receiver.next()) => msg,
+            Ok(notice) = notices.recv() => {
+                let _ = ws_sender.send(Message::Text(
+                    serde_json::json!({
+                        "type": "server_shutdown",
+                        "session_id": session_id,
+                        "seconds_left": notice.seconds_left(),
+                        "message": notice.message(),
+                        "timestamp": chrono::Utc::now()
+                    }).to_string()
+                )).await;
+                if notice == shutdown::Notice::Closing {
+                    let _ = ws_sender.send(Message::Close(None)).await;
+                    break;
+                }
+                continue;
+            }
+            Some(stop) = container::events::stopped(&mut container_events, &container_id) => {
+                let _ = ws_sender.send(Message::Text(
+                    serde_json::json!({
+                        "type": "container_stopped",
+                        "session_id": session_id,
+                        "reason": stop.cause.as_str(),
+                        "exit_code": stop.exit_code,
+                        "message": stop.message(),
+                        "timestamp": chrono::Utc::now()
+                    }).to_string()
+                )).await;
+                let _ = ws_sender.send(Message::Close(None)).await;
+                break;
+            }
+        };
 
         let msg = match msg {
             Ok(Some(msg)) => msg,
@@ -1800,7 +3770,7 @@ async fn handle_websocket(
                     let raw_input = &command[6..];
                     debug!("Handling raw control input for session {}: {:?}", session_id, raw_input);
                     
-                    match handle_interactive_input(&state.docker, &container_id, raw_input).await {
+                    match handle_interactive_input(&docker, &container_id, raw_input).await {
                         Ok(output) => {
                             if !output.trim().is_empty() {
                                 let response = serde_json::json!({
@@ -1837,7 +3807,7 @@ async fn handle_websocket(
                 
                 debug!("Executing TTY command '{}' in session {}", processed_command, session_id);
                 
-                match execute_command_with_tty(&state.docker, &container_id, &processed_command).await {
+                match execute_command_with_tty(&docker, &container_id, &processed_command).await {
                     Ok(output) => {
                         debug!("Command '{}' executed successfully in session {}", command, session_id);
                         
@@ -1894,8 +3864,7 @@ async fn handle_websocket(
         }
     }
 
-    // Cleanup container
-    cleanup_container(&state, session_id).await;
+    end_terminal(&state, session_id).await;
 }
 
 async fn handle_interactive_input(
@@ -1938,29 +3907,28 @@ async fn handle_pty_websocket(
     socket: axum::extract::ws::WebSocket,
     session_id: Uuid,
     state: AppState,
+    client_ip: Option<String>,
 ) {
     use axum::extract::ws::Message;
     use bollard::exec::{CreateExecOptions, StartExecOptions, ResizeExecOptions};
     use tokio::sync::mpsc;
 
     info!("PTY WebSocket connected for session {}", session_id);
+    let _active = state.drain.track();
+    let mut notices = state.drain.subscribe();
+    let mut container_events = state.container_events.subscribe();
 
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
-    let container_id = match start_container(&state.docker, session_id, &state).await {
-        Ok((container_id, container_name)) => {
-            info!("Started container {} for PTY session {}", container_name, session_id);
-
-            {
-                let mut sessions = state.sessions.write().await;
-                if let Some(session) = sessions.get_mut(&session_id) {
-                    session.container_id = Some(container_id.clone());
-                    session.container_name = Some(container_name.clone());
-                    session.status = "running".to_string();
-                }
-            }
+    let docker = session_docker(&state, session_id).await;
+    let (container_id, host_proxy_allowed) = match start_container(&docker, session_id, &state).await {
+        Ok(started) => {
+            info!("Started container {} for PTY session {}", started.name, session_id);
 
-            container_id
+            record_container_started(&state, session_id, &started).await;
+            // Only bridged containers can reach the host's Anyone proxy directly
+            let host_proxy_allowed = started.network == config::NetworkMode::Open && started.egress_proxy.is_none();
+            (started.id, host_proxy_allowed)
         }
         Err(e) => {
             error!("Failed to start container for session {}: {}", session_id, e);
@@ -1970,9 +3938,14 @@ async fn handle_pty_websocket(
         }
     };
 
-    // Check if privacy mode is enabled
-    let privacy_enabled = state.anyone_service.is_enabled().await;
-    let socks_port = state.anyone_service.get_socks_port();
+    // Per-session SOCKS credentials so this session gets its own circuits
+    let (privacy, socks_auth, user_id) = state.sessions.read().await
+        .get(&session_id)
+        .map(|s| (s.privacy, s.socks_auth.clone(), s.user_id.clone()))
+        .unwrap_or_default();
+    let privacy_enabled = host_proxy_allowed && privacy;
+    let isolation = state.anyone_service.isolates_by_credentials().then_some(&socks_auth);
+    let proxy_url = state.anyone_service.proxy().container_url(isolation);
 
     // Build shell command - if privacy enabled, setup proxy config first
     let shell_cmd = if privacy_enabled {
@@ -1980,13 +3953,12 @@ async fn handle_pty_websocket(
             "/bin/bash".to_string(),
             "-c".to_string(),
             format!(
-                "echo '--socks5-hostname host.docker.internal:{}' > ~/.curlrc && \
-                 echo 'proxy = socks5h://host.docker.internal:{}' >> ~/.curlrc && \
-                 export ALL_PROXY=socks5h://host.docker.internal:{} && \
-                 export all_proxy=socks5h://host.docker.internal:{} && \
-                 echo '🔐 Privacy mode ACTIVE - traffic routed through Anyone Protocol' && \
+                "echo 'proxy = {}' > ~/.curlrc && \
+                 export ALL_PROXY={} && \
+                 export all_proxy={} && \
+                 echo '🔐 Privacy mode ACTIVE - traffic routed through {}' && \
                  exec /bin/bash --login -i",
-                socks_port, socks_port, socks_port, socks_port
+                proxy_url, proxy_url, proxy_url, state.anyone_service.backend_name()
             ),
         ]
     } else {
@@ -2014,7 +3986,6 @@ async fn handle_pty_websocket(
     ];
 
     if privacy_enabled {
-        let proxy_url = format!("socks5h://host.docker.internal:{}", socks_port);
         exec_env.push(format!("ALL_PROXY={}", proxy_url));
         exec_env.push(format!("all_proxy={}", proxy_url));
         exec_env.push(format!("NOXTERM_PRIVACY=enabled"));
@@ -2032,7 +4003,7 @@ async fn handle_pty_websocket(
         ..Default::default()
     };
 
-    let exec_id = match state.docker.create_exec(&container_id, exec_config).await {
+    let exec_id = match docker.create_exec(&container_id, exec_config).await {
         Ok(exec) => exec.id,
         Err(e) => {
             error!("Failed to create PTY exec for session {}: {}", session_id, e);
@@ -2042,7 +4013,7 @@ async fn handle_pty_websocket(
         }
     };
 
-    let exec_stream = match state.docker.start_exec(&exec_id, Some(StartExecOptions {
+    let exec_stream = match docker.start_exec(&exec_id, Some(StartExecOptions {
         tty: true,
         ..Default::default()
     })).await {
@@ -2058,7 +4029,7 @@ async fn handle_pty_websocket(
     // Resize the PTY to default terminal size AFTER starting (exec must be running)
     // Give it a moment to start
     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    let resize_result = state.docker.resize_exec(&exec_id, ResizeExecOptions {
+    let resize_result = docker.resize_exec(&exec_id, ResizeExecOptions {
         height: 24,
         width: 80,
     }).await;
@@ -2081,10 +4052,33 @@ async fn handle_pty_websocket(
             let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
             let shutdown_tx2 = shutdown_tx.clone();
 
+            // Policy notices are written to the terminal by the output task
+            let (notice_tx, mut notice_rx) = mpsc::channel::<String>(16);
+            let alternate_screen = Arc::new(std::sync::atomic::AtomicBool::new(false));
+            let screen_changes = Arc::new(std::sync::atomic::AtomicU64::new(0));
+            let record_history = state.settings.security.pty_history && history_enabled(&state, &user_id).await;
+            let mut guard = PtyGuard {
+                mode: state.settings.security.pty_policy,
+                record_history,
+                policy: state.policy.clone(),
+                role: state.settings.security.role_for(&user_id).to_string(),
+                user_id,
+                session_id,
+                client_ip,
+                db_pool: state.db_pool.clone(),
+                assembler: security::line::LineAssembler::new(),
+                alternate_screen: alternate_screen.clone(),
+                screen_changes: screen_changes.clone(),
+                screen_changes_seen: 0,
+                before_screen_change: None,
+                notices: notice_tx,
+                decoder: security::line::Utf8Decoder::new(),
+            };
+
             // Channel for resize requests (exec_id needed in input task)
             let (resize_tx, mut resize_rx) = mpsc::channel::<(u16, u16)>(4);
             let exec_id_clone = exec_id.clone();
-            let docker_clone = state.docker.clone();
+            let docker_clone = docker.clone();
 
             // Spawn resize handler task
             let resize_task = tokio::spawn(async move {
@@ -2139,7 +4133,9 @@ async fn handle_pty_websocket(
                                         text.chars().take(20).collect::<String>(),
                                         text.len());
 
-                                    // Write raw terminal input to container stdin
+                                    // Write terminal input to container stdin, holding
+                                    // back lines the command policy denies
+                                    let text = guard.filter(&text).await;
                                     match input.write_all(text.as_bytes()).await {
                                         Ok(_) => {
                                             // Flush immediately to ensure data is sent
@@ -2156,7 +4152,8 @@ async fn handle_pty_websocket(
                                 Ok(Some(Ok(Message::Binary(data)))) => {
                                     last_activity = std::time::Instant::now();
 
-                                    // Binary data is raw terminal input - pass through directly
+                                    // Binary data is raw terminal input
+                                    let data = guard.filter_bytes(&data).await;
                                     if input.write_all(&data).await.is_err() {
                                         warn!("Failed to write binary to PTY stdin");
                                         break;
@@ -2199,6 +4196,8 @@ async fn handle_pty_websocket(
             });
 
             // Handle output from container stdout to WebSocket
+            let docker = docker.clone();
+            let output_container_id = container_id.clone();
             let output_task = tokio::spawn(async move {
                 let mut consecutive_errors = 0;
                 let max_consecutive_errors = 5;
@@ -2206,10 +4205,32 @@ async fn handle_pty_websocket(
 
                 loop {
                     // Read with timeout to allow periodic checks
-                    match tokio::time::timeout(
-                        std::time::Duration::from_secs(60),
-                        output.next()
-                    ).await {
+                    let next = tokio::select! {
+                        Some(notice) = notice_rx.recv() => {
+                            if ws_sender.send(Message::Text(notice)).await.is_err() {
+                                info!("WebSocket send failed - client disconnected");
+                                break;
+                            }
+                            continue;
+                        }
+                        Ok(notice) = notices.recv() => {
+                            let text = format!("\r\n\x1b[33m⚠ {}\x1b[0m\r\n", notice.message());
+                            let _ = ws_sender.send(Message::Text(text)).await;
+                            if notice == shutdown::Notice::Closing {
+                                let _ = ws_sender.send(Message::Close(None)).await;
+                                break;
+                            }
+                            continue;
+                        }
+                        Some(stop) = container::events::stopped(&mut container_events, &output_container_id) => {
+                            let text = format!("\r\n\x1b[31m✖ {}\x1b[0m\r\n", stop.message());
+                            let _ = ws_sender.send(Message::Text(text)).await;
+                            let _ = ws_sender.send(Message::Close(None)).await;
+                            break;
+                        }
+                        next = tokio::time::timeout(std::time::Duration::from_secs(60), output.next()) => next,
+                    };
+                    match next {
                         Ok(Some(Ok(log_output))) => {
                             consecutive_errors = 0; // Reset on success
                             let data = match log_output {
@@ -2231,6 +4252,13 @@ async fn handle_pty_websocket(
                                 }
                             };
 
+                            // Full-screen programs (vim, less) take input that is not a command line,
+                            // so it stays out of history and is only audited
+                            if let Some(on) = security::line::alternate_screen_change(&data) {
+                                alternate_screen.store(on, std::sync::atomic::Ordering::Relaxed);
+                                screen_changes.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            }
+
                             // Send binary data directly to preserve escape sequences
                             if ws_sender.send(Message::Binary(data.into())).await.is_err() {
                                 info!("WebSocket send failed - client disconnected");
@@ -2247,8 +4275,31 @@ async fn handle_pty_websocket(
                             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
                         }
                         Ok(None) => {
-                            info!("PTY output stream ended (shell exited)");
-                            let _ = ws_sender.send(Message::Text("\r\n\r\n[Shell exited]\r\n".to_string())).await;
+                            // The stream also ends when the container dies; its
+                            // event may arrive just after
+                            let running = docker.inspect_container(&output_container_id, None).await
+                                .ok()
+                                .and_then(|info| info.state?.running)
+                                .unwrap_or(false);
+                            let stop = if running {
+                                None
+                            } else {
+                                tokio::time::timeout(
+                                    std::time::Duration::from_secs(2),
+                                    container::events::stopped(&mut container_events, &output_container_id),
+                                ).await.ok().flatten()
+                            };
+                            match stop {
+                                Some(stop) => {
+                                    info!("PTY output stream ended: container {}", stop.cause.as_str());
+                                    let text = format!("\r\n\x1b[31m✖ {}\x1b[0m\r\n", stop.message());
+                                    let _ = ws_sender.send(Message::Text(text)).await;
+                                }
+                                None => {
+                                    info!("PTY output stream ended (shell exited)");
+                                    let _ = ws_sender.send(Message::Text("\r\n\r\n[Shell exited]\r\n".to_string())).await;
+                                }
+                            }
                             break;
                         }
                         Err(_) => {
@@ -2280,9 +4331,223 @@ async fn handle_pty_websocket(
     }
 
     info!("PTY WebSocket session {} completed", session_id);
-    cleanup_container(&state, session_id).await;
+    end_terminal(&state, session_id).await;
+}
+
+/// Remove a session's container once its terminal closes, unless the
+/// backend is shutting down: then the container is kept for reattaching
+/// after the restart
+async fn end_terminal(state: &AppState, session_id: Uuid) {
+    if state.drain.is_draining() {
+        info!("Keeping container of session {} across the restart", session_id);
+        return;
+    }
+    cleanup_container(state, session_id).await;
+}
+
+/// Applies the command policy to the lines typed into a PTY session, and
+/// records them in the user's history
+struct PtyGuard {
+    mode: config::PtyPolicyMode,
+    record_history: bool,
+    policy: Arc<security::Policy>,
+    role: String,
+    user_id: String,
+    session_id: Uuid,
+    client_ip: Option<String>,
+    db_pool: Option<DbPool>,
+    assembler: security::line::LineAssembler,
+    /// Set while the output says a full-screen program owns the terminal;
+    /// its keystrokes stay out of history and are audited, not enforced
+    alternate_screen: Arc<std::sync::atomic::AtomicBool>,
+    /// Screen switches seen in the output, and the line as it stood at the
+    /// last one: keys a full-screen program consumed may still lead the line
+    screen_changes: Arc<std::sync::atomic::AtomicU64>,
+    screen_changes_seen: u64,
+    before_screen_change: Option<String>,
+    notices: tokio::sync::mpsc::Sender<String>,
+    /// Binary frames may split a character; the rest comes in the next one
+    decoder: security::line::Utf8Decoder,
 }
 
+impl PtyGuard {
+    /// Binary input to write to the PTY: unchanged when nothing reads it,
+    /// otherwise decoded and filtered like text
+    async fn filter_bytes(&mut self, input: &[u8]) -> Vec<u8> {
+        if self.mode == config::PtyPolicyMode::Off && !self.record_history {
+            return input.to_vec();
+        }
+        let text = self.decoder.decode(input);
+        self.filter(&text).await.into_bytes()
+    }
+
+    /// Input to write to the PTY; in enforce mode a denied line's Enter is
+    /// replaced by Ctrl-C so the shell discards it
+    async fn filter(&mut self, input: &str) -> String {
+        if self.mode == config::PtyPolicyMode::Off && !self.record_history {
+            return input.to_string();
+        }
+        // Keys sent to a full-screen program are not shell lines; they are
+        // still checked and logged, but not blocked
+        let full_screen = self.alternate_screen.load(std::sync::atomic::Ordering::Relaxed);
+        let changes = self.screen_changes.load(std::sync::atomic::Ordering::Relaxed);
+        if changes != self.screen_changes_seen {
+            self.screen_changes_seen = changes;
+            self.before_screen_change = Some(self.assembler.line());
+        }
+
+        let mut filtered = String::with_capacity(input.len());
+        for segment in self.assembler.feed(input) {
+            match segment {
+                security::line::Segment::Forward(keys) => filtered.push_str(&keys),
+                security::line::Segment::Submit { line, pattern, exact, enter } => {
+                    let before = self.before_screen_change.take().unwrap_or_default();
+                    let tail = line.strip_prefix(before.as_str()).filter(|tail| !before.is_empty() && !tail.is_empty());
+                    let pattern = pattern.as_deref();
+                    if self.mode == config::PtyPolicyMode::Off || self.check(&line, tail, pattern, exact, full_screen).await {
+                        if !full_screen && pattern.is_none() {
+                            self.record(&line, exact);
+                        }
+                        filtered.push_str(&enter);
+                    } else {
+                        filtered.push('\x03');
+                    }
+                }
+                security::line::Segment::Pasted { line, pattern, exact, newline } => {
+                    if self.mode == config::PtyPolicyMode::Off
+                        || self.check(&line, None, pattern.as_deref(), exact, full_screen).await
+                    {
+                        filtered.push_str(&newline);
+                    } else {
+                        filtered.push('\x03');
+                    }
+                }
+            }
+        }
+        filtered
+    }
+
+    /// Add a line that is about to run to the user's history. Lines the
+    /// shell completed or may have edited are left out rather than recorded
+    /// half-typed.
+    fn record(&self, line: &str, exact: bool) {
+        let line = line.trim();
+        if !self.record_history || !exact || line.is_empty() {
+            return;
+        }
+        let Some(pool) = self.db_pool.clone() else {
+            return;
+        };
+
+        let (session_id, user_id, command) = (self.session_id, self.user_id.clone(), line.to_string());
+        tokio::spawn(async move {
+            let entry = db::history::Entry {
+                session_id,
+                user_id: &user_id,
+                command: &command,
+                source: db::history::Source::Pty,
+                exit_code: None,
+                timed_out: false,
+                duration_ms: None,
+            };
+            if let Err(e) = db::history::record(&pool, &entry).await {
+                warn!("Failed to record command history for session {}: {}", session_id, e);
+            }
+        });
+    }
+
+    /// Whether a submitted line may run. `tail` is what was typed after the
+    /// last screen switch, checked too in case what came before never
+    /// reached the shell; `pattern` is the line with `*` where Tab completed,
+    /// denied if anything it could complete to would be.
+    async fn check(&self, line: &str, tail: Option<&str>, pattern: Option<&str>, exact: bool, full_screen: bool) -> bool {
+        let mut validation = validate_with(&self.policy, line, &self.role);
+        if let Some(pattern) = pattern {
+            let completed = validate_with(&self.policy, pattern, &self.role);
+            if validation.is_safe && !completed.is_safe {
+                validation = completed;
+            }
+        }
+        if let Some(tail) = tail {
+            let alternative = validate_with(&self.policy, tail, &self.role);
+            let clean = validation.is_safe && validation.severity == SecuritySeverity::Safe;
+            if validation.is_safe && !alternative.is_safe || clean && alternative.severity != SecuritySeverity::Safe {
+                validation = alternative;
+            }
+        }
+        let enforce = self.mode == config::PtyPolicyMode::Enforce && !full_screen;
+        // What was checked may not be what the shell runs
+        let unverified = enforce && !exact && validation.is_safe;
+        if !unverified && validation.is_safe && validation.severity == SecuritySeverity::Safe {
+            return true;
+        }
+
+        let blocked = enforce && !validation.is_safe || unverified;
+        let (rule, reason) = if unverified {
+            ("inexact_line", "Line was edited with keys the policy cannot follow (search or control keys); type it out in full")
+        } else {
+            (
+                validation.rule.as_deref().unwrap_or("input"),
+                validation.reason.as_deref().unwrap_or("Denied by command policy"),
+            )
+        };
+
+        if enforce {
+            let notice = if blocked {
+                format!("\r\n\x1b[31m✖ Blocked by policy ({}): {}\x1b[0m\r\n", rule, reason)
+            } else {
+                format!("\r\n\x1b[33m⚠ Policy warning ({}): {}\x1b[0m\r\n", rule, reason)
+            };
+            let _ = self.notices.send(notice).await;
+        }
+
+        if let Some(ref pool) = self.db_pool {
+            let mut description = format!("{} [{}]", reason, rule);
+            if !exact {
+                description.push_str(" (line rebuilt from keystrokes; the shell may have edited it)");
+            } else if pattern.is_some() {
+                description.push_str(" (checked as completed by Tab)");
+            }
+            if full_screen {
+                description.push_str(" (typed while the terminal showed a full-screen program)");
+            }
+            let severity = match validation.severity {
+                SecuritySeverity::Critical => db::security::Severity::Critical,
+                SecuritySeverity::Warning => db::security::Severity::Warning,
+                _ => db::security::Severity::Info,
+            };
+            let _ = db::security::log_event(
+                pool,
+                Some(self.session_id),
+                &self.user_id,
+                if blocked { "command_blocked" } else { "command_flagged" },
+                severity,
+                Some(&description),
+                Some(line),
+                self.client_ip.as_deref(),
+            ).await;
+
+            if blocked {
+                let _ = db::audit::log(
+                    pool,
+                    Some(self.session_id),
+                    &self.user_id,
+                    db::audit::EventType::SecurityViolation,
+                    Some(serde_json::json!({
+                        "blocked_command": line,
+                        "rule": rule,
+                        "reason": reason,
+                        "source": "pty"
+                    })),
+                    self.client_ip.as_deref(),
+                    None,
+                ).await;
+            }
+        }
+
+        !blocked
+    }
+}
 
 async fn execute_command_with_tty(
     docker: &Docker,
@@ -2357,7 +4622,60 @@ async fn execute_command_with_tty(
     }
 }
 
-async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) -> Result<(String, String)> {
+/// Update the session cache and database once a container is running
+async fn record_container_started(state: &AppState, session_id: Uuid, started: &StartedContainer) {
+    let user_id = {
+        let mut sessions = state.sessions.write().await;
+        sessions.get_mut(&session_id).map(|session| {
+            session.container_id = Some(started.id.clone());
+            session.container_name = Some(started.name.clone());
+            session.runtime = Some(started.runtime.runtime.clone());
+            session.status = "running".to_string();
+            session.user_id.clone()
+        })
+    };
+
+    let (Some(pool), Some(user_id)) = (state.db_pool.as_ref(), user_id) else {
+        return;
+    };
+
+    if let Err(e) = db::sessions::set_container(pool, session_id, &started.id, &started.name).await {
+        error!("Failed to persist container for session {}: {}", session_id, e);
+    }
+
+    if let Err(e) = db::sessions::merge_metadata(
+        pool,
+        session_id,
+        serde_json::json!({ "runtime": started.runtime }),
+    ).await {
+        error!("Failed to store runtime for session {}: {}", session_id, e);
+    }
+
+    let _ = db::audit::log(
+        pool,
+        Some(session_id),
+        &user_id,
+        db::audit::EventType::ContainerStarted,
+        Some(serde_json::json!({
+            "container_id": started.id,
+            "container_name": started.name,
+            "runtime": started.runtime.runtime,
+            "runtime_requested": started.runtime.requested,
+            "runtime_fallback": started.runtime.fell_back,
+            "network": started.network.to_string(),
+            "egress_proxy": started.egress_proxy,
+            "transparent_proxy": started.transparent,
+            "resumed": started.resumed
+        })),
+        None,
+        None,
+    ).await;
+}
+
+/// Written by the startup command once package setup has finished (or failed)
+const SETUP_DONE_MARKER: &str = "/tmp/.noxterm-setup-done";
+
+async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) -> Result<StartedContainer> {
     use bollard::image::CreateImageOptions;
 
     let session = {
@@ -2366,8 +4684,32 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
             .ok_or_else(|| anyhow::anyhow!("Session not found"))?
     };
 
+    // Refuse rather than silently start a privacy session on a leaking network
+    let network = session.network.mode();
+    if session.privacy && network != config::NetworkMode::None && !state.anyone_service.is_enabled().await {
+        return Err(anyhow::anyhow!("Anyone network is not running, refusing to start privacy session"));
+    }
+
+    // Sessions adopted after a restart keep the container they were using
+    if let Some(ref container_id) = session.container_id {
+        if let Some(started) = resume_container(docker, state, &session, container_id).await? {
+            info!("Resuming container {} for session {}", started.name, session_id);
+            return Ok(started);
+        }
+    }
+
     let image = session.container_image.clone();
-    let container_name = format!("noxterm-session-{}", session_id.to_string().replace("-", "")[0..12].to_lowercase());
+
+    let engine = host_engine(state, &session.host, docker).await?;
+
+    // Verify the sandbox runtime before pulling anything
+    let runtime = container::runtime::resolve(
+        docker,
+        state.settings.docker.runtime_for_image(&image),
+        state.settings.docker.runtime_policy,
+    ).await?;
+
+    let container_name = container::labels::container_name(session_id);
 
     // Auto-pull image if not present
     info!("Checking for image: {}", image);
@@ -2401,9 +4743,39 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
         info!("Successfully pulled image: {}", image);
     }
 
-    // Check if privacy mode is enabled and get proxy settings
-    let privacy_enabled = state.anyone_service.is_enabled().await;
-    let socks_port = state.anyone_service.get_socks_port();
+    let upstream = egress_upstream(state, &session);
+    if let (Some(_), Some(reason)) = (&upstream, engine.egress_limitation()) {
+        return Err(anyhow::anyhow!(
+            "{} cannot route session traffic through the egress proxy ({}); network-restricted sessions need a rootful engine on this machine",
+            engine.describe(),
+            reason
+        ));
+    }
+
+    let gateway = match upstream {
+        Some(_) => Some(container::network::ensure_egress_network(docker, &state.settings.docker.egress_network).await?),
+        None => None,
+    };
+    let egress_addr = match gateway {
+        Some(gateway) => Some(state.egress.ensure_listening(gateway).await?),
+        None => None,
+    };
+
+    // Privacy sessions resolve names only through Anyone; refuse to start
+    // rather than fall back to Docker's upstream resolvers
+    let dns_servers = match (network, gateway) {
+        (config::NetworkMode::AnyoneOnly, Some(gateway)) => {
+            let resolver = state.dns.ensure_listening(gateway).await
+                .map_err(|e| anyhow::anyhow!("Anonymous DNS resolver unavailable: {}", e))?;
+            Some(vec![resolver.ip().to_string()])
+        }
+        _ => None,
+    };
+    let egress_proxy = egress_addr.map(|addr| format!("socks5h://{}", addr));
+
+    // Privacy sessions also get a netguard sidecar so tools that ignore proxy
+    // settings are still forced through Anyone
+    let transparent = network == config::NetworkMode::AnyoneOnly && state.settings.anyone.transparent;
 
     // Build environment variables - add proxy settings if privacy is enabled
     let mut env_vars = vec![
@@ -2414,24 +4786,93 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
         "SHELL=/bin/bash".to_string(),
         "LANG=en_US.UTF-8".to_string(),
         "LC_ALL=en_US.UTF-8".to_string(),
+        format!("NOXTERM_NETWORK={}", network),
     ];
 
-    if privacy_enabled {
+    if let Some(ref proxy_url) = egress_proxy {
+        // The proxy is the only reachable address, so setup goes through it too
+        env_vars.push(format!("ALL_PROXY={}", proxy_url));
+        env_vars.push(format!("all_proxy={}", proxy_url));
+        env_vars.push(format!("NOXTERM_SOCKS_PROXY={}", proxy_url.trim_start_matches("socks5h://")));
+        if network == config::NetworkMode::AnyoneOnly {
+            env_vars.push("NOXTERM_PRIVACY=enabled".to_string());
+        }
+    } else if session.privacy && network == config::NetworkMode::Open {
         // Mark privacy mode - actual proxy config done when PTY shell starts
         // DON'T set HTTP_PROXY here as it breaks apt-get during container setup
-        let proxy_host = "host.docker.internal";
+        let proxy_url = state.anyone_service.proxy().container_url(None);
         info!("🔐 Privacy mode enabled - proxy will be configured on shell start");
         env_vars.push("NOXTERM_PRIVACY=enabled".to_string());
-        env_vars.push(format!("NOXTERM_SOCKS_PROXY={}:{}", proxy_host, socks_port));
+        env_vars.push(format!(
+            "NOXTERM_SOCKS_PROXY={}",
+            proxy_url.split_once("://").map_or(proxy_url.as_str(), |(_, rest)| rest)
+        ));
     }
 
-    // Build container startup command - install Node.js 18 via NodeSource for Anyone client compatibility
-    let startup_cmd = "DEBIAN_FRONTEND=noninteractive apt-get update && apt-get install -y ca-certificates curl gnupg && mkdir -p /etc/apt/keyrings && curl -fsSL https://deb.nodesource.com/gpgkey/nodesource-repo.gpg.key | gpg --dearmor -o /etc/apt/keyrings/nodesource.gpg && echo 'deb [signed-by=/etc/apt/keyrings/nodesource.gpg] https://deb.nodesource.com/node_18.x nodistro main' | tee /etc/apt/sources.list.d/nodesource.list && apt-get update && apt-get install -y nodejs nano vim wget git htop neofetch locales && locale-gen en_US.UTF-8 && update-locale LANG=en_US.UTF-8 && tail -f /dev/null".to_string();
+    // Build container startup command - install Node.js 18 via NodeSource for Anyone client compatibility.
+    // Setup may fail behind an allowlist; the container stays up either way.
+    let setup_cmd = "DEBIAN_FRONTEND=noninteractive apt-get update && apt-get install -y ca-certificates curl gnupg && mkdir -p /etc/apt/keyrings && curl -fsSL https://deb.nodesource.com/gpgkey/nodesource-repo.gpg.key | gpg --dearmor -o /etc/apt/keyrings/nodesource.gpg && echo 'deb [signed-by=/etc/apt/keyrings/nodesource.gpg] https://deb.nodesource.com/node_18.x nodistro main' | tee /etc/apt/sources.list.d/nodesource.list && apt-get update && apt-get install -y nodejs nano vim wget git htop neofetch locales && locale-gen en_US.UTF-8 && update-locale LANG=en_US.UTF-8";
+    let mut startup_cmd = match (network, &egress_proxy) {
+        // Jobs run their own script on the bare image
+        _ if session.job_id.is_some() => format!("touch {}; tail -f /dev/null", SETUP_DONE_MARKER),
+        (config::NetworkMode::None, _) => format!("touch {}; tail -f /dev/null", SETUP_DONE_MARKER),
+        (_, Some(proxy_url)) => format!(
+            "printf 'Acquire::http::Proxy \"%s\";\\nAcquire::https::Proxy \"%s\";\\n' {proxy} {proxy} > /etc/apt/apt.conf.d/99noxterm-proxy; ({setup}); touch {marker}; tail -f /dev/null",
+            proxy = proxy_url,
+            setup = setup_cmd,
+            marker = SETUP_DONE_MARKER,
+        ),
+        _ => format!("({}); touch {}; tail -f /dev/null", setup_cmd, SETUP_DONE_MARKER),
+    };
+    if transparent {
+        // Hold setup until the sidecar's firewall is up and the leak check passed
+        startup_cmd = format!(
+            "until [ -f {} ]; do sleep 0.2; done; {}",
+            container::transparent::NETWORK_READY_MARKER,
+            startup_cmd
+        );
+    }
 
     // For privacy mode, we'll configure curl via .curlrc AFTER container starts (in PTY handler)
 
-    let config = Config {
+    let (network_mode, extra_hosts) = match (network, &egress_proxy) {
+        (config::NetworkMode::None, _) => ("none".to_string(), None),
+        (_, Some(_)) => (state.settings.docker.egress_network.clone(), None),
+        // Add host.docker.internal mapping for all platforms (ensures consistent behavior).
+        // Podman releases without host-gateway add their own entry
+        _ if engine.host_gateway => ("bridge".to_string(), Some(vec!["host.docker.internal:host-gateway".to_string()])),
+        _ => ("bridge".to_string(), None),
+    };
+
+    // Cap the writable layer where the storage driver allows it; the lifecycle
+    // disk watcher enforces the quota everywhere else
+    let limits = session.resource_limits.clone();
+    let disk_mb = limits.disk_mb;
+    let storage_opt = if engine.storage_size {
+        Some(container::storage::storage_opt(disk_mb))
+    } else {
+        info!("Storage driver cannot enforce disk size, relying on disk watcher ({} MB quota)", disk_mb);
+        None
+    };
+
+    // Rootless engines on cgroup v1 reject memory, CPU and pids limits; the
+    // host was placeable before its engine was known, so check again here
+    let mut limit_config = container::limits::host_config(&limits);
+    if !engine.resource_limits {
+        if !state.settings.docker.allow_unlimited {
+            return Err(anyhow::anyhow!(
+                "{} cannot enforce resource limits without cgroup v2, refusing session {} (NOXTERM_DOCKER_ALLOW_UNLIMITED allows it)",
+                engine.describe(), session_id
+            ));
+        }
+        warn!("{} cannot enforce resource limits without cgroup v2, starting session {} unlimited",
+            engine.describe(), session_id);
+        limit_config = HostConfig { tmpfs: limit_config.tmpfs, ..Default::default() };
+    }
+
+    let mut config = Config {
         image: Some(image),
+        labels: Some(container_owner(state, &session).labels(container::labels::Role::Session)),
         cmd: Some(vec![
             "/bin/bash".to_string(),
             "-c".to_string(),
@@ -2441,20 +4882,16 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
         working_dir: Some("/root".to_string()),
         user: Some("root".to_string()),
         host_config: Some(HostConfig {
-            memory: Some(1024 * 1024 * 1024), // 1GB memory
-            memory_swap: Some(1024 * 1024 * 1024),
-            cpu_quota: Some(100000), // 1 CPU
-            cpu_period: Some(100000),
-            pids_limit: Some(200),
+            storage_opt,
 
             auto_remove: Some(true),
             privileged: Some(false),
+            runtime: engine.runtime_name(&runtime.daemon_name),
             readonly_rootfs: Some(false),
 
-            network_mode: Some("bridge".to_string()),
-
-            // Add host.docker.internal mapping for all platforms (ensures consistent behavior)
-            extra_hosts: Some(vec!["host.docker.internal:host-gateway".to_string()]),
+            network_mode: Some(network_mode),
+            extra_hosts,
+            dns: dns_servers,
 
             cap_add: Some(vec![
                 "SETUID".to_string(),
@@ -2464,7 +4901,8 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
                 "FOWNER".to_string(),
             ]),
 
-            ..Default::default()
+            // Memory, CPU, pids and tmpfs from the session's resource tier
+            ..limit_config
         }),
         ..Default::default()
     };
@@ -2474,20 +4912,53 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
         platform: None,
     };
 
-    info!("Creating container {} for session {}", container_name, session_id);
+    info!("Creating container {} for session {} (network: {})", container_name, session_id, network);
 
-    let response = docker.create_container(Some(options), config).await?;
+    let response = match docker.create_container(Some(options.clone()), config.clone()).await {
+        Err(e) if container::storage::is_storage_opt_rejected(&e) => {
+            warn!("Docker rejected storage size limit ({}), retrying without it", e);
+            if let Some(host_config) = config.host_config.as_mut() {
+                host_config.storage_opt = None;
+            }
+            docker.create_container(Some(options), config).await?
+        }
+        result => result?,
+    };
     let container_id = response.id;
 
+    // Recorded before the container runs, so a session torn down while it
+    // starts (a cancelled job) takes the container with it
+    if let Some(session) = state.sessions.write().await.get_mut(&session_id) {
+        session.container_id = Some(container_id.clone());
+        session.container_name = Some(container_name.clone());
+    }
+
     docker.start_container(&container_id, None::<StartContainerOptions<String>>).await?;
 
+    if let Some(upstream) = upstream {
+        if let Err(e) = register_egress(docker, state, &session, &container_id, upstream).await {
+            let _ = docker.stop_container(&container_id, None).await;
+            return Err(e);
+        }
+    }
+
+    if let (true, Some(proxy)) = (transparent, egress_addr) {
+        // Fail closed: a session whose traffic might bypass Anyone never starts
+        if let Err(e) = enable_transparent_proxy(docker, state, &session, &container_id, &container_name, proxy).await {
+            error!("Transparent proxy setup failed for session {}: {}", session_id, e);
+            let _ = docker.stop_container(&container_id, None).await;
+            return Err(e);
+        }
+    }
+
     info!("Container {} started, waiting for setup completion", container_name);
     
     let mut retries = 40;
     while retries > 0 {
         tokio::time::sleep(std::time::Duration::from_secs(3)).await;
         
-        if let Ok(output) = execute_command_with_tty(docker, &container_id, "which nano && echo 'ready'").await {
+        let probe = format!("test -f {} && echo 'ready'", SETUP_DONE_MARKER);
+        if let Ok(output) = execute_command_with_tty(docker, &container_id, &probe).await {
             if output.contains("ready") {
                 info!("Container {} setup completed", container_name);
                 break;
@@ -2500,30 +4971,602 @@ async fn start_container(docker: &Docker, session_id: Uuid, state: &AppState) ->
         }
     }
 
-    Ok((container_id, container_name))
+    if session.job_id.is_none() {
+        seed_shell_history(docker, state, &session.user_id, &container_id).await;
+    }
+
+    Ok(StartedContainer {
+        id: container_id,
+        name: container_name,
+        runtime,
+        network,
+        egress_proxy,
+        transparent,
+        resumed: false,
+    })
 }
 
-async fn cleanup_container(state: &AppState, session_id: Uuid) {
-    let container_id = {
-        let sessions = state.sessions.read().await;
-        sessions.get(&session_id).and_then(|s| s.container_id.clone())
+/// Sessions that are anyone-only or have an allowlist sit on the internal
+/// egress network, where the backend's egress proxy is the only way out.
+/// So do open privacy sessions when the upstream proxy needs a login, which
+/// must stay on the host
+fn egress_upstream(state: &AppState, session: &Session) -> Option<egress::Upstream> {
+    let proxy = state.anyone_service.proxy();
+    match session.network.mode() {
+        config::NetworkMode::AnyoneOnly => Some(proxy.upstream()),
+        config::NetworkMode::Open if !session.network.allowlist.is_empty() => Some(egress::Upstream::Direct),
+        config::NetworkMode::Open if session.privacy && proxy.credentials.is_some() => Some(proxy.upstream()),
+        _ => None,
+    }
+}
+
+/// The session's container if it is still running, with its egress routes
+/// registered again; None when a new container has to be created
+async fn resume_container(
+    docker: &Docker,
+    state: &AppState,
+    session: &Session,
+    container_id: &str,
+) -> Result<Option<StartedContainer>> {
+    let details = match docker.inspect_container(container_id, None).await {
+        Ok(details) => details,
+        Err(e) => {
+            debug!("Container {} of session {} is gone: {}", container_id, session.id, e);
+            return Ok(None);
+        }
+    };
+    if !details.state.as_ref().and_then(|s| s.running).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    // The egress proxy and resolver only know containers registered since
+    // this process started
+    let network = session.network.mode();
+    let egress_proxy = match egress_upstream(state, session) {
+        Some(upstream) => {
+            let gateway = container::network::ensure_egress_network(docker, &state.settings.docker.egress_network).await?;
+            let addr = state.egress.ensure_listening(gateway).await?;
+            if network == config::NetworkMode::AnyoneOnly {
+                state.dns.ensure_listening(gateway).await
+                    .map_err(|e| anyhow::anyhow!("Anonymous DNS resolver unavailable: {}", e))?;
+            }
+            register_egress(docker, state, session, container_id, upstream).await?;
+            Some(format!("socks5h://{}", addr))
+        }
+        None => None,
+    };
+
+    let runtime = session.runtime.clone().unwrap_or_else(|| config::RuntimeClass::Runc.to_string());
+    Ok(Some(StartedContainer {
+        id: container_id.to_string(),
+        name: details.name
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| container::labels::container_name(session.id)),
+        runtime: container::ResolvedRuntime {
+            requested: runtime.clone(),
+            daemon_name: details.host_config.and_then(|h| h.runtime).unwrap_or_else(|| runtime.clone()),
+            runtime,
+            fell_back: false,
+        },
+        network,
+        egress_proxy,
+        transparent: network == config::NetworkMode::AnyoneOnly && state.settings.anyone.transparent,
+        resumed: true,
+    }))
+}
+
+/// Write the user's recent commands to `~/.bash_history` so up-arrow and
+/// Ctrl-R reach commands from earlier sessions
+async fn seed_shell_history(docker: &Docker, state: &AppState, user_id: &str, container_id: &str) {
+    let limit = state.settings.security.history_inject_lines;
+    let Some(ref pool) = state.db_pool else {
+        return;
+    };
+    if limit == 0 || !history_enabled(state, user_id).await {
+        return;
+    }
+
+    let commands = match db::history::recent_commands(pool, user_id, limit as i64).await {
+        Ok(commands) if !commands.is_empty() => commands,
+        Ok(_) => return,
+        Err(e) => {
+            warn!("Failed to load command history for {}: {}", user_id, e);
+            return;
+        }
+    };
+
+    let history = db::history::bash_history(&commands).into_bytes();
+    match container::exec::write_file(docker, container_id, "/root/.bash_history", history, false).await {
+        Ok(()) => debug!("Seeded {} history entries into {}", commands.len(), container_id),
+        Err(e) => warn!("Could not write shell history in {}: {}", container_id, e),
+    }
+}
+
+/// Labels naming this instance and the session as a container's owner
+fn container_owner<'a>(state: &'a AppState, session: &'a Session) -> container::labels::Owner<'a> {
+    container::labels::Owner {
+        instance_id: &state.settings.docker.instance_id,
+        session_id: session.id,
+        user_id: &session.user_id,
+        tier: &session.tier,
+        template: &session.container_image,
+        created_at: session.created_at,
+    }
+}
+
+/// Start the netguard sidecar, verify there is no direct egress, then release setup
+async fn enable_transparent_proxy(
+    docker: &Docker,
+    state: &AppState,
+    session: &Session,
+    container_id: &str,
+    container_name: &str,
+    proxy: SocketAddr,
+) -> Result<()> {
+    let session_id = session.id;
+    container::transparent::start_sidecar(
+        docker,
+        container_id,
+        container_name,
+        &state.settings.anyone.sidecar_image,
+        container_owner(state, session).labels(container::labels::Role::Netguard),
+        proxy,
+        proxy.ip(),
+    ).await?;
+
+    container::transparent::leak_check(docker, container_id, session_id, &state.egress).await?;
+    info!("Leak check passed for session {}", session_id);
+
+    container::exec::run(
+        docker,
+        container_id,
+        &format!("touch {}", container::transparent::NETWORK_READY_MARKER),
+        std::time::Duration::from_secs(10),
+    ).await?;
+
+    Ok(())
+}
+
+/// Bind a freshly started container's egress address to its session policy
+async fn register_egress(
+    docker: &Docker,
+    state: &AppState,
+    session: &Session,
+    container_id: &str,
+    upstream: egress::Upstream,
+) -> Result<()> {
+    let ip = container::network::container_ip(docker, container_id, &state.settings.docker.egress_network).await?;
+    let allowlist = egress::Allowlist::parse(&session.network.allowlist).map_err(|e| anyhow::anyhow!(e))?;
+    // The upstream's own login if it has one, else per-session circuit isolation
+    let isolation = match upstream {
+        egress::Upstream::Direct => None,
+        _ => state.anyone_service.proxy().credentials.or_else(|| {
+            state.anyone_service.isolates_by_credentials().then(|| session.socks_auth.clone())
+        }),
     };
 
-    if let Some(container_id) = container_id {
+    state.egress.register(ip, egress::EgressPolicy {
+        session_id: session.id,
+        upstream,
+        allowlist,
+        isolation,
+    }).await;
+
+    Ok(())
+}
+
+async fn cleanup_container(state: &AppState, session_id: Uuid) {
+    let session = release_session(state, session_id).await;
+
+    if let Some((container_id, host)) = session.and_then(|s| Some((s.container_id?, s.host))) {
         info!("Cleaning up container {} for session {}", container_id, session_id);
+        let docker = state.hosts.docker_for(&host);
         
-        if let Err(e) = state.docker.stop_container(&container_id, None).await {
+        if let Err(e) = docker.stop_container(&container_id, None).await {
             warn!("Failed to stop container {}: {}", container_id, e);
         }
         
-        if let Err(e) = state.docker.remove_container(&container_id, None).await {
+        if let Err(e) = docker.remove_container(&container_id, None).await {
             warn!("Failed to remove container {}: {}", container_id, e);
         }
     }
+}
 
-    {
+/// Seconds of the shutdown timeout kept for closing terminals and saving
+/// sessions once the countdown ends
+const SHUTDOWN_CLOSE_SECS: u64 = 10;
+
+/// Refuse new sessions, count down on attached terminals, close them and
+/// keep their sessions reattachable, all within `timeout`
+async fn shutdown_gracefully(state: &AppState, timeout: std::time::Duration) {
+    use tokio::time::Instant;
+
+    let deadline = Instant::now() + timeout;
+    let close_at = deadline - timeout.min(std::time::Duration::from_secs(SHUTDOWN_CLOSE_SECS));
+
+    state.drain.begin();
+    info!("🛑 Shutting down: {} terminals and jobs open, closing within {}s",
+        state.drain.active(), timeout.as_secs());
+
+    state.drain.countdown(close_at).await;
+
+    for job in state.jobs.read().await.values() {
+        job.cancel.notify_one();
+    }
+    state.drain.close();
+
+    // Half of what is left goes to terminals and jobs finishing up
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if tokio::time::timeout(remaining / 2, state.drain.idle()).await.is_err() {
+        warn!("{} terminals or jobs still open at shutdown", state.drain.active());
+    }
+
+    keep_sessions_for_reattach(state).await;
+
+    if let Err(e) = state.anyone_service.stop().await {
+        warn!("Failed to stop {} privacy backend: {}", state.anyone_service.backend_name(), e);
+    }
+}
+
+/// Mark sessions whose containers outlive the process as disconnected, so
+/// they can be reattached within the grace period
+async fn keep_sessions_for_reattach(state: &AppState) {
+    let sessions: Vec<Session> = state.sessions.read().await
+        .values()
+        .filter(|s| s.job_id.is_none() && s.container_id.is_some())
+        .cloned()
+        .collect();
+
+    let (Some(pool), Some(lifecycle)) = (&state.db_pool, &state.lifecycle_manager) else {
+        // Without a database nothing could find these containers again
+        futures::future::join_all(sessions.iter().map(|s| cleanup_container(state, s.id))).await;
+        return;
+    };
+
+    let grace_period_secs = lifecycle.grace_period_secs();
+    for session in &sessions {
+        if let Err(e) = db::sessions::mark_disconnected(pool, session.id, grace_period_secs).await {
+            error!("Failed to mark session {} disconnected: {}", session.id, e);
+            continue;
+        }
+        let _ = db::audit::log(
+            pool,
+            Some(session.id),
+            &session.user_id,
+            db::audit::EventType::SessionDisconnected,
+            Some(serde_json::json!({
+                "reason": "shutdown",
+                "grace_period_secs": grace_period_secs
+            })),
+            None,
+            None,
+        ).await;
+    }
+    info!("Kept {} sessions reattachable for {}s", sessions.len(), grace_period_secs);
+}
+
+/// Rebuild the session cache from the database after a restart: adopt
+/// containers that survived it, terminate sessions whose containers did
+/// not, and remove session containers nothing claims
+async fn reconcile_sessions(state: &AppState) -> Result<()> {
+    use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+
+    let mut sessions = match state.db_pool {
+        Some(ref pool) => db::sessions::list_live(pool, &state.settings.docker.instance_id).await?,
+        None => Vec::new(),
+    };
+    // This instance's containers by label, then the rest by name: those
+    // from before labels, and those of other instances so the plan leaves
+    // their sessions alone
+    let instance_id = &state.settings.docker.instance_id;
+    let mut found = Vec::new();
+    let mut unreachable = Vec::new();
+    for host in state.hosts.all() {
+        let labelled = host.docker.list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: container::labels::instance_filter(instance_id),
+            ..Default::default()
+        }));
+        let named = host.docker.list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: HashMap::from([("name".to_string(), vec![container::labels::NAME_PREFIX.to_string()])]),
+            ..Default::default()
+        }));
+        match futures::try_join!(labelled, named) {
+            Ok((labelled, named)) => {
+                let legacy = named.into_iter().filter(|c| {
+                    c.labels.as_ref().and_then(container::labels::instance_id) != Some(instance_id.as_str())
+                });
+                found.extend(labelled.into_iter().chain(legacy).filter_map(|c| reconcile::Found::from_summary(&host.name, c)));
+            }
+            Err(e) => {
+                warn!("Cannot list containers on Docker host {}: {}", host.name, e);
+                unreachable.push(host.name.clone());
+            }
+        }
+    }
+    if unreachable.len() == state.hosts.all().len() {
+        return Err(anyhow::anyhow!("No Docker host could be reached"));
+    }
+
+    // Sessions on a host that is down keep their records until it returns
+    let (waiting, live): (Vec<_>, Vec<_>) = std::mem::take(&mut sessions).into_iter().partition(|row| {
+        let host = hosts::host_of(&row.metadata).unwrap_or(&state.hosts.primary().name);
+        unreachable.iter().any(|name| name == host)
+    });
+    for row in &waiting {
+        state.sessions.write().await.insert(row.id, session_from_db(row, state));
+    }
+
+    let reconcile::Plan { adopt, mut terminate, mut reap } = reconcile::plan(instance_id, live, found);
+
+    let mut adopted = 0;
+    for (row, container) in adopt {
+        let mut session = session_from_db(&row, state);
+        if let Some(ref container) = container {
+            session.container_id = Some(container.id.clone());
+            session.container_name = Some(container.name.clone());
+            session.host = container.host.clone();
+        }
+        state.sessions.write().await.insert(session.id, session.clone());
+
+        // Privacy sessions hold the daemon again before their egress is set
+        // up, so it runs for them and their eventual release is balanced
+        if hold_anyone(state, session.id, session.privacy, &session.network).await.is_err() {
+            release_session(state, session.id).await;
+            terminate.push((row, reconcile::Loss::AnyoneUnavailable));
+            reap.extend(container);
+            continue;
+        }
+
+        let Some(container) = container else {
+            adopted += 1;
+            continue;
+        };
+        // Fail closed: a container whose egress cannot be set up again is dropped
+        let docker = state.hosts.docker_for(&container.host);
+        match resume_container(&docker, state, &session, &container.id).await {
+            Ok(Some(_)) => {
+                if let Some(ref pool) = state.db_pool {
+                    if row.container_id.as_deref() != Some(container.id.as_str()) {
+                        if let Err(e) = db::sessions::adopt_container(pool, session.id, &container.id, &container.name).await {
+                            error!("Failed to record container for session {}: {}", session.id, e);
+                        }
+                    }
+                    if hosts::host_of(&row.metadata) != Some(container.host.as_str()) {
+                        let host = serde_json::json!({ "host": container.host });
+                        if let Err(e) = db::sessions::merge_metadata(pool, session.id, host).await {
+                            error!("Failed to record host for session {}: {}", session.id, e);
+                        }
+                    }
+                }
+                info!("Adopted container {} for session {} ({})", container.name, session.id, session.status);
+                adopted += 1;
+            }
+            result => {
+                if let Err(e) = result {
+                    warn!("Cannot resume container {} for session {}: {}", container.name, session.id, e);
+                }
+                release_session(state, session.id).await;
+                terminate.push((row, reconcile::Loss::ContainerGone));
+                reap.push(container);
+            }
+        }
+    }
+
+    if let Some(ref pool) = state.db_pool {
+        for (row, loss) in &terminate {
+            if let Err(e) = db::sessions::terminate(pool, row.id).await {
+                error!("Failed to terminate session {}: {}", row.id, e);
+                continue;
+            }
+            let _ = db::audit::log(
+                pool,
+                Some(row.id),
+                &row.user_id,
+                db::audit::EventType::SessionTerminated,
+                Some(serde_json::json!({ "reason": loss.as_str(), "on_startup": true })),
+                None,
+                None,
+            ).await;
+        }
+    }
+
+    let removals = reap.iter().map(|container| async move {
+        let options = RemoveContainerOptions { force: true, ..Default::default() };
+        match state.hosts.docker_for(&container.host).remove_container(&container.id, Some(options)).await {
+            Ok(()) => info!("Removed unclaimed container {}", container.name),
+            Err(e) => warn!("Failed to remove unclaimed container {}: {}", container.name, e),
+        }
+    });
+    futures::future::join_all(removals).await;
+
+    info!("🔁 Reconciled sessions: {} restored, {} terminated, {} containers removed, {} waiting for their host",
+        adopted, terminate.len(), reap.len(), waiting.len());
+    Ok(())
+}
+
+/// A cached session rebuilt from its database row
+fn session_from_db(row: &db::sessions::DbSession, state: &AppState) -> Session {
+    let settings = &state.settings;
+    let metadata = &row.metadata;
+    Session {
+        id: row.id,
+        user_id: row.user_id.clone(),
+        status: row.status.clone(),
+        container_id: row.container_id.clone(),
+        container_name: row.container_name.clone(),
+        created_at: row.created_at,
+        container_image: row.container_image.clone(),
+        runtime: metadata.pointer("/runtime/runtime").and_then(|r| r.as_str()).map(String::from),
+        tier: metadata.get("tier")
+            .and_then(|t| t.as_str())
+            .unwrap_or(&settings.tiers.default_tier)
+            .to_string(),
+        resource_limits: serde_json::from_value(row.resource_limits.clone()).unwrap_or_default(),
+        // Sessions from before multi-host placement ran on the primary host
+        host: hosts::host_of(metadata).unwrap_or(&state.hosts.primary().name).to_string(),
+        // A session whose network was never recorded gets none rather than more
+        network: metadata.get("network")
+            .and_then(|n| serde_json::from_value(n.clone()).ok())
+            .unwrap_or_else(|| SessionNetwork {
+                mode: config::NetworkMode::None.to_string(),
+                allowlist: Vec::new(),
+            }),
+        privacy: metadata.get("privacy").and_then(|p| p.as_bool()).unwrap_or(false),
+        socks_auth: egress::socks::Credentials::for_session(row.id),
+        token_sha256: metadata.get("token_sha256").and_then(|t| t.as_str()).unwrap_or_default().to_string(),
+        job_id: None,
+    }
+}
+
+/// React to Docker events of this instance's containers as they happen
+async fn handle_container_events(state: AppState, mut events: tokio::sync::broadcast::Receiver<container::events::Event>) {
+    use container::events::Event;
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} container events", missed);
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        };
+
+        match event {
+            Event::Stopped(stop) => container_stopped(&state, stop).await,
+            Event::Oom { session_id, container_id } => {
+                warn!("Process OOM-killed in container {} of session {}", container_id, session_id);
+            }
+            Event::Health { session_id, container_id, status } => {
+                if status == "unhealthy" {
+                    warn!("Container {} of session {} is unhealthy", container_id, session_id);
+                } else {
+                    debug!("Container {} of session {} is {}", container_id, session_id, status);
+                }
+            }
+        }
+    }
+}
+
+/// A session container stopped without the backend stopping it: keep the
+/// session reattachable with a fresh container and record why
+async fn container_stopped(state: &AppState, stop: container::events::Stop) {
+    // Containers outlive a shutdown on purpose; cleanup drops sessions from
+    // the cache before stopping their containers
+    if state.drain.is_draining() {
+        return;
+    }
+    let user_id = {
         let mut sessions = state.sessions.write().await;
-        sessions.remove(&session_id);
+        match sessions.get_mut(&stop.session_id) {
+            // Job runners see their container exit themselves
+            Some(session) if session.container_id.as_deref() == Some(stop.container_id.as_str()) && session.job_id.is_none() => {
+                session.container_id = None;
+                session.container_name = None;
+                session.status = "disconnected".to_string();
+                session.user_id.clone()
+            }
+            _ => return,
+        }
+    };
+    warn!("Container {} of session {} stopped ({}, exit code {:?})",
+        stop.container_id, stop.session_id, stop.cause.as_str(), stop.exit_code);
+    state.egress.unregister(stop.session_id).await;
+
+    let (Some(pool), Some(lifecycle)) = (&state.db_pool, &state.lifecycle_manager) else {
+        return;
+    };
+    if let Err(e) = db::sessions::mark_disconnected(pool, stop.session_id, lifecycle.grace_period_secs()).await {
+        error!("Failed to mark session {} disconnected: {}", stop.session_id, e);
+    }
+    let signal = match stop.cause {
+        container::events::Cause::Killed { ref signal } => signal.clone(),
+        _ => None,
+    };
+    let _ = db::audit::log(
+        pool,
+        Some(stop.session_id),
+        &user_id,
+        db::audit::EventType::ContainerStopped,
+        Some(serde_json::json!({
+            "reason": stop.cause.as_str(),
+            "exit_code": stop.exit_code,
+            "signal": signal,
+            "container_id": stop.container_id,
+        })),
+        None,
+        None,
+    ).await;
+}
+
+/// Persist Anyone supervisor events as audit and security records
+async fn record_anyone_events(mut events: tokio::sync::broadcast::Receiver<SupervisorEvent>, pool: DbPool) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} Anyone supervisor events", missed);
+                continue;
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        };
+
+        let result = match event {
+            SupervisorEvent::Exited { code, uptime_secs, last_error } => {
+                let description = format!(
+                    "Anyone client exited with code {:?} after {}s; privacy sessions have no egress until it restarts",
+                    code, uptime_secs
+                );
+                let _ = db::security::log_event(
+                    &pool,
+                    None,
+                    "system",
+                    "privacy_service_crash",
+                    db::security::Severity::Critical,
+                    Some(&description),
+                    None,
+                    None,
+                ).await;
+                db::audit::log(
+                    &pool,
+                    None,
+                    "system",
+                    db::audit::EventType::PrivacyServiceExited,
+                    Some(serde_json::json!({
+                        "exit_code": code,
+                        "uptime_secs": uptime_secs,
+                        "last_error": last_error
+                    })),
+                    None,
+                    None,
+                ).await
+            }
+            SupervisorEvent::Restarted { attempt } => db::audit::log(
+                &pool,
+                None,
+                "system",
+                db::audit::EventType::PrivacyServiceRestarted,
+                Some(serde_json::json!({ "attempt": attempt })),
+                None,
+                None,
+            ).await,
+            SupervisorEvent::RestartFailed { attempt, error, retry_in } => db::security::log_event(
+                &pool,
+                None,
+                "system",
+                "privacy_service_restart_failed",
+                db::security::Severity::Warning,
+                Some(&format!("Restart attempt {} failed: {} (retrying in {}s)", attempt, error, retry_in.as_secs())),
+                None,
+                None,
+            ).await,
+        };
+
+        if let Err(e) = result {
+            error!("Failed to record Anyone supervisor event: {}", e);
+        }
     }
 }
 
@@ -2532,6 +5575,18 @@ async fn cleanup_container(state: &AppState, session_id: Uuid) {
 async fn main() -> Result<()> {
     use tracing_subscriber::EnvFilter;
 
+    // `noxterm-backend doctor` checks this machine's setup and exits; it
+    // never installs or starts anything
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        dotenvy::dotenv().ok();
+        let settings = config::Config::from_env()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        let hosts = connect_hosts(&settings, false).await;
+        let report = doctor::run(&settings, hosts).await;
+        print!("{}", report.render());
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
     // Use RUST_LOG if set, otherwise default to info level
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("noxterm=info,tower_http=info"));
@@ -2546,7 +5601,12 @@ async fn main() -> Result<()> {
         .init();
 
     dotenvy::dotenv().ok();
-    
+
+    let settings = config::Config::from_env()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    settings.validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+
     let config = AppConfig {
         host: std::env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
         port: std::env::var("SERVER_PORT")
@@ -2560,19 +5620,35 @@ async fn main() -> Result<()> {
     info!("Port: {}", config.port);
     info!("Environment: {}", std::env::var("ENVIRONMENT").unwrap_or_else(|_| "development".to_string()));
 
-    // Connect to Docker with cross-platform support (auto-installs if needed)
-    let docker = connect_docker().await?;
-
-    let version = docker.version().await
-        .map_err(|e| anyhow::anyhow!("Docker daemon not responding. Is Docker running?\nError: {}", e))?;
-
-    info!("✅ Docker connected successfully");
-    info!("Docker version: {}", version.version.unwrap_or_else(|| "unknown".to_string()));
+    // Connect to Docker with cross-platform support (auto-installs if allowed),
+    // or to the configured hosts
+    let hosts = Arc::new(connect_hosts(&settings, settings.server.auto_install).await?);
+
+    hosts.refresh().await;
+    for host in hosts.all() {
+        let status = host.status().await;
+        if status.healthy {
+            info!("🐳 Docker host {}: {}, {} CPUs, {} MB memory, {} containers running",
+                host.name, status.engine.as_ref().map(|e| e.describe()).unwrap_or_default(),
+                status.cpus, status.memory_bytes / (1024 * 1024), status.containers_running);
+        } else {
+            warn!("🐳 Docker host {} is unreachable: {}", host.name, status.error.unwrap_or_default());
+        }
+    }
+    tokio::spawn(hosts.clone().run_health_checks(std::time::Duration::from_secs(30)));
     info!("Platform: {} / {}", std::env::consts::OS, std::env::consts::ARCH);
+    info!("Container runtime: {} (policy: {})", settings.docker.runtime, settings.docker.runtime_policy);
+
+    let policy = match settings.security.policy_path {
+        Some(ref path) => security::Policy::load(StdPath::new(path))?,
+        None => security::Policy::builtin(),
+    };
+    info!("🛡️  Command policy loaded: {} rules ({})",
+        policy.rule_count(), settings.security.policy_path.as_deref().unwrap_or("built in"));
 
-    // Initialize Anyone Protocol service with auto-install
-    let anyone_service = Arc::new(AnyoneService::new(9050, 9051));
-    info!("🔐 Anyone Protocol service initialized (SOCKS: 9050, Control: 9051)");
+    // Initialize the privacy backend (Anyone with auto-install by default)
+    let anyone_service = Arc::new(AnyoneService::new(privacy::from_config(&settings.anyone, settings.server.auto_install)?));
+    info!("🔐 Privacy backend {} initialized (proxy: {})", anyone_service.backend_name(), anyone_service.proxy().addr);
 
     // Pre-install Anyone SDK in background (don't block startup)
     let anyone_clone = anyone_service.clone();
@@ -2618,6 +5694,7 @@ async fn main() -> Result<()> {
     };
 
     // Initialize lifecycle manager (requires database)
+    let mut reaped_sessions = None;
     let lifecycle_manager: Option<Arc<LifecycleManager>> = if let Some(ref pool) = db_pool {
         let lifecycle_config = LifecycleConfig {
             grace_period_secs: std::env::var("GRACE_PERIOD_SECONDS")
@@ -2631,13 +5708,21 @@ async fn main() -> Result<()> {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(3),
+            disk_check_interval_secs: settings.docker.disk_check_interval_secs,
+            disk_quota_mb: settings.docker.disk_limit_mb,
+            disk_warn_percent: settings.docker.disk_warn_percent,
+            history_retention_days: settings.security.history_retention_days,
+            job_artifact_dir: settings.jobs.artifact_dir.clone(),
+            job_retention_hours: settings.jobs.retention_hours,
+            instance_id: settings.docker.instance_id.clone(),
         };
 
         let manager = Arc::new(LifecycleManager::new(
-            docker.clone(),
+            hosts.clone(),
             pool.clone(),
             lifecycle_config.clone(),
         ));
+        reaped_sessions = Some(manager.subscribe());
 
         // Start background lifecycle tasks
         let lifecycle_clone = manager.clone();
@@ -2657,14 +5742,60 @@ async fn main() -> Result<()> {
 
     // ==================== End Phase 2 Initialization ====================
 
+    let egress_proxy = Arc::new(egress::EgressProxy::new(settings.docker.egress_proxy_port));
+
+    if let Some(ref pool) = db_pool {
+        tokio::spawn(record_anyone_events(anyone_service.subscribe(), pool.clone()));
+
+        match db::jobs::fail_interrupted(pool).await {
+            Ok(0) => {}
+            Ok(failed) => warn!("Marked {} jobs interrupted by the restart as failed", failed),
+            Err(e) => error!("Failed to update interrupted jobs: {}", e),
+        }
+    }
+
+    let drain = shutdown::Drain::new();
+    let job_store = jobs::JobStore::new(&settings.jobs.artifact_dir, settings.jobs.max_artifact_mb);
+
     let app_state = AppState {
         sessions: Arc::new(RwLock::new(HashMap::new())),
-        docker: Arc::new(docker),
+        hosts,
         config: config.clone(),
+        dns: Arc::new(egress::dns::DnsResolver::new(
+            anyone_service.dns(),
+            egress_proxy.clone(),
+        )),
+        egress: egress_proxy,
+        settings: Arc::new(settings),
         anyone_service,
+        privacy_defaults: Arc::new(RwLock::new(HashMap::new())),
+        user_keys: Arc::new(RwLock::new(HashMap::new())),
+        jobs: Arc::new(RwLock::new(HashMap::new())),
+        job_store,
         db_pool,
         lifecycle_manager,
+        policy: Arc::new(policy),
+        drain: drain.clone(),
+        container_events: container::events::Watcher::new(),
     };
+    let shutdown_state = app_state.clone();
+
+    // Pick up sessions and containers left by the previous run before any
+    // client can attach
+    if let Err(e) = reconcile_sessions(&app_state).await {
+        error!("Failed to reconcile sessions with Docker: {}", e);
+    }
+
+    tokio::spawn(handle_container_events(app_state.clone(), app_state.container_events.subscribe()));
+    if let Some(reaped) = reaped_sessions {
+        tokio::spawn(release_reaped_sessions(app_state.clone(), reaped));
+    }
+    for host in app_state.hosts.all() {
+        tokio::spawn(app_state.container_events.clone().run(
+            host.docker.clone(),
+            app_state.settings.docker.instance_id.clone(),
+        ));
+    }
 
     let app = Router::new()
         // Basic routes
@@ -2677,6 +5808,8 @@ async fn main() -> Result<()> {
         .route("/api/sessions", post(create_session).get(list_sessions))
         .route("/api/sessions/:id", get(get_session).delete(terminate_session))
         .route("/api/sessions/:id/reattach", post(reattach_session))
+        .route("/api/sessions/:id/tier", post(resize_session))
+        .route("/api/sessions/:id/circuits", get(get_session_circuits))
         .route("/api/sessions/:id/metrics", get(get_session_metrics))
         .route("/api/sessions/:id/metrics/history", get(get_session_metrics_history))
         .route("/api/sessions/:id/audit", get(get_session_audit_logs))
@@ -2684,12 +5817,23 @@ async fn main() -> Result<()> {
         .route("/api/sessions/:id/container", post(update_session_container))
         .route("/api/sessions/:id/reconnect", post(clear_session_disconnection))
         .route("/api/sessions/:id/validate", post(validate_command))
+        .route("/api/sessions/:id/exec", post(exec_command))
+        .route("/api/sessions/:id/exec/stream", post(exec_command_stream))
+
+        // Batch jobs
+        .route("/api/jobs", post(create_job))
+        .route("/api/jobs/:id", get(get_job).delete(cancel_job))
+        .route("/api/jobs/:id/logs", get(job_logs))
+        .route("/api/jobs/:id/artifacts/:index", get(download_job_artifact))
 
         // User management
         .route("/api/users/:user_id/containers", get(list_user_containers))
         .route("/api/users/:user_id/sessions", get(get_user_sessions))
         .route("/api/users/:user_id/active", get(get_user_active_sessions))
         .route("/api/users/:user_id/audit", get(get_user_audit_logs))
+        .route("/api/users/:user_id/history", get(get_user_history).delete(clear_user_history))
+        .route("/api/users/:user_id/jobs", get(list_user_jobs))
+        .route("/api/users/:user_id/history/settings", get(get_history_settings).post(update_history_settings))
 
         // Admin/Security endpoints
         .route("/api/security/events", get(get_security_events))
@@ -2700,6 +5844,9 @@ async fn main() -> Result<()> {
         .route("/api/privacy/disable", post(disable_privacy))
         .route("/api/privacy/status", get(privacy_status))
         .route("/api/privacy/test", get(test_privacy_connection))
+        .route("/api/privacy/new-identity", post(new_privacy_identity))
+        .route("/api/privacy/circuits", get(list_privacy_circuits))
+        .route("/api/privacy/bootstrap", get(privacy_bootstrap_stream))
 
         // WebSocket endpoints
         .route("/ws/:session_id", get(websocket_handler))
@@ -2725,9 +5872,26 @@ async fn main() -> Result<()> {
     info!("   GET  /api/sessions/:id/metrics  - Container metrics");
     info!("   DELETE /api/sessions/:id        - Terminate session");
 
-    axum::serve(listener, app)
-        .await
-        .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+    // Keep serving API requests through the countdown; the listener closes
+    // with the terminals
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(async move { drain.closing().await });
+    let mut server = tokio::spawn(async move { server.await });
+
+    tokio::select! {
+        result = &mut server => {
+            result
+                .map_err(|e| anyhow::anyhow!("Server task failed: {}", e))?
+                .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+        }
+        _ = shutdown::signal() => {
+            let timeout = std::time::Duration::from_secs(shutdown_state.settings.server.graceful_shutdown_timeout_secs);
+            if tokio::time::timeout(timeout, shutdown_gracefully(&shutdown_state, timeout)).await.is_err() {
+                warn!("Shutdown did not finish within {}s", timeout.as_secs());
+            }
+        }
+    }
 
+    info!("👋 NOXTERM Backend stopped");
     Ok(())
 }
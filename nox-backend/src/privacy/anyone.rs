@@ -0,0 +1,965 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! The Anyone Protocol client: either `@anyone-protocol/anyone-client` via
+//! npx, downloaded on demand along with Node.js when automatic installation
+//! is on (and only run when already present otherwise), or a preinstalled
+//! client whose pinned checksum is verified before every start, then run from
+//! a private copy of what was verified.
+
+use super::{PrivacyBackend, ProxyEndpoint};
+use crate::egress::dns::Lookup;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use tokio::process::Command as TokioCommand;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info, warn};
+
+/// Where a verified preinstalled client is copied to and run from
+const STAGING_DIR: &str = "anyone-client-verified";
+
+pub struct AnyoneClient {
+    socks_port: u16,
+    control_port: u16,
+    /// Preinstalled client and its expected SHA-256; `None` installs on demand
+    preinstalled: Option<(PathBuf, String)>,
+    /// Whether a missing Node.js may be installed through the system
+    /// package manager, and the client downloaded from npm
+    auto_install: bool,
+}
+
+impl AnyoneClient {
+    pub fn new(socks_port: u16, control_port: u16, auto_install: bool) -> Self {
+        Self { socks_port, control_port, preinstalled: None, auto_install }
+    }
+
+    /// Run only a copy of the client at `path`, refusing to start unless it
+    /// hashes to `sha256`
+    pub fn preinstalled(socks_port: u16, control_port: u16, path: PathBuf, sha256: String) -> Self {
+        Self {
+            socks_port,
+            control_port,
+            preinstalled: Some((path, sha256.to_lowercase())),
+            auto_install: false,
+        }
+    }
+
+    // ========================================================================
+    // Offline provisioning: verify, never install or escalate
+    // ========================================================================
+
+    /// Read the preinstalled client at `path` and check it against its pin.
+    /// A client inside an npm install (`<prefix>/node_modules/...`) is pinned
+    /// by every file under the prefix, since its Node wrapper loads the rest
+    /// of the package and the native binary from there; any other client by
+    /// its own SHA-256.
+    pub async fn verify_preinstalled(path: &Path, expected_sha256: &str) -> Result<VerifiedClient> {
+        let owned = path.to_path_buf();
+        let client = tokio::task::spawn_blocking(move || VerifiedClient::read(&owned)).await??;
+
+        if client.sha256 != expected_sha256 {
+            return Err(anyhow::anyhow!(
+                "Anyone client at {} failed checksum verification.\n\
+                expected sha256 {}\n\
+                actual   sha256 {}\n\
+                Reinstall the pinned release, or update NOXTERM_ANYONE_CLIENT_SHA256 if the upgrade was intended.",
+                path.display(), expected_sha256, client.sha256
+            ));
+        }
+
+        let (contents, executable) = client.entry_point().ok_or_else(|| anyhow::anyhow!(
+            "Anyone client at {} does not lead to a file inside its install",
+            path.display()
+        ))?;
+        if !executable {
+            return Err(anyhow::anyhow!(
+                "Anyone client at {} is not executable. Fix with: chmod +x {}",
+                path.display(), path.display()
+            ));
+        }
+
+        // The npm client is a Node script; a vendored native build needs nothing else
+        if contents.starts_with(b"#!") && contents.split(|b| *b == b'\n').next().is_some_and(|line| {
+            String::from_utf8_lossy(line).contains("node")
+        }) {
+            let (node_cmd, _) = Self::get_node_commands();
+            let node_ok = Command::new(&node_cmd)
+                .arg("--version")
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false);
+            if !node_ok {
+                return Err(anyhow::anyhow!(
+                    "The Anyone client at {} needs Node.js, which is not on PATH.\n\
+                    Offline provisioning does not install it; install Node.js 18+ from your distribution's packages.",
+                    path.display()
+                ));
+            }
+        }
+
+        info!("✅ Anyone client {} verified (sha256 {})", path.display(), client.sha256);
+        Ok(client)
+    }
+
+    /// The verified copy of the preinstalled client at `path` that runs
+    fn staged_executable(path: &Path) -> PathBuf {
+        Path::new(STAGING_DIR).join(install_layout(path).1)
+    }
+
+    // ========================================================================
+    // Cross-platform Node.js auto-installation
+    // ========================================================================
+
+    /// Check if Node.js is installed, if not, attempt to install it
+    async fn ensure_nodejs_installed(&self) -> Result<()> {
+        let (node_cmd, npm_cmd) = Self::get_node_commands();
+
+        // Check if Node.js is already installed
+        if let Ok(output) = Command::new(&node_cmd).args(["--version"]).output() {
+            if output.status.success() {
+                let version = String::from_utf8_lossy(&output.stdout);
+                info!("Node.js version: {}", version.trim());
+
+                // Also verify npm
+                if let Ok(npm_output) = Command::new(&npm_cmd).args(["--version"]).output() {
+                    if npm_output.status.success() {
+                        let npm_version = String::from_utf8_lossy(&npm_output.stdout);
+                        info!("npm version: {}", npm_version.trim());
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if !self.auto_install {
+            return Err(anyhow::anyhow!(
+                "Node.js 18+ is not installed and automatic installation is off (NOXTERM_AUTO_INSTALL).\n\
+                Install it from your distribution's packages or https://nodejs.org/, \
+                or run `noxterm-backend doctor` for what else is missing."
+            ));
+        }
+
+        // Node.js not found - attempt to install
+        info!("Node.js not found. Attempting to install...");
+
+        if cfg!(target_os = "macos") {
+            self.install_nodejs_macos().await?;
+        } else if cfg!(target_os = "linux") {
+            self.install_nodejs_linux().await?;
+        } else if cfg!(target_os = "windows") {
+            self.install_nodejs_windows().await?;
+        } else {
+            return Err(anyhow::anyhow!(
+                "Unsupported platform. Please install Node.js manually from https://nodejs.org/"
+            ));
+        }
+
+        // Verify installation
+        self.verify_nodejs_installation().await
+    }
+
+    /// Get platform-specific node/npm command names
+    pub fn get_node_commands() -> (String, String) {
+        if cfg!(target_os = "windows") {
+            ("node.exe".to_string(), "npm.cmd".to_string())
+        } else {
+            ("node".to_string(), "npm".to_string())
+        }
+    }
+
+    /// Install Node.js on macOS using Homebrew
+    async fn install_nodejs_macos(&self) -> Result<()> {
+        // Check if Homebrew is installed
+        let brew_installed = Command::new("which")
+            .arg("brew")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+
+        if brew_installed {
+            info!("Installing Node.js via Homebrew...");
+            let status = Command::new("brew")
+                .args(["install", "node"])
+                .status();
+
+            if status.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via Homebrew");
+                return Ok(());
+            }
+        }
+
+        // Try using the official installer via curl
+        info!("Installing Node.js via official script...");
+
+        // Check for nvm
+        let nvm_installed = Command::new("bash")
+            .args(["-c", "command -v nvm"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+
+        if !nvm_installed {
+            // Install nvm first
+            info!("Installing nvm (Node Version Manager)...");
+            let nvm_install = Command::new("bash")
+                .args(["-c", "curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"])
+                .status();
+
+            if nvm_install.map(|s| s.success()).unwrap_or(false) {
+                info!("nvm installed. Installing Node.js...");
+
+                // Install latest LTS Node.js using nvm
+                let node_install = Command::new("bash")
+                    .args(["-c", "source ~/.nvm/nvm.sh && nvm install --lts && nvm use --lts"])
+                    .status();
+
+                if node_install.map(|s| s.success()).unwrap_or(false) {
+                    info!("✅ Node.js installed via nvm");
+                    return Ok(());
+                }
+            }
+        }
+
+        // Fallback: download and run official pkg installer
+        warn!("Could not auto-install Node.js on macOS.");
+        Err(anyhow::anyhow!(
+            "Node.js auto-installation failed on macOS.\n\
+            Please install manually using one of these methods:\n\
+            1. Homebrew: brew install node\n\
+            2. Official installer: https://nodejs.org/\n\
+            3. nvm: curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"
+        ))
+    }
+
+    /// Install Node.js on Linux using package managers
+    async fn install_nodejs_linux(&self) -> Result<()> {
+        // Try NodeSource setup script (works on most distros)
+        info!("Installing Node.js via NodeSource...");
+
+        let nodesource_result = Command::new("bash")
+            .args(["-c", "curl -fsSL https://deb.nodesource.com/setup_lts.x | sudo -E bash -"])
+            .status();
+
+        if nodesource_result.map(|s| s.success()).unwrap_or(false) {
+            // Now install via apt
+            if Command::new("which").arg("apt-get").output().map(|o| o.status.success()).unwrap_or(false) {
+                let apt_result = Command::new("sudo")
+                    .args(["apt-get", "install", "-y", "nodejs"])
+                    .status();
+
+                if apt_result.map(|s| s.success()).unwrap_or(false) {
+                    info!("✅ Node.js installed via apt");
+                    return Ok(());
+                }
+            }
+        }
+
+        // Try apt directly (Ubuntu/Debian)
+        if Command::new("which").arg("apt-get").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via apt-get...");
+            let _ = Command::new("sudo").args(["apt-get", "update"]).status();
+            let apt_result = Command::new("sudo")
+                .args(["apt-get", "install", "-y", "nodejs", "npm"])
+                .status();
+
+            if apt_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via apt");
+                return Ok(());
+            }
+        }
+
+        // Try dnf (Fedora/RHEL)
+        if Command::new("which").arg("dnf").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via dnf...");
+            let dnf_result = Command::new("sudo")
+                .args(["dnf", "install", "-y", "nodejs", "npm"])
+                .status();
+
+            if dnf_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via dnf");
+                return Ok(());
+            }
+        }
+
+        // Try yum (CentOS/older RHEL)
+        if Command::new("which").arg("yum").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via yum...");
+            let yum_result = Command::new("sudo")
+                .args(["yum", "install", "-y", "nodejs", "npm"])
+                .status();
+
+            if yum_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via yum");
+                return Ok(());
+            }
+        }
+
+        // Try pacman (Arch)
+        if Command::new("which").arg("pacman").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via pacman...");
+            let pacman_result = Command::new("sudo")
+                .args(["pacman", "-S", "--noconfirm", "nodejs", "npm"])
+                .status();
+
+            if pacman_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via pacman");
+                return Ok(());
+            }
+        }
+
+        // Try zypper (openSUSE)
+        if Command::new("which").arg("zypper").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via zypper...");
+            let zypper_result = Command::new("sudo")
+                .args(["zypper", "install", "-y", "nodejs", "npm"])
+                .status();
+
+            if zypper_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via zypper");
+                return Ok(());
+            }
+        }
+
+        // Fallback: use nvm
+        info!("Trying nvm installation...");
+        let nvm_install = Command::new("bash")
+            .args(["-c", "curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash && source ~/.nvm/nvm.sh && nvm install --lts"])
+            .status();
+
+        if nvm_install.map(|s| s.success()).unwrap_or(false) {
+            info!("✅ Node.js installed via nvm");
+            return Ok(());
+        }
+
+        Err(anyhow::anyhow!(
+            "Node.js auto-installation failed on Linux.\n\
+            Please install manually using your package manager:\n\
+            - Debian/Ubuntu: sudo apt install nodejs npm\n\
+            - Fedora: sudo dnf install nodejs npm\n\
+            - Arch: sudo pacman -S nodejs npm\n\
+            - Or via nvm: curl -o- https://raw.githubusercontent.com/nvm-sh/nvm/v0.39.7/install.sh | bash"
+        ))
+    }
+
+    /// Install Node.js on Windows
+    async fn install_nodejs_windows(&self) -> Result<()> {
+        // Check common installation paths
+        let common_paths = [
+            r"C:\Program Files\nodejs\node.exe",
+            r"C:\Program Files (x86)\nodejs\node.exe",
+        ];
+
+        for path in &common_paths {
+            if Path::new(path).exists() {
+                info!("Found Node.js at: {}", path);
+                // It exists but might not be in PATH - try to add it
+                warn!("Node.js found but not in PATH. Please add to PATH or restart your terminal.");
+                return Ok(());
+            }
+        }
+
+        // Check if winget is available (Windows Package Manager)
+        if Command::new("winget").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via winget...");
+            let winget_result = Command::new("winget")
+                .args(["install", "--id", "OpenJS.NodeJS.LTS", "-e", "--silent"])
+                .status();
+
+            if winget_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via winget");
+                info!("Please restart your terminal to use Node.js");
+                return Ok(());
+            }
+        }
+
+        // Check if Chocolatey is available
+        if Command::new("choco").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via Chocolatey...");
+            let choco_result = Command::new("choco")
+                .args(["install", "nodejs-lts", "-y"])
+                .status();
+
+            if choco_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via Chocolatey");
+                info!("Please restart your terminal to use Node.js");
+                return Ok(());
+            }
+        }
+
+        // Check if Scoop is available
+        if Command::new("scoop").arg("--version").output().map(|o| o.status.success()).unwrap_or(false) {
+            info!("Installing Node.js via Scoop...");
+            let scoop_result = Command::new("scoop")
+                .args(["install", "nodejs-lts"])
+                .status();
+
+            if scoop_result.map(|s| s.success()).unwrap_or(false) {
+                info!("✅ Node.js installed via Scoop");
+                return Ok(());
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Node.js auto-installation failed on Windows.\n\
+            Please install manually using one of these methods:\n\
+            1. Official installer: https://nodejs.org/\n\
+            2. winget: winget install OpenJS.NodeJS.LTS\n\
+            3. Chocolatey: choco install nodejs-lts\n\
+            4. Scoop: scoop install nodejs-lts"
+        ))
+    }
+
+    /// Verify Node.js installation after attempting to install
+    async fn verify_nodejs_installation(&self) -> Result<()> {
+        let (node_cmd, npm_cmd) = Self::get_node_commands();
+
+        // Give the system a moment to update PATH
+        sleep(Duration::from_secs(1)).await;
+
+        let node_output = Command::new(&node_cmd)
+            .args(["--version"])
+            .output();
+
+        match node_output {
+            Ok(output) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout);
+                info!("✅ Node.js verified: {}", version.trim());
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Node.js installation could not be verified.\n\
+                    Please restart your terminal and try again, or install manually from https://nodejs.org/"
+                ));
+            }
+        }
+
+        let npm_output = Command::new(&npm_cmd)
+            .args(["--version"])
+            .output();
+
+        match npm_output {
+            Ok(output) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout);
+                info!("✅ npm verified: {}", version.trim());
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "npm installation could not be verified.\n\
+                    Please restart your terminal and try again."
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Anyone Protocol client installation
+    // ========================================================================
+
+    /// npx's flag to download the client when it is missing, or to fail
+    fn npx_install_flag(&self) -> &'static str {
+        if self.auto_install { "--yes" } else { "--no" }
+    }
+
+    async fn ensure_anyone_client_installed(&self) -> Result<()> {
+        let (_, npm_cmd) = Self::get_node_commands();
+        let npx_cmd = if cfg!(target_os = "windows") { "npx.cmd" } else { "npx" };
+
+        // First try to check if it's globally installed
+        let check_output = Command::new(&npm_cmd)
+            .args(["list", "-g", "@anyone-protocol/anyone-client"])
+            .output();
+
+        if let Ok(output) = check_output {
+            if output.status.success() {
+                info!("Anyone Protocol client already installed globally");
+                return Ok(());
+            }
+        }
+
+        // Also check if npx can find it (might be cached or local)
+        let npx_check = Command::new(npx_cmd)
+            .args([self.npx_install_flag(), "@anyone-protocol/anyone-client", "--version"])
+            .output();
+
+        if let Ok(output) = npx_check {
+            if output.status.success() {
+                info!("Anyone Protocol client available via npx");
+                return Ok(());
+            }
+        }
+
+        if !self.auto_install {
+            return Err(anyhow::anyhow!(
+                "The Anyone Protocol client is not installed and automatic installation is off (NOXTERM_AUTO_INSTALL).\n\
+                Install it with `npm install -g @anyone-protocol/anyone-client`, or provision it offline with \
+                NOXTERM_ANYONE_PROVISIONING=offline, NOXTERM_ANYONE_CLIENT_PATH and NOXTERM_ANYONE_CLIENT_SHA256.\n\
+                Run `noxterm-backend doctor` for what else is missing."
+            ));
+        }
+
+        info!("Installing Anyone Protocol client globally...");
+        info!("Running: npm install -g @anyone-protocol/anyone-client");
+
+        let install_output = Command::new(&npm_cmd)
+            .args(["install", "-g", "@anyone-protocol/anyone-client"])
+            .output()
+            .context("Failed to execute npm install. Check npm permissions.")?;
+
+        if !install_output.status.success() {
+            let stderr = String::from_utf8_lossy(&install_output.stderr);
+            let stdout = String::from_utf8_lossy(&install_output.stdout);
+
+            // Provide helpful error message based on common issues
+            let help_msg = if stderr.contains("EACCES") || stderr.contains("permission denied") {
+                if cfg!(target_os = "windows") {
+                    "\n\nPermission denied. Try running as Administrator or use:\n  npm config set prefix %USERPROFILE%\\.npm-global"
+                } else {
+                    "\n\nPermission denied. Fix with one of these:\n  \
+                    1. Use nvm (recommended): https://github.com/nvm-sh/nvm\n  \
+                    2. Fix npm permissions: mkdir ~/.npm-global && npm config set prefix '~/.npm-global'\n  \
+                    3. Or run with sudo (not recommended): sudo npm install -g @anyone-protocol/anyone-client"
+                }
+            } else {
+                ""
+            };
+
+            return Err(anyhow::anyhow!(
+                "Failed to install Anyone Protocol client.\nstdout: {}\nstderr: {}{}",
+                stdout.trim(),
+                stderr.trim(),
+                help_msg
+            ));
+        }
+
+        info!("✅ Successfully installed Anyone Protocol client");
+        Ok(())
+    }
+
+    async fn create_terms_agreement(&self) -> Result<()> {
+        tokio::fs::write("terms-agreement", "agreed")
+            .await
+            .context("Failed to create terms-agreement file")?;
+        debug!("Created terms-agreement file");
+        Ok(())
+    }
+}
+
+/// A preinstalled client read into memory and matched against its pin, so
+/// the copy that runs is byte for byte the one that was hashed
+pub struct VerifiedClient {
+    /// Files and symlinks of the install, relative to its root, in byte order
+    entries: Vec<(PathBuf, Entry)>,
+    /// The configured executable, relative to the root
+    executable: PathBuf,
+    /// Digest compared with the pin
+    pub sha256: String,
+}
+
+enum Entry {
+    File { contents: Vec<u8>, executable: bool },
+    Symlink(PathBuf),
+}
+
+impl std::fmt::Debug for VerifiedClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerifiedClient")
+            .field("executable", &self.executable)
+            .field("entries", &self.entries.len())
+            .field("sha256", &self.sha256)
+            .finish()
+    }
+}
+
+impl VerifiedClient {
+    fn read(path: &Path) -> Result<Self> {
+        let missing = |e: std::io::Error| anyhow::anyhow!(
+            "Anyone client not found at {} ({}).\n\
+            Offline provisioning never downloads anything. Install the client on this host, e.g.:\n  \
+            npm install --prefix /opt/anyone @anyone-protocol/anyone-client\n\
+            then set NOXTERM_ANYONE_CLIENT_PATH to /opt/anyone/node_modules/.bin/anyone-client and \
+            NOXTERM_ANYONE_CLIENT_SHA256 to the digest of the install:\n  \
+            cd /opt/anyone && find . -type f -print0 | LC_ALL=C sort -z | xargs -0 sha256sum | sha256sum\n\
+            A standalone binary outside node_modules is pinned by `sha256sum <path>`.",
+            path.display(), e
+        );
+
+        let (root, executable) = install_layout(path);
+        let Some(root) = root else {
+            let contents = std::fs::read(path).map_err(missing)?;
+            let metadata = std::fs::metadata(path).map_err(missing)?;
+            return Ok(Self {
+                sha256: hex::encode(Sha256::digest(&contents)),
+                entries: vec![(executable.clone(), Entry::File { contents, executable: is_executable(&metadata) })],
+                executable,
+            });
+        };
+        std::fs::symlink_metadata(path).map_err(missing)?;
+
+        let mut entries = Vec::new();
+        read_tree(&root, Path::new(""), &mut entries)
+            .with_context(|| format!("Failed to read the Anyone client install at {}", root.display()))?;
+        entries.sort_by(|(a, _), (b, _)| a.as_os_str().as_encoded_bytes().cmp(b.as_os_str().as_encoded_bytes()));
+        Ok(Self { sha256: manifest_sha256(&entries), entries, executable })
+    }
+
+    /// Contents and executable bit of the file the configured path leads to
+    fn entry_point(&self) -> Option<(&[u8], bool)> {
+        let mut path = self.executable.clone();
+        // As many links as Linux follows before ELOOP
+        for _ in 0..40 {
+            match self.entries.iter().find(|(p, _)| *p == path)? {
+                (_, Entry::File { contents, executable }) => return Some((contents, *executable)),
+                (link, Entry::Symlink(target)) => path = link_target(link, target)?,
+            }
+        }
+        None
+    }
+
+    /// Write the verified install to `dir`, replacing what was there, and
+    /// return the executable to run from it
+    pub async fn stage(self, dir: &Path) -> Result<PathBuf> {
+        let dir = dir.to_path_buf();
+        tokio::task::spawn_blocking(move || self.write(&dir))
+            .await?
+            .context("Failed to copy the verified Anyone client")
+    }
+
+    fn write(self, dir: &Path) -> std::io::Result<PathBuf> {
+        match std::fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        create_private_dir(dir)?;
+        for (path, entry) in self.entries {
+            let target = dir.join(&path);
+            if let Some(parent) = target.parent() {
+                create_private_dir(parent)?;
+            }
+            match entry {
+                Entry::File { contents, executable } => {
+                    let mut options = std::fs::OpenOptions::new();
+                    options.write(true).create_new(true);
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::OpenOptionsExt;
+                        options.mode(if executable { 0o700 } else { 0o600 });
+                    }
+                    #[cfg(not(unix))]
+                    let _ = executable;
+                    std::io::Write::write_all(&mut options.open(&target)?, &contents)?;
+                }
+                Entry::Symlink(link) => symlink(&link, &target)?,
+            }
+        }
+        Ok(dir.join(self.executable))
+    }
+}
+
+/// The npm prefix holding `path` (the directory above its first
+/// `node_modules`) and `path` relative to it; a client outside npm is just
+/// its file name
+fn install_layout(path: &Path) -> (Option<PathBuf>, PathBuf) {
+    let components: Vec<_> = path.components().collect();
+    match components.iter().position(|c| c.as_os_str() == "node_modules") {
+        Some(0) => (Some(PathBuf::from(".")), path.to_path_buf()),
+        Some(i) => (Some(components[..i].iter().collect()), components[i..].iter().collect()),
+        None => (None, path.file_name().map(PathBuf::from).unwrap_or_default()),
+    }
+}
+
+fn read_tree(root: &Path, dir: &Path, entries: &mut Vec<(PathBuf, Entry)>) -> Result<()> {
+    for item in std::fs::read_dir(root.join(dir))? {
+        let item = item?;
+        let path = dir.join(item.file_name());
+        // Does not follow symlinks
+        let metadata = item.metadata()?;
+        if metadata.is_dir() {
+            read_tree(root, &path, entries)?;
+        } else if metadata.is_symlink() {
+            let target = std::fs::read_link(root.join(&path))?;
+            if link_target(&path, &target).is_none() {
+                return Err(anyhow::anyhow!("{} links outside the install, to {}", path.display(), target.display()));
+            }
+            entries.push((path, Entry::Symlink(target)));
+        } else if metadata.is_file() {
+            let contents = std::fs::read(root.join(&path))?;
+            entries.push((path, Entry::File { contents, executable: is_executable(&metadata) }));
+        }
+    }
+    Ok(())
+}
+
+/// Where a symlink at `link` points, relative to the install root; `None`
+/// unless its target is relative and only climbs (`..`) before descending,
+/// no higher than the root
+fn link_target(link: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = link.parent()?.to_path_buf();
+    let mut descending = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => {
+                descending = true;
+                resolved.push(part);
+            }
+            Component::CurDir => {}
+            Component::ParentDir if !descending && resolved.pop() => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// SHA-256 of the `sha256sum` lines of every file, in the order of
+/// `find . -type f -print0 | LC_ALL=C sort -z | xargs -0 sha256sum | sha256sum`
+fn manifest_sha256(entries: &[(PathBuf, Entry)]) -> String {
+    let mut manifest = Sha256::new();
+    for (path, entry) in entries {
+        if let Entry::File { contents, .. } = entry {
+            manifest.update(format!("{}  ./{}\n", hex::encode(Sha256::digest(contents)), path.display()));
+        }
+    }
+    hex::encode(manifest.finalize())
+}
+
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        true
+    }
+}
+
+/// Create `dir` and its parents readable by this user only
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{} is a symlink; copying symlinked installs needs a Unix host", link.display()),
+    ))
+}
+
+#[async_trait]
+impl PrivacyBackend for AnyoneClient {
+    fn name(&self) -> &'static str {
+        "anyone"
+    }
+
+    fn proxy(&self) -> ProxyEndpoint {
+        ProxyEndpoint::socks(SocketAddr::from(([127, 0, 0, 1], self.socks_port)))
+    }
+
+    fn dns(&self) -> Lookup {
+        Lookup::SocksResolve(self.proxy().addr)
+    }
+
+    fn control_addr(&self) -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], self.control_port)))
+    }
+
+    fn isolates_by_credentials(&self) -> bool {
+        true
+    }
+
+    async fn prepare(&self) -> Result<()> {
+        if let Some((ref path, ref sha256)) = self.preinstalled {
+            // Run the bytes that were hashed, not whatever is at `path` by then
+            Self::verify_preinstalled(path, sha256).await?.stage(Path::new(STAGING_DIR)).await?;
+            return self.create_terms_agreement().await;
+        }
+
+        // Ensure Node.js is installed (auto-install if needed)
+        self.ensure_nodejs_installed().await?;
+
+        // Install Anyone client if not present
+        self.ensure_anyone_client_installed().await?;
+
+        // Create terms agreement file
+        self.create_terms_agreement().await
+    }
+
+    fn command(&self) -> Option<TokioCommand> {
+        if let Some((ref path, _)) = self.preinstalled {
+            let mut command = TokioCommand::new(Self::staged_executable(path));
+            command.args([
+                "-s", &self.socks_port.to_string(),
+                "-c", &self.control_port.to_string(),
+                "-v"
+            ]);
+            return Some(command);
+        }
+
+        let npx_cmd = if cfg!(target_os = "windows") { "npx.cmd" } else { "npx" };
+
+        let mut command = TokioCommand::new(npx_cmd);
+        command.args([
+            self.npx_install_flag(),
+            "@anyone-protocol/anyone-client",
+            "-s", &self.socks_port.to_string(),
+            "-c", &self.control_port.to_string(),
+            "-v"
+        ]);
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_node_commands() {
+        let (node_cmd, npm_cmd) = AnyoneClient::get_node_commands();
+
+        #[cfg(target_os = "windows")]
+        {
+            assert_eq!(node_cmd, "node.exe");
+            assert_eq!(npm_cmd, "npm.cmd");
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            assert_eq!(node_cmd, "node");
+            assert_eq!(npm_cmd, "npm");
+        }
+    }
+
+    fn vendored_client(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("noxterm-anyone-{}-{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        path
+    }
+
+    #[tokio::test]
+    async fn test_preinstalled_client_checksum() {
+        let contents = b"\x7fELF vendored anyone client";
+        let path = vendored_client("ok", contents);
+        let sha256 = hex::encode(Sha256::digest(contents));
+
+        AnyoneClient::verify_preinstalled(&path, &sha256).await.unwrap();
+
+        let err = AnyoneClient::verify_preinstalled(&path, &"0".repeat(64)).await.unwrap_err();
+        assert!(err.to_string().contains("failed checksum verification"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_preinstalled_client_explains_remediation() {
+        let missing = std::env::temp_dir().join("noxterm-anyone-does-not-exist");
+        let err = AnyoneClient::verify_preinstalled(&missing, &"0".repeat(64)).await.unwrap_err();
+        assert!(err.to_string().contains("NOXTERM_ANYONE_CLIENT_PATH"));
+    }
+
+    #[test]
+    fn test_preinstalled_command_runs_the_verified_copy() {
+        let program = |path: &str| {
+            let client = AnyoneClient::preinstalled(9050, 9051, PathBuf::from(path), "AB".repeat(32));
+            client.command().unwrap().as_std().get_program().to_owned()
+        };
+        assert_eq!(program("/opt/anyone/anyone-client"), Path::new(STAGING_DIR).join("anyone-client"));
+        assert_eq!(
+            program("/opt/anyone/node_modules/.bin/anyone-client"),
+            Path::new(STAGING_DIR).join("node_modules/.bin/anyone-client")
+        );
+    }
+
+    #[test]
+    fn test_link_target() {
+        let target = |link: &str, to: &str| link_target(Path::new(link), Path::new(to));
+        assert_eq!(target("node_modules/.bin/x", "../pkg/cli.js"), Some(PathBuf::from("node_modules/pkg/cli.js")));
+        assert_eq!(target("a", "./b"), Some(PathBuf::from("b")));
+        assert_eq!(target("node_modules/.bin/x", "../../../etc/passwd"), None);
+        assert_eq!(target("a/b", "c/../../x"), None);
+        assert_eq!(target("a", "/usr/bin/node"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_npm_install_is_pinned_and_staged() {
+        let prefix = std::env::temp_dir().join(format!("noxterm-anyone-npm-{}", std::process::id()));
+        let package = prefix.join("node_modules/@anyone-protocol/anyone-client");
+        std::fs::create_dir_all(package.join("bin")).unwrap();
+        std::fs::create_dir_all(prefix.join("node_modules/.bin")).unwrap();
+        std::fs::write(package.join("bin/cli"), "#!/bin/sh\nexec \"$(dirname \"$0\")/anon\" \"$@\"\n").unwrap();
+        std::fs::write(package.join("bin/anon"), b"\x7fELF native client").unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in ["bin/cli", "bin/anon"] {
+                std::fs::set_permissions(package.join(file), std::fs::Permissions::from_mode(0o755)).unwrap();
+            }
+        }
+        let executable = prefix.join("node_modules/.bin/anyone-client");
+        std::os::unix::fs::symlink("../@anyone-protocol/anyone-client/bin/cli", &executable).unwrap();
+
+        let line = |contents: &[u8], path: &str| format!("{}  ./{}\n", hex::encode(Sha256::digest(contents)), path);
+        let manifest = line(b"\x7fELF native client", "node_modules/@anyone-protocol/anyone-client/bin/anon")
+            + &line(
+                std::fs::read(package.join("bin/cli")).unwrap().as_slice(),
+                "node_modules/@anyone-protocol/anyone-client/bin/cli",
+            );
+        let sha256 = hex::encode(Sha256::digest(manifest.as_bytes()));
+
+        let client = AnyoneClient::verify_preinstalled(&executable, &sha256).await.unwrap();
+        let staging = prefix.with_extension("staged");
+        let staged = client.stage(&staging).await.unwrap();
+        assert_eq!(staged, staging.join("node_modules/.bin/anyone-client"));
+        assert_eq!(std::fs::read(&staged).unwrap(), std::fs::read(&executable).unwrap());
+        assert_eq!(
+            std::fs::read(staging.join("node_modules/@anyone-protocol/anyone-client/bin/anon")).unwrap(),
+            b"\x7fELF native client"
+        );
+
+        // The native binary is pinned too, not just the wrapper
+        std::fs::write(package.join("bin/anon"), b"\x7fELF swapped").unwrap();
+        let err = AnyoneClient::verify_preinstalled(&executable, &sha256).await.unwrap_err();
+        assert!(err.to_string().contains("failed checksum verification"));
+
+        std::os::unix::fs::symlink("../../../../../etc/passwd", package.join("bin/escape")).unwrap();
+        let err = AnyoneClient::verify_preinstalled(&executable, &sha256).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("links outside the install"));
+
+        std::fs::remove_dir_all(prefix).unwrap();
+        std::fs::remove_dir_all(staging).unwrap();
+    }
+
+    #[test]
+    fn test_npx_only_downloads_with_auto_install() {
+        let first_arg = |auto_install| {
+            let command = AnyoneClient::new(9050, 9051, auto_install).command().unwrap();
+            command.as_std().get_args().next().unwrap().to_owned()
+        };
+        assert_eq!(first_arg(true), "--yes");
+        assert_eq!(first_arg(false), "--no");
+    }
+}
@@ -0,0 +1,117 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! A system `tor` binary run as a client-only daemon.
+
+use super::{PrivacyBackend, ProxyEndpoint};
+use crate::egress::dns::Lookup;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::process::Command;
+use tracing::info;
+
+pub struct TorDaemon {
+    binary: String,
+    data_dir: PathBuf,
+    socks_port: u16,
+    control_port: u16,
+}
+
+impl TorDaemon {
+    pub fn new(binary: String, data_dir: PathBuf, socks_port: u16, control_port: u16) -> Self {
+        Self { binary, data_dir, socks_port, control_port }
+    }
+
+    /// Empty torrc so the system one cannot add ports or change logging
+    fn torrc(&self) -> PathBuf {
+        self.data_dir.join("torrc")
+    }
+}
+
+#[async_trait]
+impl PrivacyBackend for TorDaemon {
+    fn name(&self) -> &'static str {
+        "tor"
+    }
+
+    fn proxy(&self) -> ProxyEndpoint {
+        ProxyEndpoint::socks(SocketAddr::from(([127, 0, 0, 1], self.socks_port)))
+    }
+
+    fn dns(&self) -> Lookup {
+        Lookup::SocksResolve(self.proxy().addr)
+    }
+
+    fn control_addr(&self) -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], self.control_port)))
+    }
+
+    fn isolates_by_credentials(&self) -> bool {
+        true
+    }
+
+    async fn prepare(&self) -> Result<()> {
+        let output = Command::new(&self.binary)
+            .arg("--version")
+            .output()
+            .await
+            .with_context(|| format!(
+                "Tor binary '{}' not found. Install tor (apt install tor, brew install tor) or set NOXTERM_TOR_BINARY",
+                self.binary
+            ))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("'{} --version' failed", self.binary));
+        }
+        info!("{}", String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("tor").trim());
+
+        tokio::fs::create_dir_all(&self.data_dir)
+            .await
+            .with_context(|| format!("Failed to create Tor data directory {}", self.data_dir.display()))?;
+        #[cfg(unix)]
+        {
+            // Tor refuses a data directory other users can read
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&self.data_dir, std::fs::Permissions::from_mode(0o700)).await?;
+        }
+        tokio::fs::write(self.torrc(), "")
+            .await
+            .context("Failed to write torrc")?;
+        Ok(())
+    }
+
+    fn command(&self) -> Option<Command> {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("-f").arg(self.torrc())
+            .arg("--DataDirectory").arg(&self.data_dir)
+            .args(["--SocksPort", &format!("127.0.0.1:{} IsolateSOCKSAuth", self.socks_port)])
+            .args(["--ControlPort", &format!("127.0.0.1:{}", self.control_port)])
+            .args(["--CookieAuthentication", "1"])
+            .args(["--ClientOnly", "1"])
+            .args(["--Log", "notice stdout"]);
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_binds_loopback_ports() {
+        let tor = TorDaemon::new("tor".to_string(), PathBuf::from("/tmp/noxterm-tor"), 9150, 9151);
+        let command = tor.command().unwrap();
+        let args: Vec<String> = command
+            .as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+
+        assert!(args.windows(2).any(|w| w == ["--SocksPort", "127.0.0.1:9150 IsolateSOCKSAuth"]));
+        assert!(args.windows(2).any(|w| w == ["--ControlPort", "127.0.0.1:9151"]));
+        assert!(args.windows(2).any(|w| w == ["-f", "/tmp/noxterm-tor/torrc"]));
+        assert_eq!(tor.control_addr(), Some("127.0.0.1:9151".parse().unwrap()));
+    }
+}
@@ -0,0 +1,39 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! An existing SOCKS5 or HTTP proxy, such as a WireGuard exit exposed through
+//! wireproxy. Nothing runs locally, so there is no process to supervise.
+//! Such proxies have no RESOLVE extension, so lookups are DNS-over-TCP queries
+//! to a configured server, tunnelled through the proxy.
+
+use super::{PrivacyBackend, ProxyEndpoint};
+use crate::egress::dns::Lookup;
+use std::net::SocketAddr;
+
+pub struct UpstreamProxy {
+    endpoint: ProxyEndpoint,
+    dns_server: SocketAddr,
+}
+
+impl UpstreamProxy {
+    pub fn new(endpoint: ProxyEndpoint, dns_server: SocketAddr) -> Self {
+        Self { endpoint, dns_server }
+    }
+}
+
+impl PrivacyBackend for UpstreamProxy {
+    fn name(&self) -> &'static str {
+        "upstream"
+    }
+
+    fn proxy(&self) -> ProxyEndpoint {
+        self.endpoint.clone()
+    }
+
+    fn dns(&self) -> Lookup {
+        Lookup::Tunnel {
+            proxy: self.endpoint.upstream(),
+            server: self.dns_server,
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Privacy verification suite.
+//!
+//! Runs leak checks from inside a session's container: direct egress, DNS,
+//! exit IP versus host IP, IPv6 and WebRTC-style UDP. Public IPs come from an
+//! echo endpoint the operator chooses, so verification never has to depend on
+//! a third party and tests can point it at a local stand-in.
+
+use crate::config::NetworkMode;
+use crate::container::exec;
+use crate::egress::dns::DnsResolver;
+use anyhow::{anyhow, Result};
+use bollard::Docker;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a single check may run inside the container
+const CHECK_TIMEOUT: Duration = Duration::from_secs(25);
+/// How long the echo endpoint has to answer
+const FETCH_TIMEOUT_SECS: u64 = 15;
+/// Public resolver a leaking container would reach
+const PUBLIC_RESOLVER: &str = "9.9.9.9";
+/// TEST-NET-1 address on the STUN port; any UDP datagram that leaves is a leak
+const STUN_PROBE: &str = "192.0.2.1/3478";
+/// Printed by a probe whose attempt ran and failed
+const PROBE_BLOCKED: &str = "probe-blocked";
+
+/// Service that answers with the caller's public IP
+#[derive(Debug, Clone)]
+pub struct EchoEndpoint {
+    url: String,
+}
+
+impl EchoEndpoint {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Host part of the URL, for checking that it resolves anonymously
+    pub fn host(&self) -> Option<String> {
+        reqwest::Url::parse(&self.url).ok()?.host_str().map(str::to_string)
+    }
+
+    /// Ask the endpoint which IP `client` appears from
+    pub async fn fetch(&self, client: &reqwest::Client) -> Result<IpAddr> {
+        let body = client
+            .get(&self.url)
+            .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_echo_response(&body).ok_or_else(|| anyhow!("Echo endpoint returned no IP address"))
+    }
+
+    /// Shell command fetching the endpoint with curl, falling back to wget
+    fn shell_fetch(&self, env: &str, curl_flags: &str, wget_flags: &str) -> String {
+        let url = shell_quote(&self.url);
+        format!(
+            "{env}curl -fsS --max-time {t} {curl_flags} {url} 2>/dev/null || {env}wget -qO- -T {t} {wget_flags} {url} 2>/dev/null",
+            env = env,
+            t = FETCH_TIMEOUT_SECS,
+            curl_flags = curl_flags,
+            wget_flags = wget_flags,
+            url = url,
+        )
+    }
+}
+
+/// Read an IP from an echo response: plain text, or JSON with an `IP`, `ip`,
+/// `origin` or `address` field
+pub fn parse_echo_response(body: &str) -> Option<IpAddr> {
+    let body = body.trim();
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        return ["IP", "ip", "origin", "address"]
+            .iter()
+            .filter_map(|key| json.get(key).and_then(|v| v.as_str()))
+            // httpbin-style origins list every hop; the first is the caller
+            .find_map(|value| value.split(',').next()?.trim().parse().ok());
+    }
+    body.split_whitespace().next()?.parse().ok()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// What a connection attempt made from inside the session found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe<T> {
+    /// The attempt got out
+    Reached(T),
+    /// The attempt ran and failed
+    Blocked,
+    /// The attempt could not be made, e.g. the tool it needs is missing
+    Untested,
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check could not reach a verdict, e.g. the echo endpoint was down
+    Inconclusive,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Result of verifying one session
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    pub session_id: Uuid,
+    pub network: String,
+    pub echo_url: String,
+    pub host_ip: Option<IpAddr>,
+    pub exit_ip: Option<IpAddr>,
+    pub checks: Vec<CheckResult>,
+    /// At least one check found traffic escaping the privacy network
+    pub leak_detected: bool,
+    /// Every check passed
+    pub passed: bool,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The session container under test
+pub struct SessionTarget<'a> {
+    pub session_id: Uuid,
+    pub container_id: &'a str,
+    pub network: NetworkMode,
+    /// Proxy the session's shell is configured with, when it is not already
+    /// in the container's environment
+    pub proxy_url: Option<String>,
+}
+
+/// Runs the checks against one session container
+pub struct Verifier<'a> {
+    pub docker: &'a Docker,
+    pub dns: &'a DnsResolver,
+    pub echo: &'a EchoEndpoint,
+}
+
+impl Verifier<'_> {
+    pub async fn run(&self, target: &SessionTarget<'_>) -> VerificationReport {
+        let host_ip = self.echo.fetch(&reqwest::Client::new()).await.ok();
+
+        let env = target
+            .proxy_url
+            .as_deref()
+            .map(|url| format!("ALL_PROXY={url} all_proxy={url} ", url = shell_quote(url)))
+            .unwrap_or_default();
+        let exit_ip = self.fetch_ip(target, &self.echo.shell_fetch(&env, "", "")).await;
+        let direct_ip = self
+            .fetch_probe(target, &self.echo.shell_fetch("", "--noproxy '*'", "--no-proxy"))
+            .await;
+        let ipv6_ip = self
+            .fetch_probe(target, &self.echo.shell_fetch("", "-6 --noproxy '*'", "-6 --no-proxy"))
+            .await;
+        let global_ipv6 = self
+            .exec(target, "awk '$4 == \"00\" && $6 != \"lo\"' /proc/net/if_inet6 2>/dev/null | head -n1")
+            .await
+            .map(|out| !out.trim().is_empty());
+        let udp_sent = self.udp_probe(target, STUN_PROBE).await;
+
+        let checks = vec![
+            judge_direct_egress(host_ip, direct_ip),
+            self.dns_leak(target).await,
+            judge_exit_ip(host_ip, exit_ip),
+            judge_ipv6(global_ipv6, ipv6_ip),
+            judge_udp(udp_sent),
+        ];
+
+        VerificationReport {
+            session_id: target.session_id,
+            network: target.network.to_string(),
+            echo_url: self.echo.url().to_string(),
+            host_ip,
+            exit_ip,
+            leak_detected: checks.iter().any(|c| c.status == CheckStatus::Fail),
+            passed: checks.iter().all(|c| c.status == CheckStatus::Pass),
+            checks,
+            checked_at: chrono::Utc::now(),
+        }
+    }
+
+    /// Queries straight to a public resolver must not get out, and a unique
+    /// name looked up through the container's resolver must arrive at ours
+    async fn dns_leak(&self, target: &SessionTarget<'_>) -> CheckResult {
+        const NAME: &str = "dns_leak";
+
+        match self.udp_probe(target, &format!("{}/53", PUBLIC_RESOLVER)).await {
+            Probe::Blocked => {}
+            Probe::Reached(()) => {
+                return CheckResult::new(NAME, CheckStatus::Fail, format!("Direct DNS to {} is not blocked", PUBLIC_RESOLVER));
+            }
+            Probe::Untested => {
+                return CheckResult::new(NAME, CheckStatus::Inconclusive, "Could not send UDP from the container (no bash)");
+            }
+        }
+
+        if target.network != NetworkMode::AnyoneOnly {
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Fail,
+                "Session is not anyone-only, so lookups use the host's resolvers",
+            );
+        }
+
+        let probe_name = format!("leak-{}.noxterm.invalid", Uuid::new_v4().simple());
+        let observed = self.dns.watch(&probe_name).await;
+        let _ = self.exec(target, &format!("getent hosts {}", probe_name)).await;
+        if matches!(tokio::time::timeout(CHECK_TIMEOUT, observed).await, Ok(Ok(_))) {
+            CheckResult::new(NAME, CheckStatus::Pass, "Lookups only reach the anonymous resolver")
+        } else {
+            self.dns.unwatch(&probe_name).await;
+            CheckResult::new(NAME, CheckStatus::Fail, "A lookup from the session never reached the anonymous resolver")
+        }
+    }
+
+    async fn fetch_ip(&self, target: &SessionTarget<'_>, command: &str) -> Option<IpAddr> {
+        parse_echo_response(&self.exec(target, command).await?)
+    }
+
+    /// Run a fetch, telling a failed attempt apart from one that never ran
+    /// because neither curl nor wget is installed
+    async fn fetch_probe(&self, target: &SessionTarget<'_>, fetch: &str) -> Probe<IpAddr> {
+        let command = format!(
+            "command -v curl >/dev/null || command -v wget >/dev/null || exit 127; {{ {}; }} || echo {}",
+            fetch, PROBE_BLOCKED
+        );
+        match self.exec(target, &command).await {
+            Some(out) if out.contains(PROBE_BLOCKED) => Probe::Blocked,
+            Some(out) => parse_echo_response(&out).map_or(Probe::Untested, Probe::Reached),
+            None => Probe::Untested,
+        }
+    }
+
+    /// Send one datagram to `dest` (`host/port`) with bash's `/dev/udp`
+    async fn udp_probe(&self, target: &SessionTarget<'_>, dest: &str) -> Probe<()> {
+        let command = format!(
+            "command -v bash >/dev/null || exit 127; bash -c 'echo probe > /dev/udp/{}' 2>/dev/null && echo udp-sent || echo {}",
+            dest, PROBE_BLOCKED
+        );
+        match self.exec(target, &command).await {
+            Some(out) if out.contains(PROBE_BLOCKED) => Probe::Blocked,
+            Some(out) if out.contains("udp-sent") => Probe::Reached(()),
+            _ => Probe::Untested,
+        }
+    }
+
+    async fn exec(&self, target: &SessionTarget<'_>, command: &str) -> Option<String> {
+        exec::run(self.docker, target.container_id, command, CHECK_TIMEOUT)
+            .await
+            .ok()
+            .map(|out| out.output)
+    }
+}
+
+/// A connection that bypasses the proxy must fail, or be forced through the
+/// privacy network by the netguard sidecar
+pub fn judge_direct_egress(host_ip: Option<IpAddr>, direct_ip: Probe<IpAddr>) -> CheckResult {
+    const NAME: &str = "direct_egress";
+    match (host_ip, direct_ip) {
+        (_, Probe::Blocked) => CheckResult::new(NAME, CheckStatus::Pass, "Connections that bypass the proxy are blocked"),
+        (_, Probe::Untested) => CheckResult::new(
+            NAME,
+            CheckStatus::Inconclusive,
+            "Could not attempt a direct connection (no curl or wget in the container)",
+        ),
+        (Some(host), Probe::Reached(seen)) if host == seen => CheckResult::new(
+            NAME,
+            CheckStatus::Fail,
+            format!("A connection bypassing the proxy reached the internet from the host IP {}", seen),
+        ),
+        (Some(_), Probe::Reached(seen)) => CheckResult::new(
+            NAME,
+            CheckStatus::Pass,
+            format!("Connections that bypass the proxy are forced through the privacy network (exit {})", seen),
+        ),
+        (None, Probe::Reached(seen)) => CheckResult::new(
+            NAME,
+            CheckStatus::Inconclusive,
+            format!("A connection bypassing the proxy got out as {}, but the host IP is unknown", seen),
+        ),
+    }
+}
+
+/// Traffic through the session's proxy must not appear to come from the host
+pub fn judge_exit_ip(host_ip: Option<IpAddr>, exit_ip: Option<IpAddr>) -> CheckResult {
+    const NAME: &str = "exit_ip";
+    match (host_ip, exit_ip) {
+        (Some(host), Some(exit)) if host == exit => CheckResult::new(
+            NAME,
+            CheckStatus::Fail,
+            format!("Session traffic exits from the host IP {}", exit),
+        ),
+        (Some(_), Some(exit)) => CheckResult::new(NAME, CheckStatus::Pass, format!("Session traffic exits from {}", exit)),
+        (None, _) => CheckResult::new(NAME, CheckStatus::Inconclusive, "The host could not reach the echo endpoint"),
+        (_, None) => CheckResult::new(NAME, CheckStatus::Inconclusive, "The session could not reach the echo endpoint"),
+    }
+}
+
+/// No IPv6 traffic may leave outside the proxy
+pub fn judge_ipv6(global_address: Option<bool>, ipv6_ip: Probe<IpAddr>) -> CheckResult {
+    const NAME: &str = "ipv6";
+    match (global_address, ipv6_ip) {
+        (_, Probe::Reached(seen)) => {
+            CheckResult::new(NAME, CheckStatus::Fail, format!("IPv6 traffic got out directly as {}", seen))
+        }
+        (Some(true), Probe::Blocked) => CheckResult::new(
+            NAME,
+            CheckStatus::Pass,
+            "The session has a global IPv6 address, but direct IPv6 traffic is blocked",
+        ),
+        (Some(true), Probe::Untested) => CheckResult::new(
+            NAME,
+            CheckStatus::Inconclusive,
+            "The session has a global IPv6 address, and no curl or wget to test it with",
+        ),
+        (Some(false), _) => CheckResult::new(NAME, CheckStatus::Pass, "The session has no global IPv6 address"),
+        (None, _) => CheckResult::new(NAME, CheckStatus::Inconclusive, "Could not inspect the session's addresses"),
+    }
+}
+
+/// UDP is how WebRTC's STUN discovers the real address, so none may leave
+pub fn judge_udp(sent: Probe<()>) -> CheckResult {
+    const NAME: &str = "udp";
+    match sent {
+        Probe::Blocked => CheckResult::new(NAME, CheckStatus::Pass, "Outbound UDP (STUN) is blocked"),
+        Probe::Reached(()) => CheckResult::new(
+            NAME,
+            CheckStatus::Fail,
+            "Outbound UDP is allowed, so WebRTC can reveal the real address",
+        ),
+        Probe::Untested => {
+            CheckResult::new(NAME, CheckStatus::Inconclusive, "Could not send UDP from the container (no bash)")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Local stand-in for the echo endpoint answering with a fixed body
+    async fn echo_stand_in(body: &'static str) -> EchoEndpoint {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        EchoEndpoint::new(format!("http://{}/ip", addr))
+    }
+
+    fn direct_client() -> reqwest::Client {
+        reqwest::Client::builder().no_proxy().build().unwrap()
+    }
+
+    #[test]
+    fn test_parse_echo_response() {
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(parse_echo_response("203.0.113.7\n"), Some(ip));
+        assert_eq!(parse_echo_response(r#"{"IP":"203.0.113.7","IsAnyone":true}"#), Some(ip));
+        assert_eq!(parse_echo_response(r#"{"origin":"203.0.113.7, 10.0.0.1"}"#), Some(ip));
+        assert_eq!(parse_echo_response(r#"{"ip":"2001:db8::1"}"#), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(parse_echo_response("<html>blocked</html>"), None);
+        assert_eq!(parse_echo_response(r#"{"status":"ok"}"#), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_stand_in() {
+        let echo = echo_stand_in(r#"{"ip":"198.51.100.4"}"#).await;
+        let ip = echo.fetch(&direct_client()).await.unwrap();
+        assert_eq!(ip, "198.51.100.4".parse::<IpAddr>().unwrap());
+        assert_eq!(echo.host().as_deref(), Some("127.0.0.1"));
+
+        let broken = echo_stand_in("not an address").await;
+        assert!(broken.fetch(&direct_client()).await.is_err());
+    }
+
+    #[test]
+    fn test_judgements() {
+        let host = Some("198.51.100.4".parse().unwrap());
+        let exit = Some("203.0.113.7".parse().unwrap());
+
+        let reached = |ip: Option<IpAddr>| Probe::Reached(ip.unwrap());
+        assert_eq!(judge_direct_egress(host, Probe::Blocked).status, CheckStatus::Pass);
+        assert_eq!(judge_direct_egress(host, reached(host)).status, CheckStatus::Fail);
+        assert_eq!(judge_direct_egress(host, reached(exit)).status, CheckStatus::Pass);
+        assert_eq!(judge_direct_egress(None, reached(exit)).status, CheckStatus::Inconclusive);
+
+        assert_eq!(judge_exit_ip(host, exit).status, CheckStatus::Pass);
+        assert_eq!(judge_exit_ip(host, host).status, CheckStatus::Fail);
+        assert_eq!(judge_exit_ip(host, None).status, CheckStatus::Inconclusive);
+
+        assert_eq!(judge_ipv6(Some(true), Probe::Blocked).status, CheckStatus::Pass);
+        assert_eq!(judge_ipv6(Some(false), Probe::Reached("2001:db8::1".parse().unwrap())).status, CheckStatus::Fail);
+
+        assert_eq!(judge_udp(Probe::Blocked).status, CheckStatus::Pass);
+        assert_eq!(judge_udp(Probe::Reached(())).status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_missing_tools_are_inconclusive() {
+        let host = Some("198.51.100.4".parse().unwrap());
+
+        // No curl or wget: the direct attempt never ran, so nothing was blocked
+        assert_eq!(judge_direct_egress(host, Probe::Untested).status, CheckStatus::Inconclusive);
+        assert_eq!(judge_direct_egress(None, Probe::Untested).status, CheckStatus::Inconclusive);
+        assert_eq!(judge_ipv6(Some(true), Probe::Untested).status, CheckStatus::Inconclusive);
+        // No bash: no datagram was sent
+        assert_eq!(judge_udp(Probe::Untested).status, CheckStatus::Inconclusive);
+    }
+
+    #[test]
+    fn test_shell_fetch_quotes_url() {
+        let echo = EchoEndpoint::new("https://echo.example/ip?a=1&b=2");
+        let command = echo.shell_fetch("", "--noproxy '*'", "--no-proxy");
+        assert!(command.starts_with("curl -fsS --max-time 15 --noproxy '*' 'https://echo.example/ip?a=1&b=2'"));
+        assert!(command.contains("|| wget -qO- -T 15 --no-proxy 'https://echo.example/ip?a=1&b=2'"));
+    }
+}
@@ -0,0 +1,308 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Startup reconciliation between the database and Docker.
+//!
+//! After a crash or restart the session cache is empty, while the database
+//! still lists live sessions whose containers may still be running. Each
+//! live session is matched to its container: by label, by the container ID
+//! the database recorded, or by name for containers from before labels.
+//! Sessions with a running container are adopted back into the cache,
+//! sessions whose container is gone are terminated, and session containers
+//! no adopted session claims are removed. Containers labelled with another
+//! instance's ID are never touched, and neither are the sessions they belong
+//! to.
+
+use crate::container::{labels, transparent};
+use crate::db::sessions::DbSession;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// A session container found on a Docker host
+#[derive(Debug, Clone)]
+pub struct Found {
+    /// Docker host the container runs on
+    pub host: String,
+    pub id: String,
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    pub running: bool,
+}
+
+impl Found {
+    pub fn from_summary(host: &str, summary: bollard::models::ContainerSummary) -> Option<Self> {
+        Some(Self {
+            host: host.to_string(),
+            id: summary.id?,
+            name: summary
+                .names
+                .and_then(|names| names.into_iter().next())
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string(),
+            labels: summary.labels.unwrap_or_default(),
+            running: summary.state.as_deref() == Some("running"),
+        })
+    }
+}
+
+/// Why a live session could not be adopted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// Its container is gone or stopped
+    ContainerGone,
+    /// It held a batch job, which a restart fails
+    JobInterrupted,
+    /// It needs the Anyone network, which would not start
+    AnyoneUnavailable,
+}
+
+impl Loss {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Loss::ContainerGone => "container_gone",
+            Loss::JobInterrupted => "job_interrupted",
+            Loss::AnyoneUnavailable => "anyone_unavailable",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Sessions to restore, with the running container they keep (none for
+    /// sessions that never started one)
+    pub adopt: Vec<(DbSession, Option<Found>)>,
+    pub terminate: Vec<(DbSession, Loss)>,
+    /// Session containers nothing adopted claims
+    pub reap: Vec<Found>,
+}
+
+/// Decide what happens to each live session and each session container
+/// this instance may own
+pub fn plan(instance_id: &str, sessions: Vec<DbSession>, containers: Vec<Found>) -> Plan {
+    // Each container goes to at most one session
+    let mut claimed: HashMap<Uuid, Vec<Found>> = HashMap::new();
+    let mut reap = Vec::new();
+    let (ours, theirs): (Vec<_>, Vec<_>) = containers
+        .into_iter()
+        .partition(|found| labels::instance_id(&found.labels).is_none_or(|id| id == instance_id));
+    let elsewhere: HashSet<Uuid> = theirs.iter().filter_map(|found| labels::session_id(&found.labels)).collect();
+    for found in ours {
+        let owner = labels::session_id(&found.labels)
+            .filter(|id| sessions.iter().any(|s| s.id == *id))
+            .or_else(|| {
+                sessions
+                    .iter()
+                    .find(|s| s.container_id.as_deref() == Some(found.id.as_str()))
+                    .map(|s| s.id)
+            })
+            .or_else(|| {
+                // Containers from before labels name their session only in
+                // the container name
+                if labels::session_id(&found.labels).is_some() {
+                    return None;
+                }
+                sessions
+                    .iter()
+                    .find(|s| labels::name_matches(&found.name, s.id) || is_sidecar(&found, s.id))
+                    .map(|s| s.id)
+            });
+        match owner {
+            Some(id) => claimed.entry(id).or_default().push(found),
+            None => reap.push(found),
+        }
+    }
+
+    let mut plan = Plan { reap, ..Plan::default() };
+    for session in sessions {
+        let (sidecars, mut found): (Vec<_>, Vec<_>) = claimed
+            .remove(&session.id)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|c| is_sidecar(c, session.id));
+        // Its container runs under another instance, which reconciles it
+        if found.is_empty() && elsewhere.contains(&session.id) {
+            plan.reap.extend(sidecars);
+            continue;
+        }
+        let keep = found.iter().position(|c| c.running).map(|i| found.remove(i));
+        plan.reap.extend(found);
+
+        let is_job = session.metadata.get("job_id").is_some_and(|id| !id.is_null());
+        match keep {
+            // The netguard sidecar stays with the container it guards
+            Some(container) if !is_job => plan.adopt.push((session, Some(container))),
+            // Created but never connected: the container starts on first attach
+            None if !is_job && session.status == "created" && session.container_id.is_none() => {
+                plan.reap.extend(sidecars);
+                plan.adopt.push((session, None));
+            }
+            keep => {
+                plan.reap.extend(keep);
+                plan.reap.extend(sidecars);
+                let loss = if is_job { Loss::JobInterrupted } else { Loss::ContainerGone };
+                plan.terminate.push((session, loss));
+            }
+        }
+    }
+    plan
+}
+
+/// Whether a container is the netguard sidecar of a session's container
+fn is_sidecar(found: &Found, session_id: Uuid) -> bool {
+    if labels::session_id(&found.labels).is_some() {
+        return labels::is_netguard(&found.labels);
+    }
+    // Sidecars from before labels are named after the container they guard
+    found.name == transparent::sidecar_name(&labels::container_name(session_id))
+        || found.name == transparent::sidecar_name(&labels::legacy_container_name(session_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(status: &str, container_id: Option<&str>) -> DbSession {
+        let now = chrono::Utc::now();
+        DbSession {
+            id: Uuid::new_v4(),
+            user_id: "alice".to_string(),
+            status: status.to_string(),
+            container_id: container_id.map(String::from),
+            container_name: None,
+            container_image: "ubuntu:22.04".to_string(),
+            created_at: now,
+            last_activity: now,
+            disconnected_at: None,
+            expires_at: None,
+            resource_limits: serde_json::json!({}),
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    fn found(id: &str, name: &str, labels: HashMap<String, String>, running: bool) -> Found {
+        Found { host: "local".to_string(), id: id.to_string(), name: name.to_string(), labels, running }
+    }
+
+    fn owned_by(instance_id: &str, session_id: Uuid, role: labels::Role) -> HashMap<String, String> {
+        labels::Owner {
+            instance_id,
+            session_id,
+            user_id: "alice",
+            tier: "small",
+            template: "ubuntu:22.04",
+            created_at: chrono::Utc::now(),
+        }
+        .labels(role)
+    }
+
+    fn labelled(session_id: Uuid) -> HashMap<String, String> {
+        owned_by("test", session_id, labels::Role::Session)
+    }
+
+    #[test]
+    fn test_adopts_by_label_id_and_name() {
+        let by_label = session("running", Some("old"));
+        let by_id = session("disconnected", Some("c2"));
+        let by_name = session("running", Some("c3"));
+        let containers = vec![
+            found("c1", &labels::container_name(by_label.id), labelled(by_label.id), true),
+            found("c2", "renamed", HashMap::new(), true),
+            found("c3x", &labels::container_name(by_name.id), HashMap::new(), true),
+        ];
+
+        let plan = plan("test", vec![by_label.clone(), by_id.clone(), by_name.clone()], containers);
+        let adopted: Vec<_> = plan.adopt.iter().map(|(s, c)| (s.id, c.as_ref().unwrap().id.clone())).collect();
+        assert_eq!(adopted, vec![
+            (by_label.id, "c1".to_string()),
+            (by_id.id, "c2".to_string()),
+            (by_name.id, "c3x".to_string()),
+        ]);
+        assert!(plan.terminate.is_empty());
+        assert!(plan.reap.is_empty());
+    }
+
+    #[test]
+    fn test_terminates_missing_and_reaps_unclaimed() {
+        let gone = session("running", Some("c1"));
+        let stopped = session("disconnected", Some("c2"));
+        let fresh = session("created", None);
+        let stranger = Uuid::new_v4();
+        let containers = vec![
+            found("c2", "x", labelled(stopped.id), false),
+            found("c9", &labels::container_name(stranger), labelled(stranger), true),
+            found("c8", "noxterm-session-000000000000", HashMap::new(), true),
+        ];
+
+        let plan = plan("test", vec![gone.clone(), stopped.clone(), fresh.clone()], containers);
+        assert_eq!(plan.adopt.len(), 1);
+        assert_eq!(plan.adopt[0].0.id, fresh.id);
+        assert!(plan.adopt[0].1.is_none());
+
+        let terminated: Vec<_> = plan.terminate.iter().map(|(s, loss)| (s.id, *loss)).collect();
+        assert_eq!(terminated, vec![(gone.id, Loss::ContainerGone), (stopped.id, Loss::ContainerGone)]);
+
+        let mut reaped: Vec<_> = plan.reap.iter().map(|c| c.id.as_str()).collect();
+        reaped.sort();
+        assert_eq!(reaped, vec!["c2", "c8", "c9"]);
+    }
+
+    #[test]
+    fn test_job_sessions_are_not_adopted() {
+        let mut job = session("running", Some("c1"));
+        job.metadata = serde_json::json!({ "job_id": Uuid::new_v4() });
+        let containers = vec![found("c1", "x", labelled(job.id), true)];
+
+        let plan = plan("test", vec![job.clone()], containers);
+        assert!(plan.adopt.is_empty());
+        assert_eq!(plan.terminate[0].1, Loss::JobInterrupted);
+        assert_eq!(plan.reap[0].id, "c1");
+    }
+
+    #[test]
+    fn test_sidecars_follow_their_session() {
+        let adopted = session("running", Some("c1"));
+        let gone = session("running", Some("c2"));
+        let sidecar = |s: &DbSession| transparent::sidecar_name(&labels::container_name(s.id));
+        let containers = vec![
+            found("c1", &labels::container_name(adopted.id), labelled(adopted.id), true),
+            found("g1", &sidecar(&adopted), HashMap::new(), true),
+            found("g2", &sidecar(&gone), HashMap::new(), true),
+        ];
+
+        let plan = plan("test", vec![adopted, gone], containers);
+        assert_eq!(plan.adopt[0].1.as_ref().unwrap().id, "c1");
+        assert_eq!(plan.reap.len(), 1);
+        assert_eq!(plan.reap[0].id, "g2");
+    }
+
+    #[test]
+    fn test_keeps_one_running_container_per_session() {
+        let s = session("running", None);
+        let containers = vec![
+            found("old", "x", labelled(s.id), false),
+            found("new", "y", labelled(s.id), true),
+        ];
+
+        let plan = plan("test", vec![s], containers);
+        assert_eq!(plan.adopt[0].1.as_ref().unwrap().id, "new");
+        assert_eq!(plan.reap.len(), 1);
+        assert_eq!(plan.reap[0].id, "old");
+    }
+
+    #[test]
+    fn test_ignores_other_instances() {
+        let s = session("running", Some("c1"));
+        let containers = vec![
+            found("c1", &labels::container_name(s.id), owned_by("other", s.id, labels::Role::Session), true),
+            found("c2", "noxterm-session-other", owned_by("other", Uuid::new_v4(), labels::Role::Session), true),
+            found("g1", "anything", owned_by("test", s.id, labels::Role::Netguard), true),
+        ];
+
+        let plan = plan("test", vec![s], containers);
+        assert!(plan.adopt.is_empty());
+        assert!(plan.terminate.is_empty());
+        let reaped: Vec<_> = plan.reap.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(reaped, vec!["g1"]);
+    }
+}
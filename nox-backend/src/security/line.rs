@@ -0,0 +1,541 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Command line assembly for PTY input.
+//!
+//! Terminal input arrives as keystrokes, not commands. `LineAssembler` follows
+//! the edits readline would make (backspace, cursor keys, kill commands,
+//! history recall, bracketed paste) so the line can be checked against the
+//! command policy when Enter is pressed. It is a best-effort mirror: where
+//! Tab completed, the line also comes as a pattern with `*` for whatever the
+//! shell inserted, and edits it cannot follow, such as control keys it does
+//! not know, mark the line as inexact.
+
+/// Start and end of a bracketed paste
+const PASTE_START: &str = "200";
+const PASTE_END: &str = "201";
+
+/// Stands in the line for text the shell's tab completion inserted
+const COMPLETION: char = '\u{e000}';
+
+/// Most lines kept for up/down history recall
+const HISTORY_LIMIT: usize = 500;
+
+/// A piece of input, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Send to the PTY unchanged
+    Forward(String),
+    /// Enter was pressed on `line`; `enter` is the key itself, to be sent
+    /// only if the line is allowed. `pattern` is the line with `*` where
+    /// Tab completed, if it did.
+    Submit { line: String, pattern: Option<String>, exact: bool, enter: String },
+    /// A newline inside a bracketed paste. A shell that honours bracketed
+    /// paste keeps it in the line, one that does not runs `line` on it, so
+    /// `newline` is sent only if `line` is allowed.
+    Pasted { line: String, pattern: Option<String>, exact: bool, newline: String },
+}
+
+#[derive(Debug, Default)]
+enum Escape {
+    #[default]
+    None,
+    /// Got ESC
+    Start,
+    /// Inside `ESC [`, collecting parameters
+    Csi(String),
+    /// Got `ESC O`
+    Ss3,
+}
+
+#[derive(Debug)]
+pub struct LineAssembler {
+    line: Vec<char>,
+    cursor: usize,
+    exact: bool,
+    escape: Escape,
+    pasting: bool,
+    history: Vec<String>,
+    /// Position while browsing history, and the line being typed before it
+    recall: Option<(usize, Vec<char>)>,
+}
+
+impl Default for LineAssembler {
+    fn default() -> Self {
+        Self {
+            line: Vec::new(),
+            cursor: 0,
+            exact: true,
+            escape: Escape::None,
+            pasting: false,
+            history: Vec::new(),
+            recall: None,
+        }
+    }
+}
+
+impl LineAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The line as currently reconstructed
+    pub fn line(&self) -> String {
+        self.line.iter().filter(|&&c| c != COMPLETION).collect()
+    }
+
+    /// The line with `*` for what Tab completed, if anything
+    pub fn pattern(&self) -> Option<String> {
+        self.line
+            .contains(&COMPLETION)
+            .then(|| self.line.iter().map(|&c| if c == COMPLETION { '*' } else { c }).collect())
+    }
+
+    /// Forget the current line, e.g. when a full-screen program takes over
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.exact = true;
+        self.escape = Escape::None;
+        self.pasting = false;
+        self.recall = None;
+    }
+
+    /// Split input into what can be forwarded and the lines it submits
+    pub fn feed(&mut self, input: &str) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut forward = String::new();
+
+        for c in input.chars() {
+            if !matches!(self.escape, Escape::None) {
+                forward.push(c);
+                self.escape_char(c);
+                continue;
+            }
+
+            match c {
+                '\r' | '\n' if self.pasting => {
+                    if !forward.is_empty() {
+                        segments.push(Segment::Forward(std::mem::take(&mut forward)));
+                    }
+                    // The line so far is checked, and kept until Enter is
+                    // pressed in case the shell does keep it
+                    segments.push(Segment::Pasted {
+                        line: self.line(),
+                        pattern: self.pattern(),
+                        exact: self.exact,
+                        newline: c.to_string(),
+                    });
+                    self.insert('\n');
+                }
+                '\r' | '\n' => {
+                    if !forward.is_empty() {
+                        segments.push(Segment::Forward(std::mem::take(&mut forward)));
+                    }
+                    segments.push(self.submit(c));
+                }
+                _ => {
+                    forward.push(c);
+                    self.key(c);
+                }
+            }
+        }
+
+        // An Esc ending the input was pressed on its own (leaving insert
+        // mode, closing a menu); meta keys arrive with the key they modify
+        if matches!(self.escape, Escape::Start) {
+            self.escape = Escape::None;
+        }
+        if !forward.is_empty() {
+            segments.push(Segment::Forward(forward));
+        }
+        segments
+    }
+
+    fn submit(&mut self, enter: char) -> Segment {
+        let line = self.line();
+        if !line.trim().is_empty() {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_LIMIT {
+                self.history.remove(0);
+            }
+        }
+        let pattern = self.pattern();
+        let exact = self.exact;
+        self.reset();
+        Segment::Submit {
+            line,
+            pattern,
+            exact,
+            enter: enter.to_string(),
+        }
+    }
+
+    fn key(&mut self, c: char) {
+        match c {
+            '\x1b' => self.escape = Escape::Start,
+            '\x7f' | '\x08' => self.backspace(),
+            '\x01' => self.cursor = 0,
+            '\x05' => self.cursor = self.line.len(),
+            '\x02' => self.cursor = self.cursor.saturating_sub(1),
+            '\x06' => self.cursor = (self.cursor + 1).min(self.line.len()),
+            '\x0b' => self.line.truncate(self.cursor),
+            '\x15' => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            '\x17' => self.kill_word_back(),
+            // Ctrl-C abandons the line
+            '\x03' => self.reset(),
+            '\x04' => self.delete(),
+            '\x10' => self.history_prev(),
+            '\x0e' => self.history_next(),
+            // Redraw and bell leave the line alone
+            '\x0c' | '\x07' => {}
+            '\t' if self.pasting => self.insert(c),
+            '\t' => self.insert(COMPLETION),
+            // Reverse search, yank, quoted insert, transpose and any other
+            // control key change the line in ways only the shell knows
+            c if c.is_control() => self.exact = false,
+            c => self.insert(c),
+        }
+    }
+
+    fn escape_char(&mut self, c: char) {
+        match std::mem::take(&mut self.escape) {
+            Escape::Start => match c {
+                '[' => self.escape = Escape::Csi(String::new()),
+                'O' => self.escape = Escape::Ss3,
+                'b' => self.word_left(),
+                'f' => self.word_right(),
+                '\x7f' | '\x08' => self.kill_word_back(),
+                // Alt-d, alt-. and other meta keys edit from state we lack
+                _ => self.exact = false,
+            },
+            Escape::Csi(mut params) => {
+                if c.is_ascii_digit() || c == ';' {
+                    params.push(c);
+                    self.escape = Escape::Csi(params);
+                    return;
+                }
+                match (c, params.split(';').next().unwrap_or_default()) {
+                    ('~', PASTE_START) => self.pasting = true,
+                    ('~', PASTE_END) => self.pasting = false,
+                    ('~', "1" | "7") => self.cursor = 0,
+                    ('~', "4" | "8") => self.cursor = self.line.len(),
+                    ('~', "3") => self.delete(),
+                    ('~', _) => {}
+                    (c, _) => self.cursor_key(c),
+                }
+            }
+            Escape::Ss3 => self.cursor_key(c),
+            Escape::None => {}
+        }
+    }
+
+    fn cursor_key(&mut self, c: char) {
+        match c {
+            'A' => self.history_prev(),
+            'B' => self.history_next(),
+            'C' => self.cursor = (self.cursor + 1).min(self.line.len()),
+            'D' => self.cursor = self.cursor.saturating_sub(1),
+            'H' => self.cursor = 0,
+            'F' => self.cursor = self.line.len(),
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn word_left(&mut self) {
+        while self.cursor > 0 && !self.line[self.cursor - 1].is_alphanumeric() {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && self.line[self.cursor - 1].is_alphanumeric() {
+            self.cursor -= 1;
+        }
+    }
+
+    fn word_right(&mut self) {
+        let len = self.line.len();
+        while self.cursor < len && !self.line[self.cursor].is_alphanumeric() {
+            self.cursor += 1;
+        }
+        while self.cursor < len && self.line[self.cursor].is_alphanumeric() {
+            self.cursor += 1;
+        }
+    }
+
+    /// Ctrl-W: back to the previous whitespace
+    fn kill_word_back(&mut self) {
+        let end = self.cursor;
+        while self.cursor > 0 && self.line[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && !self.line[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+        self.line.drain(self.cursor..end);
+    }
+
+    fn history_prev(&mut self) {
+        let index = match self.recall {
+            Some((0, _)) => return,
+            Some((index, _)) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.recall = Some((self.history.len(), self.line.clone()));
+                self.history.len() - 1
+            }
+        };
+        if let Some((ref mut current, _)) = self.recall {
+            *current = index;
+        }
+        self.line = self.history[index].chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    fn history_next(&mut self) {
+        let Some((index, ref draft)) = self.recall else {
+            return;
+        };
+        if index + 1 >= self.history.len() {
+            self.line = draft.clone();
+            self.recall = None;
+        } else {
+            self.line = self.history[index + 1].chars().collect();
+            self.recall = Some((index + 1, draft.clone()));
+        }
+        self.cursor = self.line.len();
+    }
+}
+
+/// Decodes UTF-8 input that arrives in frames, holding back a character
+/// split across two of them until the rest arrives
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Text decoded so far; bytes that can never be UTF-8 become U+FFFD
+    pub fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let mut text = String::with_capacity(self.pending.len());
+        let mut rest = self.pending.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // Incomplete at the end: the next frame may finish it
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending = rest.to_vec();
+        text
+    }
+}
+
+/// Whether output switches the terminal to (`Some(true)`) or away from
+/// (`Some(false)`) the alternate screen full-screen programs draw on
+pub fn alternate_screen_change(output: &[u8]) -> Option<bool> {
+    const MODES: [&[u8]; 3] = [b"\x1b[?1049", b"\x1b[?1047", b"\x1b[?47"];
+
+    let mut change = None;
+    for i in 0..output.len() {
+        for mode in MODES {
+            if output[i..].starts_with(mode) {
+                match output.get(i + mode.len()) {
+                    Some(b'h') => change = Some(true),
+                    Some(b'l') => change = Some(false),
+                    _ => {}
+                }
+            }
+        }
+    }
+    change
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submitted(assembler: &mut LineAssembler, input: &str) -> Vec<(String, bool)> {
+        assembler
+            .feed(input)
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Submit { line, exact, .. } => Some((line, exact)),
+                Segment::Forward(_) | Segment::Pasted { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_enter_is_held_back() {
+        let mut assembler = LineAssembler::new();
+        assert_eq!(
+            assembler.feed("ls -la\r"),
+            vec![
+                Segment::Forward("ls -la".to_string()),
+                Segment::Submit {
+                    line: "ls -la".to_string(),
+                    pattern: None,
+                    exact: true,
+                    enter: "\r".to_string()
+                },
+            ]
+        );
+        // Keystrokes split across messages
+        assembler.feed("r");
+        assembler.feed("m x");
+        assert_eq!(submitted(&mut assembler, "\r"), vec![("rm x".to_string(), true)]);
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut assembler = LineAssembler::new();
+        // Backspace, then left arrow twice and insert
+        assert_eq!(submitted(&mut assembler, "rm -rf /tmpx\x7f\x1b[D\x1b[Da\r")[0].0, "rm -rf /tamp");
+        // Ctrl-U clears, Ctrl-W kills a word, Home/End keys
+        assert_eq!(submitted(&mut assembler, "junk\x15echo one two\x17three\r")[0].0, "echo one three");
+        assert_eq!(submitted(&mut assembler, "rf /\x1b[Hrm -\x1b[F\r")[0].0, "rm -rf /");
+        // Ctrl-C abandons the line
+        assert_eq!(submitted(&mut assembler, "rm -rf /\x03ls\r")[0].0, "ls");
+        // Delete key and application-mode arrows
+        assert_eq!(submitted(&mut assembler, "lsx\x1bOD\x1b[3~\r")[0].0, "ls");
+    }
+
+    #[test]
+    fn test_history_recall() {
+        let mut assembler = LineAssembler::new();
+        submitted(&mut assembler, "echo first\r");
+        submitted(&mut assembler, "echo second\r");
+        assert_eq!(submitted(&mut assembler, "\x1b[A\x1b[A\r")[0].0, "echo first");
+        assert_eq!(submitted(&mut assembler, "draft\x1b[A\x1b[B\r")[0].0, "draft");
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut assembler = LineAssembler::new();
+        // Bracketed paste keeps newlines in the line until Enter, but each
+        // one is held back until the line before it is checked
+        let segments = assembler.feed("\x1b[200~ls\rrm -rf /\ncat\x1b[201~\r");
+        let pasted: Vec<_> = segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Pasted { line, newline, .. } => Some((line.as_str(), newline.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pasted, vec![("ls", "\r"), ("ls\nrm -rf /", "\n")]);
+        assert!(segments.iter().all(|segment| !matches!(segment, Segment::Forward(keys) if keys.contains(['\r', '\n']))));
+        assert!(matches!(segments.last(), Some(Segment::Submit { line, .. }) if line == "ls\nrm -rf /\ncat"));
+        // A plain paste submits each line
+        let lines = submitted(&mut assembler, "ls\nrm -rf /\n");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].0, "rm -rf /");
+    }
+
+    fn patterns(assembler: &mut LineAssembler, input: &str) -> Vec<Option<String>> {
+        assembler
+            .feed(input)
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Submit { pattern, .. } => Some(pattern),
+                Segment::Forward(_) | Segment::Pasted { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_completion_becomes_a_pattern() {
+        let mut assembler = LineAssembler::new();
+        // Tab completion is expected, not a reason to distrust the line
+        assert_eq!(submitted(&mut assembler, "gi\tst\r"), vec![("gist".to_string(), true)]);
+        assert_eq!(patterns(&mut assembler, "gi\tst\r"), vec![Some("gi*st".to_string())]);
+        assert_eq!(patterns(&mut assembler, "cat /etc/sha\t\r"), vec![Some("cat /etc/sha*".to_string())]);
+        assert_eq!(patterns(&mut assembler, "ls\r"), vec![None]);
+
+        let policy = super::super::Policy::builtin();
+        let decision = policy.evaluate("cat /etc/sha*", "user");
+        assert_eq!(decision.rule.as_deref(), Some("account-files"));
+
+        // A pasted tab is just a tab
+        assert_eq!(patterns(&mut assembler, "\x1b[200~a\tb\x1b[201~\r"), vec![None]);
+    }
+
+    #[test]
+    fn test_bare_escape() {
+        let mut assembler = LineAssembler::new();
+        // vim: insert text, Esc on its own, then an ex command
+        assembler.feed("ihello");
+        assembler.feed("\x1b");
+        assert_eq!(submitted(&mut assembler, ":wq\r"), vec![("ihello:wq".to_string(), true)]);
+        // Alt-. arrives with its key and is still not followed
+        assert!(!submitted(&mut assembler, "ls \x1b.\r")[0].1);
+    }
+
+    #[test]
+    fn test_unknown_control_keys_are_inexact() {
+        let mut assembler = LineAssembler::new();
+        // Control keys it does not follow, e.g. Ctrl-T transposing characters
+        assert_eq!(submitted(&mut assembler, "cat /etc/shadwo\x14\r"), vec![("cat /etc/shadwo".to_string(), false)]);
+        assert!(!submitted(&mut assembler, "\x16\x0a\r")[0].1);
+        // Redraw does not change the line
+        assert_eq!(submitted(&mut assembler, "ls\x0c\r"), vec![("ls".to_string(), true)]);
+    }
+
+    #[test]
+    fn test_character_split_across_frames() {
+        let mut decoder = Utf8Decoder::new();
+        let euro = "€".as_bytes();
+        assert_eq!(decoder.decode(&[b'a', euro[0], euro[1]]), "a");
+        assert_eq!(decoder.decode(&[euro[2], b'\r']), "€\r");
+        assert_eq!(decoder.decode(&[0xff, b'b']), "\u{fffd}b");
+    }
+
+    #[test]
+    fn test_alternate_screen_change() {
+        assert_eq!(alternate_screen_change(b"\x1b[?1049h\x1b[H"), Some(true));
+        assert_eq!(alternate_screen_change(b"bye\x1b[?1049l"), Some(false));
+        assert_eq!(alternate_screen_change(b"\x1b[?1049h...\x1b[?1049l"), Some(false));
+        assert_eq!(alternate_screen_change(b"plain output"), None);
+    }
+}
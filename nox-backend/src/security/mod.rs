@@ -0,0 +1,214 @@
+//! NOXTERM Security Module
+//!
+//! Input sanitization, rate limiting, and security validation. Commands are
+//! checked by a shell-aware policy engine loaded from TOML (`policy`).
+
+pub mod line;
+pub mod paths;
+pub mod policy;
+pub mod shell;
+
+pub use policy::{Action, Decision, Policy};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Result of security validation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationResult {
+    pub is_safe: bool,
+    pub reason: Option<String>,
+    pub severity: Severity,
+    /// The command that was blocked or flagged
+    pub blocked_pattern: Option<String>,
+    /// Policy rule that decided
+    pub rule: Option<String>,
+}
+
+/// Security event severity
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Safe,
+    Warning,
+    Critical,
+}
+
+impl Default for ValidationResult {
+    fn default() -> Self {
+        Self {
+            is_safe: true,
+            reason: None,
+            severity: Severity::Safe,
+            blocked_pattern: None,
+            rule: None,
+        }
+    }
+}
+
+impl From<Decision> for ValidationResult {
+    fn from(decision: Decision) -> Self {
+        Self {
+            is_safe: decision.action != Action::Deny,
+            reason: decision.explanation,
+            severity: decision.severity,
+            blocked_pattern: decision.command,
+            rule: decision.rule,
+        }
+    }
+}
+
+/// Check input for a user with `role` against `policy`
+pub fn validate_with(policy: &Policy, input: &str, role: &str) -> ValidationResult {
+    let decision = policy.evaluate(input, role);
+    match decision.action {
+        Action::Deny => warn!(
+            "Blocked command by rule {}: {}",
+            decision.rule.as_deref().unwrap_or("input"),
+            decision.explanation.as_deref().unwrap_or_default()
+        ),
+        Action::Warn => warn!(
+            "Flagged command by rule {}: {}",
+            decision.rule.as_deref().unwrap_or("input"),
+            decision.explanation.as_deref().unwrap_or_default()
+        ),
+        Action::Allow => {}
+    }
+    decision.into()
+}
+
+/// Sanitize container name
+pub fn sanitize_container_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .take(63) // Docker container name limit
+        .collect()
+}
+
+/// Validate user ID format
+pub fn validate_user_id(user_id: &str) -> bool {
+    // User ID should be alphanumeric with underscores/hyphens, max 255 chars
+    if user_id.is_empty() || user_id.len() > 255 {
+        return false;
+    }
+
+    user_id
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Validate container image name
+pub fn validate_image_name(image: &str) -> bool {
+    // Basic validation for Docker image names
+    if image.is_empty() || image.len() > 255 {
+        return false;
+    }
+
+    // Must not contain dangerous characters
+    let invalid_chars = ['$', '`', '|', ';', '&', '>', '<', '\\', '"', '\''];
+    !image.chars().any(|c| invalid_chars.contains(&c))
+}
+
+/// Extract client IP from request headers (supports proxies)
+pub fn extract_client_ip(
+    forwarded_for: Option<&str>,
+    real_ip: Option<&str>,
+    remote_addr: Option<&str>,
+) -> Option<String> {
+    // Try X-Forwarded-For first (first IP in chain)
+    if let Some(xff) = forwarded_for {
+        if let Some(first_ip) = xff.split(',').next() {
+            let ip = first_ip.trim();
+            if !ip.is_empty() {
+                return Some(ip.to_string());
+            }
+        }
+    }
+
+    // Try X-Real-IP
+    if let Some(real) = real_ip {
+        if !real.is_empty() {
+            return Some(real.to_string());
+        }
+    }
+
+    // Fall back to remote address
+    remote_addr.map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Validate input for an ordinary user against the shipped policy
+    fn validate_input(input: &str) -> ValidationResult {
+        validate_with(&Policy::builtin(), input, "user")
+    }
+
+    #[test]
+    fn test_validate_safe_input() {
+        let result = validate_input("ls -la");
+        assert!(result.is_safe);
+    }
+
+    #[test]
+    fn test_block_rm_rf() {
+        let result = validate_input("rm -rf /");
+        assert!(!result.is_safe);
+        assert_eq!(result.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn test_block_fork_bomb() {
+        let result = validate_input(":(){ :|:& };:");
+        assert!(!result.is_safe);
+    }
+
+    #[test]
+    fn test_block_path_traversal() {
+        let result = validate_input("cat ../../../etc/passwd");
+        assert!(!result.is_safe);
+        assert_eq!(result.rule.as_deref(), Some("account-files"));
+        // Relative paths alone are not an attack
+        assert!(validate_input("vim ../src/main.rs").is_safe);
+    }
+
+    #[test]
+    fn test_validate_user_id() {
+        assert!(validate_user_id("user123"));
+        assert!(validate_user_id("user_name"));
+        assert!(validate_user_id("user-name"));
+        assert!(!validate_user_id(""));
+        assert!(!validate_user_id("user;id"));
+    }
+
+    #[test]
+    fn test_validate_image_name() {
+        assert!(validate_image_name("ubuntu:22.04"));
+        assert!(validate_image_name("nginx:latest"));
+        assert!(!validate_image_name("ubuntu; rm -rf /"));
+        assert!(!validate_image_name(""));
+    }
+
+    #[test]
+    fn test_sanitize_container_name() {
+        assert_eq!(sanitize_container_name("my-container_1"), "my-container_1");
+        assert_eq!(sanitize_container_name("bad;name"), "badname");
+    }
+
+    #[test]
+    fn test_extract_client_ip() {
+        assert_eq!(
+            extract_client_ip(Some("1.2.3.4, 5.6.7.8"), None, None),
+            Some("1.2.3.4".to_string())
+        );
+        assert_eq!(
+            extract_client_ip(None, Some("1.2.3.4"), None),
+            Some("1.2.3.4".to_string())
+        );
+        assert_eq!(
+            extract_client_ip(None, None, Some("1.2.3.4:12345")),
+            Some("1.2.3.4:12345".to_string())
+        );
+    }
+}
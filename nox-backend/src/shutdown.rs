@@ -0,0 +1,238 @@
+// BSD 3-Clause License
+// Copyright (c) 2025, NØNOS - NOXTERM 
+//
+//! Graceful shutdown.
+//!
+//! On SIGTERM or Ctrl-C the backend stops taking new sessions, counts down
+//! on every attached terminal, then closes them and exits. Terminals, jobs
+//! and anything else that must finish first hold an `Active` guard so the
+//! drain knows when nothing is left.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Serving,
+    /// No new sessions; attached terminals are counting down
+    Draining,
+    /// Terminals are being closed
+    Closing,
+}
+
+/// What attached terminals are told while the backend drains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notice {
+    /// Seconds left before terminals are closed
+    Countdown(u64),
+    Closing,
+}
+
+impl Notice {
+    pub fn message(&self) -> String {
+        match self {
+            Notice::Countdown(secs) => format!(
+                "NOXTERM is restarting in {}s. Your session will be kept and can be reattached afterwards.",
+                secs
+            ),
+            Notice::Closing => "NOXTERM is restarting now. Reattach to continue where you left off.".to_string(),
+        }
+    }
+
+    pub fn seconds_left(&self) -> u64 {
+        match self {
+            Notice::Countdown(secs) => *secs,
+            Notice::Closing => 0,
+        }
+    }
+}
+
+pub struct Drain {
+    phase: watch::Sender<Phase>,
+    notices: broadcast::Sender<Notice>,
+    active: watch::Sender<usize>,
+}
+
+/// Held by work the drain waits for; dropping it marks the work done
+pub struct Active {
+    drain: Arc<Drain>,
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.drain.active.send_modify(|n| *n = n.saturating_sub(1));
+    }
+}
+
+impl Drain {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            phase: watch::Sender::new(Phase::Serving),
+            notices: broadcast::channel(16).0,
+            active: watch::Sender::new(0),
+        })
+    }
+
+    pub fn phase(&self) -> Phase {
+        *self.phase.borrow()
+    }
+
+    /// Whether new sessions must be refused
+    pub fn is_draining(&self) -> bool {
+        self.phase() != Phase::Serving
+    }
+
+    /// Start draining; false if it had already started
+    pub fn begin(&self) -> bool {
+        self.phase.send_if_modified(|phase| {
+            if *phase != Phase::Serving {
+                return false;
+            }
+            *phase = Phase::Draining;
+            true
+        })
+    }
+
+    /// Tell attached terminals to close
+    pub fn close(&self) {
+        self.phase.send_replace(Phase::Closing);
+        let _ = self.notices.send(Notice::Closing);
+    }
+
+    /// Resolves once terminals are being closed
+    pub async fn closing(&self) {
+        let mut phase = self.phase.subscribe();
+        let _ = phase.wait_for(|phase| *phase == Phase::Closing).await;
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notice> {
+        self.notices.subscribe()
+    }
+
+    pub fn track(self: &Arc<Self>) -> Active {
+        self.active.send_modify(|n| *n += 1);
+        Active { drain: self.clone() }
+    }
+
+    pub fn active(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// Resolves once no `Active` guard is held
+    pub async fn idle(&self) {
+        let mut active = self.active.subscribe();
+        let _ = active.wait_for(|n| *n == 0).await;
+    }
+
+    /// Announce the remaining time to attached terminals until `deadline`,
+    /// returning early if everything finishes first
+    pub async fn countdown(&self, deadline: Instant) {
+        let total = deadline.saturating_duration_since(Instant::now()).as_secs();
+        for secs in countdown_marks(total) {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline - Duration::from_secs(secs)) => {}
+                _ = self.idle() => return,
+            }
+            let _ = self.notices.send(Notice::Countdown(secs));
+        }
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => {}
+            _ = self.idle() => {}
+        }
+    }
+}
+
+/// Seconds left at which terminals are reminded: the start, every ten
+/// seconds, then each of the last five
+pub fn countdown_marks(total: u64) -> Vec<u64> {
+    (1..=total)
+        .rev()
+        .filter(|&secs| secs == total || secs % 10 == 0 || secs <= 5)
+        .collect()
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_countdown_marks() {
+        assert_eq!(countdown_marks(30), vec![30, 20, 10, 5, 4, 3, 2, 1]);
+        assert_eq!(countdown_marks(12), vec![12, 10, 5, 4, 3, 2, 1]);
+        assert_eq!(countdown_marks(3), vec![3, 2, 1]);
+        assert!(countdown_marks(0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_phases() {
+        let drain = Drain::new();
+        assert!(!drain.is_draining());
+        assert!(drain.begin());
+        assert!(!drain.begin());
+        assert_eq!(drain.phase(), Phase::Draining);
+
+        let mut notices = drain.subscribe();
+        drain.close();
+        drain.closing().await;
+        assert_eq!(notices.recv().await.unwrap(), Notice::Closing);
+        assert!(drain.is_draining());
+    }
+
+    #[tokio::test]
+    async fn test_idle_waits_for_active() {
+        let drain = Drain::new();
+        let first = drain.track();
+        let second = drain.track();
+        assert_eq!(drain.active(), 2);
+
+        drop(first);
+        let idle = tokio::time::timeout(Duration::from_millis(20), drain.idle()).await;
+        assert!(idle.is_err());
+
+        drop(second);
+        drain.idle().await;
+        assert_eq!(drain.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_countdown_returns_when_idle() {
+        let drain = Drain::new();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        tokio::time::timeout(Duration::from_secs(1), drain.countdown(deadline))
+            .await
+            .expect("countdown with nothing active should end at once");
+    }
+}